@@ -0,0 +1,57 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+/// How the configs of a bundle are encrypted.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BundleEncryption {
+    /// Anyone knowing the passphrase can read the bundle.
+    Passphrase { passphrase: String },
+    /// Only the holders of the identities of these age X25519 recipients
+    /// (`age1...`) can read the bundle.
+    Recipients { recipients: Vec<String> },
+}
+
+/// Secret used to read a bundle.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BundleKey {
+    Passphrase {
+        passphrase: String,
+    },
+    /// An age X25519 identity (`AGE-SECRET-KEY-1...`).
+    Identity {
+        identity: String,
+    },
+}
+
+/// An encrypted and signed export of configs.
+#[derive(Clone, Deserialize, PartialEq, Serialize, Debug)]
+pub struct ConfigBundle {
+    pub format: String,
+    pub version: u32,
+    /// The exported configs, as an ASCII armored age file.
+    pub payload: String,
+    /// Base64 Ed25519 public key of the install that made the bundle.
+    pub signer: String,
+    /// Base64 Ed25519 signature of the payload.
+    pub signature: String,
+}
+
+/// Configs read from a bundle whose signature was verified.
+#[derive(Clone, Deserialize, PartialEq, Serialize, Debug)]
+pub struct OpenedBundle {
+    pub json: String,
+    pub signer: String,
+}
+
+/// An age X25519 key pair to receive bundles with.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct BundleIdentity {
+    /// Secret identity, kept by the receiver.
+    pub identity: String,
+    /// Public recipient, shared with whoever makes the bundle.
+    pub recipient: String,
+}
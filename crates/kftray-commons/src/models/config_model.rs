@@ -3,6 +3,8 @@ use serde::{
     Serialize,
 };
 
+use crate::models::pod_template_model::PodTemplate;
+
 #[derive(Clone, Deserialize, PartialEq, Serialize, Debug)]
 pub struct Config {
     #[serde(default)]
@@ -36,6 +38,9 @@ pub struct Config {
     pub kubeconfig: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub target: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod_template: Option<PodTemplate>,
 }
 
 impl Default for Config {
@@ -55,6 +60,7 @@ impl Default for Config {
             alias: Some("default-alias".to_string()),
             kubeconfig: Some("default".to_string()),
             target: Some("default-target".to_string()),
+            pod_template: None,
         }
     }
 }
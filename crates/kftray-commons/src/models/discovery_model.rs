@@ -0,0 +1,16 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+/// A config created by watching a context's annotated services.
+#[derive(Clone, Default, Deserialize, PartialEq, Serialize, Debug)]
+pub struct DiscoveredConfig {
+    pub config_id: i64,
+    pub context: String,
+    /// `{namespace}/{service}/{alias}/{protocol}/{remote_port}` of the
+    /// annotation entry the config comes from.
+    pub discovery_key: String,
+    /// The service or its annotation entry is gone, but the config was kept.
+    pub stale: bool,
+}
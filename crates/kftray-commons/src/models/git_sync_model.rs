@@ -0,0 +1,37 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+/// A Git repository whose config file is kept in sync with the local configs.
+#[derive(Clone, Default, Deserialize, PartialEq, Serialize, Debug)]
+#[serde(default)]
+pub struct GitSyncSubscription {
+    pub repo_url: String,
+    /// Branch, tag or commit to follow, the remote's default branch when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
+    pub config_path: String,
+    pub use_system_credentials: bool,
+    /// Minutes between polls, zero only syncs on demand.
+    pub polling_interval: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_synced_commit: Option<String>,
+    /// Unix timestamp of the last successful sync.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_synced_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Changes a sync made to the local configs, listed by alias.
+#[derive(Clone, Default, Deserialize, PartialEq, Serialize, Debug)]
+pub struct GitSyncResult {
+    pub commit: String,
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+    pub unchanged: usize,
+    /// Running forwards restarted because their config changed.
+    pub restarted: Vec<String>,
+}
@@ -0,0 +1,52 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+/// How an import is merged into the local configs.
+#[derive(Clone, Copy, Default, Deserialize, PartialEq, Serialize, Debug)]
+#[serde(default)]
+pub struct ImportOptions {
+    /// Only compute the diff, leaving the local configs untouched.
+    pub dry_run: bool,
+    /// Delete local configs that are not part of the import.
+    pub prune: bool,
+}
+
+/// A config added, changed or removed by an import.
+#[derive(Clone, Default, Deserialize, PartialEq, Serialize, Debug)]
+pub struct ConfigChange {
+    pub identity: String,
+    /// Id of the local config, unset for configs a dry run would add.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    /// Fields that differ from the local config.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+}
+
+/// An imported config left out of the merge.
+#[derive(Clone, Default, Deserialize, PartialEq, Serialize, Debug)]
+pub struct ImportConflict {
+    pub identity: String,
+    pub reason: String,
+}
+
+/// Outcome of merging an import into the local configs.
+#[derive(Clone, Default, Deserialize, PartialEq, Serialize, Debug)]
+pub struct ImportDiff {
+    pub added: Vec<ConfigChange>,
+    pub changed: Vec<ConfigChange>,
+    pub removed: Vec<ConfigChange>,
+    pub conflicting: Vec<ImportConflict>,
+    pub unchanged: usize,
+}
+
+impl ImportDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.changed.is_empty()
+            && self.removed.is_empty()
+            && self.conflicting.is_empty()
+    }
+}
@@ -1,4 +1,9 @@
+pub mod bundle_model;
 pub mod config_model;
 pub mod config_state_model;
+pub mod discovery_model;
+pub mod git_sync_model;
+pub mod import_model;
+pub mod pod_template_model;
 pub mod response;
 pub mod window;
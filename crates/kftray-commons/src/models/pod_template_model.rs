@@ -0,0 +1,65 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::core::v1::{
+    ResourceRequirements,
+    Toleration,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+/// Pod Security Standards level the proxy pod is hardened for.
+#[derive(Clone, Copy, Deserialize, PartialEq, Eq, Serialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SecurityPreset {
+    Baseline,
+    Restricted,
+}
+
+impl SecurityPreset {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SecurityPreset::Baseline => "baseline",
+            SecurityPreset::Restricted => "restricted",
+        }
+    }
+}
+
+/// Kubernetes object the proxy is deployed as.
+#[derive(Clone, Copy, Default, Deserialize, PartialEq, Eq, Serialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyController {
+    #[default]
+    Pod,
+    Deployment,
+    Job,
+}
+
+/// Per-config overrides applied on top of the global proxy pod manifest.
+#[derive(Clone, Default, Deserialize, PartialEq, Serialize, Debug)]
+#[serde(default)]
+pub struct PodTemplate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub controller: Option<ProxyController>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_deadline_seconds: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub security_preset: Option<SecurityPreset>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub image_pull_secrets: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_account: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub node_selector: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tolerations: Vec<Toleration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceRequirements>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
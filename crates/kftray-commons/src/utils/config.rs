@@ -14,6 +14,7 @@ use serde_json::{
 };
 use sqlx::Row;
 
+use crate::config_bundle::is_config_bundle;
 use crate::db::get_db_pool;
 use crate::migration::migrate_configs;
 use crate::models::config_model::Config;
@@ -215,15 +216,7 @@ pub async fn export_configs() -> Result<String, String> {
 }
 
 pub async fn import_configs(json: String) -> Result<(), String> {
-    let configs: Vec<Config> = match serde_json::from_str(&json) {
-        Ok(configs) => configs,
-        Err(e) => {
-            error!("Failed to parse JSON as Vec<Config>: {}", e);
-            let config = serde_json::from_str::<Config>(&json)
-                .map_err(|e| format!("Failed to parse config: {}", e))?;
-            vec![config]
-        }
-    };
+    let configs = parse_configs(&json)?;
 
     for config in configs {
         insert_config(config)
@@ -238,6 +231,26 @@ pub async fn import_configs(json: String) -> Result<(), String> {
     Ok(())
 }
 
+/// Parses an exported list of configs, or a single config.
+pub(crate) fn parse_configs(json: &str) -> Result<Vec<Config>, String> {
+    if is_config_bundle(json) {
+        return Err(
+            "This is an encrypted config bundle, import it with its passphrase or identity"
+                .to_string(),
+        );
+    }
+
+    match serde_json::from_str(json) {
+        Ok(configs) => Ok(configs),
+        Err(e) => {
+            error!("Failed to parse JSON as Vec<Config>: {}", e);
+            let config = serde_json::from_str::<Config>(json)
+                .map_err(|e| format!("Failed to parse config: {}", e))?;
+            Ok(vec![config])
+        }
+    }
+}
+
 fn is_value_blank(value: &JsonValue) -> bool {
     match value {
         JsonValue::String(s) => s.trim().is_empty(),
@@ -280,7 +293,7 @@ fn remove_blank_or_default_fields(value: &mut JsonValue, default_config: &JsonVa
     }
 }
 
-fn prepare_config(mut config: Config) -> Config {
+pub(crate) fn prepare_config(mut config: Config) -> Config {
     if let Some(ref mut alias) = config.alias {
         *alias = alias.trim().to_string();
     }
@@ -0,0 +1,411 @@
+//! Encrypted and signed exports of the configs, for sharing them over
+//! channels others can read.
+//!
+//! A bundle holds the exported configs as an age file, encrypted with a
+//! passphrase or to X25519 recipients, and an Ed25519 signature of that file
+//! made with the signing key of the exporting install. The key is created in
+//! the config dir on first use.
+
+use std::fs;
+use std::io::{
+    Read,
+    Write,
+};
+use std::iter;
+use std::str::FromStr;
+
+use age::armor::{
+    ArmoredReader,
+    ArmoredWriter,
+    Format,
+};
+use age::secrecy::{
+    ExposeSecret,
+    SecretString,
+};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::{
+    Signature,
+    Signer,
+    SigningKey,
+    Verifier,
+    VerifyingKey,
+};
+use log::{
+    info,
+    warn,
+};
+use rand::Rng;
+
+use crate::config::{
+    export_configs,
+    import_configs,
+};
+use crate::config_dir::get_config_dir;
+use crate::models::bundle_model::{
+    BundleEncryption,
+    BundleIdentity,
+    BundleKey,
+    ConfigBundle,
+    OpenedBundle,
+};
+
+const BUNDLE_FORMAT: &str = "kftray-config-bundle";
+const BUNDLE_VERSION: u32 = 1;
+const SIGNING_KEY_FILE: &str = "bundle_signing_key";
+
+/// Exports the configs as a bundle signed with this install's key.
+pub async fn export_encrypted_configs(encryption: &BundleEncryption) -> Result<String, String> {
+    let json = export_configs().await?;
+    let signing_key = load_or_create_signing_key()?;
+    seal_configs(&json, encryption, &signing_key)
+}
+
+/// Verifies and decrypts a bundle, then imports its configs, returning the
+/// key of the signer.
+///
+/// See [`open_bundle`] for how the signer is checked.
+pub async fn import_encrypted_configs(
+    bundle: &str, key: &BundleKey, trusted_signers: &[String],
+) -> Result<String, String> {
+    let opened = open_bundle(bundle, key, trusted_signers)?;
+    import_configs(opened.json).await?;
+
+    info!("Imported config bundle signed by {}", opened.signer);
+    Ok(opened.signer)
+}
+
+/// Encrypts exported configs and signs the result.
+pub fn seal_configs(
+    json: &str, encryption: &BundleEncryption, signing_key: &SigningKey,
+) -> Result<String, String> {
+    let payload = encrypt(json.as_bytes(), encryption)?;
+    let signature = signing_key.sign(&signed_message(&payload));
+
+    let bundle = ConfigBundle {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        payload,
+        signer: STANDARD.encode(signing_key.verifying_key().as_bytes()),
+        signature: STANDARD.encode(signature.to_bytes()),
+    };
+
+    serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())
+}
+
+/// Verifies the signature of a bundle and decrypts its configs.
+///
+/// With trusted signers, bundles signed by any other key are rejected.
+/// Without, any valid signature is accepted and the caller should show the
+/// signer so it can be compared with the key the sender shared.
+pub fn open_bundle(
+    bundle: &str, key: &BundleKey, trusted_signers: &[String],
+) -> Result<OpenedBundle, String> {
+    let bundle: ConfigBundle =
+        serde_json::from_str(bundle).map_err(|e| format!("Invalid config bundle: {}", e))?;
+
+    if bundle.format != BUNDLE_FORMAT {
+        return Err(format!("Not a config bundle: format {}", bundle.format));
+    }
+    if bundle.version != BUNDLE_VERSION {
+        return Err(format!(
+            "Unsupported config bundle version {}",
+            bundle.version
+        ));
+    }
+
+    verify_signature(&bundle)?;
+
+    if trusted_signers.is_empty() {
+        warn!(
+            "No trusted signers given, accepting bundle signed by {}",
+            bundle.signer
+        );
+    } else if !trusted_signers.iter().any(|s| s.trim() == bundle.signer) {
+        return Err(format!(
+            "Config bundle is signed by {}, which is not a trusted signer",
+            bundle.signer
+        ));
+    }
+
+    let plaintext = decrypt(&bundle.payload, key)?;
+    let json = String::from_utf8(plaintext)
+        .map_err(|e| format!("Invalid config bundle content: {}", e))?;
+
+    Ok(OpenedBundle {
+        json,
+        signer: bundle.signer,
+    })
+}
+
+/// Tells whether a file is a config bundle rather than plain exported
+/// configs.
+pub fn is_config_bundle(content: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(content)
+        .ok()
+        .and_then(|value| {
+            value
+                .get("format")
+                .and_then(|f| f.as_str())
+                .map(|f| f == BUNDLE_FORMAT)
+        })
+        .unwrap_or(false)
+}
+
+/// Public key bundles exported by this install are signed with, to share
+/// with the people importing them.
+pub fn signing_public_key() -> Result<String, String> {
+    let signing_key = load_or_create_signing_key()?;
+    Ok(STANDARD.encode(signing_key.verifying_key().as_bytes()))
+}
+
+/// Generates a key pair to receive bundles encrypted to recipients.
+pub fn generate_bundle_identity() -> BundleIdentity {
+    let identity = age::x25519::Identity::generate();
+    BundleIdentity {
+        recipient: identity.to_public().to_string(),
+        identity: identity.to_string().expose_secret().to_string(),
+    }
+}
+
+fn encrypt(plaintext: &[u8], encryption: &BundleEncryption) -> Result<String, String> {
+    let encryptor = match encryption {
+        BundleEncryption::Passphrase { passphrase } => {
+            if passphrase.is_empty() {
+                return Err("Passphrase must not be empty".to_string());
+            }
+            age::Encryptor::with_user_passphrase(SecretString::from(passphrase.clone()))
+        }
+        BundleEncryption::Recipients { recipients } => {
+            let recipients = recipients
+                .iter()
+                .map(|r| {
+                    age::x25519::Recipient::from_str(r.trim())
+                        .map_err(|e| format!("Invalid recipient {}: {}", r, e))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+
+            age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))
+                .map_err(|e| format!("Failed to encrypt configs: {}", e))?
+        }
+    };
+
+    let mut payload = Vec::new();
+    let armored =
+        ArmoredWriter::wrap_output(&mut payload, Format::AsciiArmor).map_err(|e| e.to_string())?;
+    let mut writer = encryptor
+        .wrap_output(armored)
+        .map_err(|e| format!("Failed to encrypt configs: {}", e))?;
+    writer
+        .write_all(plaintext)
+        .and_then(|_| writer.finish())
+        .and_then(|armored| armored.finish())
+        .map_err(|e| format!("Failed to encrypt configs: {}", e))?;
+
+    String::from_utf8(payload).map_err(|e| e.to_string())
+}
+
+fn decrypt(payload: &str, key: &BundleKey) -> Result<Vec<u8>, String> {
+    let decryptor = age::Decryptor::new_buffered(ArmoredReader::new(payload.as_bytes()))
+        .map_err(|e| format!("Invalid config bundle payload: {}", e))?;
+
+    let mut reader = match key {
+        BundleKey::Passphrase { passphrase } => {
+            if !decryptor.is_scrypt() {
+                return Err(
+                    "Config bundle is encrypted to recipients, an identity is needed".to_string(),
+                );
+            }
+            let identity = age::scrypt::Identity::new(SecretString::from(passphrase.clone()));
+            decryptor.decrypt(iter::once(&identity as &dyn age::Identity))
+        }
+        BundleKey::Identity { identity } => {
+            if decryptor.is_scrypt() {
+                return Err(
+                    "Config bundle is encrypted with a passphrase, not to recipients".to_string(),
+                );
+            }
+            let identity = age::x25519::Identity::from_str(identity.trim())
+                .map_err(|e| format!("Invalid identity: {}", e))?;
+            decryptor.decrypt(iter::once(&identity as &dyn age::Identity))
+        }
+    }
+    .map_err(|e| format!("Failed to decrypt config bundle: {}", e))?;
+
+    let mut plaintext = Vec::new();
+    reader
+        .read_to_end(&mut plaintext)
+        .map_err(|e| format!("Failed to decrypt config bundle: {}", e))?;
+
+    Ok(plaintext)
+}
+
+fn verify_signature(bundle: &ConfigBundle) -> Result<(), String> {
+    let signer: [u8; 32] = STANDARD
+        .decode(&bundle.signer)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| "Invalid config bundle signer".to_string())?;
+    let signer = VerifyingKey::from_bytes(&signer)
+        .map_err(|e| format!("Invalid config bundle signer: {}", e))?;
+
+    let signature = STANDARD
+        .decode(&bundle.signature)
+        .ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        .ok_or_else(|| "Invalid config bundle signature".to_string())?;
+
+    signer
+        .verify(&signed_message(&bundle.payload), &signature)
+        .map_err(|_| "Config bundle signature does not match its content".to_string())
+}
+
+/// The signature covers the format and version along with the payload, so a
+/// payload can't be passed off as another kind of bundle.
+fn signed_message(payload: &str) -> Vec<u8> {
+    format!("{}/{}\n{}", BUNDLE_FORMAT, BUNDLE_VERSION, payload).into_bytes()
+}
+
+fn load_or_create_signing_key() -> Result<SigningKey, String> {
+    let path = get_config_dir()?.join(SIGNING_KEY_FILE);
+
+    if path.exists() {
+        let encoded = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read bundle signing key: {}", e))?;
+        let seed: [u8; 32] = STANDARD
+            .decode(encoded.trim())
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| format!("Invalid bundle signing key in {}", path.display()))?;
+        return Ok(SigningKey::from_bytes(&seed));
+    }
+
+    let mut seed = [0u8; 32];
+    rand::rng().fill(&mut seed);
+    let signing_key = SigningKey::from_bytes(&seed);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(&path)
+        .and_then(|mut file| file.write_all(STANDARD.encode(seed).as_bytes()))
+        .map_err(|e| format!("Failed to save bundle signing key: {}", e))?;
+
+    info!("Created bundle signing key at {}", path.display());
+    Ok(signing_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIGS: &str = r#"[{"alias":"db","namespace":"default"}]"#;
+
+    fn signing_key(byte: u8) -> SigningKey {
+        SigningKey::from_bytes(&[byte; 32])
+    }
+
+    #[test]
+    fn recipients_round_trip() {
+        let receiver = generate_bundle_identity();
+        let encryption = BundleEncryption::Recipients {
+            recipients: vec![receiver.recipient.clone()],
+        };
+        let bundle = seal_configs(CONFIGS, &encryption, &signing_key(1)).unwrap();
+        assert!(is_config_bundle(&bundle));
+        assert!(!bundle.contains("default"));
+
+        let key = BundleKey::Identity {
+            identity: receiver.identity,
+        };
+        let opened = open_bundle(&bundle, &key, &[]).unwrap();
+        assert_eq!(opened.json, CONFIGS);
+        assert_eq!(
+            opened.signer,
+            STANDARD.encode(signing_key(1).verifying_key().as_bytes())
+        );
+    }
+
+    #[test]
+    fn other_identity_cannot_open() {
+        let encryption = BundleEncryption::Recipients {
+            recipients: vec![generate_bundle_identity().recipient],
+        };
+        let bundle = seal_configs(CONFIGS, &encryption, &signing_key(1)).unwrap();
+
+        let key = BundleKey::Identity {
+            identity: generate_bundle_identity().identity,
+        };
+        assert!(open_bundle(&bundle, &key, &[]).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_payload() {
+        let receiver = generate_bundle_identity();
+        let encryption = BundleEncryption::Recipients {
+            recipients: vec![receiver.recipient],
+        };
+        let bundle = seal_configs(CONFIGS, &encryption, &signing_key(1)).unwrap();
+
+        // Re-encrypting keeps a valid age file but breaks the signature
+        let mut parsed: ConfigBundle = serde_json::from_str(&bundle).unwrap();
+        parsed.payload = encrypt(b"[]", &encryption).unwrap();
+        let tampered = serde_json::to_string(&parsed).unwrap();
+
+        let key = BundleKey::Identity {
+            identity: receiver.identity,
+        };
+        let err = open_bundle(&tampered, &key, &[]).unwrap_err();
+        assert!(err.contains("signature"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_untrusted_signer() {
+        let receiver = generate_bundle_identity();
+        let encryption = BundleEncryption::Recipients {
+            recipients: vec![receiver.recipient],
+        };
+        let bundle = seal_configs(CONFIGS, &encryption, &signing_key(1)).unwrap();
+        let key = BundleKey::Identity {
+            identity: receiver.identity,
+        };
+
+        let trusted = vec![STANDARD.encode(signing_key(2).verifying_key().as_bytes())];
+        assert!(open_bundle(&bundle, &key, &trusted).is_err());
+
+        let trusted = vec![STANDARD.encode(signing_key(1).verifying_key().as_bytes())];
+        assert!(open_bundle(&bundle, &key, &trusted).is_ok());
+    }
+
+    #[test]
+    fn passphrase_round_trip() {
+        let encryption = BundleEncryption::Passphrase {
+            passphrase: "correct horse".to_string(),
+        };
+        let bundle = seal_configs(CONFIGS, &encryption, &signing_key(1)).unwrap();
+
+        let wrong = BundleKey::Passphrase {
+            passphrase: "wrong".to_string(),
+        };
+        assert!(open_bundle(&bundle, &wrong, &[]).is_err());
+
+        let key = BundleKey::Passphrase {
+            passphrase: "correct horse".to_string(),
+        };
+        assert_eq!(open_bundle(&bundle, &key, &[]).unwrap().json, CONFIGS);
+    }
+
+    #[test]
+    fn plain_exports_are_not_bundles() {
+        assert!(!is_config_bundle(CONFIGS));
+        assert!(!is_config_bundle("not json"));
+    }
+}
@@ -0,0 +1,529 @@
+//! Merging imported configs into the local ones.
+//!
+//! Imported configs are matched with local configs by their identity, so
+//! importing the same file twice updates the configs in place instead of
+//! duplicating them. Local only fields, like the chosen local port, are kept.
+
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+
+use log::{
+    error,
+    info,
+};
+use serde_json::{
+    json,
+    Value as JsonValue,
+};
+
+use crate::config::{
+    parse_configs,
+    prepare_config,
+    read_configs,
+};
+use crate::db::get_db_pool;
+use crate::migration::merge_json_values;
+use crate::models::config_model::Config;
+use crate::models::import_model::{
+    ConfigChange,
+    ImportConflict,
+    ImportDiff,
+    ImportOptions,
+};
+
+#[derive(Default)]
+struct MergePlan {
+    added: Vec<Config>,
+    changed: Vec<Config>,
+    removed: Vec<i64>,
+    diff: ImportDiff,
+}
+
+/// Stable identity of a config: its alias, or where it points to when it has
+/// none.
+///
+/// Shared by imports, Git syncs and discovery, so they all agree on which
+/// local config an incoming one stands for.
+pub fn config_identity(config: &Config) -> String {
+    match config
+        .alias
+        .as_deref()
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+    {
+        Some(alias) => alias.to_string(),
+        None => target_identity(config),
+    }
+}
+
+fn target_identity(config: &Config) -> String {
+    let target = config
+        .service
+        .as_deref()
+        .or(config.remote_address.as_deref())
+        .or(config.target.as_deref())
+        .unwrap_or_default();
+
+    format!(
+        "{}/{}/{}/{}/{}",
+        config.context,
+        config.namespace,
+        target,
+        config.protocol,
+        config.remote_port.unwrap_or_default()
+    )
+}
+
+/// Merges the configs of an exported JSON file into the local configs.
+///
+/// Configs matching a local config are updated in place, the others are
+/// added. Entries that can't be matched unambiguously, or that ask for a
+/// local port already in use, are reported as conflicting and left out.
+/// Local configs missing from the import are only deleted with `prune`.
+pub async fn merge_configs(json: String, options: ImportOptions) -> Result<ImportDiff, String> {
+    let configs = parse_configs(&json)?;
+    let mut plan = plan_merge(read_configs().await?, configs, options.prune)?;
+
+    if !options.dry_run {
+        let added_ids = apply_merge(&plan).await?;
+        for (change, id) in plan.diff.added.iter_mut().zip(added_ids) {
+            change.id = Some(id);
+        }
+    }
+
+    Ok(plan.diff)
+}
+
+fn plan_merge(local: Vec<Config>, configs: Vec<Config>, prune: bool) -> Result<MergePlan, String> {
+    let local: Vec<Config> = local
+        .into_iter()
+        .filter(|config| config.id.is_some())
+        .collect();
+
+    let mut by_alias: HashMap<String, Vec<&Config>> = HashMap::new();
+    let mut by_target: HashMap<String, Vec<&Config>> = HashMap::new();
+    let mut used_ports: HashMap<(String, u16), String> = HashMap::new();
+    for config in &local {
+        if let Some(alias) = config.alias.as_deref().filter(|a| !a.is_empty()) {
+            by_alias.entry(alias.to_string()).or_default().push(config);
+        }
+        by_target
+            .entry(target_identity(config))
+            .or_default()
+            .push(config);
+        if let Some(port) = config.local_port {
+            used_ports.insert(port_key(config, port), config_identity(config));
+        }
+    }
+
+    let mut plan = MergePlan::default();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut matched: HashSet<i64> = HashSet::new();
+
+    for mut config in configs {
+        let identity = config_identity(&config);
+        if !seen.insert(identity.clone()) {
+            plan.diff.conflicting.push(ImportConflict {
+                identity,
+                reason: "Appears more than once in the import".to_string(),
+            });
+            continue;
+        }
+
+        // Stored configs carry every default, so targets are compared with them
+        let candidates = match config.alias.as_deref().map(str::trim) {
+            Some(alias) if !alias.is_empty() => by_alias.get(alias),
+            _ => by_target.get(&target_identity(&with_defaults(config.clone())?)),
+        };
+
+        match candidates.map(Vec::as_slice).unwrap_or_default() {
+            [] => {
+                if let Some(port) = config.local_port.filter(|p| *p != 0) {
+                    if let Some(owner) = used_ports.get(&port_key(&config, port)) {
+                        plan.diff.conflicting.push(ImportConflict {
+                            identity,
+                            reason: format!("Local port {} is already used by {}", port, owner),
+                        });
+                        continue;
+                    }
+                }
+
+                let normalized = normalize_config(config, None)?;
+                if let Some(port) = normalized.local_port {
+                    used_ports.insert(port_key(&normalized, port), identity.clone());
+                }
+                plan.diff.added.push(ConfigChange {
+                    identity,
+                    ..Default::default()
+                });
+                plan.added.push(normalized);
+            }
+            [existing] => {
+                let id = existing.id.unwrap_or_default();
+                matched.insert(id);
+
+                config.local_port = existing.local_port;
+                config.local_address = existing.local_address.clone();
+                let normalized = normalize_config(config, Some(*existing))?;
+
+                let fields = changed_fields(existing, &normalized)?;
+                if fields.is_empty() {
+                    plan.diff.unchanged += 1;
+                } else {
+                    plan.diff.changed.push(ConfigChange {
+                        identity,
+                        id: Some(id),
+                        fields,
+                    });
+                    plan.changed.push(normalized);
+                }
+            }
+            several => {
+                matched.extend(several.iter().filter_map(|c| c.id));
+                plan.diff.conflicting.push(ImportConflict {
+                    identity,
+                    reason: format!("Matches {} local configs", several.len()),
+                });
+            }
+        }
+    }
+
+    if prune {
+        for config in &local {
+            let id = config.id.unwrap_or_default();
+            if !matched.contains(&id) {
+                plan.diff.removed.push(ConfigChange {
+                    identity: config_identity(config),
+                    id: Some(id),
+                    fields: Vec::new(),
+                });
+                plan.removed.push(id);
+            }
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Applies a merge plan in a single transaction, returning the ids of the
+/// added configs.
+async fn apply_merge(plan: &MergePlan) -> Result<Vec<i64>, String> {
+    let pool = get_db_pool().await.map_err(|e| e.to_string())?;
+    let mut transaction = pool.begin().await.map_err(|e| e.to_string())?;
+
+    for id in plan.removed.iter().copied() {
+        sqlx::query("DELETE FROM configs WHERE id = ?1")
+            .bind(id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| format!("Failed to delete config: {}", e))?;
+    }
+
+    for config in &plan.changed {
+        sqlx::query("UPDATE configs SET data = ?1 WHERE id = ?2")
+            .bind(json!(config).to_string())
+            .bind(config.id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| format!("Failed to update config: {}", e))?;
+    }
+
+    let mut added_ids = Vec::with_capacity(plan.added.len());
+    for config in &plan.added {
+        let result = sqlx::query("INSERT INTO configs (data) VALUES (?1)")
+            .bind(json!(config).to_string())
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| format!("Failed to insert config: {}", e))?;
+        added_ids.push(result.last_insert_rowid());
+    }
+
+    transaction.commit().await.map_err(|e| {
+        error!("Failed to commit config merge: {}", e);
+        e.to_string()
+    })?;
+
+    info!(
+        "Config merge applied: {} added, {} changed, {} removed, {} conflicting, {} unchanged",
+        plan.diff.added.len(),
+        plan.diff.changed.len(),
+        plan.diff.removed.len(),
+        plan.diff.conflicting.len(),
+        plan.diff.unchanged
+    );
+
+    Ok(added_ids)
+}
+
+/// Brings an imported config into the shape it is stored in, so it can be
+/// compared with the local config it replaces.
+pub(crate) fn normalize_config(
+    mut config: Config, existing: Option<&Config>,
+) -> Result<Config, String> {
+    if let Some(existing) = existing {
+        if config.local_port.is_none() || config.local_port == Some(0) {
+            config.local_port = existing.local_port;
+        }
+        if config.kubeconfig.as_deref().is_none_or(str::is_empty) {
+            config.kubeconfig = existing.kubeconfig.clone();
+        }
+        if config.alias.as_deref().is_none_or(str::is_empty) {
+            config.alias = existing.alias.clone();
+        }
+    }
+
+    let mut config = with_defaults(prepare_config(config))?;
+    config.id = existing.and_then(|c| c.id);
+
+    Ok(config)
+}
+
+/// Fills in the defaults stored configs carry, see `migrate_configs`.
+fn with_defaults(config: Config) -> Result<Config, String> {
+    let defaults = serde_json::to_value(Config::default()).map_err(|e| e.to_string())?;
+    let value = serde_json::to_value(config).map_err(|e| e.to_string())?;
+    serde_json::from_value(merge_json_values(defaults, value))
+        .map_err(|e| format!("Failed to normalize config: {}", e))
+}
+
+fn changed_fields(existing: &Config, config: &Config) -> Result<Vec<String>, String> {
+    let existing = serde_json::to_value(existing).map_err(|e| e.to_string())?;
+    let config = serde_json::to_value(config).map_err(|e| e.to_string())?;
+
+    let (JsonValue::Object(existing), JsonValue::Object(config)) = (existing, config) else {
+        return Ok(Vec::new());
+    };
+
+    let mut fields: Vec<String> = existing
+        .keys()
+        .chain(config.keys())
+        .filter(|key| key.as_str() != "id" && existing.get(*key) != config.get(*key))
+        .cloned()
+        .collect();
+    fields.sort();
+    fields.dedup();
+
+    Ok(fields)
+}
+
+fn port_key(config: &Config, port: u16) -> (String, u16) {
+    let address = config
+        .local_address
+        .clone()
+        .unwrap_or_else(|| "127.0.0.1".to_string());
+    (address, port)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(alias: Option<&str>, namespace: &str) -> Config {
+        Config {
+            id: None,
+            service: Some("postgres".to_string()),
+            namespace: namespace.to_string(),
+            local_port: None,
+            remote_port: Some(5432),
+            context: "kind".to_string(),
+            workload_type: Some("service".to_string()),
+            protocol: "tcp".to_string(),
+            remote_address: None,
+            local_address: None,
+            alias: alias.map(str::to_string),
+            domain_enabled: None,
+            kubeconfig: None,
+            target: None,
+            pod_template: None,
+        }
+    }
+
+    fn stored(id: i64, alias: &str, local_port: u16) -> Config {
+        let config = Config {
+            local_port: Some(local_port),
+            ..config(Some(alias), "default")
+        };
+        let mut config = normalize_config(config, None).unwrap();
+        config.id = Some(id);
+        config
+    }
+
+    fn identities(changes: &[ConfigChange]) -> Vec<&str> {
+        changes.iter().map(|c| c.identity.as_str()).collect()
+    }
+
+    #[test]
+    fn identity_is_alias_or_target() {
+        assert_eq!(config_identity(&config(Some(" db "), "default")), "db");
+        assert_eq!(
+            config_identity(&config(None, "default")),
+            "kind/default/postgres/tcp/5432"
+        );
+        assert_eq!(
+            config_identity(&config(Some(" "), "default")),
+            config_identity(&config(None, "default"))
+        );
+
+        let udp = Config {
+            protocol: "udp".to_string(),
+            ..config(None, "default")
+        };
+        assert_ne!(
+            config_identity(&udp),
+            config_identity(&config(None, "default"))
+        );
+
+        let proxy = Config {
+            service: None,
+            remote_address: Some("db.internal".to_string()),
+            ..config(None, "default")
+        };
+        assert_eq!(config_identity(&proxy), "kind/default/db.internal/tcp/5432");
+    }
+
+    #[test]
+    fn normalize_keeps_local_fields() {
+        let existing = Config {
+            kubeconfig: Some("/home/dev/.kube/config".to_string()),
+            ..stored(7, "db", 15432)
+        };
+
+        let normalized = normalize_config(config(None, "staging"), Some(&existing)).unwrap();
+        assert_eq!(normalized.id, Some(7));
+        assert_eq!(normalized.local_port, Some(15432));
+        assert_eq!(normalized.alias.as_deref(), Some("db"));
+        assert_eq!(
+            normalized.kubeconfig.as_deref(),
+            Some("/home/dev/.kube/config")
+        );
+        assert_eq!(normalized.namespace, "staging");
+    }
+
+    #[test]
+    fn normalize_matches_stored_shape() {
+        let normalized = normalize_config(
+            Config {
+                local_port: Some(15432),
+                ..config(Some(" db "), "default")
+            },
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(normalized.id, None);
+        assert_eq!(normalized.alias.as_deref(), Some("db"));
+        assert_eq!(normalized.kubeconfig.as_deref(), Some("default"));
+        assert_eq!(normalized, with_defaults(normalized.clone()).unwrap());
+    }
+
+    #[test]
+    fn changed_fields_ignore_id() {
+        let existing = stored(1, "db", 15432);
+        let config = Config {
+            id: Some(2),
+            namespace: "staging".to_string(),
+            local_port: Some(15433),
+            ..existing.clone()
+        };
+
+        assert_eq!(
+            changed_fields(&existing, &config).unwrap(),
+            ["local_port", "namespace"]
+        );
+        assert!(changed_fields(&existing, &existing).unwrap().is_empty());
+    }
+
+    #[test]
+    fn plan_updates_matching_configs_in_place() {
+        let local = vec![stored(1, "db", 15432), stored(2, "api", 18080)];
+        let imported = vec![
+            config(Some("db"), "staging"),
+            config(Some("api"), "default"),
+            config(Some("cache"), "default"),
+        ];
+
+        let plan = plan_merge(local, imported, false).unwrap();
+        assert_eq!(identities(&plan.diff.added), ["cache"]);
+        assert_eq!(
+            plan.diff.changed,
+            [ConfigChange {
+                identity: "db".to_string(),
+                id: Some(1),
+                fields: vec!["namespace".to_string()],
+            }]
+        );
+        assert_eq!(plan.diff.unchanged, 1);
+        assert!(plan.diff.removed.is_empty());
+        assert!(plan.diff.conflicting.is_empty());
+
+        assert_eq!(plan.changed[0].id, Some(1));
+        assert_eq!(plan.changed[0].local_port, Some(15432));
+        assert_eq!(plan.added[0].id, None);
+    }
+
+    #[test]
+    fn plan_matches_target_without_alias() {
+        let plan = plan_merge(
+            vec![stored(1, "db", 15432)],
+            vec![config(None, "default")],
+            false,
+        )
+        .unwrap();
+
+        assert!(plan.diff.added.is_empty());
+        assert_eq!(plan.diff.unchanged, 1);
+    }
+
+    #[test]
+    fn plan_prunes_only_when_asked() {
+        let local = vec![stored(1, "db", 15432), stored(2, "api", 18080)];
+        let imported = vec![config(Some("db"), "default")];
+
+        let plan = plan_merge(local.clone(), imported.clone(), false).unwrap();
+        assert!(plan.removed.is_empty());
+
+        let plan = plan_merge(local, imported, true).unwrap();
+        assert_eq!(plan.removed, [2]);
+        assert_eq!(identities(&plan.diff.removed), ["api"]);
+    }
+
+    #[test]
+    fn plan_reports_conflicts() {
+        let local = vec![
+            stored(1, "db", 15432),
+            stored(2, "api", 18080),
+            stored(3, "api", 18081),
+        ];
+        let imported = vec![
+            config(Some("cache"), "default"),
+            config(Some("cache"), "staging"),
+            Config {
+                local_port: Some(15432),
+                ..config(Some("queue"), "default")
+            },
+            config(Some("api"), "default"),
+        ];
+
+        let plan = plan_merge(local, imported, true).unwrap();
+        let reasons: Vec<(&str, &str)> = plan
+            .diff
+            .conflicting
+            .iter()
+            .map(|c| (c.identity.as_str(), c.reason.as_str()))
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                ("cache", "Appears more than once in the import"),
+                ("queue", "Local port 15432 is already used by db"),
+                ("api", "Matches 2 local configs"),
+            ]
+        );
+        assert_eq!(identities(&plan.diff.added), ["cache"]);
+
+        // Ambiguous matches are kept rather than pruned
+        assert_eq!(plan.removed, [1]);
+    }
+}
@@ -103,6 +103,47 @@ async fn create_db_table() -> Result<(), sqlx::Error> {
         e
     })?;
 
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS git_sync (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            data TEXT NOT NULL
+        )",
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| {
+        error!("Failed to create git_sync table: {}", e);
+        e
+    })?;
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS git_sync_configs (
+            config_id INTEGER PRIMARY KEY,
+            sync_key TEXT NOT NULL
+        )",
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| {
+        error!("Failed to create git_sync_configs table: {}", e);
+        e
+    })?;
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS discovered_configs (
+            config_id INTEGER PRIMARY KEY,
+            context TEXT NOT NULL,
+            discovery_key TEXT NOT NULL,
+            stale BOOLEAN NOT NULL DEFAULT false
+        )",
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| {
+        error!("Failed to create discovered_configs table: {}", e);
+        e
+    })?;
+
     sqlx::query(
         "CREATE TRIGGER IF NOT EXISTS after_insert_config
          AFTER INSERT ON configs
@@ -0,0 +1,113 @@
+//! Configs owned by a discovery watch.
+//!
+//! A watch tracks the configs it creates in `discovered_configs`, so it can
+//! update them when their service's annotation changes and flag or delete
+//! them when the service goes away. Configs created any other way are never
+//! touched by a watch.
+
+use serde_json::json;
+use sqlx::Row;
+
+use crate::config::prepare_config;
+use crate::db::get_db_pool;
+use crate::models::config_model::Config;
+use crate::models::discovery_model::DiscoveredConfig;
+
+/// Configs created by discovery, for one context or all of them.
+///
+/// Configs deleted by hand since are left out.
+pub async fn get_discovered_configs(
+    context: Option<&str>,
+) -> Result<Vec<DiscoveredConfig>, String> {
+    let pool = get_db_pool().await.map_err(|e| e.to_string())?;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let rows = sqlx::query(
+        "SELECT d.config_id, d.context, d.discovery_key, d.stale
+         FROM discovered_configs d
+         JOIN configs c ON c.id = d.config_id
+         WHERE ?1 IS NULL OR d.context = ?1",
+    )
+    .bind(context)
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(DiscoveredConfig {
+                config_id: row.try_get("config_id").map_err(|e| e.to_string())?,
+                context: row.try_get("context").map_err(|e| e.to_string())?,
+                discovery_key: row.try_get("discovery_key").map_err(|e| e.to_string())?,
+                stale: row.try_get("stale").map_err(|e| e.to_string())?,
+            })
+        })
+        .collect()
+}
+
+/// Inserts a discovered config and tracks it, returning its id.
+pub async fn insert_discovered_config(
+    context: &str, discovery_key: &str, config: Config,
+) -> Result<i64, String> {
+    let config = prepare_config(config);
+
+    let pool = get_db_pool().await.map_err(|e| e.to_string())?;
+    let mut transaction = pool.begin().await.map_err(|e| e.to_string())?;
+
+    let id = sqlx::query("INSERT INTO configs (data) VALUES (?1)")
+        .bind(json!(config).to_string())
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| format!("Failed to insert config: {}", e))?
+        .last_insert_rowid();
+
+    sqlx::query(
+        "INSERT OR REPLACE INTO discovered_configs (config_id, context, discovery_key, stale)
+         VALUES (?1, ?2, ?3, false)",
+    )
+    .bind(id)
+    .bind(context)
+    .bind(discovery_key)
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    transaction.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(id)
+}
+
+pub async fn set_discovered_config_stale(config_id: i64, stale: bool) -> Result<(), String> {
+    let pool = get_db_pool().await.map_err(|e| e.to_string())?;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    sqlx::query("UPDATE discovered_configs SET stale = ?1 WHERE config_id = ?2")
+        .bind(stale)
+        .bind(config_id)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Deletes a discovered config along with its tracking.
+pub async fn delete_discovered_config(config_id: i64) -> Result<(), String> {
+    let pool = get_db_pool().await.map_err(|e| e.to_string())?;
+    let mut transaction = pool.begin().await.map_err(|e| e.to_string())?;
+
+    sqlx::query("DELETE FROM configs WHERE id = ?1")
+        .bind(config_id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| format!("Failed to delete config: {}", e))?;
+    sqlx::query("DELETE FROM discovered_configs WHERE config_id = ?1")
+        .bind(config_id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    transaction.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
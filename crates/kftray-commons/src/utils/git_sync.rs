@@ -0,0 +1,420 @@
+//! Persistent sync of the configs with a file in a Git repository.
+//!
+//! Configs created by a sync are tracked in `git_sync_configs` by a key
+//! derived from the file, so the next sync can tell which local configs it
+//! owns. Only those are updated or removed, and configs that did not change
+//! are left untouched so their running forwards keep going.
+
+use std::collections::HashMap;
+use std::time::{
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+use log::{
+    error,
+    info,
+};
+use serde_json::json;
+use sqlx::Row;
+
+use crate::config::read_configs;
+use crate::config_merge::{
+    config_identity,
+    normalize_config,
+};
+use crate::db::get_db_pool;
+use crate::models::config_model::Config;
+use crate::models::git_sync_model::GitSyncSubscription;
+
+/// Changes needed to bring the local configs in line with a synced file.
+#[derive(Debug, Default)]
+pub struct GitSyncPlan {
+    /// New configs, with their sync key.
+    pub added: Vec<(String, Config)>,
+    /// Changed configs, carrying the id of the local config they replace.
+    pub updated: Vec<(String, Config)>,
+    /// Local configs no longer in the file.
+    pub removed: Vec<Config>,
+    /// Local configs identical to their entry in the file.
+    pub unchanged: Vec<(String, Config)>,
+}
+
+impl GitSyncPlan {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+pub async fn get_git_sync() -> Result<Option<GitSyncSubscription>, String> {
+    let pool = get_db_pool().await.map_err(|e| e.to_string())?;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let row = sqlx::query("SELECT data FROM git_sync WHERE id = 1")
+        .fetch_optional(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    match row {
+        Some(row) => {
+            let data: String = row.try_get("data").map_err(|e| e.to_string())?;
+            serde_json::from_str(&data)
+                .map(Some)
+                .map_err(|e| format!("Failed to parse git sync subscription: {}", e))
+        }
+        None => Ok(None),
+    }
+}
+
+pub async fn save_git_sync(subscription: &GitSyncSubscription) -> Result<(), String> {
+    let pool = get_db_pool().await.map_err(|e| e.to_string())?;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let data = json!(subscription).to_string();
+
+    sqlx::query("INSERT OR REPLACE INTO git_sync (id, data) VALUES (1, ?1)")
+        .bind(data)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| format!("Failed to save git sync subscription: {}", e))?;
+
+    Ok(())
+}
+
+/// Removes the subscription. Synced configs are kept as local configs.
+pub async fn delete_git_sync() -> Result<(), String> {
+    let pool = get_db_pool().await.map_err(|e| e.to_string())?;
+    let mut transaction = pool.begin().await.map_err(|e| e.to_string())?;
+
+    sqlx::query("DELETE FROM git_sync")
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| e.to_string())?;
+    sqlx::query("DELETE FROM git_sync_configs")
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    transaction.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Records the outcome of a sync on the subscription, keeping the last synced
+/// commit when the sync failed.
+pub async fn record_git_sync(commit: Option<String>, error: Option<String>) -> Result<(), String> {
+    let mut subscription = get_git_sync()
+        .await?
+        .ok_or_else(|| "No git sync subscription configured".to_string())?;
+
+    if let Some(commit) = commit {
+        subscription.last_synced_commit = Some(commit);
+        subscription.last_synced_at = Some(unix_now());
+    }
+    subscription.last_error = error;
+
+    save_git_sync(&subscription).await
+}
+
+/// Compares the configs of a synced file with the local ones.
+///
+/// A local config belongs to the sync when an earlier sync created it, or
+/// when it is untracked and has the alias of an entry in the file. The local
+/// port and kubeconfig are machine specific, so they are kept when the file
+/// leaves them out.
+pub async fn plan_git_sync(configs: Vec<Config>) -> Result<GitSyncPlan, String> {
+    plan_sync(
+        read_configs().await?,
+        read_tracked_configs().await?,
+        configs,
+    )
+}
+
+fn plan_sync(
+    local: Vec<Config>, tracked: Vec<(String, i64)>, configs: Vec<Config>,
+) -> Result<GitSyncPlan, String> {
+    let local: HashMap<i64, Config> = local
+        .into_iter()
+        .filter_map(|config| config.id.map(|id| (id, config)))
+        .collect();
+
+    let mut tracked: HashMap<String, i64> = tracked
+        .into_iter()
+        .filter(|(_, id)| local.contains_key(id))
+        .collect();
+
+    let mut untracked: HashMap<String, i64> = HashMap::new();
+    for (id, config) in &local {
+        if !tracked.values().any(|tracked_id| tracked_id == id) {
+            untracked.entry(config_identity(config)).or_insert(*id);
+        }
+    }
+
+    let mut plan = GitSyncPlan::default();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+
+    for config in configs {
+        // Entries sharing an identity are told apart by their position
+        let mut key = config_identity(&config);
+        let count = seen.entry(key.clone()).or_default();
+        *count += 1;
+        if *count > 1 {
+            key = format!("{}#{}", key, count);
+        }
+
+        let existing = tracked
+            .remove(&key)
+            .or_else(|| untracked.remove(&key))
+            .and_then(|id| local.get(&id));
+
+        let normalized = normalize_config(config, existing)?;
+        match existing {
+            Some(existing) if *existing == normalized => {
+                plan.unchanged.push((key, normalized));
+            }
+            Some(_) => plan.updated.push((key, normalized)),
+            None => plan.added.push((key, normalized)),
+        }
+    }
+
+    plan.removed = tracked
+        .into_values()
+        .filter_map(|id| local.get(&id).cloned())
+        .collect();
+
+    Ok(plan)
+}
+
+/// Applies a sync plan in a single transaction, returning the ids of the
+/// added configs.
+pub async fn apply_git_sync(plan: &GitSyncPlan) -> Result<Vec<i64>, String> {
+    let pool = get_db_pool().await.map_err(|e| e.to_string())?;
+    let mut transaction = pool.begin().await.map_err(|e| e.to_string())?;
+
+    sqlx::query("DELETE FROM git_sync_configs")
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    for config in &plan.removed {
+        sqlx::query("DELETE FROM configs WHERE id = ?1")
+            .bind(config.id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| format!("Failed to delete config: {}", e))?;
+    }
+
+    for (_, config) in &plan.updated {
+        sqlx::query("UPDATE configs SET data = ?1 WHERE id = ?2")
+            .bind(json!(config).to_string())
+            .bind(config.id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| format!("Failed to update config: {}", e))?;
+    }
+
+    let mut added_ids = Vec::with_capacity(plan.added.len());
+    for (_, config) in &plan.added {
+        let result = sqlx::query("INSERT INTO configs (data) VALUES (?1)")
+            .bind(json!(config).to_string())
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| format!("Failed to insert config: {}", e))?;
+        added_ids.push(result.last_insert_rowid());
+    }
+
+    let tracked = plan
+        .updated
+        .iter()
+        .chain(&plan.unchanged)
+        .map(|(key, config)| (key, config.id.unwrap_or_default()))
+        .chain(
+            plan.added
+                .iter()
+                .zip(&added_ids)
+                .map(|((key, _), id)| (key, *id)),
+        );
+    for (key, id) in tracked {
+        sqlx::query(
+            "INSERT OR REPLACE INTO git_sync_configs (config_id, sync_key) VALUES (?1, ?2)",
+        )
+        .bind(id)
+        .bind(key.as_str())
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+
+    transaction.commit().await.map_err(|e| {
+        error!("Failed to commit git sync: {}", e);
+        e.to_string()
+    })?;
+
+    info!(
+        "Git sync applied: {} added, {} updated, {} removed, {} unchanged",
+        plan.added.len(),
+        plan.updated.len(),
+        plan.removed.len(),
+        plan.unchanged.len()
+    );
+
+    Ok(added_ids)
+}
+
+async fn read_tracked_configs() -> Result<Vec<(String, i64)>, String> {
+    let pool = get_db_pool().await.map_err(|e| e.to_string())?;
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+
+    let rows = sqlx::query("SELECT config_id, sync_key FROM git_sync_configs")
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    rows.into_iter()
+        .map(|row| {
+            let id: i64 = row.try_get("config_id").map_err(|e| e.to_string())?;
+            let key: String = row.try_get("sync_key").map_err(|e| e.to_string())?;
+            Ok((key, id))
+        })
+        .collect()
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(alias: Option<&str>, namespace: &str) -> Config {
+        Config {
+            id: None,
+            service: Some("postgres".to_string()),
+            namespace: namespace.to_string(),
+            local_port: None,
+            remote_port: Some(5432),
+            context: "kind".to_string(),
+            workload_type: Some("service".to_string()),
+            protocol: "tcp".to_string(),
+            remote_address: None,
+            local_address: None,
+            alias: alias.map(str::to_string),
+            domain_enabled: None,
+            kubeconfig: None,
+            target: None,
+            pod_template: None,
+        }
+    }
+
+    fn stored(id: i64, config: Config, local_port: u16) -> Config {
+        let config = Config {
+            local_port: Some(local_port),
+            ..config
+        };
+        let mut config = normalize_config(config, None).unwrap();
+        config.id = Some(id);
+        config
+    }
+
+    fn keys(entries: &[(String, Config)]) -> Vec<&str> {
+        entries.iter().map(|(key, _)| key.as_str()).collect()
+    }
+
+    fn tracked(keys: &[(&str, i64)]) -> Vec<(String, i64)> {
+        keys.iter()
+            .map(|(key, id)| (key.to_string(), *id))
+            .collect()
+    }
+
+    #[test]
+    fn detects_added_changed_and_removed() {
+        let local = vec![
+            stored(1, config(Some("db"), "default"), 15432),
+            stored(2, config(Some("api"), "default"), 18080),
+            stored(3, config(Some("cache"), "default"), 16379),
+        ];
+        let file = vec![
+            config(Some("db"), "staging"),
+            config(Some("cache"), "default"),
+            config(Some("queue"), "default"),
+        ];
+
+        let plan = plan_sync(local, tracked(&[("db", 1), ("api", 2), ("cache", 3)]), file).unwrap();
+        assert_eq!(keys(&plan.added), ["queue"]);
+        assert_eq!(keys(&plan.updated), ["db"]);
+        assert_eq!(keys(&plan.unchanged), ["cache"]);
+        assert_eq!(
+            plan.removed.iter().map(|c| c.id).collect::<Vec<_>>(),
+            [Some(2)]
+        );
+
+        assert_eq!(plan.updated[0].1.id, Some(1));
+        assert_eq!(plan.updated[0].1.namespace, "staging");
+        assert_eq!(plan.added[0].1.id, None);
+    }
+
+    #[test]
+    fn keeps_local_fields() {
+        let local = Config {
+            kubeconfig: Some("/home/dev/.kube/config".to_string()),
+            ..stored(1, config(Some("db"), "default"), 15432)
+        };
+
+        let plan = plan_sync(
+            vec![local],
+            tracked(&[("db", 1)]),
+            vec![config(Some("db"), "staging")],
+        )
+        .unwrap();
+        let (_, updated) = &plan.updated[0];
+        assert_eq!(updated.local_port, Some(15432));
+        assert_eq!(
+            updated.kubeconfig.as_deref(),
+            Some("/home/dev/.kube/config")
+        );
+    }
+
+    #[test]
+    fn adopts_untracked_configs_only_by_key() {
+        let local = vec![
+            stored(1, config(Some("db"), "default"), 15432),
+            stored(2, config(Some("manual"), "default"), 18080),
+        ];
+
+        let plan = plan_sync(local, Vec::new(), vec![config(Some("db"), "default")]).unwrap();
+        assert_eq!(keys(&plan.unchanged), ["db"]);
+        assert_eq!(plan.unchanged[0].1.id, Some(1));
+        assert!(plan.added.is_empty());
+
+        // Configs the sync never created are not removed
+        assert!(plan.removed.is_empty());
+    }
+
+    #[test]
+    fn keys_by_alias_or_target() {
+        let target = "kind/default/postgres/tcp/5432";
+        let file = vec![
+            config(None, "default"),
+            config(None, "default"),
+            config(Some("db"), "default"),
+        ];
+
+        let plan = plan_sync(Vec::new(), Vec::new(), file).unwrap();
+        assert_eq!(keys(&plan.added), [target, &format!("{}#2", target), "db"]);
+
+        // A tracked target key still matches once the config got an alias
+        let local = stored(1, config(Some("db"), "default"), 15432);
+        let plan = plan_sync(
+            vec![local],
+            tracked(&[(target, 1)]),
+            vec![config(None, "default")],
+        )
+        .unwrap();
+        assert_eq!(keys(&plan.unchanged), [target]);
+        assert_eq!(plan.unchanged[0].1.alias.as_deref(), Some("db"));
+    }
+}
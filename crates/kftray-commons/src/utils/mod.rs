@@ -1,7 +1,11 @@
 pub mod config;
+pub mod config_bundle;
+pub mod config_merge;
 pub mod config_dir;
 pub mod config_state;
 pub mod db;
+pub mod discovery;
+pub mod git_sync;
 pub mod github;
 pub mod migration;
 pub mod validate_configs;
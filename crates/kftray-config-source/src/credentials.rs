@@ -0,0 +1,463 @@
+//! Credentials for Git remotes and config sources.
+//!
+//! Git remotes are authenticated with the first credential that works out of
+//! the SSH agent, SSH keys, the Git credential helper and `~/.git-credentials`.
+//! Tokens for other sources are kept in the OS keyring, per host.
+
+use std::fmt;
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use git2::Cred;
+use keyring::{
+    Entry,
+    Error as KeyringError,
+};
+use log::{
+    info,
+    warn,
+};
+use url::Url;
+
+use crate::error::SourceError;
+
+/// Keyring service holding the tokens of config sources
+const TOKEN_KEYRING_SERVICE: &str = "kftray";
+
+/// A way of authenticating to a Git remote.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum AuthMethod {
+    /// The token given for the source
+    Token,
+    SshAgent,
+    SshKey(PathBuf),
+    /// The Git credential helper, e.g. the OS credential store
+    CredentialHelper,
+    /// An entry of `~/.git-credentials`, by username
+    StoredCredentials(String),
+}
+
+impl fmt::Display for AuthMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthMethod::Token => write!(f, "token"),
+            AuthMethod::SshAgent => write!(f, "SSH agent"),
+            AuthMethod::SshKey(path) => write!(f, "SSH key {}", path.display()),
+            AuthMethod::CredentialHelper => write!(f, "credential helper"),
+            AuthMethod::StoredCredentials(username) => {
+                write!(f, "stored credentials of {}", username)
+            }
+        }
+    }
+}
+
+pub fn try_credentials_from_file() -> Vec<(String, String)> {
+    let home_dir = std::env::var("HOME").unwrap_or_default();
+    let credentials_path = Path::new(&home_dir).join(".git-credentials");
+
+    match std::fs::read_to_string(credentials_path) {
+        Ok(content) => parse_git_credentials(&content),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn parse_git_credentials(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .filter_map(|line| {
+            Url::parse(line.trim()).ok().and_then(|url| {
+                let username = url.username().to_string();
+                let password = url.password()?.to_string();
+                if !username.is_empty() {
+                    Some((username, password))
+                } else {
+                    None
+                }
+            })
+        })
+        .collect()
+}
+
+/// Tries the credential chain for a Git remote, reporting every method
+/// before it is tried.
+pub fn get_git_credentials(
+    url: &str, username: &str, report: &dyn Fn(AuthMethod),
+) -> Result<Cred, git2::Error> {
+    info!("Getting credentials for URL: {}", url);
+
+    if url.starts_with("git@") || url.starts_with("ssh://") {
+        if let Ok(cred) = try_ssh_authentication(username, report) {
+            return Ok(cred);
+        }
+    }
+    if let Ok(cred) = try_credential_helper(url, username, report) {
+        return Ok(cred);
+    }
+
+    // Fall back to stored credentials
+    try_stored_credentials(report)
+}
+
+fn try_ssh_agent(username: &str, report: &dyn Fn(AuthMethod)) -> Result<Cred, git2::Error> {
+    report(AuthMethod::SshAgent);
+    match Cred::ssh_key_from_agent(username) {
+        Ok(cred) => {
+            info!("Successfully authenticated with SSH agent");
+            Ok(cred)
+        }
+        Err(e) => {
+            info!("SSH agent authentication failed: {}", e);
+            Err(e)
+        }
+    }
+}
+
+fn try_git_ssh_config(username: &str, report: &dyn Fn(AuthMethod)) -> Result<Cred, git2::Error> {
+    let config = git2::Config::open_default()?;
+
+    let key_path_str = config.get_string("core.sshCommand").map_err(|e| {
+        info!("No core.sshCommand in git config: {}", e);
+        e
+    })?;
+
+    let key_arg_pos = key_path_str.find(" -i ").ok_or_else(|| {
+        info!("No -i flag in core.sshCommand");
+        git2::Error::from_str("No -i flag in core.sshCommand")
+    })?;
+
+    let key_path_start = key_arg_pos + 4;
+    let key_path_end = key_path_str[key_path_start..]
+        .find(' ')
+        .map(|pos| key_path_start + pos)
+        .unwrap_or(key_path_str.len());
+
+    let key_path_str = &key_path_str[key_path_start..key_path_end];
+    let key_path = Path::new(key_path_str);
+
+    if !key_path.exists() {
+        info!(
+            "SSH key from git config doesn't exist: {}",
+            key_path.display()
+        );
+        return Err(git2::Error::from_str(
+            "SSH key from git config doesn't exist",
+        ));
+    }
+
+    info!("Trying SSH key from git config: {}", key_path.display());
+    report(AuthMethod::SshKey(key_path.to_path_buf()));
+    match Cred::ssh_key(username, None, key_path, None) {
+        Ok(cred) => {
+            info!("Successfully authenticated with SSH key from git config");
+            Ok(cred)
+        }
+        Err(e) => {
+            info!("Failed to use SSH key from git config: {}", e);
+            Err(e)
+        }
+    }
+}
+
+fn try_env_ssh_key(username: &str, report: &dyn Fn(AuthMethod)) -> Result<Cred, git2::Error> {
+    let key_path_str = std::env::var("SSH_KEY_PATH")
+        .map_err(|_| git2::Error::from_str("SSH_KEY_PATH environment variable not set"))?;
+
+    let key_path = PathBuf::from(key_path_str);
+    if !key_path.exists() {
+        info!(
+            "SSH key from environment variable doesn't exist: {}",
+            key_path.display()
+        );
+        return Err(git2::Error::from_str(
+            "SSH key from environment variable doesn't exist",
+        ));
+    }
+
+    info!("Trying SSH key from SSH_KEY_PATH: {}", key_path.display());
+    report(AuthMethod::SshKey(key_path.clone()));
+    match Cred::ssh_key(username, None, &key_path, None) {
+        Ok(cred) => {
+            info!("Successfully authenticated with SSH key from environment variable");
+            Ok(cred)
+        }
+        Err(e) => {
+            info!("Failed to use SSH key from environment variable: {}", e);
+            Err(e)
+        }
+    }
+}
+
+fn get_ssh_directories() -> Vec<PathBuf> {
+    let mut ssh_dirs = Vec::new();
+
+    if let Ok(home_dir) = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE")) {
+        ssh_dirs.push(PathBuf::from(home_dir).join(".ssh"));
+    }
+
+    if let Ok(custom_ssh_dir) = std::env::var("SSH_DIR") {
+        ssh_dirs.push(PathBuf::from(custom_ssh_dir));
+    }
+
+    ssh_dirs
+}
+
+fn try_standard_key_names(
+    username: &str, ssh_dirs: &[PathBuf], report: &dyn Fn(AuthMethod),
+) -> Result<Cred, git2::Error> {
+    let key_names = ["id_ed25519", "id_rsa", "id_ecdsa", "id_dsa"];
+
+    for dir in ssh_dirs {
+        if !dir.exists() || !dir.is_dir() {
+            continue;
+        }
+
+        for key_name in &key_names {
+            let key_path = dir.join(key_name);
+            if key_path.exists() {
+                info!("Trying standard SSH key: {}", key_path.display());
+                report(AuthMethod::SshKey(key_path.clone()));
+                if let Ok(cred) = Cred::ssh_key(username, None, &key_path, None) {
+                    info!(
+                        "Successfully authenticated with standard SSH key: {}",
+                        key_name
+                    );
+                    return Ok(cred);
+                }
+            }
+        }
+    }
+
+    Err(git2::Error::from_str(
+        "No standard SSH keys found or none worked",
+    ))
+}
+
+fn try_key_file(
+    username: &str, path: &Path, report: &dyn Fn(AuthMethod),
+) -> Result<Cred, git2::Error> {
+    if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
+        if file_name.ends_with(".pub")
+            || file_name == "known_hosts"
+            || file_name == "authorized_keys"
+            || file_name == "config"
+        {
+            return Err(git2::Error::from_str("Not a private key file"));
+        }
+    } else {
+        return Err(git2::Error::from_str("Invalid file name"));
+    }
+
+    info!("Trying potential SSH key: {}", path.display());
+    report(AuthMethod::SshKey(path.to_path_buf()));
+    match Cred::ssh_key(username, None, path, None) {
+        Ok(cred) => {
+            info!(
+                "Successfully authenticated with SSH key: {}",
+                path.display()
+            );
+            Ok(cred)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn scan_directory_for_keys(
+    username: &str, dir: &Path, report: &dyn Fn(AuthMethod),
+) -> Result<Cred, git2::Error> {
+    if !dir.exists() || !dir.is_dir() {
+        return Err(git2::Error::from_str(
+            "Directory doesn't exist or is not a directory",
+        ));
+    }
+
+    info!("Scanning for SSH keys in: {}", dir.display());
+
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            info!("Failed to read SSH directory {}: {}", dir.display(), e);
+            return Err(git2::Error::from_str(&format!(
+                "Failed to read directory: {}",
+                e
+            )));
+        }
+    };
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+
+        let path = entry.path();
+
+        if !path.is_file() {
+            continue;
+        }
+
+        if let Ok(cred) = try_key_file(username, &path, report) {
+            return Ok(cred);
+        }
+    }
+
+    Err(git2::Error::from_str(
+        "No valid SSH keys found in directory",
+    ))
+}
+
+fn scan_subdirectories_for_keys(
+    username: &str, dir: &Path, report: &dyn Fn(AuthMethod),
+) -> Result<Cred, git2::Error> {
+    if !dir.exists() || !dir.is_dir() {
+        return Err(git2::Error::from_str(
+            "Directory doesn't exist or is not a directory",
+        ));
+    }
+
+    let subdirs = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            info!("Failed to read directory {}: {}", dir.display(), e);
+            return Err(git2::Error::from_str(&format!(
+                "Failed to read directory: {}",
+                e
+            )));
+        }
+    };
+
+    for subdir_entry in subdirs {
+        let subdir_entry = match subdir_entry {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+
+        let subdir_path = subdir_entry.path();
+        if !subdir_path.is_dir() {
+            continue;
+        }
+
+        let subdir_entries = match std::fs::read_dir(&subdir_path) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for file_entry in subdir_entries {
+            let file_entry = match file_entry {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+
+            let file_path = file_entry.path();
+            if !file_path.is_file() {
+                continue;
+            }
+
+            if let Ok(cred) = try_key_file(username, &file_path, report) {
+                return Ok(cred);
+            }
+        }
+    }
+
+    Err(git2::Error::from_str(
+        "No valid SSH keys found in subdirectories",
+    ))
+}
+
+fn try_ssh_authentication(
+    username: &str, report: &dyn Fn(AuthMethod),
+) -> Result<Cred, git2::Error> {
+    if let Ok(cred) = try_ssh_agent(username, report) {
+        return Ok(cred);
+    }
+
+    if let Ok(cred) = try_git_ssh_config(username, report) {
+        return Ok(cred);
+    }
+
+    if let Ok(cred) = try_env_ssh_key(username, report) {
+        return Ok(cred);
+    }
+
+    let ssh_dirs = get_ssh_directories();
+
+    if let Ok(cred) = try_standard_key_names(username, &ssh_dirs, report) {
+        return Ok(cred);
+    }
+
+    for dir in &ssh_dirs {
+        if let Ok(cred) = scan_directory_for_keys(username, dir, report) {
+            return Ok(cred);
+        }
+
+        if let Ok(cred) = scan_subdirectories_for_keys(username, dir, report) {
+            return Ok(cred);
+        }
+    }
+
+    Err(git2::Error::from_str(
+        "SSH authentication failed: no valid SSH keys found",
+    ))
+}
+
+fn try_credential_helper(
+    url: &str, username: &str, report: &dyn Fn(AuthMethod),
+) -> Result<Cred, git2::Error> {
+    report(AuthMethod::CredentialHelper);
+    if let Ok(config) = git2::Config::open_default() {
+        match Cred::credential_helper(&config, url, Some(username)) {
+            Ok(cred) => {
+                info!("Successfully retrieved credentials from OS credential store");
+                Ok(cred)
+            }
+            Err(e) => {
+                info!("Credential helper failed: {}", e);
+                Err(e)
+            }
+        }
+    } else {
+        Err(git2::Error::from_str("Failed to open git config"))
+    }
+}
+
+fn try_stored_credentials(report: &dyn Fn(AuthMethod)) -> Result<Cred, git2::Error> {
+    let credentials = try_credentials_from_file();
+
+    for (username, password) in credentials {
+        info!("Trying stored credentials for username: {}", username);
+        report(AuthMethod::StoredCredentials(username.clone()));
+        if let Ok(cred) = Cred::userpass_plaintext(&username, &password) {
+            info!("Successfully authenticated with stored credentials");
+            return Ok(cred);
+        }
+    }
+
+    Err(git2::Error::from_str("No valid credentials found"))
+}
+
+/// Stores the token used for the sources of a host in the OS keyring.
+pub fn store_token(host: &str, token: &str) -> Result<(), SourceError> {
+    token_entry(host)?
+        .set_password(token)
+        .map_err(SourceError::from)
+}
+
+/// Reads the token stored for a host, if any.
+pub fn get_token(host: &str) -> Option<String> {
+    token_entry(host).ok()?.get_password().ok()
+}
+
+pub fn delete_token(host: &str) -> Result<(), SourceError> {
+    match token_entry(host)?.delete_credential() {
+        Ok(()) | Err(KeyringError::NoEntry) => Ok(()),
+        Err(e) => {
+            warn!("Failed to delete token for {}: {}", host, e);
+            Err(e.into())
+        }
+    }
+}
+
+fn token_entry(host: &str) -> Result<Entry, SourceError> {
+    Entry::new(TOKEN_KEYRING_SERVICE, &format!("source_token:{}", host)).map_err(SourceError::from)
+}
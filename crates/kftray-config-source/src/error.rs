@@ -0,0 +1,130 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::credentials::AuthMethod;
+
+/// Errors of reading a config source.
+#[derive(Debug)]
+pub enum SourceError {
+    /// No credential was accepted by the remote, listing the methods tried
+    Auth {
+        url: String,
+        tried: Vec<AuthMethod>,
+    },
+    /// The repository could not be cloned with libgit2 nor the `git` command
+    Clone {
+        url: String,
+        message: String,
+    },
+    /// The branch, tag or commit does not exist on the remote
+    RefNotFound {
+        url: String,
+        git_ref: String,
+    },
+    Git(git2::Error),
+    /// The config file could not be read
+    ConfigFile {
+        path: PathBuf,
+        source: io::Error,
+    },
+    NotADirectory(PathBuf),
+    Http {
+        url: String,
+        message: String,
+    },
+    HttpStatus {
+        url: String,
+        status: u16,
+    },
+    /// A token would be sent to a URL that isn't `https`
+    InsecureUrl(String),
+    Keyring(keyring::Error),
+    Io(io::Error),
+    /// The blocking task reading the source failed
+    Task(String),
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceError::Auth { url, tried } if tried.is_empty() => {
+                write!(
+                    f,
+                    "Authentication to {} failed: no credentials configured",
+                    url
+                )
+            }
+            SourceError::Auth { url, tried } => {
+                let tried: Vec<String> = tried.iter().map(ToString::to_string).collect();
+                write!(
+                    f,
+                    "Authentication to {} failed, tried: {}",
+                    url,
+                    tried.join(", ")
+                )
+            }
+            SourceError::Clone { url, message } => write!(
+                f,
+                "Failed to clone {}. Please check your credentials and repository URL. Error: {}",
+                url, message
+            ),
+            SourceError::RefNotFound { url, git_ref } => {
+                write!(f, "Ref {} not found in {}", git_ref, url)
+            }
+            SourceError::Git(err) => write!(f, "Git error: {}", err),
+            SourceError::ConfigFile { path, source } => write!(
+                f,
+                "Failed to read config file at {}: {}",
+                path.display(),
+                source
+            ),
+            SourceError::NotADirectory(path) => {
+                write!(f, "{} is not a directory", path.display())
+            }
+            SourceError::Http { url, message } => write!(f, "Failed to fetch {}: {}", url, message),
+            SourceError::HttpStatus { url, status } => {
+                write!(f, "Failed to fetch {}: server returned {}", url, status)
+            }
+            SourceError::InsecureUrl(url) => write!(
+                f,
+                "Refusing to send the token for {} over an insecure connection, use an https URL",
+                url
+            ),
+            SourceError::Keyring(err) => write!(f, "Keyring error: {}", err),
+            SourceError::Io(err) => write!(f, "IO Error: {}", err),
+            SourceError::Task(msg) => write!(f, "Task failed: {}", msg),
+        }
+    }
+}
+
+impl Error for SourceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SourceError::Git(err) => Some(err),
+            SourceError::ConfigFile { source, .. } => Some(source),
+            SourceError::Keyring(err) => Some(err),
+            SourceError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<git2::Error> for SourceError {
+    fn from(err: git2::Error) -> Self {
+        SourceError::Git(err)
+    }
+}
+
+impl From<keyring::Error> for SourceError {
+    fn from(err: keyring::Error) -> Self {
+        SourceError::Keyring(err)
+    }
+}
+
+impl From<io::Error> for SourceError {
+    fn from(err: io::Error) -> Self {
+        SourceError::Io(err)
+    }
+}
@@ -0,0 +1,329 @@
+//! Config files read from Git repositories, on any host.
+
+use std::path::Path;
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+use std::sync::{
+    Arc,
+    Mutex,
+};
+
+use git2::{
+    CertificateCheckStatus,
+    Cred,
+    Direction,
+    ErrorCode,
+    FetchOptions,
+    RemoteCallbacks,
+    Repository,
+};
+use log::{
+    error,
+    info,
+    warn,
+};
+use tempfile::TempDir;
+
+use crate::credentials::{
+    get_git_credentials,
+    AuthMethod,
+};
+use crate::error::SourceError;
+use crate::local::read_config_file;
+use crate::progress::{
+    GitProgress,
+    ProgressCallback,
+};
+
+/// How to reach a Git repository.
+#[derive(Clone, Default)]
+pub struct GitOptions {
+    /// Branch, tag or commit, the remote's default branch when unset.
+    pub git_ref: Option<String>,
+    /// Try the SSH agent, SSH keys and the Git credential helpers.
+    pub use_system_credentials: bool,
+    /// Token for HTTPS remotes, tried before the system credentials.
+    pub token: Option<String>,
+    pub progress: Option<ProgressCallback>,
+}
+
+impl GitOptions {
+    fn report(&self, progress: GitProgress) {
+        if let Some(callback) = &self.progress {
+            callback(progress);
+        }
+    }
+
+    fn git_ref(&self) -> Option<&str> {
+        self.git_ref.as_deref().filter(|r| !r.is_empty())
+    }
+}
+
+/// Auth methods tried by the callbacks of one operation
+type TriedMethods = Arc<Mutex<Vec<AuthMethod>>>;
+
+/// Clones a repository at a branch, tag or commit and reads the config file,
+/// returning its content and the commit it was read from.
+pub fn clone_and_read_config(
+    repo_url: &str, config_path: &str, options: &GitOptions,
+) -> Result<(String, String), SourceError> {
+    let temp_dir = TempDir::new()?;
+
+    let tried = TriedMethods::default();
+    let mut callbacks = setup_git_callbacks(options, tried.clone());
+    if let Some(progress) = options.progress.clone() {
+        callbacks.transfer_progress(move |stats| {
+            if stats.received_objects() < stats.total_objects() {
+                progress(GitProgress::Receiving {
+                    received_objects: stats.received_objects(),
+                    total_objects: stats.total_objects(),
+                    received_bytes: stats.received_bytes(),
+                });
+            } else {
+                progress(GitProgress::Resolving {
+                    indexed_deltas: stats.indexed_deltas(),
+                    total_deltas: stats.total_deltas(),
+                });
+            }
+            true
+        });
+    }
+    let mut builder = setup_repo_builder(callbacks);
+
+    info!("Attempting to clone repository: {}", repo_url);
+    options.report(GitProgress::Connecting {
+        url: repo_url.to_string(),
+    });
+    let repo = clone_repository(&mut builder, repo_url, options, &tried, temp_dir.path())?;
+    let commit = checkout_ref(&repo, repo_url, options)?;
+
+    let content = read_config_file(temp_dir.path(), config_path)?;
+    Ok((content, commit))
+}
+
+/// Checks out a branch, tag or commit of a fresh clone, returning the id of
+/// the checked out commit.
+fn checkout_ref(
+    repo: &Repository, repo_url: &str, options: &GitOptions,
+) -> Result<String, SourceError> {
+    let Some(git_ref) = options.git_ref() else {
+        let commit = repo.head()?.peel_to_commit()?.id().to_string();
+        options.report(GitProgress::CheckingOut {
+            commit: commit.clone(),
+        });
+        return Ok(commit);
+    };
+
+    let commit = repo
+        .revparse_single(&format!("origin/{}", git_ref))
+        .or_else(|_| repo.revparse_single(git_ref))
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|_| SourceError::RefNotFound {
+            url: repo_url.to_string(),
+            git_ref: git_ref.to_string(),
+        })?;
+
+    options.report(GitProgress::CheckingOut {
+        commit: commit.id().to_string(),
+    });
+    repo.checkout_tree(
+        commit.as_object(),
+        Some(git2::build::CheckoutBuilder::new().force()),
+    )?;
+    repo.set_head_detached(commit.id())?;
+
+    Ok(commit.id().to_string())
+}
+
+/// Looks up the commit a ref points to on the remote without cloning it.
+pub fn resolve_remote_commit(repo_url: &str, options: &GitOptions) -> Result<String, SourceError> {
+    let git_ref = options.git_ref();
+    if let Some(commit) =
+        git_ref.filter(|r| r.len() == 40 && r.chars().all(|c| c.is_ascii_hexdigit()))
+    {
+        return Ok(commit.to_lowercase());
+    }
+
+    let mut remote = git2::Remote::create_detached(repo_url)?;
+    let tried = TriedMethods::default();
+    let callbacks = setup_git_callbacks(options, tried.clone());
+    options.report(GitProgress::Connecting {
+        url: repo_url.to_string(),
+    });
+    let connection = remote
+        .connect_auth(Direction::Fetch, Some(callbacks), None)
+        .map_err(|e| auth_error(e, repo_url, &tried))?;
+    let heads = connection.list()?;
+
+    // Peeled tags come first so annotated tags resolve to their commit
+    let candidates = match git_ref {
+        Some(git_ref) => vec![
+            format!("refs/tags/{}^{{}}", git_ref),
+            format!("refs/heads/{}", git_ref),
+            format!("refs/tags/{}", git_ref),
+            git_ref.to_string(),
+        ],
+        None => vec!["HEAD".to_string()],
+    };
+
+    candidates
+        .iter()
+        .find_map(|name| {
+            heads
+                .iter()
+                .find(|head| head.name() == name)
+                .map(|head| head.oid().to_string())
+        })
+        .ok_or_else(|| SourceError::RefNotFound {
+            url: repo_url.to_string(),
+            git_ref: git_ref.unwrap_or("HEAD").to_string(),
+        })
+}
+
+fn setup_git_callbacks(options: &GitOptions, tried: TriedMethods) -> RemoteCallbacks<'static> {
+    let mut callbacks = RemoteCallbacks::new();
+    let use_system_credentials = options.use_system_credentials;
+    let token = options.token.clone().filter(|t| !t.is_empty());
+
+    // Only set up credentials callback if authentication is needed
+    if use_system_credentials || token.is_some() {
+        let attempts = AtomicUsize::new(0);
+        let progress = options.progress.clone();
+
+        callbacks.credentials(move |url, username_from_url, allowed_types| {
+            let current_attempt = attempts.fetch_add(1, Ordering::SeqCst);
+            if current_attempt >= 3 {
+                return Err(git2::Error::from_str(
+                    "Authentication failed after 3 attempts",
+                ));
+            }
+
+            info!(
+                "Auth attempt {} - URL: {}, Username: {:?}, Allowed types: {:?}",
+                current_attempt + 1,
+                url,
+                username_from_url,
+                allowed_types
+            );
+
+            let report = |method: AuthMethod| {
+                if let Some(progress) = &progress {
+                    progress(GitProgress::AuthAttempt {
+                        attempt: current_attempt + 1,
+                        method: method.clone(),
+                    });
+                }
+                if let Ok(mut tried) = tried.lock() {
+                    if !tried.contains(&method) {
+                        tried.push(method);
+                    }
+                }
+            };
+
+            let is_https_url = url.starts_with("https://");
+
+            if let Some(token) = token.as_deref().filter(|_| {
+                is_https_url && allowed_types.contains(git2::CredentialType::USER_PASS_PLAINTEXT)
+            }) {
+                info!("Using token authentication for HTTPS");
+                report(AuthMethod::Token);
+                return Cred::userpass_plaintext("git", token);
+            }
+
+            if use_system_credentials {
+                let username = username_from_url.unwrap_or("git");
+                get_git_credentials(url, username, &report)
+            } else {
+                Err(git2::Error::from_str("No authentication method configured"))
+            }
+        });
+    }
+
+    callbacks.certificate_check(|_cert, _hostname| Ok(CertificateCheckStatus::CertificateOk));
+
+    callbacks
+}
+
+fn setup_repo_builder(callbacks: RemoteCallbacks) -> git2::build::RepoBuilder {
+    let mut fetch_opts = FetchOptions::new();
+    fetch_opts.remote_callbacks(callbacks);
+
+    let mut builder = git2::build::RepoBuilder::new();
+    builder.fetch_options(fetch_opts);
+    builder
+}
+
+fn clone_repository(
+    builder: &mut git2::build::RepoBuilder, repo_url: &str, options: &GitOptions,
+    tried: &TriedMethods, path: &Path,
+) -> Result<Repository, SourceError> {
+    match builder.clone(repo_url, path) {
+        Ok(repo) => Ok(repo),
+        Err(e) => {
+            warn!(
+                "Repository clone failed: {}, trying fallback with system git command",
+                e
+            );
+            options.report(GitProgress::SystemGitFallback);
+
+            try_clone_with_system_git(repo_url, options.git_ref(), path).map_err(|message| {
+                match auth_error(e, repo_url, tried) {
+                    err @ SourceError::Auth { .. } => err,
+                    _ => SourceError::Clone {
+                        url: repo_url.to_string(),
+                        message,
+                    },
+                }
+            })
+        }
+    }
+}
+
+/// Turns an authentication failure into an error listing the methods tried.
+fn auth_error(err: git2::Error, repo_url: &str, tried: &TriedMethods) -> SourceError {
+    // The credentials callback gives up with its own error after 3 attempts
+    let gave_up = err.message().starts_with("Authentication failed");
+    if err.code() != ErrorCode::Auth && !gave_up {
+        return SourceError::Git(err);
+    }
+
+    SourceError::Auth {
+        url: repo_url.to_string(),
+        tried: tried.lock().map(|tried| tried.clone()).unwrap_or_default(),
+    }
+}
+
+fn try_clone_with_system_git(
+    repo_url: &str, git_ref: Option<&str>, path: &Path,
+) -> Result<Repository, String> {
+    use std::process::Command;
+
+    let mut command = Command::new("git");
+    command
+        .arg("clone")
+        .arg("--depth=1")
+        .arg("--single-branch")
+        .arg("--no-tags")
+        .arg("--filter=blob:none")
+        .arg("--recurse-submodules=no");
+    if let Some(git_ref) = git_ref {
+        command.arg("--branch").arg(git_ref);
+    }
+
+    let output = command
+        .arg(repo_url)
+        .arg(path)
+        .output()
+        .map_err(|e| format!("Failed to execute git command: {}", e))?;
+
+    if output.status.success() {
+        info!("Successfully cloned repository using system git");
+        Repository::open(path).map_err(|e| format!("Failed to open repository: {}", e))
+    } else {
+        let error_msg = String::from_utf8_lossy(&output.stderr);
+        error!("System git clone failed: {}", error_msg);
+        Err(format!("System git clone failed: {}", error_msg))
+    }
+}
@@ -0,0 +1,106 @@
+//! Config files served over HTTPS, like a raw file URL of a Git host or an
+//! object storage bucket.
+
+use log::{
+    debug,
+    info,
+};
+use reqwest::header::{
+    ETAG,
+    IF_NONE_MATCH,
+};
+use reqwest::StatusCode;
+use url::Url;
+
+use crate::error::SourceError;
+use crate::local::content_revision;
+use crate::source::FetchedConfig;
+
+/// Downloads a config file, sending the ETag of the last download so an
+/// unchanged file is not transferred again. A token is only sent over
+/// `https`.
+pub async fn fetch_config_url(
+    url: &str, token: Option<&str>, etag: Option<&str>,
+) -> Result<FetchedConfig, SourceError> {
+    let token = token.filter(|t| !t.is_empty());
+    if token.is_some() && !is_https(url) {
+        return Err(SourceError::InsecureUrl(url.to_string()));
+    }
+
+    let client = reqwest::Client::new();
+    let mut request = client.get(url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    if let Some(etag) = etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+
+    let response = request.send().await.map_err(|e| SourceError::Http {
+        url: url.to_string(),
+        message: e.to_string(),
+    })?;
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        debug!("Config at {} is unchanged", url);
+        return Ok(FetchedConfig::Unchanged);
+    }
+    if !response.status().is_success() {
+        return Err(SourceError::HttpStatus {
+            url: url.to_string(),
+            status: response.status().as_u16(),
+        });
+    }
+
+    // Without an ETag the content itself tells whether the file changed
+    let revision = response
+        .headers()
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let content = response.text().await.map_err(|e| SourceError::Http {
+        url: url.to_string(),
+        message: e.to_string(),
+    })?;
+    info!("Downloaded config from {}", url);
+
+    let revision = revision.unwrap_or_else(|| content_revision(&content));
+    if etag == Some(revision.as_str()) {
+        return Ok(FetchedConfig::Unchanged);
+    }
+
+    Ok(FetchedConfig::Changed { content, revision })
+}
+
+fn is_https(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| url.scheme() == "https")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fetch_config_url_sends_tokens_over_https_only() {
+        // Nothing listens on the discard port, so a request that is sent fails
+        let url = "http://127.0.0.1:9/configs.json";
+
+        assert!(matches!(
+            fetch_config_url(url, Some("secret"), None).await,
+            Err(SourceError::InsecureUrl(_))
+        ));
+        assert!(matches!(
+            fetch_config_url("not a url", Some("secret"), None).await,
+            Err(SourceError::InsecureUrl(_))
+        ));
+        assert!(matches!(
+            fetch_config_url(url, None, None).await,
+            Err(SourceError::Http { .. })
+        ));
+        assert!(matches!(
+            fetch_config_url(url, Some(""), None).await,
+            Err(SourceError::Http { .. })
+        ));
+    }
+}
@@ -0,0 +1,19 @@
+pub mod credentials;
+pub mod error;
+pub mod git;
+pub mod https;
+pub mod local;
+pub mod progress;
+pub mod source;
+
+pub use credentials::AuthMethod;
+pub use error::SourceError;
+pub use git::GitOptions;
+pub use progress::{
+    GitProgress,
+    ProgressCallback,
+};
+pub use source::{
+    ConfigSource,
+    FetchedConfig,
+};
@@ -0,0 +1,75 @@
+//! Config files read from a local directory, like a checkout or a mounted
+//! volume.
+
+use std::path::Path;
+
+use sha2::{
+    Digest,
+    Sha256,
+};
+
+use crate::error::SourceError;
+
+pub fn read_config_file(dir: &Path, config_path: &str) -> Result<String, SourceError> {
+    let config_path = Path::new(config_path);
+    let full_path = dir.join(config_path);
+
+    std::fs::read_to_string(&full_path).map_err(|source| SourceError::ConfigFile {
+        path: full_path,
+        source,
+    })
+}
+
+/// Reads a config file from a directory, returning its content and a
+/// revision derived from it.
+///
+/// The revision is the SHA-256 of the content, so it can be stored and
+/// compared with the one of a later read, even by another build.
+pub fn read_local_config(dir: &Path, config_path: &str) -> Result<(String, String), SourceError> {
+    if !dir.is_dir() {
+        return Err(SourceError::NotADirectory(dir.to_path_buf()));
+    }
+
+    let content = read_config_file(dir, config_path)?;
+    let revision = content_revision(&content);
+
+    Ok((content, revision))
+}
+
+pub(crate) fn content_revision(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_revision_is_stable() {
+        assert_eq!(
+            content_revision(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(content_revision("[]"), content_revision("[]"));
+        assert_ne!(content_revision("[]"), content_revision("[{}]"));
+    }
+
+    #[test]
+    fn test_read_local_config_requires_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("configs.json");
+        std::fs::write(&file, "[]").unwrap();
+
+        assert!(matches!(
+            read_local_config(&file, "configs.json"),
+            Err(SourceError::NotADirectory(_))
+        ));
+        assert!(matches!(
+            read_local_config(dir.path(), "missing.json"),
+            Err(SourceError::ConfigFile { .. })
+        ));
+    }
+}
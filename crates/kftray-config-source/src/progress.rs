@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use crate::credentials::AuthMethod;
+
+/// Steps of reading a config file from a Git repository.
+#[derive(Clone, PartialEq, Debug)]
+pub enum GitProgress {
+    Connecting {
+        url: String,
+    },
+    /// A credential is about to be tried, `attempt` counts the requests of
+    /// the remote.
+    AuthAttempt {
+        attempt: usize,
+        method: AuthMethod,
+    },
+    Receiving {
+        received_objects: usize,
+        total_objects: usize,
+        received_bytes: usize,
+    },
+    Resolving {
+        indexed_deltas: usize,
+        total_deltas: usize,
+    },
+    /// libgit2 failed and the `git` command is tried instead.
+    SystemGitFallback,
+    CheckingOut {
+        commit: String,
+    },
+}
+
+/// Receives the progress of a Git operation. Called from the thread doing
+/// the work, so it should return quickly.
+pub type ProgressCallback = Arc<dyn Fn(GitProgress) + Send + Sync>;
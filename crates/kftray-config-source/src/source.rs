@@ -0,0 +1,270 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use log::{
+    debug,
+    warn,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use url::Url;
+
+use crate::error::SourceError;
+use crate::git::{
+    clone_and_read_config,
+    resolve_remote_commit,
+    GitOptions,
+};
+use crate::https::fetch_config_url;
+use crate::local::read_local_config;
+use crate::progress::ProgressCallback;
+
+/// Where a config file is read from.
+///
+/// Tokens are never part of a source, so it can be stored and shown as is.
+#[derive(Clone, Deserialize, PartialEq, Serialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ConfigSource {
+    /// A file in a Git repository on any host, or a local repository.
+    Git {
+        repo_url: String,
+        /// Branch, tag or commit, the remote's default branch when unset.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        git_ref: Option<String>,
+        config_path: String,
+        #[serde(default)]
+        use_system_credentials: bool,
+    },
+    /// A file served over HTTPS, authenticated with a bearer token.
+    Https { url: String },
+    /// A file in a local directory.
+    Local { dir: PathBuf, config_path: String },
+}
+
+/// Result of reading a source.
+#[derive(Clone, PartialEq, Debug)]
+pub enum FetchedConfig {
+    /// The source is still at the revision of the last read.
+    Unchanged,
+    /// Content of the config file, with the commit, ETag or content hash it
+    /// was read at.
+    Changed { content: String, revision: String },
+}
+
+impl ConfigSource {
+    /// Reads the config file of the source.
+    ///
+    /// With the revision of an earlier read, sources that can tell cheaply
+    /// whether they changed skip the download: Git asks the remote for the
+    /// ref's commit and HTTPS sends the ETag.
+    pub async fn fetch(
+        &self, token: Option<String>, known_revision: Option<&str>,
+    ) -> Result<FetchedConfig, SourceError> {
+        self.fetch_with_progress(token, known_revision, None).await
+    }
+
+    /// Same as [`ConfigSource::fetch`], reporting the progress of Git
+    /// sources.
+    pub async fn fetch_with_progress(
+        &self, token: Option<String>, known_revision: Option<&str>,
+        progress: Option<ProgressCallback>,
+    ) -> Result<FetchedConfig, SourceError> {
+        match self {
+            ConfigSource::Git {
+                repo_url,
+                git_ref,
+                config_path,
+                use_system_credentials,
+            } => {
+                let repo_url = repo_url.clone();
+                let config_path = config_path.clone();
+                let options = GitOptions {
+                    git_ref: git_ref.clone(),
+                    use_system_credentials: *use_system_credentials,
+                    token,
+                    progress,
+                };
+                let known_revision = known_revision.map(str::to_string);
+
+                tokio::task::spawn_blocking(move || {
+                    if let Some(known) = known_revision {
+                        match resolve_remote_commit(&repo_url, &options) {
+                            Ok(commit) if commit == known => {
+                                debug!("{} is still at {}", repo_url, commit);
+                                return Ok(FetchedConfig::Unchanged);
+                            }
+                            Ok(_) => {}
+                            Err(e) => warn!("Failed to check the remote for new commits: {}", e),
+                        }
+                    }
+
+                    let (content, revision) =
+                        clone_and_read_config(&repo_url, &config_path, &options)?;
+                    Ok(FetchedConfig::Changed { content, revision })
+                })
+                .await
+                .map_err(|e| SourceError::Task(e.to_string()))?
+            }
+            ConfigSource::Https { url } => {
+                fetch_config_url(url, token.as_deref(), known_revision).await
+            }
+            ConfigSource::Local { dir, config_path } => {
+                let (content, revision) = read_local_config(dir, config_path)?;
+                if known_revision == Some(revision.as_str()) {
+                    return Ok(FetchedConfig::Unchanged);
+                }
+                Ok(FetchedConfig::Changed { content, revision })
+            }
+        }
+    }
+
+    /// Host the source's token belongs to, see
+    /// [`crate::credentials::get_token`]. Local sources have none.
+    pub fn host(&self) -> Option<String> {
+        let url = match self {
+            ConfigSource::Git { repo_url, .. } => repo_url,
+            ConfigSource::Https { url } => url,
+            ConfigSource::Local { .. } => return None,
+        };
+
+        // scp-like Git URLs, e.g. git@gitlab.com:group/repo.git
+        if let Some((user_host, _)) = url.split_once(':').filter(|_| !url.contains("://")) {
+            return user_host
+                .rsplit('@')
+                .next()
+                .map(str::to_string)
+                .filter(|h| !h.is_empty());
+        }
+
+        Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+    }
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Git {
+                repo_url,
+                git_ref,
+                config_path,
+                ..
+            } => match git_ref {
+                Some(git_ref) => write!(f, "{}@{}:{}", repo_url, git_ref, config_path),
+                None => write!(f, "{}:{}", repo_url, config_path),
+            },
+            ConfigSource::Https { url } => write!(f, "{}", url),
+            ConfigSource::Local { dir, config_path } => {
+                write!(f, "{}", dir.join(config_path).display())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(repo_url: &str, git_ref: Option<&str>) -> ConfigSource {
+        ConfigSource::Git {
+            repo_url: repo_url.to_string(),
+            git_ref: git_ref.map(str::to_string),
+            config_path: "configs.json".to_string(),
+            use_system_credentials: false,
+        }
+    }
+
+    #[test]
+    fn test_host() {
+        let hosts = [
+            ("https://github.com/team/configs", Some("github.com")),
+            ("git@gitlab.com:group/configs.git", Some("gitlab.com")),
+            ("gitlab.internal:group/configs.git", Some("gitlab.internal")),
+            (
+                "ssh://git@gitea.local:2222/team/configs.git",
+                Some("gitea.local"),
+            ),
+            ("@:configs.git", None),
+            ("not a url", None),
+        ];
+        for (repo_url, host) in hosts {
+            assert_eq!(git(repo_url, None).host().as_deref(), host, "{}", repo_url);
+        }
+
+        let https = ConfigSource::Https {
+            url: "https://configs.example.com:8443/kftray.json".to_string(),
+        };
+        assert_eq!(https.host().as_deref(), Some("configs.example.com"));
+
+        let local = ConfigSource::Local {
+            dir: PathBuf::from("/srv/configs"),
+            config_path: "configs.json".to_string(),
+        };
+        assert_eq!(local.host(), None);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            git("https://github.com/team/configs", None).to_string(),
+            "https://github.com/team/configs:configs.json"
+        );
+        assert_eq!(
+            git("https://github.com/team/configs", Some("v1.2")).to_string(),
+            "https://github.com/team/configs@v1.2:configs.json"
+        );
+        assert_eq!(
+            ConfigSource::Https {
+                url: "https://example.com/kftray.json".to_string()
+            }
+            .to_string(),
+            "https://example.com/kftray.json"
+        );
+        assert_eq!(
+            ConfigSource::Local {
+                dir: PathBuf::from("/srv/configs"),
+                config_path: "team/configs.json".to_string(),
+            }
+            .to_string(),
+            PathBuf::from("/srv/configs/team/configs.json")
+                .display()
+                .to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_local_revision() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("configs.json"), "[]").unwrap();
+        let source = ConfigSource::Local {
+            dir: dir.path().to_path_buf(),
+            config_path: "configs.json".to_string(),
+        };
+
+        let FetchedConfig::Changed { content, revision } = source.fetch(None, None).await.unwrap()
+        else {
+            panic!("first read should return the content");
+        };
+        assert_eq!(content, "[]");
+
+        assert_eq!(
+            source.fetch(None, Some(&revision)).await.unwrap(),
+            FetchedConfig::Unchanged
+        );
+
+        std::fs::write(dir.path().join("configs.json"), r#"[{"alias":"db"}]"#).unwrap();
+        match source.fetch(None, Some(&revision)).await.unwrap() {
+            FetchedConfig::Changed {
+                content,
+                revision: new_revision,
+            } => {
+                assert_eq!(content, r#"[{"alias":"db"}]"#);
+                assert_ne!(new_revision, revision);
+            }
+            FetchedConfig::Unchanged => panic!("changed file was reported unchanged"),
+        }
+    }
+}
@@ -0,0 +1,599 @@
+use std::collections::HashMap;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::sync::{
+    Arc,
+    Mutex,
+    Weak,
+};
+use std::time::Duration;
+
+use anyhow::{
+    Context,
+    Result,
+};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{
+    DateTime,
+    Utc,
+};
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tracing::{
+    debug,
+    error,
+};
+
+use crate::parser::{
+    RequestParser,
+    ResponseParser,
+};
+use crate::redaction::RedactionRules;
+
+pub const CASSETTE_VERSION: u32 = 1;
+
+pub const REPLAY_HEADER: &str = "X-Kftray-Replay";
+
+const CHUNKED_TERMINATOR: &[u8] = b"0\r\n\r\n";
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    static ref RECORDERS: DashMap<PathBuf, Arc<CassetteRecorder>> = DashMap::new();
+}
+
+/// How a config uses its cassette. Recording captures through the HTTP
+/// logger, so HTTP logs are written while recording. Replaying serves the
+/// local port from the cassette without connecting to the cluster.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CassetteMode {
+    #[default]
+    Off,
+    Record,
+    Replay,
+}
+
+impl std::fmt::Display for CassetteMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CassetteMode::Off => write!(f, "off"),
+            CassetteMode::Record => write!(f, "record"),
+            CassetteMode::Replay => write!(f, "replay"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CassetteConfig {
+    pub mode: CassetteMode,
+    pub path: PathBuf,
+}
+
+impl CassetteConfig {
+    pub fn default_path(config_id: i64) -> Result<PathBuf> {
+        let home_dir = dirs::home_dir().context("Failed to determine home directory")?;
+        Ok(home_dir
+            .join(".kftray")
+            .join("cassettes")
+            .join(format!("{}.json", config_id)))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        match self.mode {
+            CassetteMode::Replay => Cassette::load(&self.path).map(|_| ()),
+            CassetteMode::Record | CassetteMode::Off => Ok(()),
+        }
+    }
+}
+
+/// A recorded request/response pair. Both messages are kept as the HTTP/1
+/// bytes seen on the wire, base64 encoded, with their header blocks passed
+/// through the redaction rules.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    pub path: String,
+    pub status: Option<u16>,
+    pub recorded_at: DateTime<Utc>,
+    pub request: String,
+    pub response: String,
+}
+
+impl Interaction {
+    pub fn new(request: &[u8], response: &[u8], redaction: &RedactionRules) -> Result<Self> {
+        let request = redact_head(request, redaction);
+        let response = redact_head(response, redaction);
+
+        let (method, path) = match RequestParser::parse(&request)? {
+            (Some(method), Some(path), _, _) => (method.to_string(), path.to_string()),
+            _ => anyhow::bail!("Incomplete HTTP request"),
+        };
+        let (status, _) = ResponseParser::parse(&response)?;
+
+        Ok(Self {
+            method,
+            path,
+            status,
+            recorded_at: Utc::now(),
+            request: STANDARD.encode(&request),
+            response: STANDARD.encode(&response),
+        })
+    }
+}
+
+/// Redacts the start line and headers of a message, leaving its body as is.
+fn redact_head(message: &[u8], redaction: &RedactionRules) -> Vec<u8> {
+    if !redaction.is_enabled() {
+        return message.to_vec();
+    }
+
+    let head_len = message
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map_or(message.len(), |end| end + 4);
+    let (head, body) = message.split_at(head_len);
+
+    let mut redacted = redaction
+        .redact_header_block(&String::from_utf8_lossy(head))
+        .into_bytes();
+    redacted.extend_from_slice(body);
+    redacted
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Cassette {
+    pub version: u32,
+    pub interactions: Vec<Interaction>,
+}
+
+impl Default for Cassette {
+    fn default() -> Self {
+        Self {
+            version: CASSETTE_VERSION,
+            interactions: Vec::new(),
+        }
+    }
+}
+
+impl Cassette {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read(path)
+            .with_context(|| format!("Failed to read cassette {}", path.display()))?;
+        let cassette: Cassette = serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse cassette {}", path.display()))?;
+
+        if cassette.version > CASSETTE_VERSION {
+            anyhow::bail!(
+                "Cassette {} has unsupported version {}",
+                path.display(),
+                cassette.version
+            );
+        }
+
+        Ok(cassette)
+    }
+
+    /// Writes the cassette through a temporary file so a crash never leaves
+    /// a truncated cassette behind.
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .context("Failed to create cassette directory")?;
+        }
+
+        let temp_path = path.with_extension("json.tmp");
+        tokio::fs::write(&temp_path, serde_json::to_vec_pretty(self)?)
+            .await
+            .with_context(|| format!("Failed to write cassette {}", temp_path.display()))?;
+        tokio::fs::rename(&temp_path, path)
+            .await
+            .with_context(|| format!("Failed to replace cassette {}", path.display()))
+    }
+}
+
+/// Appends interactions to a cassette file. Loggers recording to the same
+/// path share one recorder, so connections of a forward write in turn.
+///
+/// Interactions are kept in memory and saved every [`FLUSH_INTERVAL`] and on
+/// release, so requests never wait on the cassette being rewritten.
+#[derive(Debug)]
+pub struct CassetteRecorder {
+    path: PathBuf,
+    state: tokio::sync::Mutex<RecorderState>,
+    /// Held while saving, so saves land in the order they were taken
+    saving: tokio::sync::Mutex<()>,
+}
+
+#[derive(Debug)]
+struct RecorderState {
+    cassette: Cassette,
+    unsaved: bool,
+}
+
+impl CassetteRecorder {
+    pub fn shared(path: &Path) -> Result<Arc<Self>> {
+        if let Some(recorder) = RECORDERS.get(path) {
+            return Ok(Arc::clone(recorder.value()));
+        }
+
+        let cassette = if path.exists() {
+            Cassette::load(path)?
+        } else {
+            Cassette::default()
+        };
+        let recorder = Arc::new(Self {
+            path: path.to_path_buf(),
+            state: tokio::sync::Mutex::new(RecorderState {
+                cassette,
+                unsaved: false,
+            }),
+            saving: tokio::sync::Mutex::new(()),
+        });
+
+        let shared = Arc::clone(
+            RECORDERS
+                .entry(path.to_path_buf())
+                .or_insert_with(|| Arc::clone(&recorder))
+                .value(),
+        );
+        if Arc::ptr_eq(&shared, &recorder) {
+            Self::spawn_flusher(Arc::downgrade(&recorder));
+        }
+
+        Ok(shared)
+    }
+
+    /// Saves the recorder periodically until it is dropped. Outside a
+    /// runtime it is only saved on release.
+    fn spawn_flusher(recorder: Weak<Self>) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        runtime.spawn(async move {
+            let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(recorder) = recorder.upgrade() else {
+                    break;
+                };
+                if let Err(e) = recorder.flush().await {
+                    error!("Failed to save cassette: {:?}", e);
+                }
+            }
+        });
+    }
+
+    /// Saves the shared recorder of `path` and forgets it, so the next
+    /// recording reloads the file from disk.
+    pub async fn release(path: &Path) -> Result<()> {
+        match RECORDERS.remove(path) {
+            Some((_, recorder)) => recorder.flush().await,
+            None => Ok(()),
+        }
+    }
+
+    pub async fn record(
+        &self, request: &[u8], response: &[u8], redaction: &RedactionRules,
+    ) -> Result<()> {
+        let interaction = Interaction::new(request, response, redaction)?;
+        debug!(
+            "Recording {} {} to cassette {}",
+            interaction.method,
+            interaction.path,
+            self.path.display()
+        );
+
+        let mut state = self.state.lock().await;
+        state.cassette.interactions.push(interaction);
+        state.unsaved = true;
+        Ok(())
+    }
+
+    /// Saves the interactions recorded since the last save, if any.
+    pub async fn flush(&self) -> Result<()> {
+        let _saving = self.saving.lock().await;
+
+        let cassette = {
+            let mut state = self.state.lock().await;
+            if !state.unsaved {
+                return Ok(());
+            }
+            state.unsaved = false;
+            state.cassette.clone()
+        };
+
+        let result = cassette.save(&self.path).await;
+        if result.is_err() {
+            self.state.lock().await.unsaved = true;
+        }
+        result
+    }
+}
+
+struct RecordedResponse {
+    method: String,
+    path: String,
+    response: Vec<u8>,
+}
+
+/// Serves recorded responses. Requests match on method and path; repeated
+/// requests walk through the recorded responses in order and keep returning
+/// the last one. When no exact path matches, the query string is ignored.
+pub struct CassettePlayer {
+    responses: Vec<RecordedResponse>,
+    cursors: Mutex<HashMap<(String, String), usize>>,
+}
+
+impl CassettePlayer {
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_cassette(&Cassette::load(path)?)
+    }
+
+    pub fn from_cassette(cassette: &Cassette) -> Result<Self> {
+        let responses = cassette
+            .interactions
+            .iter()
+            .map(|interaction| {
+                Ok(RecordedResponse {
+                    method: interaction.method.clone(),
+                    path: interaction.path.clone(),
+                    response: STANDARD.decode(&interaction.response).with_context(|| {
+                        format!(
+                            "Invalid response recorded for {} {}",
+                            interaction.method, interaction.path
+                        )
+                    })?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            responses,
+            cursors: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.responses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.responses.is_empty()
+    }
+
+    /// Returns the recorded response for `request`, or a 404 marked as a
+    /// replay miss.
+    pub fn respond(&self, request: &[u8]) -> Vec<u8> {
+        let Ok((Some(method), Some(path), _, _)) = RequestParser::parse(request) else {
+            return Self::miss("?", "?");
+        };
+
+        let exact = self.candidates(|recorded| recorded.method == method && recorded.path == path);
+        let candidates = if exact.is_empty() {
+            let path = strip_query(path);
+            self.candidates(|recorded| {
+                recorded.method == method && strip_query(&recorded.path) == path
+            })
+        } else {
+            exact
+        };
+
+        if candidates.is_empty() {
+            debug!("No recorded response for {} {}", method, path);
+            return Self::miss(method, path);
+        }
+
+        let mut cursors = self.cursors.lock().unwrap_or_else(|e| e.into_inner());
+        let cursor = cursors
+            .entry((method.to_string(), path.to_string()))
+            .or_insert(0);
+        let index = candidates[(*cursor).min(candidates.len() - 1)];
+        *cursor += 1;
+
+        self.responses[index].response.clone()
+    }
+
+    fn candidates(&self, matches: impl Fn(&RecordedResponse) -> bool) -> Vec<usize> {
+        self.responses
+            .iter()
+            .enumerate()
+            .filter(|(_, recorded)| matches(recorded))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    fn miss(method: &str, path: &str) -> Vec<u8> {
+        let body = format!("No recorded response for {} {}\n", method, path);
+        format!(
+            "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\n{}: miss\r\n\r\n{}",
+            body.len(),
+            REPLAY_HEADER,
+            body
+        )
+        .into_bytes()
+    }
+}
+
+fn strip_query(path: &str) -> &str {
+    path.split(['?', '#']).next().unwrap_or(path)
+}
+
+/// Returns the length of the first complete HTTP/1 request in `buffer`, or
+/// `None` while more bytes are needed.
+pub fn request_len(buffer: &[u8]) -> Result<Option<usize>> {
+    let Some(head_end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") else {
+        return Ok(None);
+    };
+    let head_len = head_end + 4;
+
+    let (_, _, _, headers) = RequestParser::parse(&buffer[..head_len])?;
+    if RequestParser::is_chunked_transfer(&headers) {
+        return Ok(buffer[head_len..]
+            .windows(CHUNKED_TERMINATOR.len())
+            .position(|w| w == CHUNKED_TERMINATOR)
+            .map(|end| head_len + end + CHUNKED_TERMINATOR.len()));
+    }
+
+    let total = head_len + RequestParser::get_content_length(&headers);
+    Ok((buffer.len() >= total).then_some(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn cassette(pairs: &[(&[u8], &[u8])]) -> Cassette {
+        Cassette {
+            interactions: pairs
+                .iter()
+                .map(|(request, response)| {
+                    Interaction::new(request, response, &RedactionRules::disabled()).unwrap()
+                })
+                .collect(),
+            ..Cassette::default()
+        }
+    }
+
+    #[test]
+    fn test_replay_walks_recorded_responses() {
+        let player = CassettePlayer::from_cassette(&cassette(&[
+            (
+                b"GET /items?page=1 HTTP/1.1\r\n\r\n",
+                b"HTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\na",
+            ),
+            (
+                b"GET /items?page=1 HTTP/1.1\r\n\r\n",
+                b"HTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\nb",
+            ),
+        ]))
+        .unwrap();
+
+        let request = b"GET /items?page=1 HTTP/1.1\r\nHost: a\r\n\r\n";
+        assert!(player.respond(request).ends_with(b"a"));
+        assert!(player.respond(request).ends_with(b"b"));
+        assert!(player.respond(request).ends_with(b"b"));
+
+        assert!(player
+            .respond(b"GET /items?page=2 HTTP/1.1\r\n\r\n")
+            .ends_with(b"a"));
+
+        let miss = String::from_utf8(player.respond(b"POST /items HTTP/1.1\r\n\r\n")).unwrap();
+        assert!(miss.starts_with("HTTP/1.1 404 Not Found"));
+        assert!(miss.contains("X-Kftray-Replay: miss"));
+    }
+
+    #[test]
+    fn test_request_len() {
+        assert_eq!(request_len(b"GET / HTTP/1.1\r\nHost: a").unwrap(), None);
+        assert_eq!(
+            request_len(b"GET / HTTP/1.1\r\n\r\nGET /next").unwrap(),
+            Some(18)
+        );
+        assert_eq!(
+            request_len(b"POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\nab").unwrap(),
+            None
+        );
+        assert_eq!(
+            request_len(b"POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\nabcdGET").unwrap(),
+            Some(42)
+        );
+        assert_eq!(
+            request_len(
+                b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nab\r\n0\r\n\r\n"
+            )
+            .unwrap(),
+            Some(59)
+        );
+    }
+
+    #[test]
+    fn test_interaction_redacts_headers() {
+        let interaction = Interaction::new(
+            b"POST /login HTTP/1.1\r\nAuthorization: Bearer abc\r\nCookie: session=1\r\nContent-Length: 15\r\n\r\n{\"user\":\"dev\"}",
+            b"HTTP/1.1 200 OK\r\nSet-Cookie: session=2\r\nContent-Length: 2\r\n\r\nok",
+            &RedactionRules::default(),
+        )
+        .unwrap();
+
+        let request = String::from_utf8(STANDARD.decode(&interaction.request).unwrap()).unwrap();
+        let response = String::from_utf8(STANDARD.decode(&interaction.response).unwrap()).unwrap();
+
+        assert_eq!(interaction.path, "/login");
+        assert!(!request.contains("abc"));
+        assert!(!request.contains("session=1"));
+        assert!(request.contains("Authorization: [REDACTED]\r\n"));
+        assert!(request.ends_with("\r\n\r\n{\"user\":\"dev\"}"));
+        assert!(!response.contains("session=2"));
+        assert!(response.ends_with("Content-Length: 2\r\n\r\nok"));
+
+        let raw = Interaction::new(
+            b"GET / HTTP/1.1\r\nAuthorization: Bearer abc\r\n\r\n",
+            b"HTTP/1.1 204 No Content\r\n\r\n",
+            &RedactionRules::disabled(),
+        )
+        .unwrap();
+        assert!(String::from_utf8(STANDARD.decode(&raw.request).unwrap())
+            .unwrap()
+            .contains("Bearer abc"));
+    }
+
+    #[tokio::test]
+    async fn test_recorder_appends_to_cassette() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("cassettes").join("1.json");
+
+        let recorder = CassetteRecorder::shared(&path).unwrap();
+        assert!(Arc::ptr_eq(
+            &recorder,
+            &CassetteRecorder::shared(&path).unwrap()
+        ));
+
+        recorder
+            .record(
+                b"GET /health HTTP/1.1\r\n\r\n",
+                b"HTTP/1.1 204 No Content\r\n\r\n",
+                &RedactionRules::default(),
+            )
+            .await
+            .unwrap();
+        assert!(recorder
+            .record(b"GET /hea", b"", &RedactionRules::default())
+            .await
+            .is_err());
+        assert!(!path.exists());
+
+        recorder.flush().await.unwrap();
+        let saved = Cassette::load(&path).unwrap();
+        assert_eq!(saved.interactions.len(), 1);
+        assert_eq!(saved.interactions[0].status, Some(204));
+
+        recorder
+            .record(
+                b"GET /ready HTTP/1.1\r\n\r\n",
+                b"HTTP/1.1 200 OK\r\n\r\n",
+                &RedactionRules::default(),
+            )
+            .await
+            .unwrap();
+        CassetteRecorder::release(&path).await.unwrap();
+        let player = CassettePlayer::load(&path).unwrap();
+        assert_eq!(player.len(), 2);
+        assert!(!Arc::ptr_eq(
+            &recorder,
+            &CassetteRecorder::shared(&path).unwrap()
+        ));
+        CassetteRecorder::release(&path).await.unwrap();
+    }
+}
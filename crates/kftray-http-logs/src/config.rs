@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::path::{
     Path,
     PathBuf,
@@ -10,10 +11,14 @@ use anyhow::{
 use chrono::Utc;
 use tokio::fs;
 
+use crate::redaction::RedactionConfig;
+
 pub const DEFAULT_MAX_LOG_SIZE: u64 = 10 * 1024 * 1024;
 
 pub const DEFAULT_LOG_RETENTION_DAYS: u64 = 7;
 
+pub const DEFAULT_MAX_CONFIG_DISK_USAGE: u64 = 100 * 1024 * 1024;
+
 pub const HTTP_LOG_EXTENSION: &str = "http";
 
 #[derive(Debug, Clone)]
@@ -21,7 +26,12 @@ pub struct LogConfig {
     log_dir: PathBuf,
     max_log_size: u64,
     retention_days: u64,
+    max_config_disk_usage: u64,
+    config_disk_quotas: BTreeMap<i64, u64>,
     file_extension: String,
+    redaction: RedactionConfig,
+    grpc_descriptor_set: Option<PathBuf>,
+    cassette: Option<PathBuf>,
 }
 
 impl LogConfig {
@@ -30,7 +40,12 @@ impl LogConfig {
             log_dir,
             max_log_size: DEFAULT_MAX_LOG_SIZE,
             retention_days: DEFAULT_LOG_RETENTION_DAYS,
+            max_config_disk_usage: DEFAULT_MAX_CONFIG_DISK_USAGE,
+            config_disk_quotas: BTreeMap::new(),
             file_extension: HTTP_LOG_EXTENSION.to_string(),
+            redaction: RedactionConfig::default(),
+            grpc_descriptor_set: None,
+            cassette: None,
         }
     }
 
@@ -55,6 +70,35 @@ impl LogConfig {
         self.retention_days
     }
 
+    pub fn max_config_disk_usage(&self) -> u64 {
+        self.max_config_disk_usage
+    }
+
+    /// Disk quota of a config's logs, `max_config_disk_usage` unless the
+    /// config has its own.
+    pub fn config_disk_quota(&self, config_id: i64) -> u64 {
+        self.config_disk_quotas
+            .get(&config_id)
+            .copied()
+            .unwrap_or(self.max_config_disk_usage)
+    }
+
+    pub fn file_extension(&self) -> &str {
+        &self.file_extension
+    }
+
+    pub fn redaction(&self) -> &RedactionConfig {
+        &self.redaction
+    }
+
+    pub fn grpc_descriptor_set(&self) -> Option<&Path> {
+        self.grpc_descriptor_set.as_deref()
+    }
+
+    pub fn cassette(&self) -> Option<&Path> {
+        self.cassette.as_deref()
+    }
+
     pub async fn create_log_file_path(&self, config_id: i64, local_port: u16) -> Result<PathBuf> {
         self.ensure_log_directory().await?;
 
@@ -88,7 +132,12 @@ pub struct LogConfigBuilder {
     log_dir: PathBuf,
     max_log_size: Option<u64>,
     retention_days: Option<u64>,
+    max_config_disk_usage: Option<u64>,
+    config_disk_quotas: BTreeMap<i64, u64>,
     file_extension: Option<String>,
+    redaction: Option<RedactionConfig>,
+    grpc_descriptor_set: Option<PathBuf>,
+    cassette: Option<PathBuf>,
 }
 
 impl LogConfigBuilder {
@@ -97,23 +146,72 @@ impl LogConfigBuilder {
             log_dir,
             max_log_size: None,
             retention_days: None,
+            max_config_disk_usage: None,
+            config_disk_quotas: BTreeMap::new(),
             file_extension: None,
+            redaction: None,
+            grpc_descriptor_set: None,
+            cassette: None,
         }
     }
 
+    pub fn max_log_size(mut self, max_log_size: u64) -> Self {
+        self.max_log_size = Some(max_log_size);
+        self
+    }
+
+    pub fn retention_days(mut self, retention_days: u64) -> Self {
+        self.retention_days = Some(retention_days);
+        self
+    }
+
+    pub fn max_config_disk_usage(mut self, max_config_disk_usage: u64) -> Self {
+        self.max_config_disk_usage = Some(max_config_disk_usage);
+        self
+    }
+
+    /// Disk quotas of the configs that don't use `max_config_disk_usage`.
+    pub fn config_disk_quotas(mut self, quotas: BTreeMap<i64, u64>) -> Self {
+        self.config_disk_quotas = quotas;
+        self
+    }
+
     pub fn file_extension(mut self, extension: impl Into<String>) -> Self {
         self.file_extension = Some(extension.into());
         self
     }
 
+    pub fn redaction(mut self, redaction: RedactionConfig) -> Self {
+        self.redaction = Some(redaction);
+        self
+    }
+
+    pub fn grpc_descriptor_set(mut self, path: Option<PathBuf>) -> Self {
+        self.grpc_descriptor_set = path;
+        self
+    }
+
+    /// Records captured request/response pairs to the cassette at `path`.
+    pub fn cassette(mut self, path: Option<PathBuf>) -> Self {
+        self.cassette = path;
+        self
+    }
+
     pub fn build(self) -> LogConfig {
         LogConfig {
             log_dir: self.log_dir,
             max_log_size: self.max_log_size.unwrap_or(DEFAULT_MAX_LOG_SIZE),
             retention_days: self.retention_days.unwrap_or(DEFAULT_LOG_RETENTION_DAYS),
+            max_config_disk_usage: self
+                .max_config_disk_usage
+                .unwrap_or(DEFAULT_MAX_CONFIG_DISK_USAGE),
+            config_disk_quotas: self.config_disk_quotas,
             file_extension: self
                 .file_extension
                 .unwrap_or_else(|| HTTP_LOG_EXTENSION.to_string()),
+            redaction: self.redaction.unwrap_or_default(),
+            grpc_descriptor_set: self.grpc_descriptor_set,
+            cassette: self.cassette,
         }
     }
 }
@@ -134,4 +232,15 @@ mod tests {
 
         assert!(temp_dir.path().exists());
     }
+
+    #[test]
+    fn test_config_disk_quota_defaults_to_global() {
+        let config = LogConfig::builder(PathBuf::from("/tmp"))
+            .max_config_disk_usage(1000)
+            .config_disk_quotas(BTreeMap::from([(2, 50)]))
+            .build();
+
+        assert_eq!(config.config_disk_quota(1), 1000);
+        assert_eq!(config.config_disk_quota(2), 50);
+    }
 }
@@ -0,0 +1,428 @@
+use anyhow::Result;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tracing::trace;
+
+use crate::parser::{
+    BodyParser,
+    RequestParser,
+    ResponseParser,
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilterRules {
+    pub paths: Vec<String>,
+    pub methods: Vec<String>,
+    pub status_classes: Vec<String>,
+    pub content_types: Vec<String>,
+}
+
+impl FilterRules {
+    fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+            && self.methods.is_empty()
+            && self.status_classes.is_empty()
+            && self.content_types.is_empty()
+    }
+
+    fn has_response_rules(&self) -> bool {
+        !self.status_classes.is_empty() || !self.content_types.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogFilterConfig {
+    pub include: FilterRules,
+    pub exclude: FilterRules,
+    pub sample_rate: f64,
+}
+
+impl Default for LogFilterConfig {
+    fn default() -> Self {
+        Self {
+            include: FilterRules::default(),
+            exclude: FilterRules::default(),
+            sample_rate: 1.0,
+        }
+    }
+}
+
+impl LogFilterConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !(0.0..=1.0).contains(&self.sample_rate) {
+            return Err(anyhow::anyhow!(
+                "Sample rate must be between 0.0 and 1.0, got {}",
+                self.sample_rate
+            ));
+        }
+
+        for class in self
+            .include
+            .status_classes
+            .iter()
+            .chain(self.exclude.status_classes.iter())
+        {
+            StatusMatcher::parse(class)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StatusMatcher {
+    Class(u16),
+    Exact(u16),
+}
+
+impl StatusMatcher {
+    fn parse(value: &str) -> Result<Self> {
+        let value = value.trim().to_ascii_lowercase();
+
+        if let Some(class) = value.strip_suffix("xx") {
+            if let Ok(class @ 1..=5) = class.parse::<u16>() {
+                return Ok(StatusMatcher::Class(class));
+            }
+        } else if let Ok(code @ 100..=599) = value.parse::<u16>() {
+            return Ok(StatusMatcher::Exact(code));
+        }
+
+        Err(anyhow::anyhow!(
+            "Invalid status class '{}', expected e.g. '2xx' or '404'",
+            value
+        ))
+    }
+
+    fn matches(&self, status: u16) -> bool {
+        match self {
+            StatusMatcher::Class(class) => status / 100 == *class,
+            StatusMatcher::Exact(code) => status == *code,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct CompiledRules {
+    paths: Vec<String>,
+    methods: Vec<String>,
+    status: Vec<StatusMatcher>,
+    content_types: Vec<String>,
+}
+
+impl CompiledRules {
+    fn compile(rules: &FilterRules) -> Result<Self> {
+        Ok(Self {
+            paths: rules.paths.iter().map(|p| p.trim().to_string()).collect(),
+            methods: rules
+                .methods
+                .iter()
+                .map(|m| m.trim().to_ascii_uppercase())
+                .collect(),
+            status: rules
+                .status_classes
+                .iter()
+                .map(|s| StatusMatcher::parse(s))
+                .collect::<Result<Vec<_>>>()?,
+            content_types: rules
+                .content_types
+                .iter()
+                .map(|c| c.trim().to_ascii_lowercase())
+                .collect(),
+        })
+    }
+
+    fn path_matches(&self, path: &str) -> bool {
+        let path = strip_query(path);
+        self.paths.iter().any(|pattern| glob_match(pattern, path))
+    }
+
+    fn method_matches(&self, method: &str) -> bool {
+        self.methods.iter().any(|m| m.eq_ignore_ascii_case(method))
+    }
+
+    fn status_matches(&self, status: u16) -> bool {
+        self.status.iter().any(|s| s.matches(status))
+    }
+
+    fn content_type_matches(&self, content_type: &str) -> bool {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+        self.content_types
+            .iter()
+            .any(|pattern| glob_match(pattern, &mime))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+    include: CompiledRules,
+    exclude: CompiledRules,
+    sample_rate: f64,
+    has_response_rules: bool,
+    is_passthrough: bool,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self::compile(&LogFilterConfig::default()).expect("default log filter must compile")
+    }
+}
+
+impl LogFilter {
+    pub fn compile(config: &LogFilterConfig) -> Result<Self> {
+        config.validate()?;
+
+        Ok(Self {
+            include: CompiledRules::compile(&config.include)?,
+            exclude: CompiledRules::compile(&config.exclude)?,
+            sample_rate: config.sample_rate,
+            has_response_rules: config.include.has_response_rules()
+                || config.exclude.has_response_rules(),
+            is_passthrough: config.include.is_empty()
+                && config.exclude.is_empty()
+                && config.sample_rate >= 1.0,
+        })
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        self.is_passthrough
+    }
+
+    pub fn has_response_rules(&self) -> bool {
+        self.has_response_rules
+    }
+
+    pub fn matches_request(&self, method: &str, path: &str) -> bool {
+        if !self.include.paths.is_empty() && !self.include.path_matches(path) {
+            return false;
+        }
+
+        if !self.include.methods.is_empty() && !self.include.method_matches(method) {
+            return false;
+        }
+
+        !(self.exclude.path_matches(path) || self.exclude.method_matches(method))
+    }
+
+    pub fn matches_response(&self, status: Option<u16>, content_type: Option<&str>) -> bool {
+        if !self.include.status.is_empty()
+            && !status.is_some_and(|s| self.include.status_matches(s))
+        {
+            return false;
+        }
+
+        if !self.include.content_types.is_empty()
+            && !content_type.is_some_and(|c| self.include.content_type_matches(c))
+        {
+            return false;
+        }
+
+        let excluded_status = status.is_some_and(|s| self.exclude.status_matches(s));
+        let excluded_type = content_type.is_some_and(|c| self.exclude.content_type_matches(c));
+
+        !(excluded_status || excluded_type)
+    }
+
+    pub fn sample(&self) -> bool {
+        if self.sample_rate >= 1.0 {
+            return true;
+        }
+        if self.sample_rate <= 0.0 {
+            return false;
+        }
+
+        rand::random::<f64>() < self.sample_rate
+    }
+
+    /// Returns `None` when the buffer does not start with a complete request
+    /// head, e.g. a continuation of a body, so callers can keep the decision
+    /// made for the request it belongs to.
+    pub fn should_log_request(&self, request_buffer: &[u8]) -> Option<bool> {
+        let (method, path) = match RequestParser::parse(request_buffer) {
+            Ok((Some(method), Some(path), _, _)) => (method, path),
+            _ => return None,
+        };
+
+        if self.is_passthrough {
+            return Some(true);
+        }
+
+        let matched = self.matches_request(method, path) && self.sample();
+        trace!(
+            "HTTP log filter decision for {} {}: {}",
+            method,
+            path,
+            matched
+        );
+
+        Some(matched)
+    }
+
+    pub fn should_log_response(&self, response_buffer: &[u8]) -> bool {
+        if !self.has_response_rules {
+            return true;
+        }
+
+        let (status, headers) = ResponseParser::parse(response_buffer).unwrap_or_default();
+        let content_type = BodyParser::get_content_type(&headers);
+
+        let matched = self.matches_response(status, content_type);
+        trace!(
+            "HTTP log filter decision for response {:?} ({:?}): {}",
+            status,
+            content_type,
+            matched
+        );
+
+        matched
+    }
+}
+
+fn strip_query(path: &str) -> &str {
+    path.split(['?', '#']).next().unwrap_or(path)
+}
+
+/// Matches `text` against a glob where `*` and `?` stop at `/` and `**`
+/// crosses path segments.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_match_from(&pattern, &text)
+}
+
+fn glob_match_from(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            (0..=text.len()).any(|i| glob_match_from(rest, &text[i..]))
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            for i in 0..=text.len() {
+                if glob_match_from(rest, &text[i..]) {
+                    return true;
+                }
+                if i < text.len() && text[i] == '/' {
+                    break;
+                }
+            }
+            false
+        }
+        Some('?') => {
+            matches!(text.first(), Some(c) if *c != '/')
+                && glob_match_from(&pattern[1..], &text[1..])
+        }
+        Some(c) => text.first() == Some(c) && glob_match_from(&pattern[1..], &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("/healthz", "/healthz"));
+        assert!(glob_match("/api/*", "/api/users"));
+        assert!(!glob_match("/api/*", "/api/users/1"));
+        assert!(glob_match("/api/**", "/api/users/1"));
+        assert!(glob_match("/v?/items", "/v1/items"));
+        assert!(glob_match("application/*", "application/json"));
+        assert!(!glob_match("/metrics", "/metrics/extra"));
+    }
+
+    #[test]
+    fn test_exclude_health_checks() {
+        let filter = LogFilter::compile(&LogFilterConfig {
+            exclude: FilterRules {
+                paths: vec!["/healthz".to_string(), "/metrics".to_string()],
+                methods: vec!["options".to_string()],
+                ..FilterRules::default()
+            },
+            ..LogFilterConfig::default()
+        })
+        .unwrap();
+
+        assert_eq!(
+            filter.should_log_request(b"GET /healthz?verbose=1 HTTP/1.1\r\nHost: a\r\n\r\n"),
+            Some(false)
+        );
+        assert_eq!(
+            filter.should_log_request(b"OPTIONS /api/users HTTP/1.1\r\nHost: a\r\n\r\n"),
+            Some(false)
+        );
+        assert_eq!(
+            filter.should_log_request(b"GET /api/users HTTP/1.1\r\nHost: a\r\n\r\n"),
+            Some(true)
+        );
+        assert_eq!(filter.should_log_request(b"{\"partial\": true}"), None);
+    }
+
+    #[test]
+    fn test_include_rules() {
+        let filter = LogFilter::compile(&LogFilterConfig {
+            include: FilterRules {
+                paths: vec!["/api/**".to_string()],
+                methods: vec!["POST".to_string()],
+                status_classes: vec!["4xx".to_string(), "500".to_string()],
+                content_types: vec!["application/json".to_string()],
+            },
+            ..LogFilterConfig::default()
+        })
+        .unwrap();
+
+        assert!(filter.has_response_rules());
+        assert!(filter.matches_request("POST", "/api/orders/1"));
+        assert!(!filter.matches_request("GET", "/api/orders/1"));
+        assert!(!filter.matches_request("POST", "/static/app.js"));
+
+        assert!(filter.should_log_response(
+            b"HTTP/1.1 404 Not Found\r\nContent-Type: application/json; charset=utf-8\r\n\r\n{}"
+        ));
+        assert!(!filter
+            .should_log_response(b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n{}"));
+        assert!(!filter.should_log_response(
+            b"HTTP/1.1 500 Internal Server Error\r\nContent-Type: text/html\r\n\r\n"
+        ));
+    }
+
+    #[test]
+    fn test_sampling_bounds() {
+        let never = LogFilter::compile(&LogFilterConfig {
+            sample_rate: 0.0,
+            ..LogFilterConfig::default()
+        })
+        .unwrap();
+        assert!(!never.is_passthrough());
+        assert_eq!(
+            never.should_log_request(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n"),
+            Some(false)
+        );
+
+        assert!(LogFilter::default().is_passthrough());
+        assert!(LogFilterConfig {
+            sample_rate: 1.5,
+            ..LogFilterConfig::default()
+        }
+        .validate()
+        .is_err());
+        assert!(LogFilterConfig {
+            include: FilterRules {
+                status_classes: vec!["9xx".to_string()],
+                ..FilterRules::default()
+            },
+            ..LogFilterConfig::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -14,18 +14,36 @@ use tracing::{
     trace,
 };
 
+use crate::grpc::{
+    decompress_grpc_payload,
+    is_grpc_content_type,
+    split_grpc_frames,
+    GrpcDecoder,
+};
+use crate::hpack::HeaderList;
+use crate::http2::{
+    error_code_name,
+    Http2Message,
+};
 use crate::message::LogMessage;
 use crate::parser::{
     BodyParser,
     RequestParser,
     ResponseParser,
 };
+use crate::redaction::RedactionRules;
+use crate::websocket::{
+    close_code_name,
+    FrameDirection,
+    Opcode,
+    WebSocketMessage,
+};
 
 pub struct MessageFormatter;
 
 impl MessageFormatter {
     pub async fn format_request(
-        buffer: &Bytes, trace_id: &str, timestamp: DateTime<Utc>,
+        buffer: &Bytes, trace_id: &str, timestamp: DateTime<Utc>, redaction: &RedactionRules,
     ) -> Result<LogMessage> {
         debug!("Formatting request with trace ID: {}", trace_id);
 
@@ -34,14 +52,19 @@ impl MessageFormatter {
         let (method, path, version, headers) = RequestParser::parse(buffer)?;
 
         if let (Some(method), Some(path), Some(version)) = (method, path, version) {
-            log_entry.push_str(&format!("{} {} HTTP/1.{}\n", method, path, version));
+            log_entry.push_str(&format!(
+                "{} {} HTTP/1.{}\n",
+                method,
+                redaction.redact_text(path),
+                version
+            ));
 
-            Self::append_headers(&headers, &mut log_entry);
+            Self::append_headers(&headers, &mut log_entry, redaction);
 
             log_entry.push('\n');
 
             if let Some(body) = RequestParser::extract_body(buffer) {
-                Self::format_body(body, &headers, &mut log_entry).await?;
+                Self::format_body(body, &headers, &mut log_entry, redaction).await?;
                 Self::append_log_separator(&mut log_entry);
             } else {
                 Self::append_log_separator(&mut log_entry);
@@ -53,11 +76,33 @@ impl MessageFormatter {
 
     pub async fn format_response(
         buffer: &Bytes, trace_id: &str, timestamp: DateTime<Utc>, took: i64,
+        redaction: &RedactionRules,
     ) -> Result<LogMessage> {
         debug!("Formatting response with trace ID: {}", trace_id);
 
         let mut log_entry = Self::create_metadata_header(trace_id, timestamp, Some(took));
+        Self::append_response(buffer, &mut log_entry, redaction).await?;
+
+        Ok(LogMessage::Response(log_entry))
+    }
+
+    /// Formats a response synthesized by a mock rule instead of the service.
+    pub async fn format_mocked_response(
+        buffer: &Bytes, trace_id: &str, timestamp: DateTime<Utc>, took: i64, rule: &str,
+        redaction: &RedactionRules,
+    ) -> Result<LogMessage> {
+        debug!("Formatting mocked response with trace ID: {}", trace_id);
 
+        let mut log_entry = Self::create_metadata_header(trace_id, timestamp, Some(took));
+        log_entry.push_str(&format!("# Mocked by rule: {}\n", rule));
+        Self::append_response(buffer, &mut log_entry, redaction).await?;
+
+        Ok(LogMessage::Response(log_entry))
+    }
+
+    async fn append_response(
+        buffer: &Bytes, log_entry: &mut String, redaction: &RedactionRules,
+    ) -> Result<()> {
         let (status_code, headers) = ResponseParser::parse(buffer)?;
 
         if let Some(status) = status_code {
@@ -67,23 +112,24 @@ impl MessageFormatter {
                 Self::status_text(status)
             ));
 
-            Self::append_headers(&headers, &mut log_entry);
+            Self::append_headers(&headers, log_entry, redaction);
             log_entry.push('\n');
 
             if let Some(body) = RequestParser::extract_body(buffer) {
                 let processed_body = Self::process_response_body(body, &headers);
-                Self::format_body(&processed_body, &headers, &mut log_entry).await?;
-                Self::append_log_separator(&mut log_entry);
+                Self::format_body(&processed_body, &headers, log_entry, redaction).await?;
+                Self::append_log_separator(log_entry);
             } else {
-                Self::append_log_separator(&mut log_entry);
+                Self::append_log_separator(log_entry);
             }
         }
 
-        Ok(LogMessage::Response(log_entry))
+        Ok(())
     }
 
     pub fn format_preformatted_response(
         trace_id: &str, timestamp: DateTime<Utc>, took: i64, buffer: &Bytes,
+        redaction: &RedactionRules,
     ) -> String {
         debug!(
             "Formatting preformatted response with trace ID: {}",
@@ -98,7 +144,7 @@ impl MessageFormatter {
                 let body_bytes = &buffer[headers_end..];
 
                 if let Ok(headers_str) = std::str::from_utf8(headers_bytes) {
-                    log_entry.push_str(headers_str);
+                    log_entry.push_str(&redaction.redact_header_block(headers_str));
                     log_entry.push('\n');
                 } else {
                     log_entry.push_str("HTTP/1.1 200 OK\n<unparseable headers>\n\n");
@@ -155,15 +201,20 @@ impl MessageFormatter {
                     }
 
                     let content_type_str = content_type.unwrap_or("text/plain");
-                    Self::format_content(&mut log_entry, content_type_str, &processed_body);
+                    Self::format_content(
+                        &mut log_entry,
+                        content_type_str,
+                        &processed_body,
+                        redaction,
+                    );
                 }
             } else if let Ok(content) = std::str::from_utf8(buffer) {
-                log_entry.push_str(content);
+                log_entry.push_str(&redaction.redact_text(content));
             } else {
                 log_entry.push_str("<binary content>");
             }
         } else if let Ok(content) = std::str::from_utf8(buffer) {
-            log_entry.push_str(content);
+            log_entry.push_str(&redaction.redact_text(content));
         } else {
             log_entry.push_str("<binary content>");
         }
@@ -172,11 +223,350 @@ impl MessageFormatter {
         log_entry
     }
 
-    fn format_content(log_entry: &mut String, content_type: &str, body: &[u8]) {
+    pub async fn format_http2_request(
+        message: &Http2Message, stream_id: u32, trace_id: &str, timestamp: DateTime<Utc>,
+        redaction: &RedactionRules, grpc: &GrpcDecoder,
+    ) -> Result<LogMessage> {
+        debug!(
+            "Formatting HTTP/2 request on stream {} with trace ID: {}",
+            stream_id, trace_id
+        );
+
+        let mut log_entry = Self::create_metadata_header(trace_id, timestamp, None);
+        log_entry.push_str(&format!("# HTTP/2 stream: {}\n", stream_id));
+
+        let path = message.path().unwrap_or("/");
+        log_entry.push_str(&format!(
+            "{} {} HTTP/2\n",
+            message.method().unwrap_or("GET"),
+            redaction.redact_text(path)
+        ));
+
+        Self::append_http2_headers(&message.headers, &mut log_entry, redaction);
+        log_entry.push('\n');
+
+        Self::format_http2_body(message, path, true, &mut log_entry, redaction, grpc).await?;
+        Self::append_http2_status(message, &mut log_entry, redaction);
+        Self::append_log_separator(&mut log_entry);
+
+        Ok(LogMessage::Request(log_entry))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn format_http2_response(
+        message: &Http2Message, stream_id: u32, request_path: &str, trace_id: &str,
+        timestamp: DateTime<Utc>, took: i64, redaction: &RedactionRules, grpc: &GrpcDecoder,
+    ) -> Result<LogMessage> {
+        debug!(
+            "Formatting HTTP/2 response on stream {} with trace ID: {}",
+            stream_id, trace_id
+        );
+
+        let mut log_entry = Self::create_metadata_header(trace_id, timestamp, Some(took));
+        log_entry.push_str(&format!("# HTTP/2 stream: {}\n", stream_id));
+
+        match message.status() {
+            Some(status) => log_entry.push_str(&format!(
+                "HTTP/2 {} {}\n",
+                status,
+                Self::status_text(status)
+            )),
+            None => log_entry.push_str("# <no response headers>\n"),
+        }
+
+        Self::append_http2_headers(&message.headers, &mut log_entry, redaction);
+        log_entry.push('\n');
+
+        Self::format_http2_body(
+            message,
+            request_path,
+            false,
+            &mut log_entry,
+            redaction,
+            grpc,
+        )
+        .await?;
+
+        if !message.trailers.is_empty() {
+            log_entry.push_str("\n\n# Trailers:\n");
+            for (name, value) in &message.trailers {
+                log_entry.push_str(&format!(
+                    "# {}: {}\n",
+                    name,
+                    redaction.redact_header_value(name, value)
+                ));
+            }
+        }
+
+        Self::append_http2_status(message, &mut log_entry, redaction);
+        Self::append_log_separator(&mut log_entry);
+
+        Ok(LogMessage::Response(log_entry))
+    }
+
+    pub fn format_websocket_message(
+        message: &WebSocketMessage, direction: FrameDirection, trace_id: &str,
+        redaction: &RedactionRules,
+    ) -> LogMessage {
+        trace!(
+            "Formatting WebSocket {} message with trace ID: {}",
+            message.opcode,
+            trace_id
+        );
+
+        let mut log_entry = String::with_capacity(256 + message.payload.len());
+        log_entry.push_str("\n# ----------------------------------------\n");
+        log_entry.push_str(&format!("# Trace ID: {}\n", trace_id));
+        log_entry.push_str(&format!(
+            "# WebSocket frame at: {}\n",
+            message.timestamp.to_rfc3339()
+        ));
+        log_entry.push_str(&format!("# Direction: {}\n", direction));
+        log_entry.push_str(&format!(
+            "# Opcode: {} ({} bytes{}{})\n",
+            message.opcode,
+            message.payload_len,
+            if message.fragments > 1 {
+                format!(", {} fragments", message.fragments)
+            } else {
+                String::new()
+            },
+            if message.truncated { ", truncated" } else { "" }
+        ));
+
+        match message.opcode {
+            Opcode::Close => match message.close_status() {
+                Some((code, reason)) => {
+                    log_entry.push_str(&format!(
+                        "# Close code: {} ({})",
+                        code,
+                        close_code_name(code)
+                    ));
+                    if !reason.is_empty() {
+                        log_entry.push_str(&format!(
+                            "\n# Close reason: {}",
+                            redaction.redact_text(&reason)
+                        ));
+                    }
+                }
+                None => log_entry.push_str("# Close code: none"),
+            },
+            _ if message.compressed => {
+                log_entry.push_str(&format!(
+                    "# <compressed message: {} bytes>",
+                    message.payload.len()
+                ));
+            }
+            Opcode::Text => {
+                let text = String::from_utf8_lossy(&message.payload);
+                let text = redaction
+                    .redact_json_body(text.as_bytes())
+                    .map(|json| String::from_utf8_lossy(&json).into_owned())
+                    .unwrap_or_else(|| text.into_owned());
+                log_entry.push_str(&redaction.redact_text(&text));
+            }
+            Opcode::Ping | Opcode::Pong if message.payload.is_empty() => {}
+            _ => {
+                let preview_len = message.payload.len().min(64);
+                log_entry.push_str(&format!(
+                    "# <binary message: {} bytes>\n# {}{}",
+                    message.payload_len,
+                    message.payload[..preview_len]
+                        .iter()
+                        .map(|b| format!("{:02x}", b))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    if message.payload.len() > preview_len {
+                        " ..."
+                    } else {
+                        ""
+                    }
+                ));
+            }
+        }
+
+        Self::append_log_separator(&mut log_entry);
+        LogMessage::WebSocketFrame(log_entry)
+    }
+
+    pub fn format_protocol_command(
+        protocol: &str, summary: &str, detail: Option<&str>, trace_id: &str,
+        timestamp: DateTime<Utc>, redaction: &RedactionRules,
+    ) -> LogMessage {
+        let mut log_entry = Self::create_metadata_header(trace_id, timestamp, None);
+        log_entry.push_str(&format!("# Protocol: {}\n", protocol));
+        log_entry.push_str(&format!("# Command: {}\n", summary));
+
+        if let Some(detail) = detail {
+            log_entry.push('\n');
+            log_entry.push_str(&redaction.redact_text(detail));
+        }
+
+        Self::append_log_separator(&mut log_entry);
+        LogMessage::Request(log_entry)
+    }
+
+    pub fn format_protocol_reply(
+        protocol: &str, summary: &str, is_error: bool, trace_id: &str, timestamp: DateTime<Utc>,
+        took: i64, redaction: &RedactionRules,
+    ) -> LogMessage {
+        let mut log_entry = Self::create_metadata_header(trace_id, timestamp, Some(took));
+        log_entry.push_str(&format!("# Protocol: {}\n", protocol));
+        log_entry.push_str(&format!(
+            "# Reply: {}\n\n",
+            if is_error { "error" } else { "ok" }
+        ));
+        log_entry.push_str(&redaction.redact_text(summary));
+
+        Self::append_log_separator(&mut log_entry);
+        LogMessage::Response(log_entry)
+    }
+
+    pub fn format_protocol_info(
+        protocol: &str, info: &str, timestamp: DateTime<Utc>, redaction: &RedactionRules,
+    ) -> LogMessage {
+        let mut log_entry = String::with_capacity(128 + info.len());
+        log_entry.push_str("\n# ----------------------------------------\n");
+        log_entry.push_str(&format!("# Protocol: {}\n", protocol));
+        log_entry.push_str(&format!("# Event at: {}\n", timestamp.to_rfc3339()));
+        log_entry.push_str(&redaction.redact_text(info));
+
+        Self::append_log_separator(&mut log_entry);
+        LogMessage::ProtocolEvent(log_entry)
+    }
+
+    fn append_http2_headers(
+        headers: &HeaderList, log_entry: &mut String, redaction: &RedactionRules,
+    ) {
+        for (name, value) in headers.iter().filter(|(name, _)| !name.starts_with(':')) {
+            log_entry.push_str(name);
+            log_entry.push_str(": ");
+            log_entry.push_str(&redaction.redact_header_value(name, value));
+            log_entry.push('\n');
+        }
+    }
+
+    fn append_http2_status(
+        message: &Http2Message, log_entry: &mut String, redaction: &RedactionRules,
+    ) {
+        if message.truncated {
+            log_entry.push_str("\n# <stream incomplete or truncated>");
+        }
+
+        if let Some(code) = message.reset_code {
+            log_entry.push_str(&format!(
+                "\n# Stream reset: {} ({})",
+                error_code_name(code),
+                code
+            ));
+        }
+
+        if let Some(status) = message.trailer("grpc-status") {
+            log_entry.push_str(&format!("\n# gRPC status: {}", status));
+            if let Some(grpc_message) = message.trailer("grpc-message") {
+                log_entry.push_str(&format!(" ({})", redaction.redact_text(grpc_message)));
+            }
+        }
+    }
+
+    async fn format_http2_body(
+        message: &Http2Message, path: &str, is_request: bool, log_entry: &mut String,
+        redaction: &RedactionRules, grpc: &GrpcDecoder,
+    ) -> Result<()> {
+        if message.body.is_empty() {
+            return Ok(());
+        }
+
+        let content_type = message.content_type().unwrap_or_default();
+        if is_grpc_content_type(content_type) {
+            Self::format_grpc_body(message, path, is_request, log_entry, redaction, grpc);
+            return Ok(());
+        }
+
+        let headers: Vec<Header<'_>> = message
+            .headers
+            .iter()
+            .filter(|(name, _)| !name.starts_with(':'))
+            .map(|(name, value)| Header {
+                name,
+                value: value.as_bytes(),
+            })
+            .collect();
+
+        Self::format_body(&message.body, &headers, log_entry, redaction).await
+    }
+
+    fn format_grpc_body(
+        message: &Http2Message, path: &str, is_request: bool, log_entry: &mut String,
+        redaction: &RedactionRules, grpc: &GrpcDecoder,
+    ) {
+        let encoding = message.header("grpc-encoding").unwrap_or("identity");
+        let (frames, remaining) = split_grpc_frames(&message.body);
+
+        for (index, frame) in frames.iter().enumerate() {
+            if index > 0 {
+                log_entry.push('\n');
+            }
+            log_entry.push_str(&format!(
+                "# gRPC message {} ({} bytes{})\n",
+                index + 1,
+                frame.payload.len(),
+                if frame.compressed { ", compressed" } else { "" }
+            ));
+
+            if frame.trailers {
+                log_entry.push_str(
+                    &redaction.redact_header_block(&String::from_utf8_lossy(&frame.payload)),
+                );
+                continue;
+            }
+
+            let payload = if frame.compressed {
+                match decompress_grpc_payload(&frame.payload, encoding) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        log_entry.push_str(&format!("# <{}>\n", e));
+                        continue;
+                    }
+                }
+            } else {
+                frame.payload.clone()
+            };
+
+            let formatted = grpc.format_message(path, is_request, &payload);
+            let formatted = match serde_json::from_str::<serde_json::Value>(&formatted) {
+                Ok(mut json) if grpc.has_descriptors() => {
+                    redaction.redact_json(&mut json);
+                    serde_json::to_string_pretty(&json).unwrap_or(formatted)
+                }
+                _ => formatted,
+            };
+            log_entry.push_str(&redaction.redact_text(&formatted));
+        }
+
+        if remaining > 0 {
+            log_entry.push_str(&format!(
+                "\n# <{} bytes of incomplete gRPC message>",
+                remaining
+            ));
+        }
+    }
+
+    fn format_content(
+        log_entry: &mut String, content_type: &str, body: &[u8], redaction: &RedactionRules,
+    ) {
         if body.is_empty() {
             return;
         }
 
+        let mut content = String::new();
+        Self::format_content_body(&mut content, content_type, body, redaction);
+        log_entry.push_str(&redaction.redact_text(&content));
+    }
+
+    fn format_content_body(
+        log_entry: &mut String, content_type: &str, body: &[u8], redaction: &RedactionRules,
+    ) {
         let content_type_lower = content_type.to_lowercase();
 
         if content_type_lower.contains("javascript") {
@@ -188,7 +578,8 @@ impl MessageFormatter {
         if content_type_lower.contains("json") {
             debug!("Attempting to format as JSON");
             if let Ok(text) = std::str::from_utf8(body) {
-                if let Ok(json) = serde_json::from_str::<serde_json::Value>(text.trim()) {
+                if let Ok(mut json) = serde_json::from_str::<serde_json::Value>(text.trim()) {
+                    redaction.redact_json(&mut json);
                     if let Ok(pretty) = serde_json::to_string_pretty(&json) {
                         log_entry.push_str(&pretty);
                         return;
@@ -345,7 +736,7 @@ impl MessageFormatter {
         header
     }
 
-    fn append_headers(headers: &[Header<'_>], log_entry: &mut String) {
+    fn append_headers(headers: &[Header<'_>], log_entry: &mut String, redaction: &RedactionRules) {
         let additional_capacity = headers
             .iter()
             .map(|h| h.name.len() + h.value.len() + 3)
@@ -360,7 +751,7 @@ impl MessageFormatter {
             log_entry.push_str(header.name);
             log_entry.push_str(": ");
             if let Ok(value) = std::str::from_utf8(header.value) {
-                log_entry.push_str(value);
+                log_entry.push_str(&redaction.redact_header_value(header.name, value));
             }
             log_entry.push('\n');
         }
@@ -396,7 +787,7 @@ impl MessageFormatter {
     }
 
     async fn format_body(
-        body: &[u8], headers: &[Header<'_>], log_entry: &mut String,
+        body: &[u8], headers: &[Header<'_>], log_entry: &mut String, redaction: &RedactionRules,
     ) -> Result<()> {
         let content_length = RequestParser::get_content_length(headers);
 
@@ -407,12 +798,16 @@ impl MessageFormatter {
 
         let body_content = match Self::try_decompress_body(body, headers).await {
             Ok(content) => content,
-            Err(e) => Self::handle_decompression_error(body, headers, log_entry, e)?,
+            Err(e) => Self::handle_decompression_error(body, headers, log_entry, e, redaction)?,
         };
 
+        let body_content = redaction
+            .redact_json_body(&body_content)
+            .unwrap_or(body_content);
+
         let content_type = Self::extract_content_type(headers);
         let formatted_body = BodyParser::format_body(&body_content, content_type)?;
-        log_entry.push_str(&formatted_body);
+        log_entry.push_str(&redaction.redact_text(&formatted_body));
 
         Ok(())
     }
@@ -429,6 +824,7 @@ impl MessageFormatter {
 
     fn handle_decompression_error(
         body: &[u8], headers: &[Header<'_>], log_entry: &mut String, error: anyhow::Error,
+        redaction: &RedactionRules,
     ) -> Result<Vec<u8>> {
         log_entry.push_str(&format!(
             "<!-- Debug: Failed to process content: {} -->",
@@ -444,7 +840,11 @@ impl MessageFormatter {
 
         if let Ok(body_str) = std::str::from_utf8(body) {
             if !body_str.is_empty() {
-                log_entry.push_str(body_str);
+                let redacted = redaction
+                    .redact_json_body(body_str.as_bytes())
+                    .map(|json| String::from_utf8_lossy(&json).into_owned())
+                    .unwrap_or_else(|| body_str.to_string());
+                log_entry.push_str(&redaction.redact_text(&redacted));
                 return Ok(Vec::new());
             }
         }
@@ -617,7 +1017,7 @@ impl MessageFormatter {
         None
     }
 
-    fn status_text(status: u16) -> &'static str {
+    pub(crate) fn status_text(status: u16) -> &'static str {
         match status {
             100 => "Continue",
             101 => "Switching Protocols",
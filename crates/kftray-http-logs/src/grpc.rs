@@ -0,0 +1,339 @@
+use std::fmt::Write;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{
+    anyhow,
+    Context,
+    Result,
+};
+use flate2::read::{
+    GzDecoder,
+    ZlibDecoder,
+};
+use prost_reflect::{
+    DescriptorPool,
+    DynamicMessage,
+    MessageDescriptor,
+};
+use tracing::{
+    debug,
+    error,
+};
+
+const GRPC_FRAME_HEADER_LEN: usize = 5;
+const GRPC_WEB_TRAILERS_FLAG: u8 = 0x80;
+const MAX_NESTING_DEPTH: usize = 16;
+
+pub fn is_grpc_content_type(content_type: &str) -> bool {
+    content_type
+        .trim()
+        .to_ascii_lowercase()
+        .starts_with("application/grpc")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrpcFrame {
+    pub compressed: bool,
+    pub trailers: bool,
+    pub payload: Vec<u8>,
+}
+
+/// Splits a gRPC body into its length-prefixed messages. The second value
+/// is the number of trailing bytes that did not form a complete message.
+pub fn split_grpc_frames(body: &[u8]) -> (Vec<GrpcFrame>, usize) {
+    let mut frames = Vec::new();
+    let mut offset = 0;
+
+    while body.len() - offset >= GRPC_FRAME_HEADER_LEN {
+        let flags = body[offset];
+        let len = u32::from_be_bytes([
+            body[offset + 1],
+            body[offset + 2],
+            body[offset + 3],
+            body[offset + 4],
+        ]) as usize;
+
+        let start = offset + GRPC_FRAME_HEADER_LEN;
+        if body.len() - start < len {
+            break;
+        }
+
+        frames.push(GrpcFrame {
+            compressed: flags & 0x01 != 0,
+            trailers: flags & GRPC_WEB_TRAILERS_FLAG != 0,
+            payload: body[start..start + len].to_vec(),
+        });
+        offset = start + len;
+    }
+
+    (frames, body.len() - offset)
+}
+
+pub fn decompress_grpc_payload(payload: &[u8], encoding: &str) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+
+    match encoding.trim().to_ascii_lowercase().as_str() {
+        "gzip" => {
+            GzDecoder::new(payload).read_to_end(&mut output)?;
+        }
+        "deflate" => {
+            ZlibDecoder::new(payload).read_to_end(&mut output)?;
+        }
+        "identity" | "" => output.extend_from_slice(payload),
+        other => return Err(anyhow!("Unsupported grpc-encoding '{}'", other)),
+    }
+
+    Ok(output)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProtoValue {
+    Varint(u64),
+    Fixed64(u64),
+    Fixed32(u32),
+    LengthDelimited(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtoField {
+    pub number: u64,
+    pub value: ProtoValue,
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut value = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*pos).ok_or_else(|| anyhow!("Truncated varint"))?;
+        *pos += 1;
+
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(anyhow!("Varint too long"))
+}
+
+fn read_bytes<'a>(data: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8]> {
+    let end = pos
+        .checked_add(len)
+        .filter(|end| *end <= data.len())
+        .ok_or_else(|| anyhow!("Field length {} exceeds message", len))?;
+    let bytes = &data[*pos..end];
+    *pos = end;
+    Ok(bytes)
+}
+
+/// Decodes protobuf wire format without a schema. Groups are not supported.
+pub fn decode_raw_protobuf(data: &[u8]) -> Result<Vec<ProtoField>> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let key = read_varint(data, &mut pos)?;
+        let number = key >> 3;
+        if number == 0 {
+            return Err(anyhow!("Invalid field number 0"));
+        }
+
+        let value = match key & 0x7 {
+            0 => ProtoValue::Varint(read_varint(data, &mut pos)?),
+            1 => {
+                let bytes = read_bytes(data, &mut pos, 8)?;
+                ProtoValue::Fixed64(u64::from_le_bytes(bytes.try_into()?))
+            }
+            2 => {
+                let len = read_varint(data, &mut pos)? as usize;
+                ProtoValue::LengthDelimited(read_bytes(data, &mut pos, len)?.to_vec())
+            }
+            5 => {
+                let bytes = read_bytes(data, &mut pos, 4)?;
+                ProtoValue::Fixed32(u32::from_le_bytes(bytes.try_into()?))
+            }
+            wire_type => return Err(anyhow!("Unsupported wire type {}", wire_type)),
+        };
+
+        fields.push(ProtoField { number, value });
+    }
+
+    Ok(fields)
+}
+
+/// Renders a message in the style of `protoc --decode_raw`.
+pub fn format_raw_protobuf(data: &[u8]) -> Result<String> {
+    let fields = decode_raw_protobuf(data)?;
+    let mut output = String::new();
+    write_raw_fields(&fields, 0, &mut output);
+    Ok(output)
+}
+
+fn write_raw_fields(fields: &[ProtoField], depth: usize, output: &mut String) {
+    let indent = "  ".repeat(depth);
+
+    for field in fields {
+        let _ = match &field.value {
+            ProtoValue::Varint(v) => writeln!(output, "{}{}: {}", indent, field.number, v),
+            ProtoValue::Fixed64(v) => writeln!(output, "{}{}: 0x{:016x}", indent, field.number, v),
+            ProtoValue::Fixed32(v) => writeln!(output, "{}{}: 0x{:08x}", indent, field.number, v),
+            ProtoValue::LengthDelimited(bytes) => {
+                if let Some(text) = printable_text(bytes) {
+                    writeln!(output, "{}{}: {:?}", indent, field.number, text)
+                } else if let Some(nested) = decode_nested(bytes, depth) {
+                    let _ = writeln!(output, "{}{} {{", indent, field.number);
+                    write_raw_fields(&nested, depth + 1, output);
+                    writeln!(output, "{}}}", indent)
+                } else {
+                    writeln!(
+                        output,
+                        "{}{}: \"{}\"",
+                        indent,
+                        field.number,
+                        bytes.escape_ascii()
+                    )
+                }
+            }
+        };
+    }
+}
+
+fn printable_text(bytes: &[u8]) -> Option<&str> {
+    let text = std::str::from_utf8(bytes).ok()?;
+    let printable = text
+        .chars()
+        .all(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t'));
+
+    printable.then_some(text)
+}
+
+fn decode_nested(bytes: &[u8], depth: usize) -> Option<Vec<ProtoField>> {
+    if bytes.is_empty() || depth >= MAX_NESTING_DEPTH {
+        return None;
+    }
+
+    decode_raw_protobuf(bytes).ok()
+}
+
+/// Decodes gRPC payloads, using a descriptor set when one is configured and
+/// falling back to a raw field dump otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct GrpcDecoder {
+    pool: Option<DescriptorPool>,
+}
+
+impl GrpcDecoder {
+    pub fn raw() -> Self {
+        Self { pool: None }
+    }
+
+    pub fn from_descriptor_set(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read descriptor set {}", path.display()))?;
+        let pool = DescriptorPool::decode(bytes.as_slice())
+            .with_context(|| format!("Invalid descriptor set {}", path.display()))?;
+
+        debug!(
+            "Loaded gRPC descriptor set {} ({} services)",
+            path.display(),
+            pool.services().len()
+        );
+
+        Ok(Self { pool: Some(pool) })
+    }
+
+    pub fn from_config(descriptor_set: Option<&Path>) -> Self {
+        match descriptor_set {
+            Some(path) => Self::from_descriptor_set(path).unwrap_or_else(|e| {
+                error!("Falling back to raw gRPC decoding: {:?}", e);
+                Self::raw()
+            }),
+            None => Self::raw(),
+        }
+    }
+
+    pub fn has_descriptors(&self) -> bool {
+        self.pool.is_some()
+    }
+
+    /// `grpc_path` is the HTTP/2 `:path`, e.g. `/package.Service/Method`.
+    pub fn format_message(&self, grpc_path: &str, is_request: bool, payload: &[u8]) -> String {
+        if let Some(descriptor) = self.message_descriptor(grpc_path, is_request) {
+            match DynamicMessage::decode(descriptor.clone(), payload) {
+                Ok(message) => match serde_json::to_string_pretty(&message) {
+                    Ok(json) => return json,
+                    Err(e) => debug!("Failed to serialize {}: {:?}", descriptor.full_name(), e),
+                },
+                Err(e) => debug!("Failed to decode {}: {:?}", descriptor.full_name(), e),
+            }
+        }
+
+        match format_raw_protobuf(payload) {
+            Ok(dump) if !dump.is_empty() => dump,
+            Ok(_) => "<empty message>".to_string(),
+            Err(_) => format!("<binary content, {} bytes>", payload.len()),
+        }
+    }
+
+    fn message_descriptor(&self, grpc_path: &str, is_request: bool) -> Option<MessageDescriptor> {
+        let pool = self.pool.as_ref()?;
+        let (service, method) = grpc_path.trim_start_matches('/').split_once('/')?;
+
+        let method = pool
+            .get_service_by_name(service)?
+            .methods()
+            .find(|m| m.name() == method)?;
+
+        Some(if is_request {
+            method.input()
+        } else {
+            method.output()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_grpc_frames() {
+        let mut body = vec![0, 0, 0, 0, 3, 0x08, 0x96, 0x01];
+        body.extend_from_slice(&[1, 0, 0, 0, 2, 0xaa]);
+
+        let (frames, remaining) = split_grpc_frames(&body);
+        assert_eq!(frames.len(), 1);
+        assert!(!frames[0].compressed);
+        assert_eq!(frames[0].payload, vec![0x08, 0x96, 0x01]);
+        assert_eq!(remaining, 6);
+    }
+
+    #[test]
+    fn test_format_raw_protobuf() {
+        // 1: 150, 2: "testing", 3 { 1: 1 }, 4: fixed32
+        let payload = [
+            0x08, 0x96, 0x01, 0x12, 0x07, b't', b'e', b's', b't', b'i', b'n', b'g', 0x1a, 0x02,
+            0x08, 0x01, 0x25, 0x01, 0x00, 0x00, 0x00,
+        ];
+
+        let dump = format_raw_protobuf(&payload).unwrap();
+        assert_eq!(
+            dump,
+            "1: 150\n2: \"testing\"\n3 {\n  1: 1\n}\n4: 0x00000001\n"
+        );
+
+        assert!(format_raw_protobuf(&[0x0b]).is_err());
+        assert_eq!(
+            GrpcDecoder::raw().format_message("/a.B/C", true, &[0xff]),
+            "<binary content, 1 bytes>"
+        );
+    }
+
+    #[test]
+    fn test_is_grpc_content_type() {
+        assert!(is_grpc_content_type("application/grpc"));
+        assert!(is_grpc_content_type("application/grpc+proto"));
+        assert!(!is_grpc_content_type("application/json"));
+    }
+}
@@ -0,0 +1,623 @@
+use std::collections::{
+    HashMap,
+    VecDeque,
+};
+
+use anyhow::{
+    anyhow,
+    Result,
+};
+use lazy_static::lazy_static;
+
+const DEFAULT_TABLE_SIZE: usize = 4096;
+const ENTRY_OVERHEAD: usize = 32;
+const EOS_SYMBOL: u16 = 256;
+
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+// (code, bit length) for each symbol, RFC 7541 Appendix B.
+const HUFFMAN_CODES: [(u32, u8); 257] = [
+    (0x1ff8, 13),
+    (0x7fffd8, 23),
+    (0xfffffe2, 28),
+    (0xfffffe3, 28),
+    (0xfffffe4, 28),
+    (0xfffffe5, 28),
+    (0xfffffe6, 28),
+    (0xfffffe7, 28),
+    (0xfffffe8, 28),
+    (0xffffea, 24),
+    (0x3ffffffc, 30),
+    (0xfffffe9, 28),
+    (0xfffffea, 28),
+    (0x3ffffffd, 30),
+    (0xfffffeb, 28),
+    (0xfffffec, 28),
+    (0xfffffed, 28),
+    (0xfffffee, 28),
+    (0xfffffef, 28),
+    (0xffffff0, 28),
+    (0xffffff1, 28),
+    (0xffffff2, 28),
+    (0x3ffffffe, 30),
+    (0xffffff3, 28),
+    (0xffffff4, 28),
+    (0xffffff5, 28),
+    (0xffffff6, 28),
+    (0xffffff7, 28),
+    (0xffffff8, 28),
+    (0xffffff9, 28),
+    (0xffffffa, 28),
+    (0xffffffb, 28),
+    (0x14, 6),
+    (0x3f8, 10),
+    (0x3f9, 10),
+    (0xffa, 12),
+    (0x1ff9, 13),
+    (0x15, 6),
+    (0xf8, 8),
+    (0x7fa, 11),
+    (0x3fa, 10),
+    (0x3fb, 10),
+    (0xf9, 8),
+    (0x7fb, 11),
+    (0xfa, 8),
+    (0x16, 6),
+    (0x17, 6),
+    (0x18, 6),
+    (0x0, 5),
+    (0x1, 5),
+    (0x2, 5),
+    (0x19, 6),
+    (0x1a, 6),
+    (0x1b, 6),
+    (0x1c, 6),
+    (0x1d, 6),
+    (0x1e, 6),
+    (0x1f, 6),
+    (0x5c, 7),
+    (0xfb, 8),
+    (0x7ffc, 15),
+    (0x20, 6),
+    (0xffb, 12),
+    (0x3fc, 10),
+    (0x1ffa, 13),
+    (0x21, 6),
+    (0x5d, 7),
+    (0x5e, 7),
+    (0x5f, 7),
+    (0x60, 7),
+    (0x61, 7),
+    (0x62, 7),
+    (0x63, 7),
+    (0x64, 7),
+    (0x65, 7),
+    (0x66, 7),
+    (0x67, 7),
+    (0x68, 7),
+    (0x69, 7),
+    (0x6a, 7),
+    (0x6b, 7),
+    (0x6c, 7),
+    (0x6d, 7),
+    (0x6e, 7),
+    (0x6f, 7),
+    (0x70, 7),
+    (0x71, 7),
+    (0x72, 7),
+    (0xfc, 8),
+    (0x73, 7),
+    (0xfd, 8),
+    (0x1ffb, 13),
+    (0x7fff0, 19),
+    (0x1ffc, 13),
+    (0x3ffc, 14),
+    (0x22, 6),
+    (0x7ffd, 15),
+    (0x3, 5),
+    (0x23, 6),
+    (0x4, 5),
+    (0x24, 6),
+    (0x5, 5),
+    (0x25, 6),
+    (0x26, 6),
+    (0x27, 6),
+    (0x6, 5),
+    (0x74, 7),
+    (0x75, 7),
+    (0x28, 6),
+    (0x29, 6),
+    (0x2a, 6),
+    (0x7, 5),
+    (0x2b, 6),
+    (0x76, 7),
+    (0x2c, 6),
+    (0x8, 5),
+    (0x9, 5),
+    (0x2d, 6),
+    (0x77, 7),
+    (0x78, 7),
+    (0x79, 7),
+    (0x7a, 7),
+    (0x7b, 7),
+    (0x7ffe, 15),
+    (0x7fc, 11),
+    (0x3ffd, 14),
+    (0x1ffd, 13),
+    (0xffffffc, 28),
+    (0xfffe6, 20),
+    (0x3fffd2, 22),
+    (0xfffe7, 20),
+    (0xfffe8, 20),
+    (0x3fffd3, 22),
+    (0x3fffd4, 22),
+    (0x3fffd5, 22),
+    (0x7fffd9, 23),
+    (0x3fffd6, 22),
+    (0x7fffda, 23),
+    (0x7fffdb, 23),
+    (0x7fffdc, 23),
+    (0x7fffdd, 23),
+    (0x7fffde, 23),
+    (0xffffeb, 24),
+    (0x7fffdf, 23),
+    (0xffffec, 24),
+    (0xffffed, 24),
+    (0x3fffd7, 22),
+    (0x7fffe0, 23),
+    (0xffffee, 24),
+    (0x7fffe1, 23),
+    (0x7fffe2, 23),
+    (0x7fffe3, 23),
+    (0x7fffe4, 23),
+    (0x1fffdc, 21),
+    (0x3fffd8, 22),
+    (0x7fffe5, 23),
+    (0x3fffd9, 22),
+    (0x7fffe6, 23),
+    (0x7fffe7, 23),
+    (0xffffef, 24),
+    (0x3fffda, 22),
+    (0x1fffdd, 21),
+    (0xfffe9, 20),
+    (0x3fffdb, 22),
+    (0x3fffdc, 22),
+    (0x7fffe8, 23),
+    (0x7fffe9, 23),
+    (0x1fffde, 21),
+    (0x7fffea, 23),
+    (0x3fffdd, 22),
+    (0x3fffde, 22),
+    (0xfffff0, 24),
+    (0x1fffdf, 21),
+    (0x3fffdf, 22),
+    (0x7fffeb, 23),
+    (0x7fffec, 23),
+    (0x1fffe0, 21),
+    (0x1fffe1, 21),
+    (0x3fffe0, 22),
+    (0x1fffe2, 21),
+    (0x7fffed, 23),
+    (0x3fffe1, 22),
+    (0x7fffee, 23),
+    (0x7fffef, 23),
+    (0xfffea, 20),
+    (0x3fffe2, 22),
+    (0x3fffe3, 22),
+    (0x3fffe4, 22),
+    (0x7ffff0, 23),
+    (0x3fffe5, 22),
+    (0x3fffe6, 22),
+    (0x7ffff1, 23),
+    (0x3ffffe0, 26),
+    (0x3ffffe1, 26),
+    (0xfffeb, 20),
+    (0x7fff1, 19),
+    (0x3fffe7, 22),
+    (0x7ffff2, 23),
+    (0x3fffe8, 22),
+    (0x1ffffec, 25),
+    (0x3ffffe2, 26),
+    (0x3ffffe3, 26),
+    (0x3ffffe4, 26),
+    (0x7ffffde, 27),
+    (0x7ffffdf, 27),
+    (0x3ffffe5, 26),
+    (0xfffff1, 24),
+    (0x1ffffed, 25),
+    (0x7fff2, 19),
+    (0x1fffe3, 21),
+    (0x3ffffe6, 26),
+    (0x7ffffe0, 27),
+    (0x7ffffe1, 27),
+    (0x3ffffe7, 26),
+    (0x7ffffe2, 27),
+    (0xfffff2, 24),
+    (0x1fffe4, 21),
+    (0x1fffe5, 21),
+    (0x3ffffe8, 26),
+    (0x3ffffe9, 26),
+    (0xffffffd, 28),
+    (0x7ffffe3, 27),
+    (0x7ffffe4, 27),
+    (0x7ffffe5, 27),
+    (0xfffec, 20),
+    (0xfffff3, 24),
+    (0xfffed, 20),
+    (0x1fffe6, 21),
+    (0x3fffe9, 22),
+    (0x1fffe7, 21),
+    (0x1fffe8, 21),
+    (0x7ffff3, 23),
+    (0x3fffea, 22),
+    (0x3fffeb, 22),
+    (0x1ffffee, 25),
+    (0x1ffffef, 25),
+    (0xfffff4, 24),
+    (0xfffff5, 24),
+    (0x3ffffea, 26),
+    (0x7ffff4, 23),
+    (0x3ffffeb, 26),
+    (0x7ffffe6, 27),
+    (0x3ffffec, 26),
+    (0x3ffffed, 26),
+    (0x7ffffe7, 27),
+    (0x7ffffe8, 27),
+    (0x7ffffe9, 27),
+    (0x7ffffea, 27),
+    (0x7ffffeb, 27),
+    (0xffffffe, 28),
+    (0x7ffffec, 27),
+    (0x7ffffed, 27),
+    (0x7ffffee, 27),
+    (0x7ffffef, 27),
+    (0x7fffff0, 27),
+    (0x3ffffee, 26),
+    (0x3fffffff, 30),
+];
+
+lazy_static! {
+    static ref HUFFMAN_DECODE: HashMap<(u8, u32), u16> = HUFFMAN_CODES
+        .iter()
+        .enumerate()
+        .map(|(symbol, &(code, bits))| ((bits, code), symbol as u16))
+        .collect();
+}
+
+pub type HeaderList = Vec<(String, String)>;
+
+/// Decoder state for one direction of an HTTP/2 connection. Every header
+/// block sent in that direction has to go through the same decoder, in
+/// order, for the dynamic table to stay in sync with the peer.
+#[derive(Debug, Clone)]
+pub struct HpackDecoder {
+    dynamic_table: VecDeque<(String, String)>,
+    table_size: usize,
+    max_table_size: usize,
+}
+
+impl Default for HpackDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HpackDecoder {
+    pub fn new() -> Self {
+        Self {
+            dynamic_table: VecDeque::new(),
+            table_size: 0,
+            max_table_size: DEFAULT_TABLE_SIZE,
+        }
+    }
+
+    pub fn decode(&mut self, block: &[u8]) -> Result<HeaderList> {
+        let mut headers = Vec::new();
+        let mut pos = 0;
+
+        while pos < block.len() {
+            let first = block[pos];
+
+            if first & 0x80 != 0 {
+                let index = decode_integer(block, &mut pos, 7)?;
+                let (name, value) = self.lookup(index)?;
+                headers.push((name, value));
+            } else if first & 0xc0 == 0x40 {
+                let (name, value) = self.decode_literal(block, &mut pos, 6)?;
+                self.insert(name.clone(), value.clone());
+                headers.push((name, value));
+            } else if first & 0xe0 == 0x20 {
+                let size = decode_integer(block, &mut pos, 5)?;
+                self.max_table_size = size;
+                self.evict();
+            } else {
+                let (name, value) = self.decode_literal(block, &mut pos, 4)?;
+                headers.push((name, value));
+            }
+        }
+
+        Ok(headers)
+    }
+
+    fn decode_literal(
+        &self, block: &[u8], pos: &mut usize, prefix_bits: u8,
+    ) -> Result<(String, String)> {
+        let index = decode_integer(block, pos, prefix_bits)?;
+        let name = if index == 0 {
+            decode_string(block, pos)?
+        } else {
+            self.lookup(index)?.0
+        };
+        let value = decode_string(block, pos)?;
+
+        Ok((name, value))
+    }
+
+    fn lookup(&self, index: usize) -> Result<(String, String)> {
+        if index == 0 {
+            return Err(anyhow!("Invalid HPACK index 0"));
+        }
+
+        if let Some((name, value)) = STATIC_TABLE.get(index - 1) {
+            return Ok((name.to_string(), value.to_string()));
+        }
+
+        self.dynamic_table
+            .get(index - STATIC_TABLE.len() - 1)
+            .cloned()
+            .ok_or_else(|| anyhow!("HPACK index {} out of range", index))
+    }
+
+    fn insert(&mut self, name: String, value: String) {
+        let size = name.len() + value.len() + ENTRY_OVERHEAD;
+
+        if size > self.max_table_size {
+            self.dynamic_table.clear();
+            self.table_size = 0;
+            return;
+        }
+
+        self.table_size += size;
+        self.dynamic_table.push_front((name, value));
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.table_size > self.max_table_size {
+            match self.dynamic_table.pop_back() {
+                Some((name, value)) => {
+                    self.table_size -= name.len() + value.len() + ENTRY_OVERHEAD;
+                }
+                None => {
+                    self.table_size = 0;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn decode_integer(block: &[u8], pos: &mut usize, prefix_bits: u8) -> Result<usize> {
+    let mask = (1u16 << prefix_bits) as u8 - 1;
+    let first = *block
+        .get(*pos)
+        .ok_or_else(|| anyhow!("Truncated HPACK integer"))?;
+    *pos += 1;
+
+    let mut value = (first & mask) as usize;
+    if value < mask as usize {
+        return Ok(value);
+    }
+
+    let mut shift = 0;
+    loop {
+        let byte = *block
+            .get(*pos)
+            .ok_or_else(|| anyhow!("Truncated HPACK integer"))?;
+        *pos += 1;
+
+        if shift > 28 {
+            return Err(anyhow!("HPACK integer overflow"));
+        }
+
+        value += ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn decode_string(block: &[u8], pos: &mut usize) -> Result<String> {
+    let huffman = block
+        .get(*pos)
+        .map(|b| b & 0x80 != 0)
+        .ok_or_else(|| anyhow!("Truncated HPACK string"))?;
+    let len = decode_integer(block, pos, 7)?;
+
+    let end = pos
+        .checked_add(len)
+        .filter(|end| *end <= block.len())
+        .ok_or_else(|| anyhow!("HPACK string length {} exceeds header block", len))?;
+    let raw = &block[*pos..end];
+    *pos = end;
+
+    let bytes = if huffman {
+        decode_huffman(raw)?
+    } else {
+        raw.to_vec()
+    };
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn decode_huffman(data: &[u8]) -> Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(data.len() * 8 / 5);
+    let mut code: u32 = 0;
+    let mut bits: u8 = 0;
+
+    for byte in data {
+        for shift in (0..8).rev() {
+            code = (code << 1) | ((byte >> shift) & 1) as u32;
+            bits += 1;
+
+            if let Some(&symbol) = HUFFMAN_DECODE.get(&(bits, code)) {
+                if symbol == EOS_SYMBOL {
+                    return Err(anyhow!("Huffman string contains EOS symbol"));
+                }
+                output.push(symbol as u8);
+                code = 0;
+                bits = 0;
+            } else if bits >= 30 {
+                return Err(anyhow!("Invalid Huffman code"));
+            }
+        }
+    }
+
+    let padding_is_valid = bits < 8 && code == (1u32 << bits) - 1;
+    if !padding_is_valid {
+        return Err(anyhow!("Invalid Huffman padding"));
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        s.split_whitespace()
+            .map(|b| u8::from_str_radix(b, 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_requests_without_huffman() {
+        let mut decoder = HpackDecoder::new();
+
+        let first = decoder
+            .decode(&hex(
+                "82 86 84 41 0f 77 77 77 2e 65 78 61 6d 70 6c 65 2e 63 6f 6d",
+            ))
+            .unwrap();
+        assert_eq!(
+            first,
+            vec![
+                (":method".to_string(), "GET".to_string()),
+                (":scheme".to_string(), "http".to_string()),
+                (":path".to_string(), "/".to_string()),
+                (":authority".to_string(), "www.example.com".to_string()),
+            ]
+        );
+
+        let second = decoder
+            .decode(&hex("82 86 84 be 58 08 6e 6f 2d 63 61 63 68 65"))
+            .unwrap();
+        assert_eq!(second[3].1, "www.example.com");
+        assert_eq!(
+            second[4],
+            ("cache-control".to_string(), "no-cache".to_string())
+        );
+    }
+
+    #[test]
+    fn test_decode_huffman_request() {
+        let mut decoder = HpackDecoder::new();
+
+        let headers = decoder
+            .decode(&hex("82 86 84 41 8c f1 e3 c2 e5 f2 3a 6b a0 ab 90 f4 ff"))
+            .unwrap();
+        assert_eq!(
+            headers[3],
+            (":authority".to_string(), "www.example.com".to_string())
+        );
+
+        let headers = decoder
+            .decode(&hex("82 86 84 be 58 86 a8 eb 10 64 9c bf"))
+            .unwrap();
+        assert_eq!(headers[4].1, "no-cache");
+
+        let headers = decoder
+            .decode(&hex(
+                "82 87 85 bf 40 88 25 a8 49 e9 5b a9 7d 7f 89 25 a8 49 e9 5b b8 e8 b4 bf",
+            ))
+            .unwrap();
+        assert_eq!(headers[1].1, "https");
+        assert_eq!(headers[2].1, "/index.html");
+        assert_eq!(
+            headers[4],
+            ("custom-key".to_string(), "custom-value".to_string())
+        );
+    }
+
+    #[test]
+    fn test_invalid_index() {
+        let mut decoder = HpackDecoder::new();
+        assert!(decoder.decode(&[0x80]).is_err());
+        assert!(decoder.decode(&[0xff, 0x10]).is_err());
+    }
+}
@@ -0,0 +1,686 @@
+use std::collections::HashMap;
+
+use anyhow::{
+    anyhow,
+    Result,
+};
+use tracing::{
+    debug,
+    trace,
+};
+
+use crate::hpack::{
+    HeaderList,
+    HpackDecoder,
+};
+
+pub const HTTP2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+const FRAME_HEADER_LEN: usize = 9;
+const MAX_STREAM_BODY_SIZE: usize = 1024 * 1024;
+
+const FLAG_END_STREAM: u8 = 0x1;
+const FLAG_END_HEADERS: u8 = 0x4;
+const FLAG_PADDED: u8 = 0x8;
+const FLAG_PRIORITY: u8 = 0x20;
+
+/// Returns `None` while `buffer` is still a prefix of the client connection
+/// preface and more bytes are needed to decide.
+pub fn detect_http2(buffer: &[u8]) -> Option<bool> {
+    let len = buffer.len().min(HTTP2_PREFACE.len());
+
+    if buffer[..len] != HTTP2_PREFACE[..len] {
+        Some(false)
+    } else if len < HTTP2_PREFACE.len() {
+        None
+    } else {
+        Some(true)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    Data,
+    Headers,
+    Priority,
+    RstStream,
+    Settings,
+    PushPromise,
+    Ping,
+    GoAway,
+    WindowUpdate,
+    Continuation,
+    Unknown(u8),
+}
+
+impl From<u8> for FrameType {
+    fn from(value: u8) -> Self {
+        match value {
+            0x0 => FrameType::Data,
+            0x1 => FrameType::Headers,
+            0x2 => FrameType::Priority,
+            0x3 => FrameType::RstStream,
+            0x4 => FrameType::Settings,
+            0x5 => FrameType::PushPromise,
+            0x6 => FrameType::Ping,
+            0x7 => FrameType::GoAway,
+            0x8 => FrameType::WindowUpdate,
+            0x9 => FrameType::Continuation,
+            other => FrameType::Unknown(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub frame_type: FrameType,
+    pub flags: u8,
+    pub stream_id: u32,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    fn has_flag(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Strips the pad length byte and trailing padding from DATA, HEADERS
+    /// and PUSH_PROMISE payloads.
+    fn unpadded_payload(&self) -> Result<&[u8]> {
+        if !self.has_flag(FLAG_PADDED) {
+            return Ok(&self.payload);
+        }
+
+        let pad_len = *self
+            .payload
+            .first()
+            .ok_or_else(|| anyhow!("Padded frame without pad length"))?
+            as usize;
+        let end = self
+            .payload
+            .len()
+            .checked_sub(pad_len)
+            .filter(|end| *end >= 1)
+            .ok_or_else(|| anyhow!("Frame padding exceeds payload"))?;
+
+        Ok(&self.payload[1..end])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Http2Direction {
+    ClientToServer,
+    ServerToClient,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Http2Message {
+    pub headers: HeaderList,
+    pub body: Vec<u8>,
+    pub trailers: HeaderList,
+    pub truncated: bool,
+    pub reset_code: Option<u32>,
+}
+
+impl Http2Message {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn trailer(&self, name: &str) -> Option<&str> {
+        self.trailers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn method(&self) -> Option<&str> {
+        self.header(":method")
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        self.header(":path")
+    }
+
+    pub fn status(&self) -> Option<u16> {
+        self.header(":status").and_then(|s| s.parse().ok())
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.header("content-type")
+    }
+
+    fn append_body(&mut self, data: &[u8]) {
+        let remaining = MAX_STREAM_BODY_SIZE.saturating_sub(self.body.len());
+        if data.len() > remaining {
+            self.truncated = true;
+        }
+        self.body
+            .extend_from_slice(&data[..data.len().min(remaining)]);
+    }
+
+    fn is_informational(&self) -> bool {
+        self.status().is_some_and(|s| (100..200).contains(&s))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Http2Event {
+    Request {
+        stream_id: u32,
+        message: Http2Message,
+    },
+    Response {
+        stream_id: u32,
+        message: Http2Message,
+    },
+}
+
+pub fn error_code_name(code: u32) -> &'static str {
+    match code {
+        0x0 => "NO_ERROR",
+        0x1 => "PROTOCOL_ERROR",
+        0x2 => "INTERNAL_ERROR",
+        0x3 => "FLOW_CONTROL_ERROR",
+        0x4 => "SETTINGS_TIMEOUT",
+        0x5 => "STREAM_CLOSED",
+        0x6 => "FRAME_SIZE_ERROR",
+        0x7 => "REFUSED_STREAM",
+        0x8 => "CANCEL",
+        0x9 => "COMPRESSION_ERROR",
+        0xa => "CONNECT_ERROR",
+        0xb => "ENHANCE_YOUR_CALM",
+        0xc => "INADEQUATE_SECURITY",
+        0xd => "HTTP_1_1_REQUIRED",
+        _ => "UNKNOWN",
+    }
+}
+
+#[derive(Debug, Default)]
+struct PendingHeaderBlock {
+    stream_id: u32,
+    block: Vec<u8>,
+    end_stream: bool,
+    is_push_promise: bool,
+}
+
+#[derive(Debug)]
+struct FrameReader {
+    buffer: Vec<u8>,
+    awaiting_preface: bool,
+    hpack: HpackDecoder,
+    pending_headers: Option<PendingHeaderBlock>,
+}
+
+impl FrameReader {
+    fn new(awaiting_preface: bool) -> Self {
+        Self {
+            buffer: Vec::new(),
+            awaiting_preface,
+            hpack: HpackDecoder::new(),
+            pending_headers: None,
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) -> Result<Vec<Frame>> {
+        self.buffer.extend_from_slice(data);
+
+        if self.awaiting_preface {
+            match detect_http2(&self.buffer) {
+                Some(true) => {
+                    self.buffer.drain(..HTTP2_PREFACE.len());
+                    self.awaiting_preface = false;
+                }
+                Some(false) => return Err(anyhow!("Missing HTTP/2 connection preface")),
+                None => return Ok(Vec::new()),
+            }
+        }
+
+        let mut frames = Vec::new();
+        let mut offset = 0;
+
+        while self.buffer.len() - offset >= FRAME_HEADER_LEN {
+            let header = &self.buffer[offset..offset + FRAME_HEADER_LEN];
+            let length =
+                ((header[0] as usize) << 16) | ((header[1] as usize) << 8) | header[2] as usize;
+
+            if self.buffer.len() - offset < FRAME_HEADER_LEN + length {
+                break;
+            }
+
+            let stream_id =
+                u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & 0x7fff_ffff;
+            let payload_start = offset + FRAME_HEADER_LEN;
+
+            frames.push(Frame {
+                frame_type: FrameType::from(header[3]),
+                flags: header[4],
+                stream_id,
+                payload: self.buffer[payload_start..payload_start + length].to_vec(),
+            });
+
+            offset = payload_start + length;
+        }
+
+        self.buffer.drain(..offset);
+        Ok(frames)
+    }
+}
+
+#[derive(Debug, Default)]
+struct StreamState {
+    request: Http2Message,
+    response: Http2Message,
+    request_emitted: bool,
+    response_done: bool,
+}
+
+/// Reassembles the request and response of every stream on an HTTP/2
+/// connection from the raw bytes flowing in each direction.
+#[derive(Debug)]
+pub struct Http2Session {
+    client: FrameReader,
+    server: FrameReader,
+    streams: HashMap<u32, StreamState>,
+    failed: bool,
+}
+
+impl Default for Http2Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Http2Session {
+    pub fn new() -> Self {
+        Self {
+            client: FrameReader::new(true),
+            server: FrameReader::new(false),
+            streams: HashMap::new(),
+            failed: false,
+        }
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.failed
+    }
+
+    pub fn feed(&mut self, direction: Http2Direction, data: &[u8]) -> Result<Vec<Http2Event>> {
+        if self.failed {
+            return Ok(Vec::new());
+        }
+
+        let result = self.process(direction, data);
+        if result.is_err() {
+            // Once a frame or header block is lost the HPACK state can no
+            // longer be trusted, so stop decoding this connection.
+            self.failed = true;
+            self.streams.clear();
+        }
+
+        result
+    }
+
+    /// Flushes streams that were still open when the connection closed.
+    pub fn finish(&mut self) -> Vec<Http2Event> {
+        let mut stream_ids: Vec<u32> = self.streams.keys().copied().collect();
+        stream_ids.sort_unstable();
+
+        let mut events = Vec::new();
+        for stream_id in stream_ids {
+            self.complete_stream(stream_id, &mut events);
+        }
+
+        events
+    }
+
+    fn process(&mut self, direction: Http2Direction, data: &[u8]) -> Result<Vec<Http2Event>> {
+        let frames = match direction {
+            Http2Direction::ClientToServer => self.client.push(data)?,
+            Http2Direction::ServerToClient => self.server.push(data)?,
+        };
+
+        let mut events = Vec::new();
+        for frame in frames {
+            trace!(
+                "HTTP/2 {:?} frame {:?} on stream {} ({} bytes)",
+                direction,
+                frame.frame_type,
+                frame.stream_id,
+                frame.payload.len()
+            );
+            self.handle_frame(direction, frame, &mut events)?;
+        }
+
+        Ok(events)
+    }
+
+    fn reader(&mut self, direction: Http2Direction) -> &mut FrameReader {
+        match direction {
+            Http2Direction::ClientToServer => &mut self.client,
+            Http2Direction::ServerToClient => &mut self.server,
+        }
+    }
+
+    fn handle_frame(
+        &mut self, direction: Http2Direction, frame: Frame, events: &mut Vec<Http2Event>,
+    ) -> Result<()> {
+        if self.reader(direction).pending_headers.is_some()
+            && frame.frame_type != FrameType::Continuation
+        {
+            return Err(anyhow!(
+                "Expected CONTINUATION frame, got {:?}",
+                frame.frame_type
+            ));
+        }
+
+        match frame.frame_type {
+            FrameType::Headers => {
+                let mut fragment = frame.unpadded_payload()?;
+                if frame.has_flag(FLAG_PRIORITY) {
+                    fragment = fragment
+                        .get(5..)
+                        .ok_or_else(|| anyhow!("HEADERS frame too short for priority"))?;
+                }
+
+                let pending = PendingHeaderBlock {
+                    stream_id: frame.stream_id,
+                    block: fragment.to_vec(),
+                    end_stream: frame.has_flag(FLAG_END_STREAM),
+                    is_push_promise: false,
+                };
+                self.buffer_header_block(
+                    direction,
+                    pending,
+                    frame.has_flag(FLAG_END_HEADERS),
+                    events,
+                )?;
+            }
+            FrameType::PushPromise => {
+                let fragment = frame
+                    .unpadded_payload()?
+                    .get(4..)
+                    .ok_or_else(|| anyhow!("PUSH_PROMISE frame too short"))?;
+
+                let pending = PendingHeaderBlock {
+                    stream_id: frame.stream_id,
+                    block: fragment.to_vec(),
+                    end_stream: false,
+                    is_push_promise: true,
+                };
+                self.buffer_header_block(
+                    direction,
+                    pending,
+                    frame.has_flag(FLAG_END_HEADERS),
+                    events,
+                )?;
+            }
+            FrameType::Continuation => {
+                let reader = self.reader(direction);
+                let mut pending = reader
+                    .pending_headers
+                    .take()
+                    .ok_or_else(|| anyhow!("Unexpected CONTINUATION frame"))?;
+                pending.block.extend_from_slice(&frame.payload);
+                self.buffer_header_block(
+                    direction,
+                    pending,
+                    frame.has_flag(FLAG_END_HEADERS),
+                    events,
+                )?;
+            }
+            FrameType::Data => {
+                let data = frame.unpadded_payload()?;
+                let stream = self.streams.entry(frame.stream_id).or_default();
+                match direction {
+                    Http2Direction::ClientToServer => stream.request.append_body(data),
+                    Http2Direction::ServerToClient => stream.response.append_body(data),
+                }
+
+                if frame.has_flag(FLAG_END_STREAM) {
+                    self.end_stream(direction, frame.stream_id, events);
+                }
+            }
+            FrameType::RstStream => {
+                let code = frame
+                    .payload
+                    .get(..4)
+                    .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+                    .unwrap_or_default();
+                debug!(
+                    "HTTP/2 stream {} reset by {:?}: {}",
+                    frame.stream_id,
+                    direction,
+                    error_code_name(code)
+                );
+
+                if let Some(stream) = self.streams.get_mut(&frame.stream_id) {
+                    stream.response.reset_code = Some(code);
+                    self.complete_stream(frame.stream_id, events);
+                }
+            }
+            FrameType::GoAway => {
+                debug!("HTTP/2 GOAWAY received from {:?}", direction);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn buffer_header_block(
+        &mut self, direction: Http2Direction, pending: PendingHeaderBlock, end_headers: bool,
+        events: &mut Vec<Http2Event>,
+    ) -> Result<()> {
+        if !end_headers {
+            self.reader(direction).pending_headers = Some(pending);
+            return Ok(());
+        }
+
+        let headers = self.reader(direction).hpack.decode(&pending.block)?;
+        if pending.is_push_promise {
+            return Ok(());
+        }
+
+        let stream = self.streams.entry(pending.stream_id).or_default();
+        let message = match direction {
+            Http2Direction::ClientToServer => &mut stream.request,
+            Http2Direction::ServerToClient => &mut stream.response,
+        };
+
+        if message.headers.is_empty() || message.is_informational() {
+            message.headers = headers;
+        } else {
+            message.trailers.extend(headers);
+        }
+
+        if pending.end_stream {
+            self.end_stream(direction, pending.stream_id, events);
+        }
+
+        Ok(())
+    }
+
+    fn end_stream(
+        &mut self, direction: Http2Direction, stream_id: u32, events: &mut Vec<Http2Event>,
+    ) {
+        match direction {
+            Http2Direction::ClientToServer => self.emit_request(stream_id, events),
+            Http2Direction::ServerToClient => {
+                if let Some(stream) = self.streams.get_mut(&stream_id) {
+                    stream.response_done = true;
+                }
+                self.complete_stream(stream_id, events);
+            }
+        }
+    }
+
+    fn emit_request(&mut self, stream_id: u32, events: &mut Vec<Http2Event>) {
+        if let Some(stream) = self.streams.get_mut(&stream_id) {
+            if stream.request_emitted || stream.request.headers.is_empty() {
+                return;
+            }
+
+            stream.request_emitted = true;
+            events.push(Http2Event::Request {
+                stream_id,
+                message: std::mem::take(&mut stream.request),
+            });
+        }
+    }
+
+    fn complete_stream(&mut self, stream_id: u32, events: &mut Vec<Http2Event>) {
+        self.emit_request(stream_id, events);
+
+        if let Some(mut stream) = self.streams.remove(&stream_id) {
+            if !stream.request_emitted {
+                return;
+            }
+
+            if !stream.response_done && stream.response.reset_code.is_none() {
+                stream.response.truncated = true;
+            }
+
+            events.push(Http2Event::Response {
+                stream_id,
+                message: stream.response,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(frame_type: u8, flags: u8, stream_id: u32, payload: &[u8]) -> Vec<u8> {
+        let len = payload.len();
+        let mut out = vec![
+            (len >> 16) as u8,
+            (len >> 8) as u8,
+            len as u8,
+            frame_type,
+            flags,
+        ];
+        out.extend_from_slice(&stream_id.to_be_bytes());
+        out.extend_from_slice(payload);
+        out
+    }
+
+    fn literal(name: &str, value: &str) -> Vec<u8> {
+        let mut out = vec![0x00, name.len() as u8];
+        out.extend_from_slice(name.as_bytes());
+        out.push(value.len() as u8);
+        out.extend_from_slice(value.as_bytes());
+        out
+    }
+
+    #[test]
+    fn test_detect_http2() {
+        assert_eq!(detect_http2(HTTP2_PREFACE), Some(true));
+        assert_eq!(detect_http2(b"PRI * HTTP/2"), None);
+        assert_eq!(detect_http2(b"GET / HTTP/1.1\r\n"), Some(false));
+        assert_eq!(detect_http2(b"PUT /items HTTP/1.1\r\n"), Some(false));
+    }
+
+    #[test]
+    fn test_reassembles_stream() {
+        let mut session = Http2Session::new();
+
+        let mut client = HTTP2_PREFACE.to_vec();
+        client.extend(frame(0x4, 0, 0, &[]));
+        let mut block = vec![0x83, 0x86];
+        block.extend(literal(":path", "/echo.Echo/Say"));
+        client.extend(frame(0x1, FLAG_END_HEADERS, 1, &block));
+        client.extend(frame(0x0, FLAG_END_STREAM, 1, b"ping"));
+
+        let (first, second) = client.split_at(30);
+        assert!(session
+            .feed(Http2Direction::ClientToServer, first)
+            .unwrap()
+            .is_empty());
+        let events = session
+            .feed(Http2Direction::ClientToServer, second)
+            .unwrap();
+
+        match &events[..] {
+            [Http2Event::Request { stream_id, message }] => {
+                assert_eq!(*stream_id, 1);
+                assert_eq!(message.method(), Some("POST"));
+                assert_eq!(message.path(), Some("/echo.Echo/Say"));
+                assert_eq!(message.body, b"ping");
+            }
+            other => panic!("unexpected events: {:?}", other),
+        }
+
+        let mut server = frame(0x4, 0, 0, &[]);
+        let headers = [0x88].to_vec();
+        let mut continuation = literal("content-type", "application/grpc");
+        server.extend(frame(0x1, 0, 1, &headers));
+        server.extend(frame(0x9, FLAG_END_HEADERS, 1, &continuation));
+        server.extend(frame(
+            0x0,
+            FLAG_PADDED,
+            1,
+            &[2, b'p', b'o', b'n', b'g', 0, 0],
+        ));
+        continuation = literal("grpc-status", "0");
+        server.extend(frame(
+            0x1,
+            FLAG_END_HEADERS | FLAG_END_STREAM,
+            1,
+            &continuation,
+        ));
+
+        let events = session
+            .feed(Http2Direction::ServerToClient, &server)
+            .unwrap();
+        match &events[..] {
+            [Http2Event::Response { message, .. }] => {
+                assert_eq!(message.status(), Some(200));
+                assert_eq!(message.content_type(), Some("application/grpc"));
+                assert_eq!(message.body, b"pong");
+                assert_eq!(message.trailer("grpc-status"), Some("0"));
+                assert!(!message.truncated);
+            }
+            other => panic!("unexpected events: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reset_and_finish() {
+        let mut session = Http2Session::new();
+
+        let mut client = HTTP2_PREFACE.to_vec();
+        client.extend(frame(0x1, FLAG_END_HEADERS, 1, &[0x82, 0x84]));
+        client.extend(frame(0x1, FLAG_END_HEADERS, 3, &[0x82, 0x84]));
+        client.extend(frame(0x3, 0, 1, &8u32.to_be_bytes()));
+
+        let events = session
+            .feed(Http2Direction::ClientToServer, &client)
+            .unwrap();
+        assert_eq!(events.len(), 2);
+        match &events[1] {
+            Http2Event::Response { stream_id, message } => {
+                assert_eq!(*stream_id, 1);
+                assert_eq!(message.reset_code, Some(8));
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        let events = session.finish();
+        assert!(matches!(
+            &events[..],
+            [Http2Event::Request { stream_id: 3, .. }, Http2Event::Response { stream_id: 3, message }]
+                if message.truncated
+        ));
+
+        assert!(session
+            .feed(Http2Direction::ClientToServer, &frame(0x9, 0, 5, &[]))
+            .is_err());
+        assert!(session.is_failed());
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+use tracing::{
+    debug,
+    error,
+};
+
+use crate::filter::LogFilter;
+use crate::http2::{
+    Http2Direction,
+    Http2Event,
+    Http2Session,
+};
+use crate::HttpLogger;
+
+struct LoggedStream {
+    request_id: String,
+    path: String,
+}
+
+#[derive(Default)]
+struct ConnectionState {
+    session: Http2Session,
+    streams: HashMap<u32, Option<LoggedStream>>,
+}
+
+/// Logs every stream of a single HTTP/2 connection. Both directions of the
+/// connection must be fed through the same handler.
+pub struct Http2ConnectionHandler {
+    logger: HttpLogger,
+    filter: Arc<LogFilter>,
+    state: Mutex<ConnectionState>,
+}
+
+impl Http2ConnectionHandler {
+    pub fn new(logger: HttpLogger, filter: Arc<LogFilter>) -> Self {
+        Self {
+            logger,
+            filter,
+            state: Mutex::new(ConnectionState::default()),
+        }
+    }
+
+    pub async fn handle_client_data(&self, data: &[u8]) {
+        self.handle_data(Http2Direction::ClientToServer, data).await;
+    }
+
+    pub async fn handle_server_data(&self, data: &[u8]) {
+        self.handle_data(Http2Direction::ServerToClient, data).await;
+    }
+
+    pub async fn finish(&self) {
+        let mut state = self.state.lock().await;
+        let events = state.session.finish();
+        self.log_events(&mut state, events).await;
+    }
+
+    async fn handle_data(&self, direction: Http2Direction, data: &[u8]) {
+        let mut state = self.state.lock().await;
+
+        match state.session.feed(direction, data) {
+            Ok(events) => self.log_events(&mut state, events).await,
+            Err(e) => error!("Stopped decoding HTTP/2 connection: {:?}", e),
+        }
+    }
+
+    async fn log_events(&self, state: &mut ConnectionState, events: Vec<Http2Event>) {
+        for event in events {
+            match event {
+                Http2Event::Request { stream_id, message } => {
+                    let method = message.method().unwrap_or_default();
+                    let path = message.path().unwrap_or_default();
+
+                    let logged =
+                        if self.filter.matches_request(method, path) && self.filter.sample() {
+                            let request_id = self
+                                .logger
+                                .log_http2_request(
+                                    stream_id,
+                                    &message,
+                                    self.filter.has_response_rules(),
+                                )
+                                .await;
+                            Some(LoggedStream {
+                                request_id,
+                                path: path.to_string(),
+                            })
+                        } else {
+                            debug!("HTTP/2 stream {} skipped by HTTP log filter", stream_id);
+                            None
+                        };
+
+                    state.streams.insert(stream_id, logged);
+                }
+                Http2Event::Response { stream_id, message } => {
+                    let Some(Some(stream)) = state.streams.remove(&stream_id) else {
+                        continue;
+                    };
+
+                    if self
+                        .filter
+                        .matches_response(message.status(), message.content_type())
+                    {
+                        self.logger
+                            .log_http2_response(
+                                stream_id,
+                                &message,
+                                &stream.path,
+                                stream.request_id,
+                            )
+                            .await;
+                    } else {
+                        debug!(
+                            "HTTP/2 stream {} response skipped by HTTP log filter",
+                            stream_id
+                        );
+                        self.logger.discard_request(&stream.request_id);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,21 +1,81 @@
+pub mod cassette;
 pub mod config;
+pub mod filter;
 pub mod formatter;
+pub mod grpc;
+pub mod hpack;
+pub mod http2;
+pub mod http2_handler;
 pub mod http_request_handler;
 pub mod http_response_analyzer;
 pub mod http_response_handler;
 pub mod logger;
 pub mod message;
+pub mod mock;
 pub mod models;
 pub mod parser;
+pub mod protocol_handler;
+pub mod protocols;
+pub mod redaction;
+pub mod retention;
 pub mod state;
+pub mod websocket;
+pub mod websocket_handler;
 
+pub use cassette::{
+    Cassette,
+    CassetteConfig,
+    CassetteMode,
+    CassettePlayer,
+    CassetteRecorder,
+};
 pub use config::LogConfig;
+pub use filter::{
+    LogFilter,
+    LogFilterConfig,
+};
+pub use grpc::GrpcDecoder;
+pub use http2::{
+    Http2Direction,
+    Http2Event,
+    Http2Message,
+    Http2Session,
+};
+pub use http2_handler::Http2ConnectionHandler;
 pub use http_request_handler::HttpRequestHandler;
 pub use http_response_analyzer::HttpResponseAnalyzer;
 pub use http_response_handler::HttpResponseHandler;
 pub use logger::HttpLogger;
+pub use mock::{
+    HeaderRewrite,
+    MockDecision,
+    MockEngine,
+    MockRule,
+    MockRulesConfig,
+};
 pub use models::HttpLogState;
+pub use protocol_handler::ProtocolConnectionHandler;
+pub use protocols::{
+    LogProtocol,
+    ProtocolDecoder,
+    ProtocolEvent,
+};
+pub use redaction::{
+    RedactionConfig,
+    RedactionRules,
+};
+pub use retention::{
+    ConfigLogUsage,
+    LogUsageReport,
+    PruneReport,
+    RetentionManager,
+};
 pub use state::{
     LogState,
     LogStateManager,
 };
+pub use websocket::{
+    FrameDirection,
+    WebSocketMessage,
+};
+pub use websocket_handler::WebSocketConnectionHandler;
@@ -1,4 +1,7 @@
-use std::path::PathBuf;
+use std::path::{
+    Path,
+    PathBuf,
+};
 use std::sync::Arc;
 
 use anyhow::{
@@ -14,16 +17,12 @@ use chrono::{
     DateTime,
     Utc,
 };
-use dashmap::DashMap;
-use lazy_static::lazy_static;
-use tokio::fs::{
-    File,
-    OpenOptions,
-};
-use tokio::io::{
-    AsyncWriteExt,
-    BufWriter,
+use dashmap::{
+    DashMap,
+    DashSet,
 };
+use lazy_static::lazy_static;
+use tokio::io::AsyncWriteExt;
 use tokio::sync::mpsc::{
     self,
     Sender,
@@ -37,13 +36,28 @@ use tracing::{
 };
 use uuid::Uuid;
 
+use crate::cassette::CassetteRecorder;
 use crate::config::LogConfig;
 use crate::formatter::MessageFormatter;
+use crate::grpc::GrpcDecoder;
+use crate::http2::Http2Message;
 use crate::message::LogMessage;
 use crate::models::{
     calculate_time_diff,
     TraceInfo,
 };
+use crate::redaction::{
+    RedactionConfig,
+    RedactionRules,
+};
+use crate::retention::{
+    LogWriter,
+    RetentionManager,
+};
+use crate::websocket::{
+    FrameDirection,
+    WebSocketMessage,
+};
 
 lazy_static! {
     static ref BUFFER_POOL: Arc<tokio::sync::Mutex<Vec<BytesMut>>> =
@@ -57,11 +71,21 @@ const TRACE_CLEANUP_INTERVAL_SECS: u64 = 5;
 const TRACE_EXPIRY_SECS: i64 = 1800;
 
 type TraceMap = Arc<DashMap<String, TraceInfo>>;
+type PendingRequestMap = Arc<DashMap<String, LogMessage>>;
+type RecordedRequestMap = Arc<DashMap<String, Bytes>>;
+type SkippedRequestSet = Arc<DashSet<String>>;
 
 #[derive(Clone, Debug)]
 pub struct HttpLogger {
     log_sender: Sender<LogMessage>,
     trace_map: TraceMap,
+    pending_requests: PendingRequestMap,
+    recorded_requests: RecordedRequestMap,
+    /// Requests the log filter skipped, kept only for the cassette
+    skipped_requests: SkippedRequestSet,
+    cassette: Option<Arc<CassetteRecorder>>,
+    redaction: Arc<RedactionRules>,
+    grpc: Arc<GrpcDecoder>,
     shutdown: Arc<tokio::sync::watch::Sender<()>>,
     #[allow(dead_code)]
     config: LogConfig,
@@ -73,24 +97,35 @@ pub struct HttpLogger {
 
 impl HttpLogger {
     pub async fn new(log_config: LogConfig, log_file_path: PathBuf) -> Result<Self> {
+        let redaction = Arc::new(RedactionRules::compile(log_config.redaction())?);
+        let grpc = Arc::new(GrpcDecoder::from_config(log_config.grpc_descriptor_set()));
+        let cassette = log_config
+            .cassette()
+            .map(CassetteRecorder::shared)
+            .transpose()?;
         let (log_sender, mut log_receiver) = mpsc::channel::<LogMessage>(CHANNEL_CAPACITY);
 
-        let log_file = Arc::new(RwLock::new(BufWriter::with_capacity(
-            64 * 1024,
-            OpenOptions::new()
-                .append(true)
-                .create(true)
-                .open(&log_file_path)
-                .await
-                .context("Failed to open log file")?,
-        )));
+        let retention = RetentionManager::new(log_config.clone());
+        RetentionManager::ensure_background_task(&log_config);
+
+        if retention.needs_rotation(&log_file_path).await? {
+            if let Err(e) = retention.rotate_and_compress(&log_file_path).await {
+                error!("Failed to rotate existing log file: {:?}", e);
+            }
+        }
+
+        let log_file = Arc::new(RwLock::new(LogWriter::open(&log_file_path).await?));
 
         let trace_map: TraceMap = Arc::new(DashMap::with_capacity(1024));
+        let pending_requests: PendingRequestMap = Arc::new(DashMap::new());
+        let recorded_requests: RecordedRequestMap = Arc::new(DashMap::new());
+        let skipped_requests: SkippedRequestSet = Arc::new(DashSet::new());
         let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(());
         let mut shutdown_rx_writer = shutdown_rx.clone();
 
         let writer_task = tokio::spawn({
             let log_file = log_file.clone();
+            let log_file_path = log_file_path.clone();
             async move {
                 let mut flush_interval =
                     tokio::time::interval(Duration::from_millis(FLUSH_INTERVAL_MS));
@@ -124,6 +159,10 @@ impl HttpLogger {
                                 } else {
                                     debug!("Successfully wrote log batch");
 
+                                    if let Err(e) = Self::rotate_if_needed(&log_file, &log_file_path, &retention).await {
+                                        error!("Failed to rotate log file: {:?}", e);
+                                    }
+
                                     if is_response {
                                         if let Ok(mut file) = log_file.try_write() {
                                             if let Err(e) = file.get_mut().sync_data().await {
@@ -145,6 +184,8 @@ impl HttpLogger {
 
                                 if let Err(e) = Self::write_log_batch(&log_file, &message_batch).await {
                                     error!("Failed to write log batch: {:?}", e);
+                                } else if let Err(e) = Self::rotate_if_needed(&log_file, &log_file_path, &retention).await {
+                                    error!("Failed to rotate log file: {:?}", e);
                                 }
                                 message_batch.clear();
                                 last_flush = now;
@@ -196,6 +237,8 @@ impl HttpLogger {
 
         let cleanup_task = tokio::spawn({
             let trace_map = trace_map.clone();
+            let pending_requests = pending_requests.clone();
+            let recorded_requests = recorded_requests.clone();
             async move {
                 let mut interval =
                     tokio::time::interval(Duration::from_secs(TRACE_CLEANUP_INTERVAL_SECS));
@@ -206,6 +249,8 @@ impl HttpLogger {
                             trace_map.retain(|_, trace_info| {
                                 now.signed_duration_since(trace_info.timestamp).num_seconds() < TRACE_EXPIRY_SECS
                             });
+                            pending_requests.retain(|request_id, _| trace_map.contains_key(request_id));
+                            recorded_requests.retain(|request_id, _| trace_map.contains_key(request_id));
                         }
                         _ = shutdown_rx.changed() => {
                             debug!("Shutting down cleanup task");
@@ -222,6 +267,12 @@ impl HttpLogger {
         Ok(Self {
             log_sender,
             trace_map,
+            pending_requests,
+            recorded_requests,
+            skipped_requests,
+            cassette,
+            redaction,
+            grpc,
             shutdown: Arc::new(shutdown_tx),
             config: log_config,
             writer_task: writer_task_handle,
@@ -230,7 +281,21 @@ impl HttpLogger {
     }
 
     pub async fn for_config(config_id: i64, local_port: u16) -> Result<Self> {
-        let log_config = LogConfig::new(LogConfig::default_log_directory()?);
+        Self::for_config_with_redaction(config_id, local_port, RedactionConfig::default()).await
+    }
+
+    pub async fn for_config_with_redaction(
+        config_id: i64, local_port: u16, redaction: RedactionConfig,
+    ) -> Result<Self> {
+        let log_config = LogConfig::builder(LogConfig::default_log_directory()?)
+            .redaction(redaction)
+            .build();
+        Self::for_config_with_log_config(config_id, local_port, log_config).await
+    }
+
+    pub async fn for_config_with_log_config(
+        config_id: i64, local_port: u16, log_config: LogConfig,
+    ) -> Result<Self> {
         let log_path = log_config
             .create_log_file_path(config_id, local_port)
             .await?;
@@ -241,6 +306,7 @@ impl HttpLogger {
         let request_id = Uuid::new_v4().to_string();
         let timestamp = Utc::now();
         let trace_id = request_id.clone();
+        self.keep_for_cassette(&request_id, &buffer);
 
         if let Err(e) = self
             .send_request_log(buffer, trace_id.clone(), timestamp)
@@ -260,7 +326,246 @@ impl HttpLogger {
         request_id
     }
 
+    pub async fn log_request_deferred(&self, buffer: Bytes) -> String {
+        let request_id = Uuid::new_v4().to_string();
+        let timestamp = Utc::now();
+        self.keep_for_cassette(&request_id, &buffer);
+
+        match MessageFormatter::format_request(&buffer, &request_id, timestamp, &self.redaction)
+            .await
+        {
+            Ok(log_entry) => {
+                self.pending_requests.insert(request_id.clone(), log_entry);
+            }
+            Err(e) => error!("Failed to format deferred request: {:?}", e),
+        }
+
+        self.trace_map.insert(
+            request_id.clone(),
+            TraceInfo {
+                trace_id: request_id.clone(),
+                timestamp,
+            },
+        );
+
+        request_id
+    }
+
+    pub async fn log_http2_request(
+        &self, stream_id: u32, message: &Http2Message, deferred: bool,
+    ) -> String {
+        let request_id = Uuid::new_v4().to_string();
+        let timestamp = Utc::now();
+
+        match MessageFormatter::format_http2_request(
+            message,
+            stream_id,
+            &request_id,
+            timestamp,
+            &self.redaction,
+            &self.grpc,
+        )
+        .await
+        {
+            Ok(log_entry) if deferred => {
+                self.pending_requests.insert(request_id.clone(), log_entry);
+            }
+            Ok(log_entry) => {
+                if let Err(e) = self.log_sender.send(log_entry).await {
+                    error!("Failed to send HTTP/2 request log message: {:?}", e);
+                }
+            }
+            Err(e) => error!("Failed to format HTTP/2 request: {:?}", e),
+        }
+
+        self.trace_map.insert(
+            request_id.clone(),
+            TraceInfo {
+                trace_id: request_id.clone(),
+                timestamp,
+            },
+        );
+
+        request_id
+    }
+
+    pub async fn log_http2_response(
+        &self, stream_id: u32, message: &Http2Message, request_path: &str, request_id: String,
+    ) {
+        if let Some((_, pending_request)) = self.pending_requests.remove(&request_id) {
+            if let Err(e) = self.log_sender.send(pending_request).await {
+                error!("Failed to send deferred request log message: {:?}", e);
+            }
+        }
+
+        let timestamp = Utc::now();
+        let took_ms = self
+            .trace_map
+            .get(&request_id)
+            .map(|trace_info| calculate_time_diff(trace_info.timestamp, timestamp))
+            .unwrap_or(0);
+
+        let result = MessageFormatter::format_http2_response(
+            message,
+            stream_id,
+            request_path,
+            &request_id,
+            timestamp,
+            took_ms,
+            &self.redaction,
+            &self.grpc,
+        )
+        .await;
+
+        match result {
+            Ok(log_entry) => {
+                if let Err(e) = self.log_sender.send(log_entry).await {
+                    error!("Failed to send HTTP/2 response log message: {:?}", e);
+                }
+            }
+            Err(e) => error!("Failed to format HTTP/2 response: {:?}", e),
+        }
+    }
+
+    pub async fn log_websocket_message(
+        &self, trace_id: &str, direction: FrameDirection, message: &WebSocketMessage,
+    ) {
+        let log_entry = MessageFormatter::format_websocket_message(
+            message,
+            direction,
+            trace_id,
+            &self.redaction,
+        );
+
+        if let Err(e) = self.log_sender.send(log_entry).await {
+            error!("Failed to send WebSocket log message: {:?}", e);
+        }
+    }
+
+    pub async fn log_protocol_command(
+        &self, protocol: &str, summary: &str, detail: Option<&str>,
+    ) -> String {
+        let request_id = Uuid::new_v4().to_string();
+        let timestamp = Utc::now();
+
+        let log_entry = MessageFormatter::format_protocol_command(
+            protocol,
+            summary,
+            detail,
+            &request_id,
+            timestamp,
+            &self.redaction,
+        );
+        if let Err(e) = self.log_sender.send(log_entry).await {
+            error!("Failed to send {} command log message: {:?}", protocol, e);
+        }
+
+        self.trace_map.insert(
+            request_id.clone(),
+            TraceInfo {
+                trace_id: request_id.clone(),
+                timestamp,
+            },
+        );
+
+        request_id
+    }
+
+    pub async fn log_protocol_reply(
+        &self, protocol: &str, summary: &str, is_error: bool, request_id: &str,
+    ) {
+        let timestamp = Utc::now();
+        let took_ms = self
+            .trace_map
+            .remove(request_id)
+            .map(|(_, trace_info)| calculate_time_diff(trace_info.timestamp, timestamp))
+            .unwrap_or(0);
+
+        let log_entry = MessageFormatter::format_protocol_reply(
+            protocol,
+            summary,
+            is_error,
+            request_id,
+            timestamp,
+            took_ms,
+            &self.redaction,
+        );
+        if let Err(e) = self.log_sender.send(log_entry).await {
+            error!("Failed to send {} reply log message: {:?}", protocol, e);
+        }
+    }
+
+    pub async fn log_protocol_info(&self, protocol: &str, info: &str) {
+        let log_entry =
+            MessageFormatter::format_protocol_info(protocol, info, Utc::now(), &self.redaction);
+
+        if let Err(e) = self.log_sender.send(log_entry).await {
+            error!("Failed to send {} log message: {:?}", protocol, e);
+        }
+    }
+
+    pub fn discard_request(&self, request_id: &str) {
+        if self.pending_requests.remove(request_id).is_some() {
+            debug!("Discarded deferred request log for ID: {}", request_id);
+        }
+        self.recorded_requests.remove(request_id);
+        self.skipped_requests.remove(request_id);
+        self.trace_map.remove(request_id);
+    }
+
+    /// Keeps a request the log filter skipped so it still reaches the
+    /// cassette, returning the id to pass along with its response. `None`
+    /// when not recording.
+    pub fn skip_request(&self, buffer: Bytes) -> Option<String> {
+        self.cassette.as_ref()?;
+
+        let request_id = Uuid::new_v4().to_string();
+        self.keep_for_cassette(&request_id, &buffer);
+        self.skipped_requests.insert(request_id.clone());
+        Some(request_id)
+    }
+
+    /// Whether a request was skipped by the log filter and is only kept for
+    /// the cassette.
+    pub fn is_skipped(&self, request_id: &str) -> bool {
+        self.skipped_requests.contains(request_id)
+    }
+
+    /// Records a response the log filter skipped to the cassette, then
+    /// forgets its request.
+    pub async fn skip_response(&self, buffer: Bytes, request_id: &str) {
+        self.record_to_cassette(request_id, &buffer).await;
+        self.discard_request(request_id);
+    }
+
+    fn keep_for_cassette(&self, request_id: &str, buffer: &Bytes) {
+        if self.cassette.is_some() {
+            self.recorded_requests
+                .insert(request_id.to_string(), buffer.clone());
+        }
+    }
+
+    async fn record_to_cassette(&self, request_id: &str, response: &Bytes) {
+        let Some(cassette) = &self.cassette else {
+            return;
+        };
+
+        if let Some((_, request)) = self.recorded_requests.remove(request_id) {
+            if let Err(e) = cassette.record(&request, response, &self.redaction).await {
+                error!("Failed to record interaction to cassette: {:?}", e);
+            }
+        }
+    }
+
     pub async fn log_response(&self, buffer: Bytes, request_id: String) {
+        if let Some((_, pending_request)) = self.pending_requests.remove(&request_id) {
+            if let Err(e) = self.log_sender.send(pending_request).await {
+                error!("Failed to send deferred request log message: {:?}", e);
+            }
+        }
+
+        self.record_to_cassette(&request_id, &buffer).await;
+
         let timestamp = Utc::now();
         let is_preformatted = buffer.len() > 5 && &buffer[0..5] == b"HTTP/";
 
@@ -281,6 +586,42 @@ impl HttpLogger {
         }
     }
 
+    pub async fn log_mocked_response(&self, buffer: Bytes, request_id: String, rule: &str) {
+        if let Some((_, pending_request)) = self.pending_requests.remove(&request_id) {
+            if let Err(e) = self.log_sender.send(pending_request).await {
+                error!("Failed to send deferred request log message: {:?}", e);
+            }
+        }
+
+        self.recorded_requests.remove(&request_id);
+
+        let timestamp = Utc::now();
+        let took_ms = self
+            .trace_map
+            .remove(&request_id)
+            .map(|(_, trace_info)| calculate_time_diff(trace_info.timestamp, timestamp))
+            .unwrap_or(0);
+
+        let result = MessageFormatter::format_mocked_response(
+            &buffer,
+            &request_id,
+            timestamp,
+            took_ms,
+            rule,
+            &self.redaction,
+        )
+        .await;
+
+        match result {
+            Ok(log_entry) => {
+                if let Err(e) = self.log_sender.send(log_entry).await {
+                    error!("Failed to send mocked response log message: {:?}", e);
+                }
+            }
+            Err(e) => error!("Failed to format mocked response: {:?}", e),
+        }
+    }
+
     async fn send_response_log_internal(
         &self, buffer: Bytes, request_id: String, timestamp: DateTime<Utc>, took_ms: i64,
         is_preformatted: bool,
@@ -303,7 +644,9 @@ impl HttpLogger {
     ) -> Result<()> {
         debug!("Formatting request log for trace ID: {}", trace_id);
 
-        let log_entry = MessageFormatter::format_request(&buffer, &trace_id, timestamp).await?;
+        let log_entry =
+            MessageFormatter::format_request(&buffer, &trace_id, timestamp, &self.redaction)
+                .await?;
 
         match self.log_sender.send(log_entry).await {
             Ok(_) => debug!("Successfully sent request log message to channel"),
@@ -329,8 +672,14 @@ impl HttpLogger {
             debug!("Response doesn't appear to be a valid HTTP response, but will log anyway");
         }
 
-        let log_entry =
-            MessageFormatter::format_response(&buffer, &trace_id, timestamp, took_ms).await?;
+        let log_entry = MessageFormatter::format_response(
+            &buffer,
+            &trace_id,
+            timestamp,
+            took_ms,
+            &self.redaction,
+        )
+        .await?;
 
         let message_size = log_entry.size();
         match self.log_sender.send(log_entry).await {
@@ -356,7 +705,13 @@ impl HttpLogger {
     async fn send_preformatted_response_log(
         &self, buffer: Bytes, trace_id: String, timestamp: DateTime<Utc>, took_ms: i64,
     ) -> Result<()> {
-        let message = LogMessage::new_preformatted_response(trace_id, timestamp, took_ms, buffer);
+        let message = LogMessage::new_preformatted_response(
+            trace_id,
+            timestamp,
+            took_ms,
+            buffer,
+            &self.redaction,
+        );
 
         if let Err(e) = self.log_sender.send(message).await {
             error!("Failed to send preformatted response log message: {:?}", e);
@@ -375,7 +730,7 @@ impl HttpLogger {
     }
 
     async fn write_log_batch(
-        log_file: &Arc<RwLock<BufWriter<File>>>, messages: &[LogMessage],
+        log_file: &Arc<RwLock<LogWriter>>, messages: &[LogMessage],
     ) -> Result<()> {
         if messages.is_empty() {
             return Ok(());
@@ -451,8 +806,40 @@ impl HttpLogger {
         Ok(())
     }
 
+    async fn rotate_if_needed(
+        log_file: &Arc<RwLock<LogWriter>>, log_file_path: &Path, retention: &RetentionManager,
+    ) -> Result<()> {
+        if !retention.exceeds_max_log_size(log_file.read().await.written()) {
+            return Ok(());
+        }
+
+        let mut log_file = log_file.write().await;
+        log_file
+            .flush()
+            .await
+            .context("Failed to flush log file before rotation")?;
+
+        let rotated_path = retention.rotate(log_file_path).await?;
+        *log_file = LogWriter::open(log_file_path).await?;
+        drop(log_file);
+
+        debug!("Log file rotated to {}", rotated_path.display());
+
+        let retention = retention.clone();
+        tokio::spawn(async move {
+            if let Err(e) = retention.compress(&rotated_path).await {
+                error!("Failed to compress rotated log file: {:?}", e);
+            }
+            if let Err(e) = retention.enforce_quotas().await {
+                error!("Failed to enforce HTTP log quota: {:?}", e);
+            }
+        });
+
+        Ok(())
+    }
+
     async fn write_single_log(
-        log_file: &Arc<RwLock<BufWriter<File>>>, message: &LogMessage,
+        log_file: &Arc<RwLock<LogWriter>>, message: &LogMessage,
     ) -> Result<()> {
         let mut log_file = log_file.write().await;
         trace!(
@@ -482,6 +869,11 @@ impl HttpLogger {
         debug!("Initiating HTTP logger shutdown sequence");
 
         let _ = self.flush().await;
+        if let Some(cassette) = &self.cassette {
+            if let Err(e) = cassette.flush().await {
+                error!("Failed to save cassette: {:?}", e);
+            }
+        }
 
         let shutdown_signal = self.shutdown.clone();
 
@@ -527,3 +919,68 @@ impl HttpLogger {
         debug!("HTTP logger shutdown sequence completed");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::cassette::Cassette;
+
+    #[tokio::test]
+    async fn test_skipped_requests_reach_the_cassette() {
+        let temp_dir = TempDir::new().unwrap();
+        let cassette_path = temp_dir.path().join("cassette.json");
+        let config = LogConfig::builder(temp_dir.path().to_path_buf())
+            .cassette(Some(cassette_path.clone()))
+            .build();
+        let logger = HttpLogger::new(config, temp_dir.path().join("1_8080.http"))
+            .await
+            .unwrap();
+
+        let logged = logger
+            .log_request(Bytes::from_static(b"GET /logged HTTP/1.1\r\n\r\n"))
+            .await;
+        let skipped = logger
+            .skip_request(Bytes::from_static(b"GET /skipped HTTP/1.1\r\n\r\n"))
+            .unwrap();
+        assert!(!logger.is_skipped(&logged));
+        assert!(logger.is_skipped(&skipped));
+
+        logger
+            .skip_response(
+                Bytes::from_static(b"HTTP/1.1 204 No Content\r\n\r\n"),
+                &skipped,
+            )
+            .await;
+        logger
+            .log_response(Bytes::from_static(b"HTTP/1.1 200 OK\r\n\r\n"), logged)
+            .await;
+        assert!(!logger.is_skipped(&skipped));
+        logger.shutdown().await;
+
+        let cassette = Cassette::load(&cassette_path).unwrap();
+        let paths: Vec<&str> = cassette
+            .interactions
+            .iter()
+            .map(|interaction| interaction.path.as_str())
+            .collect();
+        assert_eq!(paths, ["/skipped", "/logged"]);
+        CassetteRecorder::release(&cassette_path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_skip_request_without_cassette() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = LogConfig::builder(temp_dir.path().to_path_buf()).build();
+        let logger = HttpLogger::new(config, temp_dir.path().join("1_8080.http"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            logger.skip_request(Bytes::from_static(b"GET / HTTP/1.1\r\n\r\n")),
+            None
+        );
+        logger.shutdown().await;
+    }
+}
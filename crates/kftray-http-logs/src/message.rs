@@ -5,6 +5,7 @@ use chrono::{
 };
 
 use crate::formatter::MessageFormatter;
+use crate::redaction::RedactionRules;
 
 #[derive(Debug, Clone)]
 pub enum HttpMessage {
@@ -26,6 +27,8 @@ pub enum LogMessage {
     Request(String),
     Response(String),
     PreformattedResponse(String),
+    WebSocketFrame(String),
+    ProtocolEvent(String),
     TriggerFlush,
 }
 
@@ -35,6 +38,8 @@ impl LogMessage {
             LogMessage::Request(log) => log.as_bytes(),
             LogMessage::Response(log) => log.as_bytes(),
             LogMessage::PreformattedResponse(log) => log.as_bytes(),
+            LogMessage::WebSocketFrame(log) => log.as_bytes(),
+            LogMessage::ProtocolEvent(log) => log.as_bytes(),
             LogMessage::TriggerFlush => &[],
         }
     }
@@ -51,6 +56,8 @@ impl LogMessage {
             LogMessage::Request(_) => "Request",
             LogMessage::Response(_) => "Response",
             LogMessage::PreformattedResponse(_) => "PreformattedResponse",
+            LogMessage::WebSocketFrame(_) => "WebSocketFrame",
+            LogMessage::ProtocolEvent(_) => "ProtocolEvent",
             LogMessage::TriggerFlush => "TriggerFlush",
         }
     }
@@ -68,9 +75,11 @@ impl LogMessage {
 
     pub fn new_preformatted_response(
         trace_id: String, timestamp: DateTime<Utc>, took_ms: i64, buffer: Bytes,
+        redaction: &RedactionRules,
     ) -> Self {
-        let formatted =
-            MessageFormatter::format_preformatted_response(&trace_id, timestamp, took_ms, &buffer);
+        let formatted = MessageFormatter::format_preformatted_response(
+            &trace_id, timestamp, took_ms, &buffer, redaction,
+        );
 
         LogMessage::PreformattedResponse(formatted)
     }
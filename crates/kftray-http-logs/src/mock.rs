@@ -0,0 +1,486 @@
+use std::collections::BTreeMap;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::time::Duration;
+
+use anyhow::{
+    Context,
+    Result,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tracing::trace;
+
+use crate::filter::glob_match;
+use crate::formatter::MessageFormatter;
+use crate::parser::RequestParser;
+
+pub const MOCK_HEADER: &str = "X-Kftray-Mock";
+
+const CHUNKED_TERMINATOR: &[u8] = b"0\r\n\r\n";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MockMatch {
+    pub method: Option<String>,
+    pub path: Option<String>,
+    /// Header name to glob on its value, all of which must match.
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<String>,
+    pub body_file: Option<PathBuf>,
+}
+
+impl Default for MockResponse {
+    fn default() -> Self {
+        Self {
+            status: 200,
+            headers: BTreeMap::new(),
+            body: None,
+            body_file: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeaderRewrite {
+    pub set: BTreeMap<String, String>,
+    pub remove: Vec<String>,
+}
+
+impl HeaderRewrite {
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.remove.is_empty()
+    }
+}
+
+/// A rule either answers the request itself (`respond`) or lets it through
+/// to the service with rewritten headers. `delay_ms` applies in both cases.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MockRule {
+    pub name: String,
+    pub enabled: bool,
+    #[serde(rename = "match")]
+    pub matcher: MockMatch,
+    pub respond: Option<MockResponse>,
+    pub request_headers: HeaderRewrite,
+    pub response_headers: HeaderRewrite,
+    pub delay_ms: Option<u64>,
+}
+
+impl Default for MockRule {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            enabled: true,
+            matcher: MockMatch::default(),
+            respond: None,
+            request_headers: HeaderRewrite::default(),
+            response_headers: HeaderRewrite::default(),
+            delay_ms: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MockRulesConfig {
+    pub rules: Vec<MockRule>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MockDecision {
+    pub rule: String,
+    pub delay: Option<Duration>,
+    /// Complete HTTP/1.1 response to send instead of forwarding the request.
+    pub response: Option<Vec<u8>>,
+    pub request_headers: HeaderRewrite,
+    pub response_headers: HeaderRewrite,
+}
+
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    rule: MockRule,
+    method: Option<String>,
+    response: Option<Vec<u8>>,
+}
+
+impl CompiledRule {
+    fn compile(rule: &MockRule, index: usize) -> Result<Self> {
+        let name = if rule.name.is_empty() {
+            format!("rule-{}", index + 1)
+        } else {
+            rule.name.clone()
+        };
+
+        let response = rule
+            .respond
+            .as_ref()
+            .map(|respond| build_response(&name, respond))
+            .transpose()
+            .with_context(|| format!("Invalid mock rule '{}'", name))?;
+
+        Ok(Self {
+            rule: MockRule {
+                name,
+                ..rule.clone()
+            },
+            method: rule
+                .matcher
+                .method
+                .as_ref()
+                .map(|m| m.trim().to_ascii_uppercase()),
+            response,
+        })
+    }
+
+    fn matches(&self, method: &str, path: &str, headers: &[httparse::Header<'_>]) -> bool {
+        if self
+            .method
+            .as_ref()
+            .is_some_and(|m| !m.eq_ignore_ascii_case(method))
+        {
+            return false;
+        }
+
+        let path = path.split(['?', '#']).next().unwrap_or(path);
+        if self
+            .rule
+            .matcher
+            .path
+            .as_ref()
+            .is_some_and(|pattern| !glob_match(pattern, path))
+        {
+            return false;
+        }
+
+        self.rule.matcher.headers.iter().all(|(name, pattern)| {
+            headers
+                .iter()
+                .filter(|h| h.name.eq_ignore_ascii_case(name))
+                .any(|h| glob_match(pattern, &String::from_utf8_lossy(h.value)))
+        })
+    }
+}
+
+fn guess_content_type(path: Option<&Path>) -> &'static str {
+    let extension = path
+        .and_then(|p| p.extension())
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("json") => "application/json",
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("js") => "text/javascript",
+        Some("css") => "text/css",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("svg") => "image/svg+xml",
+        Some(_) => "application/octet-stream",
+        None => "text/plain; charset=utf-8",
+    }
+}
+
+fn build_response(name: &str, respond: &MockResponse) -> Result<Vec<u8>> {
+    if !(100..=599).contains(&respond.status) {
+        anyhow::bail!("status must be between 100 and 599, got {}", respond.status);
+    }
+
+    let body = match (&respond.body_file, &respond.body) {
+        (Some(path), _) => std::fs::read(path)
+            .with_context(|| format!("Failed to read mock body file {}", path.display()))?,
+        (None, Some(body)) => body.as_bytes().to_vec(),
+        (None, None) => Vec::new(),
+    };
+
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\n",
+        respond.status,
+        MessageFormatter::status_text(respond.status)
+    );
+
+    let has_header = |name: &str| respond.headers.keys().any(|h| h.eq_ignore_ascii_case(name));
+    if !has_header("content-type") && !body.is_empty() {
+        head.push_str(&format!(
+            "Content-Type: {}\r\n",
+            guess_content_type(respond.body_file.as_deref())
+        ));
+    }
+    for (header, value) in &respond.headers {
+        if header.eq_ignore_ascii_case("content-length") {
+            continue;
+        }
+        head.push_str(&format!("{}: {}\r\n", header, value));
+    }
+    head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    head.push_str(&format!("{}: {}\r\n\r\n", MOCK_HEADER, name));
+
+    let mut response = head.into_bytes();
+    response.extend_from_slice(&body);
+    Ok(response)
+}
+
+/// Compiled rules of a config. The first enabled rule that matches a
+/// request decides what happens to it.
+#[derive(Debug, Clone, Default)]
+pub struct MockEngine {
+    rules: Vec<CompiledRule>,
+}
+
+impl MockEngine {
+    pub fn compile(config: &MockRulesConfig) -> Result<Self> {
+        let rules = config
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| rule.enabled)
+            .map(|(index, rule)| CompiledRule::compile(rule, index))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns `None` when `request` does not start with a complete request
+    /// head or no rule matches it.
+    pub fn evaluate(&self, request: &[u8]) -> Option<MockDecision> {
+        let (method, path, headers) = match RequestParser::parse(request) {
+            Ok((Some(method), Some(path), _, headers)) => (method, path, headers),
+            _ => return None,
+        };
+
+        let compiled = self
+            .rules
+            .iter()
+            .find(|rule| rule.matches(method, path, &headers))?;
+        trace!(
+            "Mock rule '{}' matched {} {}",
+            compiled.rule.name,
+            method,
+            path
+        );
+
+        Some(MockDecision {
+            rule: compiled.rule.name.clone(),
+            delay: compiled.rule.delay_ms.map(Duration::from_millis),
+            response: compiled.response.clone(),
+            request_headers: compiled.rule.request_headers.clone(),
+            response_headers: compiled.rule.response_headers.clone(),
+        })
+    }
+}
+
+/// Applies `rewrite` to the head of an HTTP/1 request or response, leaving
+/// any body bytes untouched. Returns `None` when `message` has no complete
+/// head.
+pub fn rewrite_head(message: &[u8], rewrite: &HeaderRewrite) -> Option<Vec<u8>> {
+    let head_end = message.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = std::str::from_utf8(&message[..head_end]).ok()?;
+    let mut lines = head.split("\r\n");
+
+    let mut rewritten = String::with_capacity(head.len() + 64);
+    rewritten.push_str(lines.next()?);
+    rewritten.push_str("\r\n");
+
+    for line in lines {
+        let name = line.split(':').next().unwrap_or_default().trim();
+        let replaced = rewrite
+            .remove
+            .iter()
+            .chain(rewrite.set.keys())
+            .any(|h| h.eq_ignore_ascii_case(name));
+        if !replaced {
+            rewritten.push_str(line);
+            rewritten.push_str("\r\n");
+        }
+    }
+
+    for (name, value) in &rewrite.set {
+        rewritten.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    rewritten.push_str("\r\n");
+
+    let mut out = rewritten.into_bytes();
+    out.extend_from_slice(&message[head_end + 4..]);
+    Some(out)
+}
+
+/// Tracks the body of a request answered by a mock so its remaining bytes
+/// are not sent to the service. Chunked bodies end at the first zero-size
+/// chunk; trailers are not supported.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PendingBody {
+    #[default]
+    None,
+    Length(usize),
+    Chunked,
+}
+
+impl PendingBody {
+    pub fn after_request(request: &[u8]) -> Self {
+        let Ok((_, _, _, headers)) = RequestParser::parse(request) else {
+            return PendingBody::None;
+        };
+        let body = RequestParser::extract_body(request).unwrap_or_default();
+
+        let mut pending = if RequestParser::is_chunked_transfer(&headers) {
+            PendingBody::Chunked
+        } else {
+            PendingBody::Length(RequestParser::get_content_length(&headers))
+        };
+        pending.consume(body);
+        pending
+    }
+
+    /// Consumes body bytes from the front of `data`, returning what follows
+    /// the body.
+    pub fn consume<'a>(&mut self, data: &'a [u8]) -> &'a [u8] {
+        match *self {
+            PendingBody::None => data,
+            PendingBody::Length(remaining) => {
+                let consumed = remaining.min(data.len());
+                *self = match remaining - consumed {
+                    0 => PendingBody::None,
+                    left => PendingBody::Length(left),
+                };
+                &data[consumed..]
+            }
+            PendingBody::Chunked => match data
+                .windows(CHUNKED_TERMINATOR.len())
+                .position(|w| w == CHUNKED_TERMINATOR)
+            {
+                Some(end) => {
+                    *self = PendingBody::None;
+                    &data[end + CHUNKED_TERMINATOR.len()..]
+                }
+                None => &[],
+            },
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        *self != PendingBody::None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    fn engine(rules: Vec<MockRule>) -> MockEngine {
+        MockEngine::compile(&MockRulesConfig { rules }).unwrap()
+    }
+
+    #[test]
+    fn test_canned_response_from_file() {
+        let mut file = NamedTempFile::with_suffix(".json").unwrap();
+        file.write_all(b"{\"id\":1}").unwrap();
+
+        let engine = engine(vec![MockRule {
+            name: "user".to_string(),
+            matcher: MockMatch {
+                method: Some("get".to_string()),
+                path: Some("/api/users/*".to_string()),
+                headers: BTreeMap::from([("x-env".to_string(), "dev*".to_string())]),
+            },
+            respond: Some(MockResponse {
+                status: 201,
+                body_file: Some(file.path().to_path_buf()),
+                ..MockResponse::default()
+            }),
+            ..MockRule::default()
+        }]);
+
+        assert!(engine
+            .evaluate(b"GET /api/users/1 HTTP/1.1\r\nX-Env: prod\r\n\r\n")
+            .is_none());
+        assert!(engine
+            .evaluate(b"POST /api/users/1 HTTP/1.1\r\nX-Env: dev\r\n\r\n")
+            .is_none());
+
+        let decision = engine
+            .evaluate(b"GET /api/users/1?full=1 HTTP/1.1\r\nX-Env: dev-eu\r\n\r\n")
+            .unwrap();
+        assert_eq!(decision.rule, "user");
+        assert_eq!(
+            String::from_utf8(decision.response.unwrap()).unwrap(),
+            "HTTP/1.1 201 Created\r\nContent-Type: application/json\r\nContent-Length: 8\r\nX-Kftray-Mock: user\r\n\r\n{\"id\":1}"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_head() {
+        let rewrite = HeaderRewrite {
+            set: BTreeMap::from([("Authorization".to_string(), "Bearer dev".to_string())]),
+            remove: vec!["cookie".to_string()],
+        };
+
+        let rewritten = rewrite_head(
+            b"POST /login HTTP/1.1\r\nHost: a\r\nCookie: s=1\r\nauthorization: x\r\n\r\nbody",
+            &rewrite,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(rewritten).unwrap(),
+            "POST /login HTTP/1.1\r\nHost: a\r\nAuthorization: Bearer dev\r\n\r\nbody"
+        );
+        assert!(rewrite_head(b"POST /login HTTP/1.1\r\nHost", &rewrite).is_none());
+    }
+
+    #[test]
+    fn test_pending_body() {
+        let mut pending =
+            PendingBody::after_request(b"POST /a HTTP/1.1\r\nContent-Length: 10\r\n\r\n1234");
+        assert_eq!(pending, PendingBody::Length(6));
+        assert_eq!(pending.consume(b"567890GET"), b"GET");
+        assert!(!pending.is_pending());
+
+        let mut pending = PendingBody::after_request(
+            b"POST /a HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nabcd\r\n",
+        );
+        assert!(pending.is_pending());
+        assert_eq!(pending.consume(b"0\r\n\r\nGET"), b"GET");
+        assert!(!pending.is_pending());
+    }
+
+    #[test]
+    fn test_disabled_and_invalid_rules() {
+        let disabled = engine(vec![MockRule {
+            enabled: false,
+            ..MockRule::default()
+        }]);
+        assert!(disabled.is_empty());
+
+        let invalid = MockRulesConfig {
+            rules: vec![MockRule {
+                respond: Some(MockResponse {
+                    body_file: Some(PathBuf::from("/nonexistent/mock.json")),
+                    ..MockResponse::default()
+                }),
+                ..MockRule::default()
+            }],
+        };
+        assert!(MockEngine::compile(&invalid).is_err());
+    }
+}
@@ -1,9 +1,17 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
 use anyhow::Result;
 use chrono::{
     DateTime,
     Utc,
 };
 
+use crate::cassette::CassetteConfig;
+use crate::filter::LogFilterConfig;
+use crate::mock::MockRulesConfig;
+use crate::protocols::LogProtocol;
+use crate::redaction::RedactionConfig;
 use crate::state::LogStateManager;
 
 #[derive(Debug, Clone)]
@@ -31,6 +39,80 @@ impl HttpLogState {
     pub async fn get_http_logs(&self, config_id: i64) -> Result<bool> {
         self.state_manager.get_http_logs(config_id).await
     }
+
+    pub async fn set_redaction_config(
+        &self, config_id: i64, redaction: RedactionConfig,
+    ) -> Result<()> {
+        self.state_manager
+            .set_redaction_config(config_id, redaction)
+            .await
+    }
+
+    pub async fn get_redaction_config(&self, config_id: i64) -> Result<RedactionConfig> {
+        self.state_manager.get_redaction_config(config_id).await
+    }
+
+    pub async fn set_filter_config(&self, config_id: i64, filter: LogFilterConfig) -> Result<()> {
+        self.state_manager
+            .set_filter_config(config_id, filter)
+            .await
+    }
+
+    pub async fn get_filter_config(&self, config_id: i64) -> Result<LogFilterConfig> {
+        self.state_manager.get_filter_config(config_id).await
+    }
+
+    pub async fn set_grpc_descriptor_set(
+        &self, config_id: i64, descriptor_set: Option<PathBuf>,
+    ) -> Result<()> {
+        self.state_manager
+            .set_grpc_descriptor_set(config_id, descriptor_set)
+            .await
+    }
+
+    pub async fn get_grpc_descriptor_set(&self, config_id: i64) -> Result<Option<PathBuf>> {
+        self.state_manager.get_grpc_descriptor_set(config_id).await
+    }
+
+    pub async fn set_log_protocol(&self, config_id: i64, protocol: LogProtocol) -> Result<()> {
+        self.state_manager
+            .set_log_protocol(config_id, protocol)
+            .await
+    }
+
+    pub async fn get_log_protocol(&self, config_id: i64) -> Result<LogProtocol> {
+        self.state_manager.get_log_protocol(config_id).await
+    }
+
+    pub async fn set_mock_rules(&self, config_id: i64, rules: MockRulesConfig) -> Result<()> {
+        self.state_manager.set_mock_rules(config_id, rules).await
+    }
+
+    pub async fn get_mock_rules(&self, config_id: i64) -> Result<MockRulesConfig> {
+        self.state_manager.get_mock_rules(config_id).await
+    }
+
+    pub async fn set_cassette(
+        &self, config_id: i64, cassette: Option<CassetteConfig>,
+    ) -> Result<()> {
+        self.state_manager.set_cassette(config_id, cassette).await
+    }
+
+    pub async fn get_cassette(&self, config_id: i64) -> Result<Option<CassetteConfig>> {
+        self.state_manager.get_cassette(config_id).await
+    }
+
+    pub async fn set_disk_quota(&self, config_id: i64, quota: Option<u64>) -> Result<()> {
+        self.state_manager.set_disk_quota(config_id, quota).await
+    }
+
+    pub async fn get_disk_quota(&self, config_id: i64) -> Result<Option<u64>> {
+        self.state_manager.get_disk_quota(config_id).await
+    }
+
+    pub async fn disk_quotas(&self) -> BTreeMap<i64, u64> {
+        self.state_manager.disk_quotas().await
+    }
 }
 
 impl Default for HttpLogState {
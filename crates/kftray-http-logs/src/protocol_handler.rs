@@ -0,0 +1,92 @@
+use std::collections::VecDeque;
+
+use tokio::sync::Mutex;
+use tracing::error;
+
+use crate::protocols::{
+    ProtocolDecoder,
+    ProtocolEvent,
+};
+use crate::websocket::FrameDirection;
+use crate::HttpLogger;
+
+struct ConnectionState {
+    decoder: Box<dyn ProtocolDecoder>,
+    pending: VecDeque<String>,
+    failed: bool,
+}
+
+/// Logs a non-HTTP connection through a protocol decoder, pairing every
+/// reply with the oldest command still waiting for one.
+pub struct ProtocolConnectionHandler {
+    logger: HttpLogger,
+    protocol: &'static str,
+    state: Mutex<ConnectionState>,
+}
+
+impl ProtocolConnectionHandler {
+    pub fn new(logger: HttpLogger, decoder: Box<dyn ProtocolDecoder>) -> Self {
+        Self {
+            logger,
+            protocol: decoder.protocol(),
+            state: Mutex::new(ConnectionState {
+                decoder,
+                pending: VecDeque::new(),
+                failed: false,
+            }),
+        }
+    }
+
+    pub async fn handle_client_data(&self, data: &[u8]) {
+        self.handle_data(FrameDirection::ClientToServer, data).await;
+    }
+
+    pub async fn handle_server_data(&self, data: &[u8]) {
+        self.handle_data(FrameDirection::ServerToClient, data).await;
+    }
+
+    async fn handle_data(&self, direction: FrameDirection, data: &[u8]) {
+        let mut state = self.state.lock().await;
+
+        if state.failed {
+            return;
+        }
+
+        let events = match state.decoder.decode(direction, data) {
+            Ok(events) => events,
+            Err(e) => {
+                error!(
+                    "Stopped decoding {} connection ({}): {:?}",
+                    self.protocol, direction, e
+                );
+                state.failed = true;
+                return;
+            }
+        };
+
+        for event in events {
+            match event {
+                ProtocolEvent::Command { summary, detail } => {
+                    let request_id = self
+                        .logger
+                        .log_protocol_command(self.protocol, &summary, detail.as_deref())
+                        .await;
+                    state.pending.push_back(request_id);
+                }
+                ProtocolEvent::Reply { summary, is_error } => match state.pending.pop_front() {
+                    Some(request_id) => {
+                        self.logger
+                            .log_protocol_reply(self.protocol, &summary, is_error, &request_id)
+                            .await;
+                    }
+                    None => {
+                        self.logger.log_protocol_info(self.protocol, &summary).await;
+                    }
+                },
+                ProtocolEvent::Info(info) => {
+                    self.logger.log_protocol_info(self.protocol, &info).await;
+                }
+            }
+        }
+    }
+}
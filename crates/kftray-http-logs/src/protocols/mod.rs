@@ -0,0 +1,90 @@
+pub mod mysql;
+pub mod postgres;
+pub mod redis;
+
+use std::fmt;
+
+use anyhow::Result;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::websocket::FrameDirection;
+
+const MAX_STATEMENT_LEN: usize = 16 * 1024;
+
+/// Protocol used to interpret traffic on a logged forward. `Http` covers
+/// HTTP/1.x, HTTP/2 and WebSocket, which are told apart automatically.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogProtocol {
+    #[default]
+    Http,
+    Postgres,
+    Mysql,
+    Redis,
+}
+
+impl fmt::Display for LogProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogProtocol::Http => write!(f, "http"),
+            LogProtocol::Postgres => write!(f, "postgres"),
+            LogProtocol::Mysql => write!(f, "mysql"),
+            LogProtocol::Redis => write!(f, "redis"),
+        }
+    }
+}
+
+impl LogProtocol {
+    /// Returns a fresh decoder for one connection, or `None` for HTTP which
+    /// is handled by the HTTP logging path.
+    pub fn decoder(&self) -> Option<Box<dyn ProtocolDecoder>> {
+        match self {
+            LogProtocol::Http => None,
+            LogProtocol::Postgres => Some(Box::new(postgres::PostgresDecoder::new())),
+            LogProtocol::Mysql => Some(Box::new(mysql::MysqlDecoder::new())),
+            LogProtocol::Redis => Some(Box::new(redis::RedisDecoder::new())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProtocolEvent {
+    /// Something the client asked the server to do, e.g. a query.
+    Command {
+        summary: String,
+        detail: Option<String>,
+    },
+    /// The server's answer to the oldest outstanding command.
+    Reply { summary: String, is_error: bool },
+    /// Connection level information that is not tied to a command.
+    Info(String),
+}
+
+/// Decodes one connection of a non-HTTP protocol. Implementations keep
+/// whatever per-connection state they need and are fed the bytes of each
+/// direction in order.
+pub trait ProtocolDecoder: Send {
+    fn protocol(&self) -> &'static str;
+
+    fn decode(&mut self, direction: FrameDirection, data: &[u8]) -> Result<Vec<ProtocolEvent>>;
+}
+
+pub(crate) fn truncate_statement(statement: &str) -> String {
+    if statement.len() <= MAX_STATEMENT_LEN {
+        return statement.trim().to_string();
+    }
+
+    let mut end = MAX_STATEMENT_LEN;
+    while !statement.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!(
+        "{}... <{} more bytes>",
+        statement[..end].trim_start(),
+        statement.len() - end
+    )
+}
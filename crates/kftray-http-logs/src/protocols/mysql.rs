@@ -0,0 +1,678 @@
+use std::collections::{
+    HashMap,
+    VecDeque,
+};
+
+use anyhow::Result;
+
+use super::{
+    truncate_statement,
+    ProtocolDecoder,
+    ProtocolEvent,
+};
+use crate::websocket::FrameDirection;
+
+const MAX_BUFFERED_PACKET: usize = 1024 * 1024;
+const MAX_PACKET_LEN: usize = 0xffffff;
+
+const CLIENT_CONNECT_WITH_DB: u32 = 1 << 3;
+const CLIENT_PROTOCOL_41: u32 = 1 << 9;
+const CLIENT_SSL: u32 = 1 << 11;
+const CLIENT_SECURE_CONNECTION: u32 = 1 << 15;
+const CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA: u32 = 1 << 21;
+const CLIENT_DEPRECATE_EOF: u32 = 1 << 24;
+
+const SERVER_MORE_RESULTS_EXISTS: u16 = 0x0008;
+
+const COM_QUIT: u8 = 0x01;
+const COM_INIT_DB: u8 = 0x02;
+const COM_QUERY: u8 = 0x03;
+const COM_PING: u8 = 0x0e;
+const COM_STMT_PREPARE: u8 = 0x16;
+const COM_STMT_EXECUTE: u8 = 0x17;
+const COM_STMT_SEND_LONG_DATA: u8 = 0x18;
+const COM_STMT_CLOSE: u8 = 0x19;
+
+#[derive(Debug)]
+struct Packet {
+    payload: Vec<u8>,
+    continuation: bool,
+}
+
+/// Splits a stream into `3-byte length + sequence id + payload` packets.
+/// Payloads larger than `MAX_BUFFERED_PACKET` are cut short and the rest is
+/// skipped.
+#[derive(Debug, Default)]
+struct PacketReader {
+    buffer: Vec<u8>,
+    skip: usize,
+    previous_was_max: bool,
+}
+
+impl PacketReader {
+    fn push(&mut self, data: &[u8]) -> Vec<Packet> {
+        let mut data = data;
+        if self.skip > 0 {
+            let skipped = self.skip.min(data.len());
+            self.skip -= skipped;
+            data = &data[skipped..];
+        }
+        self.buffer.extend_from_slice(data);
+
+        let mut packets = Vec::new();
+        let mut pos = 0;
+
+        while let Some(header) = self.buffer.get(pos..pos + 4) {
+            let len = u32::from_le_bytes([header[0], header[1], header[2], 0]) as usize;
+            let body_start = pos + 4;
+            let available = self.buffer.len() - body_start;
+            let continuation = self.previous_was_max;
+
+            if len > MAX_BUFFERED_PACKET && available < len {
+                packets.push(Packet {
+                    payload: self.buffer[body_start..].to_vec(),
+                    continuation,
+                });
+                self.skip = len - available;
+                self.previous_was_max = len == MAX_PACKET_LEN;
+                pos = self.buffer.len();
+                break;
+            }
+
+            if available < len {
+                break;
+            }
+
+            packets.push(Packet {
+                payload: self.buffer[body_start..body_start + len].to_vec(),
+                continuation,
+            });
+            self.previous_was_max = len == MAX_PACKET_LEN;
+            pos = body_start + len;
+        }
+
+        self.buffer.drain(..pos);
+        packets
+    }
+}
+
+fn read_lenenc(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let first = *data.get(*pos)?;
+    *pos += 1;
+
+    let width = match first {
+        0xfc => 2,
+        0xfd => 3,
+        0xfe => 8,
+        0xfb | 0xff => return None,
+        value => return Some(value as u64),
+    };
+
+    let bytes = data.get(*pos..*pos + width)?;
+    *pos += width;
+
+    let mut value = [0u8; 8];
+    value[..width].copy_from_slice(bytes);
+    Some(u64::from_le_bytes(value))
+}
+
+fn read_cstring(data: &[u8], pos: &mut usize) -> Option<String> {
+    let rest = data.get(*pos..)?;
+    let end = rest.iter().position(|b| *b == 0)?;
+    *pos += end + 1;
+    Some(String::from_utf8_lossy(&rest[..end]).into_owned())
+}
+
+fn format_error(payload: &[u8]) -> String {
+    let code = payload
+        .get(1..3)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .unwrap_or_default();
+
+    let (state, message) = match payload.get(3) {
+        Some(b'#') => (
+            payload
+                .get(4..9)
+                .map(|s| String::from_utf8_lossy(s).into_owned()),
+            payload.get(9..).unwrap_or_default(),
+        ),
+        _ => (None, payload.get(3..).unwrap_or_default()),
+    };
+
+    match state {
+        Some(state) => format!(
+            "ERROR {} ({}): {}",
+            code,
+            state,
+            String::from_utf8_lossy(message)
+        ),
+        None => format!("ERROR {}: {}", code, String::from_utf8_lossy(message)),
+    }
+}
+
+struct OkPacket {
+    affected_rows: u64,
+    status: u16,
+}
+
+fn parse_ok(payload: &[u8]) -> OkPacket {
+    let mut pos = 1;
+    let affected_rows = read_lenenc(payload, &mut pos).unwrap_or_default();
+    let _last_insert_id = read_lenenc(payload, &mut pos);
+    let status = payload
+        .get(pos..pos + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .unwrap_or_default();
+
+    OkPacket {
+        affected_rows,
+        status,
+    }
+}
+
+fn eof_status(payload: &[u8]) -> u16 {
+    payload
+        .get(3..5)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .unwrap_or_default()
+}
+
+#[derive(Debug)]
+enum PendingCommand {
+    Generic,
+    Prepare(String),
+}
+
+#[derive(Debug, Default)]
+enum ResponseState {
+    #[default]
+    Awaiting,
+    ColumnDefinitions {
+        remaining: u64,
+    },
+    ColumnsEof,
+    Rows {
+        rows: u64,
+    },
+    Skip {
+        remaining: u64,
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct MysqlDecoder {
+    client: PacketReader,
+    server: PacketReader,
+    capabilities: u32,
+    handshake_sent: bool,
+    authenticated: bool,
+    encrypted: bool,
+    pending: VecDeque<PendingCommand>,
+    statements: HashMap<u32, String>,
+    response: ResponseState,
+    results: Vec<String>,
+}
+
+impl MysqlDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn deprecate_eof(&self) -> bool {
+        self.capabilities & CLIENT_DEPRECATE_EOF != 0
+    }
+
+    fn decode_client(&mut self, data: &[u8]) -> Vec<ProtocolEvent> {
+        let mut events = Vec::new();
+
+        for packet in self.client.push(data) {
+            if packet.continuation {
+                continue;
+            }
+
+            if !self.handshake_sent {
+                self.handle_handshake_response(&packet.payload, &mut events);
+                if self.encrypted {
+                    break;
+                }
+                continue;
+            }
+
+            if !self.authenticated {
+                continue;
+            }
+
+            self.handle_command(&packet.payload, &mut events);
+        }
+
+        events
+    }
+
+    fn handle_handshake_response(&mut self, payload: &[u8], events: &mut Vec<ProtocolEvent>) {
+        self.handshake_sent = true;
+        self.capabilities = payload
+            .get(..4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .unwrap_or_default();
+
+        if self.capabilities & CLIENT_SSL != 0 && payload.len() <= 32 {
+            self.encrypted = true;
+            events.push(ProtocolEvent::Info(
+                "TLS requested, statements are not visible".to_string(),
+            ));
+            return;
+        }
+
+        if self.capabilities & CLIENT_PROTOCOL_41 == 0 {
+            events.push(ProtocolEvent::Info("Login (pre-4.1 protocol)".to_string()));
+            return;
+        }
+
+        let mut pos = 32;
+        let user = read_cstring(payload, &mut pos).unwrap_or_default();
+
+        let auth_len = if self.capabilities & CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA != 0 {
+            read_lenenc(payload, &mut pos)
+        } else if self.capabilities & CLIENT_SECURE_CONNECTION != 0 {
+            let len = payload.get(pos).copied();
+            pos += 1;
+            len.map(u64::from)
+        } else {
+            read_cstring(payload, &mut pos).map(|_| 0)
+        };
+        pos += auth_len.unwrap_or_default() as usize;
+
+        let mut summary = format!("Login user={}", user);
+        if self.capabilities & CLIENT_CONNECT_WITH_DB != 0 {
+            if let Some(database) = read_cstring(payload, &mut pos) {
+                summary.push_str(&format!(" database={}", database));
+            }
+        }
+
+        events.push(ProtocolEvent::Info(summary));
+    }
+
+    fn handle_command(&mut self, payload: &[u8], events: &mut Vec<ProtocolEvent>) {
+        let Some((&command, args)) = payload.split_first() else {
+            return;
+        };
+        let text = || truncate_statement(&String::from_utf8_lossy(args));
+
+        let (summary, detail, pending) = match command {
+            COM_QUERY => ("QUERY".to_string(), Some(text()), PendingCommand::Generic),
+            COM_STMT_PREPARE => (
+                "PREPARE".to_string(),
+                Some(text()),
+                PendingCommand::Prepare(text()),
+            ),
+            COM_STMT_EXECUTE => {
+                let id = args
+                    .get(..4)
+                    .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .unwrap_or_default();
+                (
+                    format!("EXECUTE statement {}", id),
+                    self.statements.get(&id).cloned(),
+                    PendingCommand::Generic,
+                )
+            }
+            COM_INIT_DB => (
+                format!("USE {}", String::from_utf8_lossy(args)),
+                None,
+                PendingCommand::Generic,
+            ),
+            COM_PING => ("PING".to_string(), None, PendingCommand::Generic),
+            COM_QUIT => {
+                events.push(ProtocolEvent::Info("Quit".to_string()));
+                return;
+            }
+            COM_STMT_CLOSE => {
+                if let Some(b) = args.get(..4) {
+                    self.statements
+                        .remove(&u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+                }
+                return;
+            }
+            COM_STMT_SEND_LONG_DATA => return,
+            other => (
+                format!("COMMAND 0x{:02x}", other),
+                None,
+                PendingCommand::Generic,
+            ),
+        };
+
+        self.pending.push_back(pending);
+        events.push(ProtocolEvent::Command { summary, detail });
+    }
+
+    fn decode_server(&mut self, data: &[u8]) -> Vec<ProtocolEvent> {
+        let mut events = Vec::new();
+
+        for packet in self.server.push(data) {
+            if packet.continuation {
+                continue;
+            }
+
+            let payload = packet.payload.as_slice();
+
+            if !self.handshake_sent {
+                if payload.first() == Some(&10) {
+                    let mut pos = 1;
+                    let version = read_cstring(payload, &mut pos).unwrap_or_default();
+                    events.push(ProtocolEvent::Info(format!("Server version {}", version)));
+                } else if payload.first() == Some(&0xff) {
+                    events.push(ProtocolEvent::Info(format_error(payload)));
+                }
+                continue;
+            }
+
+            if !self.authenticated {
+                match payload.first() {
+                    Some(0x00) => {
+                        self.authenticated = true;
+                        events.push(ProtocolEvent::Info("Authenticated".to_string()));
+                    }
+                    Some(0xff) => events.push(ProtocolEvent::Info(format_error(payload))),
+                    _ => {}
+                }
+                continue;
+            }
+
+            self.handle_response_packet(payload, &mut events);
+        }
+
+        events
+    }
+
+    fn handle_response_packet(&mut self, payload: &[u8], events: &mut Vec<ProtocolEvent>) {
+        let first = payload.first().copied().unwrap_or_default();
+
+        match std::mem::take(&mut self.response) {
+            ResponseState::Awaiting => match first {
+                0x00 => {
+                    if let Some(PendingCommand::Prepare(query)) = self.pending.front() {
+                        let query = query.clone();
+                        self.handle_prepare_ok(payload, query, events);
+                        return;
+                    }
+
+                    let ok = parse_ok(payload);
+                    self.results
+                        .push(format!("OK, {} rows affected", ok.affected_rows));
+                    self.finish_result(ok.status, events);
+                }
+                0xff => {
+                    self.results.push(format_error(payload));
+                    self.emit_reply(true, events);
+                }
+                0xfb => {
+                    self.results.push("LOCAL INFILE requested".to_string());
+                    self.emit_reply(false, events);
+                }
+                _ => {
+                    let mut pos = 0;
+                    let columns = read_lenenc(payload, &mut pos).unwrap_or_default();
+                    self.response = ResponseState::ColumnDefinitions { remaining: columns };
+                }
+            },
+            ResponseState::ColumnDefinitions { remaining } => {
+                self.response = match remaining.saturating_sub(1) {
+                    0 if self.deprecate_eof() => ResponseState::Rows { rows: 0 },
+                    0 => ResponseState::ColumnsEof,
+                    remaining => ResponseState::ColumnDefinitions { remaining },
+                };
+            }
+            ResponseState::ColumnsEof => {
+                self.response = ResponseState::Rows { rows: 0 };
+            }
+            ResponseState::Rows { rows } => {
+                let terminator = first == 0xfe
+                    && (payload.len() < 9
+                        || (self.deprecate_eof() && payload.len() < MAX_PACKET_LEN));
+
+                if first == 0xff {
+                    self.results.push(format_error(payload));
+                    self.emit_reply(true, events);
+                } else if terminator {
+                    let status = if self.deprecate_eof() {
+                        parse_ok(payload).status
+                    } else {
+                        eof_status(payload)
+                    };
+                    self.results.push(format!(
+                        "{} {}",
+                        rows,
+                        if rows == 1 { "row" } else { "rows" }
+                    ));
+                    self.finish_result(status, events);
+                } else {
+                    self.response = ResponseState::Rows { rows: rows + 1 };
+                }
+            }
+            ResponseState::Skip { remaining } => {
+                if remaining > 1 {
+                    self.response = ResponseState::Skip {
+                        remaining: remaining - 1,
+                    };
+                }
+            }
+        }
+    }
+
+    fn handle_prepare_ok(
+        &mut self, payload: &[u8], query: String, events: &mut Vec<ProtocolEvent>,
+    ) {
+        let read_u16 = |at: usize| {
+            payload
+                .get(at..at + 2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]) as u64)
+                .unwrap_or_default()
+        };
+        let id = payload
+            .get(1..5)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .unwrap_or_default();
+        let columns = read_u16(5);
+        let params = read_u16(7);
+
+        self.statements.insert(id, query);
+        self.results.push(format!(
+            "Prepared statement {} ({} params, {} columns)",
+            id, params, columns
+        ));
+        self.emit_reply(false, events);
+
+        let mut remaining = columns + params;
+        if !self.deprecate_eof() {
+            remaining += u64::from(columns > 0) + u64::from(params > 0);
+        }
+        if remaining > 0 {
+            self.response = ResponseState::Skip { remaining };
+        }
+    }
+
+    fn finish_result(&mut self, status: u16, events: &mut Vec<ProtocolEvent>) {
+        if status & SERVER_MORE_RESULTS_EXISTS == 0 {
+            self.emit_reply(false, events);
+        }
+    }
+
+    fn emit_reply(&mut self, is_error: bool, events: &mut Vec<ProtocolEvent>) {
+        self.pending.pop_front();
+        self.response = ResponseState::Awaiting;
+
+        events.push(ProtocolEvent::Reply {
+            summary: std::mem::take(&mut self.results).join("; "),
+            is_error,
+        });
+    }
+}
+
+impl ProtocolDecoder for MysqlDecoder {
+    fn protocol(&self) -> &'static str {
+        "mysql"
+    }
+
+    fn decode(&mut self, direction: FrameDirection, data: &[u8]) -> Result<Vec<ProtocolEvent>> {
+        if self.encrypted {
+            return Ok(Vec::new());
+        }
+
+        Ok(match direction {
+            FrameDirection::ClientToServer => self.decode_client(data),
+            FrameDirection::ServerToClient => self.decode_server(data),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(seq: u8, payload: &[u8]) -> Vec<u8> {
+        let len = payload.len() as u32;
+        let mut out = len.to_le_bytes()[..3].to_vec();
+        out.push(seq);
+        out.extend_from_slice(payload);
+        out
+    }
+
+    fn connect(capabilities: u32) -> MysqlDecoder {
+        let mut decoder = MysqlDecoder::new();
+
+        let greeting = packet(0, b"\x0a8.0.36\0rest");
+        assert_eq!(
+            decoder
+                .decode(FrameDirection::ServerToClient, &greeting)
+                .unwrap(),
+            vec![ProtocolEvent::Info("Server version 8.0.36".to_string())]
+        );
+
+        let mut response = capabilities.to_le_bytes().to_vec();
+        response.extend_from_slice(&[0; 28]);
+        response.extend_from_slice(b"app\0");
+        response.extend_from_slice(&[2, 0xaa, 0xbb]);
+        response.extend_from_slice(b"shop\0");
+        assert_eq!(
+            decoder
+                .decode(FrameDirection::ClientToServer, &packet(1, &response))
+                .unwrap(),
+            vec![ProtocolEvent::Info(
+                "Login user=app database=shop".to_string()
+            )]
+        );
+
+        decoder
+            .decode(
+                FrameDirection::ServerToClient,
+                &packet(2, &[0, 0, 0, 2, 0, 0, 0]),
+            )
+            .unwrap();
+        decoder
+    }
+
+    #[test]
+    fn test_query_result_set() {
+        let capabilities = CLIENT_PROTOCOL_41 | CLIENT_SECURE_CONNECTION | CLIENT_CONNECT_WITH_DB;
+        let mut decoder = connect(capabilities);
+
+        let events = decoder
+            .decode(
+                FrameDirection::ClientToServer,
+                &packet(0, b"\x03SELECT id FROM orders"),
+            )
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![ProtocolEvent::Command {
+                summary: "QUERY".to_string(),
+                detail: Some("SELECT id FROM orders".to_string()),
+            }]
+        );
+
+        let mut server = packet(1, &[1]);
+        server.extend(packet(2, b"\x03defcolumn"));
+        server.extend(packet(3, &[0xfe, 0, 0, 2, 0]));
+        server.extend(packet(4, b"\x011"));
+        server.extend(packet(5, b"\x012"));
+        server.extend(packet(6, &[0xfe, 0, 0, 2, 0]));
+        assert_eq!(
+            decoder
+                .decode(FrameDirection::ServerToClient, &server)
+                .unwrap(),
+            vec![ProtocolEvent::Reply {
+                summary: "2 rows".to_string(),
+                is_error: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_error_and_prepare() {
+        let capabilities = CLIENT_PROTOCOL_41
+            | CLIENT_SECURE_CONNECTION
+            | CLIENT_CONNECT_WITH_DB
+            | CLIENT_DEPRECATE_EOF;
+        let mut decoder = connect(capabilities);
+
+        decoder
+            .decode(
+                FrameDirection::ClientToServer,
+                &packet(0, b"\x03SELECT * FROM missing"),
+            )
+            .unwrap();
+        let mut error = vec![0xff, 0x7a, 0x04, b'#'];
+        error.extend_from_slice(b"42S02Table 'shop.missing' doesn't exist");
+        assert_eq!(
+            decoder
+                .decode(FrameDirection::ServerToClient, &packet(1, &error))
+                .unwrap(),
+            vec![ProtocolEvent::Reply {
+                summary: "ERROR 1146 (42S02): Table 'shop.missing' doesn't exist".to_string(),
+                is_error: true,
+            }]
+        );
+
+        decoder
+            .decode(
+                FrameDirection::ClientToServer,
+                &packet(0, b"\x16SELECT * FROM orders WHERE id = ?"),
+            )
+            .unwrap();
+        let mut server = packet(1, &[0, 7, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0]);
+        server.extend(packet(2, b"\x03defparam"));
+        server.extend(packet(3, b"\x03defcolumn"));
+        let events = decoder
+            .decode(FrameDirection::ServerToClient, &server)
+            .unwrap();
+        assert_eq!(events.len(), 1);
+
+        let events = decoder
+            .decode(
+                FrameDirection::ClientToServer,
+                &packet(0, &[0x17, 7, 0, 0, 0, 0, 1, 0, 0, 0]),
+            )
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![ProtocolEvent::Command {
+                summary: "EXECUTE statement 7".to_string(),
+                detail: Some("SELECT * FROM orders WHERE id = ?".to_string()),
+            }]
+        );
+
+        let mut server = packet(1, &[1]);
+        server.extend(packet(2, b"\x03defcolumn"));
+        server.extend(packet(3, &[0x00, 0x00, 0x01]));
+        server.extend(packet(4, &[0xfe, 0, 0, 2, 0, 0, 0]));
+        assert_eq!(
+            decoder
+                .decode(FrameDirection::ServerToClient, &server)
+                .unwrap(),
+            vec![ProtocolEvent::Reply {
+                summary: "1 row".to_string(),
+                is_error: false,
+            }]
+        );
+    }
+}
@@ -0,0 +1,515 @@
+use std::collections::HashMap;
+
+use anyhow::{
+    anyhow,
+    Result,
+};
+
+use super::{
+    truncate_statement,
+    ProtocolDecoder,
+    ProtocolEvent,
+};
+use crate::websocket::FrameDirection;
+
+const MAX_BUFFERED_MESSAGE: usize = 1024 * 1024;
+
+const PROTOCOL_VERSION_3: u32 = 196608;
+const SSL_REQUEST: u32 = 80877103;
+const GSSENC_REQUEST: u32 = 80877104;
+const CANCEL_REQUEST: u32 = 80877102;
+
+#[derive(Debug)]
+struct Message {
+    tag: u8,
+    body: Vec<u8>,
+    truncated: bool,
+}
+
+/// Splits a stream into `tag + int32 length + body` messages. Bodies larger
+/// than `MAX_BUFFERED_MESSAGE` are skipped rather than buffered.
+#[derive(Debug, Default)]
+struct MessageReader {
+    buffer: Vec<u8>,
+    skip: usize,
+}
+
+impl MessageReader {
+    fn push(&mut self, data: &[u8], untagged_first: bool) -> Result<Vec<Message>> {
+        let mut data = data;
+        if self.skip > 0 {
+            let skipped = self.skip.min(data.len());
+            self.skip -= skipped;
+            data = &data[skipped..];
+        }
+        self.buffer.extend_from_slice(data);
+
+        let mut messages = Vec::new();
+        let mut pos = 0;
+        let mut untagged = untagged_first;
+
+        loop {
+            let header_len = if untagged { 4 } else { 5 };
+            let Some(header) = self.buffer.get(pos..pos + header_len) else {
+                break;
+            };
+
+            let (tag, len_bytes) = if untagged {
+                (0, header)
+            } else {
+                (header[0], &header[1..])
+            };
+            let len = u32::from_be_bytes(len_bytes.try_into()?) as usize;
+            if len < 4 {
+                return Err(anyhow!("Invalid PostgreSQL message length {}", len));
+            }
+
+            let body_len = len - 4;
+            let body_start = pos + header_len;
+            let available = self.buffer.len() - body_start;
+
+            if body_len > MAX_BUFFERED_MESSAGE && available < body_len {
+                messages.push(Message {
+                    tag,
+                    body: self.buffer[body_start..].to_vec(),
+                    truncated: true,
+                });
+                self.skip = body_len - available;
+                pos = self.buffer.len();
+                break;
+            }
+
+            if available < body_len {
+                break;
+            }
+
+            messages.push(Message {
+                tag,
+                body: self.buffer[body_start..body_start + body_len].to_vec(),
+                truncated: false,
+            });
+            pos = body_start + body_len;
+            untagged = false;
+        }
+
+        self.buffer.drain(..pos);
+        Ok(messages)
+    }
+}
+
+fn read_cstring(body: &[u8], pos: &mut usize) -> Option<String> {
+    let rest = body.get(*pos..)?;
+    let end = rest.iter().position(|b| *b == 0)?;
+    *pos += end + 1;
+    Some(String::from_utf8_lossy(&rest[..end]).into_owned())
+}
+
+fn format_error(body: &[u8]) -> String {
+    let mut fields = HashMap::new();
+    let mut pos = 0;
+
+    while let Some(&code) = body.get(pos) {
+        if code == 0 {
+            break;
+        }
+        pos += 1;
+        match read_cstring(body, &mut pos) {
+            Some(value) => {
+                fields.insert(code, value);
+            }
+            None => break,
+        }
+    }
+
+    let severity = fields
+        .get(&b'V')
+        .or_else(|| fields.get(&b'S'))
+        .map(String::as_str)
+        .unwrap_or("ERROR");
+    let mut summary = format!(
+        "{} {}: {}",
+        severity,
+        fields.get(&b'C').map(String::as_str).unwrap_or("?????"),
+        fields.get(&b'M').map(String::as_str).unwrap_or_default()
+    );
+
+    if let Some(detail) = fields.get(&b'D') {
+        summary.push_str(&format!(" (detail: {})", detail));
+    }
+
+    summary
+}
+
+#[derive(Debug, Default)]
+struct PendingReply {
+    tags: Vec<String>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct ExtendedBatch {
+    statement: Option<String>,
+    executed: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct PostgresDecoder {
+    client: MessageReader,
+    server: MessageReader,
+    startup_done: bool,
+    awaiting_ssl_response: bool,
+    encrypted: bool,
+    ready: bool,
+    statements: HashMap<String, String>,
+    batch: Option<ExtendedBatch>,
+    reply: PendingReply,
+}
+
+impl PostgresDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn decode_client(&mut self, data: &[u8]) -> Result<Vec<ProtocolEvent>> {
+        let untagged = !self.startup_done;
+        let messages = self.client.push(data, untagged)?;
+        let mut events = Vec::new();
+
+        for (index, message) in messages.into_iter().enumerate() {
+            if index == 0 && untagged {
+                self.handle_startup(&message, &mut events);
+                if self.awaiting_ssl_response {
+                    break;
+                }
+                continue;
+            }
+
+            self.handle_client_message(message, &mut events);
+        }
+
+        Ok(events)
+    }
+
+    fn handle_startup(&mut self, message: &Message, events: &mut Vec<ProtocolEvent>) {
+        let code = message
+            .body
+            .get(..4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .unwrap_or_default();
+
+        match code {
+            SSL_REQUEST | GSSENC_REQUEST => {
+                self.awaiting_ssl_response = true;
+            }
+            CANCEL_REQUEST => {
+                self.startup_done = true;
+                events.push(ProtocolEvent::Info("Cancel request".to_string()));
+            }
+            PROTOCOL_VERSION_3 => {
+                self.startup_done = true;
+
+                let mut pos = 4;
+                let mut params = Vec::new();
+                while let (Some(key), Some(value)) = (
+                    read_cstring(&message.body, &mut pos),
+                    read_cstring(&message.body, &mut pos),
+                ) {
+                    if key.is_empty() {
+                        break;
+                    }
+                    if matches!(key.as_str(), "user" | "database" | "application_name") {
+                        params.push(format!("{}={}", key, value));
+                    }
+                }
+
+                events.push(ProtocolEvent::Info(format!("Startup {}", params.join(" "))));
+            }
+            other => {
+                self.startup_done = true;
+                events.push(ProtocolEvent::Info(format!(
+                    "Unknown startup code {}",
+                    other
+                )));
+            }
+        }
+    }
+
+    fn handle_client_message(&mut self, message: Message, events: &mut Vec<ProtocolEvent>) {
+        let mut pos = 0;
+
+        match message.tag {
+            b'Q' => {
+                let query = read_cstring(&message.body, &mut pos)
+                    .unwrap_or_else(|| String::from_utf8_lossy(&message.body).into_owned());
+                events.push(ProtocolEvent::Command {
+                    summary: "QUERY".to_string(),
+                    detail: Some(truncate_statement(&query)),
+                });
+            }
+            b'P' => {
+                let name = read_cstring(&message.body, &mut pos).unwrap_or_default();
+                let query = read_cstring(&message.body, &mut pos)
+                    .unwrap_or_else(|| "<truncated>".to_string());
+                self.statements.insert(name, query.clone());
+                self.batch.get_or_insert_with(Default::default).statement = Some(query);
+            }
+            b'B' => {
+                let _portal = read_cstring(&message.body, &mut pos);
+                let statement = read_cstring(&message.body, &mut pos).unwrap_or_default();
+                let query = self.statements.get(&statement).cloned();
+                let batch = self.batch.get_or_insert_with(Default::default);
+                if batch.statement.is_none() {
+                    batch.statement = query;
+                }
+            }
+            b'E' => {
+                self.batch.get_or_insert_with(Default::default).executed = true;
+            }
+            b'S' => {
+                let batch = self.batch.take().unwrap_or_default();
+                events.push(ProtocolEvent::Command {
+                    summary: if batch.executed { "EXECUTE" } else { "SYNC" }.to_string(),
+                    detail: batch.statement.map(|s| truncate_statement(&s)),
+                });
+            }
+            b'C' => {
+                let kind = message.body.first().copied().unwrap_or(b'S');
+                pos = 1;
+                let name = read_cstring(&message.body, &mut pos).unwrap_or_default();
+                if kind == b'S' {
+                    self.statements.remove(&name);
+                }
+            }
+            b'X' => events.push(ProtocolEvent::Info("Terminate".to_string())),
+            b'f' => events.push(ProtocolEvent::Info("COPY failed by client".to_string())),
+            _ => {}
+        }
+    }
+
+    fn decode_server(&mut self, data: &[u8]) -> Result<Vec<ProtocolEvent>> {
+        let mut events = Vec::new();
+        let mut data = data;
+
+        if self.awaiting_ssl_response {
+            let Some((&answer, rest)) = data.split_first() else {
+                return Ok(events);
+            };
+            self.awaiting_ssl_response = false;
+
+            if answer == b'S' || answer == b'G' {
+                self.encrypted = true;
+                events.push(ProtocolEvent::Info(
+                    "Encryption negotiated, statements are not visible".to_string(),
+                ));
+                return Ok(events);
+            }
+
+            data = rest;
+        }
+
+        for message in self.server.push(data, false)? {
+            self.handle_server_message(message, &mut events);
+        }
+
+        Ok(events)
+    }
+
+    fn handle_server_message(&mut self, message: Message, events: &mut Vec<ProtocolEvent>) {
+        let mut pos = 0;
+
+        match message.tag {
+            b'C' => {
+                if let Some(tag) = read_cstring(&message.body, &mut pos) {
+                    self.reply.tags.push(tag);
+                }
+            }
+            b'I' => self.reply.tags.push("EMPTY QUERY".to_string()),
+            b'E' => {
+                let error = format_error(&message.body);
+                if self.ready {
+                    self.reply.error = Some(error);
+                } else {
+                    events.push(ProtocolEvent::Info(error));
+                }
+            }
+            b'A' => {
+                let _pid = message.body.get(..4);
+                pos = 4;
+                let channel = read_cstring(&message.body, &mut pos).unwrap_or_default();
+                let payload = read_cstring(&message.body, &mut pos).unwrap_or_default();
+                events.push(ProtocolEvent::Info(format!(
+                    "NOTIFY {} {}",
+                    channel, payload
+                )));
+            }
+            b'Z' => {
+                let reply = std::mem::take(&mut self.reply);
+
+                if !self.ready {
+                    self.ready = true;
+                    events.push(ProtocolEvent::Info("Ready for query".to_string()));
+                    return;
+                }
+
+                let (summary, is_error) = match reply.error {
+                    Some(error) => (error, true),
+                    None if reply.tags.is_empty() => ("OK".to_string(), false),
+                    None => (reply.tags.join("; "), false),
+                };
+                events.push(ProtocolEvent::Reply { summary, is_error });
+            }
+            _ if message.truncated => {}
+            _ => {}
+        }
+    }
+}
+
+impl ProtocolDecoder for PostgresDecoder {
+    fn protocol(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn decode(&mut self, direction: FrameDirection, data: &[u8]) -> Result<Vec<ProtocolEvent>> {
+        if self.encrypted {
+            return Ok(Vec::new());
+        }
+
+        match direction {
+            FrameDirection::ClientToServer => self.decode_client(data),
+            FrameDirection::ServerToClient => self.decode_server(data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(tag: u8, body: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        out.extend_from_slice(&((body.len() + 4) as u32).to_be_bytes());
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn startup() -> Vec<u8> {
+        let mut body = PROTOCOL_VERSION_3.to_be_bytes().to_vec();
+        body.extend_from_slice(b"user\0app\0database\0shop\0\0");
+        let mut out = ((body.len() + 4) as u32).to_be_bytes().to_vec();
+        out.extend(body);
+        out
+    }
+
+    #[test]
+    fn test_simple_query() {
+        let mut decoder = PostgresDecoder::new();
+
+        let events = decoder
+            .decode(FrameDirection::ClientToServer, &startup())
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![ProtocolEvent::Info(
+                "Startup user=app database=shop".to_string()
+            )]
+        );
+
+        let mut server = message(b'R', &[0, 0, 0, 0]);
+        server.extend(message(b'Z', b"I"));
+        decoder
+            .decode(FrameDirection::ServerToClient, &server)
+            .unwrap();
+
+        let query = message(b'Q', b"SELECT * FROM orders\0");
+        let events = decoder
+            .decode(FrameDirection::ClientToServer, &query)
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![ProtocolEvent::Command {
+                summary: "QUERY".to_string(),
+                detail: Some("SELECT * FROM orders".to_string()),
+            }]
+        );
+
+        let mut server = message(b'T', &[0, 0]);
+        server.extend(message(b'D', &[0, 0]));
+        server.extend(message(b'C', b"SELECT 1\0"));
+        server.extend(message(b'Z', b"I"));
+        let (first, second) = server.split_at(7);
+        assert!(decoder
+            .decode(FrameDirection::ServerToClient, first)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            decoder
+                .decode(FrameDirection::ServerToClient, second)
+                .unwrap(),
+            vec![ProtocolEvent::Reply {
+                summary: "SELECT 1".to_string(),
+                is_error: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_extended_query_error() {
+        let mut decoder = PostgresDecoder::new();
+        decoder
+            .decode(FrameDirection::ClientToServer, &startup())
+            .unwrap();
+        decoder
+            .decode(FrameDirection::ServerToClient, &message(b'Z', b"I"))
+            .unwrap();
+
+        let mut client = message(b'P', b"\0SELECT * FROM missing WHERE id = $1\0\0\0");
+        client.extend(message(b'B', b"\0\0\0\0\0\0\0\0"));
+        client.extend(message(b'E', b"\0\0\0\0\0"));
+        client.extend(message(b'S', b""));
+        let events = decoder
+            .decode(FrameDirection::ClientToServer, &client)
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![ProtocolEvent::Command {
+                summary: "EXECUTE".to_string(),
+                detail: Some("SELECT * FROM missing WHERE id = $1".to_string()),
+            }]
+        );
+
+        let mut server = message(
+            b'E',
+            b"SERROR\0VERROR\0C42P01\0Mrelation \"missing\" does not exist\0\0",
+        );
+        server.extend(message(b'Z', b"I"));
+        assert_eq!(
+            decoder
+                .decode(FrameDirection::ServerToClient, &server)
+                .unwrap(),
+            vec![ProtocolEvent::Reply {
+                summary: "ERROR 42P01: relation \"missing\" does not exist".to_string(),
+                is_error: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_ssl_request_stops_decoding() {
+        let mut decoder = PostgresDecoder::new();
+        let mut ssl = 8u32.to_be_bytes().to_vec();
+        ssl.extend_from_slice(&SSL_REQUEST.to_be_bytes());
+
+        assert!(decoder
+            .decode(FrameDirection::ClientToServer, &ssl)
+            .unwrap()
+            .is_empty());
+        let events = decoder
+            .decode(FrameDirection::ServerToClient, b"S")
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(decoder
+            .decode(FrameDirection::ClientToServer, &[0x16, 0x03, 0x01])
+            .unwrap()
+            .is_empty());
+    }
+}
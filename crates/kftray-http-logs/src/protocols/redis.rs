@@ -0,0 +1,471 @@
+use std::collections::VecDeque;
+
+use anyhow::{
+    anyhow,
+    bail,
+    Result,
+};
+
+use super::{
+    ProtocolDecoder,
+    ProtocolEvent,
+};
+use crate::websocket::FrameDirection;
+
+const MAX_BUFFERED_VALUE: usize = 8 * 1024 * 1024;
+const MAX_ARGUMENT_LEN: usize = 256;
+const MAX_NESTING: usize = 32;
+
+const REDACTED: &str = "<redacted>";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Array(Vec<Value>),
+    Map(usize),
+    Set(usize),
+    Push(Vec<Value>),
+    Scalar(&'static str, String),
+    Nil,
+}
+
+fn find_line(buf: &[u8], pos: usize) -> Option<(&[u8], usize)> {
+    let rest = buf.get(pos..)?;
+    let end = rest.windows(2).position(|w| w == b"\r\n")?;
+    Some((&rest[..end], pos + end + 2))
+}
+
+fn parse_len(line: &[u8]) -> Result<i64> {
+    std::str::from_utf8(line)?
+        .parse::<i64>()
+        .map_err(|e| anyhow!("Invalid RESP length: {}", e))
+}
+
+/// Parses one RESP2/RESP3 value starting at `pos`, returning the value and
+/// the position after it, or `None` when more data is needed.
+fn parse_value(buf: &[u8], pos: usize, depth: usize) -> Result<Option<(Value, usize)>> {
+    if depth > MAX_NESTING {
+        bail!("RESP value nested too deeply");
+    }
+
+    let Some(&kind) = buf.get(pos) else {
+        return Ok(None);
+    };
+    let Some((line, next)) = find_line(buf, pos + 1) else {
+        return Ok(None);
+    };
+    let text = || String::from_utf8_lossy(line).into_owned();
+
+    let value = match kind {
+        b'+' => Value::Simple(text()),
+        b'-' => Value::Error(text()),
+        b':' => Value::Integer(parse_len(line)?),
+        b'_' => Value::Nil,
+        b'#' => Value::Scalar("boolean", text()),
+        b',' => Value::Scalar("double", text()),
+        b'(' => Value::Scalar("big number", text()),
+        b'$' | b'!' | b'=' => {
+            let len = parse_len(line)?;
+            if len < 0 {
+                return Ok(Some((Value::Nil, next)));
+            }
+            let len = len as usize;
+            let Some(data) = buf.get(next..next + len) else {
+                return Ok(None);
+            };
+            if buf.len() < next + len + 2 {
+                return Ok(None);
+            }
+            let data = data.to_vec();
+            let end = next + len + 2;
+            return Ok(Some((
+                match kind {
+                    b'!' => Value::Error(String::from_utf8_lossy(&data).into_owned()),
+                    b'=' => Value::Bulk(data.get(4..).unwrap_or_default().to_vec()),
+                    _ => Value::Bulk(data),
+                },
+                end,
+            )));
+        }
+        b'*' | b'~' | b'>' | b'%' | b'|' => {
+            let count = parse_len(line)?;
+            if count < 0 {
+                return Ok(Some((Value::Nil, next)));
+            }
+            let elements = if matches!(kind, b'%' | b'|') {
+                count as usize * 2
+            } else {
+                count as usize
+            };
+
+            let mut items = Vec::new();
+            let mut cursor = next;
+            for _ in 0..elements {
+                let Some((item, after)) = parse_value(buf, cursor, depth + 1)? else {
+                    return Ok(None);
+                };
+                items.push(item);
+                cursor = after;
+            }
+
+            return Ok(Some(match kind {
+                b'*' => (Value::Array(items), cursor),
+                b'~' => (Value::Set(items.len()), cursor),
+                b'>' => (Value::Push(items), cursor),
+                b'%' => (Value::Map(count as usize), cursor),
+                // Attributes annotate the value that follows them.
+                _ => match parse_value(buf, cursor, depth + 1)? {
+                    Some(result) => result,
+                    None => return Ok(None),
+                },
+            }));
+        }
+        _ => bail!("Unexpected RESP type byte 0x{:02x}", kind),
+    };
+
+    Ok(Some((value, next)))
+}
+
+fn quote(data: &[u8]) -> String {
+    let text = String::from_utf8_lossy(&data[..data.len().min(MAX_ARGUMENT_LEN)]);
+    let mut quoted = format!("{:?}", text);
+    if data.len() > MAX_ARGUMENT_LEN {
+        quoted.push_str(&format!(
+            "... <{} more bytes>",
+            data.len() - MAX_ARGUMENT_LEN
+        ));
+    }
+    quoted
+}
+
+fn format_reply(value: &Value) -> String {
+    match value {
+        Value::Simple(text) => text.clone(),
+        Value::Error(text) => format!("(error) {}", text),
+        Value::Integer(n) => format!("(integer) {}", n),
+        Value::Bulk(data) => quote(data),
+        Value::Array(items) if items.is_empty() => "(empty array)".to_string(),
+        Value::Array(items) => format!("(array {})", items.len()),
+        Value::Map(n) => format!("(map {})", n),
+        Value::Set(n) => format!("(set {})", n),
+        Value::Push(items) => format!("(push {})", items.len()),
+        Value::Scalar(kind, text) => format!("({}) {}", kind, text),
+        Value::Nil => "(nil)".to_string(),
+    }
+}
+
+fn argument_bytes(value: &Value) -> Vec<u8> {
+    match value {
+        Value::Bulk(data) => data.clone(),
+        Value::Simple(text) => text.as_bytes().to_vec(),
+        Value::Integer(n) => n.to_string().into_bytes(),
+        other => format_reply(other).into_bytes(),
+    }
+}
+
+/// Replaces credentials in commands that carry them.
+fn redact_arguments(name: &str, args: &mut [String]) {
+    match name {
+        "AUTH" => args.iter_mut().for_each(|arg| *arg = REDACTED.to_string()),
+        "HELLO" => {
+            if let Some(index) = args.iter().position(|a| a.eq_ignore_ascii_case("\"auth\"")) {
+                if let Some(password) = args.get_mut(index + 2) {
+                    *password = REDACTED.to_string();
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_subscription(name: &str) -> bool {
+    matches!(
+        name,
+        "SUBSCRIBE" | "PSUBSCRIBE" | "SSUBSCRIBE" | "UNSUBSCRIBE" | "PUNSUBSCRIBE" | "SUNSUBSCRIBE"
+    )
+}
+
+#[derive(Debug)]
+struct PendingCommand {
+    name: String,
+    remaining_replies: usize,
+    replies: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct RedisDecoder {
+    client: Vec<u8>,
+    server: Vec<u8>,
+    pending: VecDeque<PendingCommand>,
+}
+
+impl RedisDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn decode_client(&mut self, data: &[u8]) -> Result<Vec<ProtocolEvent>> {
+        self.client.extend_from_slice(data);
+        let mut events = Vec::new();
+        let mut pos = 0;
+
+        loop {
+            let arguments = match self.client.get(pos) {
+                None => break,
+                Some(b'*') => match parse_value(&self.client, pos, 0)? {
+                    Some((Value::Array(items), next)) => {
+                        pos = next;
+                        items.iter().map(argument_bytes).collect::<Vec<_>>()
+                    }
+                    Some((_, next)) => {
+                        pos = next;
+                        continue;
+                    }
+                    None => break,
+                },
+                Some(_) => match find_line(&self.client, pos) {
+                    Some((line, next)) => {
+                        let arguments = line
+                            .split(|b| b.is_ascii_whitespace())
+                            .filter(|part| !part.is_empty())
+                            .map(<[u8]>::to_vec)
+                            .collect::<Vec<_>>();
+                        pos = next;
+                        arguments
+                    }
+                    None => break,
+                },
+            };
+
+            if let Some(event) = self.handle_command(arguments) {
+                events.push(event);
+            }
+        }
+
+        self.client.drain(..pos);
+        if self.client.len() > MAX_BUFFERED_VALUE {
+            bail!("Redis command exceeds {} bytes", MAX_BUFFERED_VALUE);
+        }
+
+        Ok(events)
+    }
+
+    fn handle_command(&mut self, arguments: Vec<Vec<u8>>) -> Option<ProtocolEvent> {
+        let (name, args) = arguments.split_first()?;
+        let name = String::from_utf8_lossy(name).to_ascii_uppercase();
+
+        let mut args = args.iter().map(|arg| quote(arg)).collect::<Vec<_>>();
+        redact_arguments(&name, &mut args);
+
+        let remaining_replies = if is_subscription(&name) {
+            args.len().max(1)
+        } else {
+            1
+        };
+        self.pending.push_back(PendingCommand {
+            name: name.clone(),
+            remaining_replies,
+            replies: Vec::new(),
+        });
+
+        let mut detail = name.clone();
+        for arg in &args {
+            detail.push(' ');
+            detail.push_str(arg);
+        }
+
+        Some(ProtocolEvent::Command {
+            summary: name,
+            detail: Some(detail),
+        })
+    }
+
+    fn decode_server(&mut self, data: &[u8]) -> Result<Vec<ProtocolEvent>> {
+        self.server.extend_from_slice(data);
+        let mut events = Vec::new();
+        let mut pos = 0;
+
+        while let Some((value, next)) = parse_value(&self.server, pos, 0)? {
+            pos = next;
+            self.handle_reply(value, &mut events);
+        }
+
+        self.server.drain(..pos);
+        if self.server.len() > MAX_BUFFERED_VALUE {
+            bail!("Redis reply exceeds {} bytes", MAX_BUFFERED_VALUE);
+        }
+
+        Ok(events)
+    }
+
+    fn handle_reply(&mut self, value: Value, events: &mut Vec<ProtocolEvent>) {
+        let kind = match &value {
+            Value::Array(items) | Value::Push(items) => items
+                .first()
+                .map(|first| String::from_utf8_lossy(&argument_bytes(first)).to_ascii_uppercase()),
+            _ => None,
+        };
+
+        let expects_subscription = self
+            .pending
+            .front()
+            .is_some_and(|pending| is_subscription(&pending.name));
+        let is_message = matches!(kind.as_deref(), Some("MESSAGE" | "PMESSAGE" | "SMESSAGE"));
+        let is_confirmation = kind.as_deref().is_some_and(is_subscription);
+
+        if matches!(value, Value::Push(_))
+            || is_message
+            || (is_confirmation && !expects_subscription)
+        {
+            let (Value::Array(items) | Value::Push(items)) = &value else {
+                return;
+            };
+            let parts = items
+                .iter()
+                .map(|item| quote(&argument_bytes(item)))
+                .collect::<Vec<_>>();
+            events.push(ProtocolEvent::Info(format!("(push) {}", parts.join(" "))));
+            return;
+        }
+
+        let Some(pending) = self.pending.front_mut() else {
+            events.push(ProtocolEvent::Info(format!(
+                "Unsolicited reply {}",
+                format_reply(&value)
+            )));
+            return;
+        };
+
+        pending.replies.push(format_reply(&value));
+        pending.remaining_replies -= 1;
+        if pending.remaining_replies > 0 {
+            return;
+        }
+
+        let pending = self.pending.pop_front().expect("pending command");
+        events.push(ProtocolEvent::Reply {
+            summary: pending.replies.join("; "),
+            is_error: matches!(value, Value::Error(_)),
+        });
+    }
+}
+
+impl ProtocolDecoder for RedisDecoder {
+    fn protocol(&self) -> &'static str {
+        "redis"
+    }
+
+    fn decode(&mut self, direction: FrameDirection, data: &[u8]) -> Result<Vec<ProtocolEvent>> {
+        match direction {
+            FrameDirection::ClientToServer => self.decode_client(data),
+            FrameDirection::ServerToClient => self.decode_server(data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(summary: &str, detail: &str) -> ProtocolEvent {
+        ProtocolEvent::Command {
+            summary: summary.to_string(),
+            detail: Some(detail.to_string()),
+        }
+    }
+
+    fn reply(summary: &str, is_error: bool) -> ProtocolEvent {
+        ProtocolEvent::Reply {
+            summary: summary.to_string(),
+            is_error,
+        }
+    }
+
+    #[test]
+    fn test_pipelined_commands() {
+        let mut decoder = RedisDecoder::new();
+
+        let client =
+            b"*2\r\n$4\r\nAUTH\r\n$6\r\nsecret\r\n*2\r\n$3\r\nget\r\n$6\r\nuser:1\r\nPING\r\n";
+        let (first, second) = client.split_at(30);
+        let mut events = decoder
+            .decode(FrameDirection::ClientToServer, first)
+            .unwrap();
+        events.extend(
+            decoder
+                .decode(FrameDirection::ClientToServer, second)
+                .unwrap(),
+        );
+        assert_eq!(
+            events,
+            vec![
+                command("AUTH", "AUTH <redacted>"),
+                command("GET", "GET \"user:1\""),
+                command("PING", "PING"),
+            ]
+        );
+
+        let events = decoder
+            .decode(
+                FrameDirection::ServerToClient,
+                b"+OK\r\n$5\r\nalice\r\n-ERR wrong\r\n",
+            )
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                reply("OK", false),
+                reply("\"alice\"", false),
+                reply("(error) ERR wrong", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resp3_and_pubsub() {
+        let mut decoder = RedisDecoder::new();
+
+        decoder
+            .decode(
+                FrameDirection::ClientToServer,
+                b"*3\r\n$9\r\nSUBSCRIBE\r\n$1\r\na\r\n$1\r\nb\r\n",
+            )
+            .unwrap();
+
+        let events = decoder
+            .decode(
+                FrameDirection::ServerToClient,
+                b"*3\r\n$9\r\nsubscribe\r\n$1\r\na\r\n:1\r\n*3\r\n$9\r\nsubscribe\r\n$1\r\nb\r\n:2\r\n>3\r\n$7\r\nmessage\r\n$1\r\na\r\n$2\r\nhi\r\n",
+            )
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                reply("(array 3); (array 3)", false),
+                ProtocolEvent::Info("(push) \"message\" \"a\" \"hi\"".to_string()),
+            ]
+        );
+
+        decoder
+            .decode(FrameDirection::ClientToServer, b"*1\r\n$6\r\nCONFIG\r\n")
+            .unwrap();
+        let events = decoder
+            .decode(
+                FrameDirection::ServerToClient,
+                b"|1\r\n+ttl\r\n:3\r\n%1\r\n+maxmemory\r\n$1\r\n0\r\n",
+            )
+            .unwrap();
+        assert_eq!(events, vec![reply("(map 1)", false)]);
+    }
+
+    #[test]
+    fn test_invalid_reply() {
+        let mut decoder = RedisDecoder::new();
+        assert!(decoder
+            .decode(FrameDirection::ServerToClient, b"?what\r\n")
+            .is_err());
+    }
+}
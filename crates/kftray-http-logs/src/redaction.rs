@@ -0,0 +1,530 @@
+use anyhow::{
+    Context,
+    Result,
+};
+use regex::Regex;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_json::Value;
+use tracing::{
+    debug,
+    trace,
+};
+
+pub const DEFAULT_REDACTION_PLACEHOLDER: &str = "[REDACTED]";
+
+pub const DEFAULT_REDACTED_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+    "x-auth-token",
+    "x-access-token",
+    "x-csrf-token",
+    "x-xsrf-token",
+    "x-amz-security-token",
+];
+
+pub const DEFAULT_REDACTED_JSON_PATHS: &[&str] = &[
+    "$..password",
+    "$..passwd",
+    "$..secret",
+    "$..client_secret",
+    "$..token",
+    "$..access_token",
+    "$..refresh_token",
+    "$..id_token",
+    "$..api_key",
+    "$..apiKey",
+    "$..private_key",
+];
+
+/// Patterns with a capture group named `secret` only have that group
+/// replaced, so surrounding context such as the query parameter name is kept.
+pub const DEFAULT_REDACTED_PATTERNS: &[&str] = &[
+    r"(?i)\bbearer\s+(?P<secret>[a-z0-9\-._~+/]+=*)",
+    r"(?i)[?&](?:access_token|api_key|apikey|token|password|secret|signature)=(?P<secret>[^&\s#]+)",
+    r"\beyJ[a-zA-Z0-9_-]{8,}\.[a-zA-Z0-9_-]{8,}\.[a-zA-Z0-9_-]{8,}\b",
+    r"\b(?:AKIA|ASIA)[A-Z0-9]{16}\b",
+    r"(?i)\b[a-z0-9._%+-]+@[a-z0-9.-]+\.[a-z]{2,}\b",
+];
+
+const SECRET_GROUP: &str = "secret";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedactionConfig {
+    pub enabled: bool,
+    pub headers: Vec<String>,
+    pub json_paths: Vec<String>,
+    pub patterns: Vec<String>,
+    pub placeholder: String,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            headers: DEFAULT_REDACTED_HEADERS
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            json_paths: DEFAULT_REDACTED_JSON_PATHS
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            patterns: DEFAULT_REDACTED_PATTERNS
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            placeholder: DEFAULT_REDACTION_PLACEHOLDER.to_string(),
+        }
+    }
+}
+
+impl RedactionConfig {
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Self::default()
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        RedactionRules::compile(self).map(|_| ())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PathSegment {
+    Key(String),
+    AnyKey,
+    Index(usize),
+    AnyIndex,
+    Descendant(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct JsonPath {
+    segments: Vec<PathSegment>,
+}
+
+impl JsonPath {
+    fn parse(expression: &str) -> Result<Self> {
+        let rest = expression
+            .trim()
+            .strip_prefix('$')
+            .ok_or_else(|| anyhow::anyhow!("JSON path must start with '$': {}", expression))?;
+
+        let mut segments = Vec::new();
+        let mut chars = rest.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '.' => {
+                    let descendant = chars.peek() == Some(&'.');
+                    if descendant {
+                        chars.next();
+                    }
+
+                    let mut name = String::new();
+                    while let Some(&next) = chars.peek() {
+                        if next == '.' || next == '[' {
+                            break;
+                        }
+                        name.push(next);
+                        chars.next();
+                    }
+
+                    if name.is_empty() {
+                        return Err(anyhow::anyhow!(
+                            "Empty segment in JSON path: {}",
+                            expression
+                        ));
+                    }
+
+                    segments.push(match (descendant, name.as_str()) {
+                        (true, _) => PathSegment::Descendant(name),
+                        (false, "*") => PathSegment::AnyKey,
+                        (false, _) => PathSegment::Key(name),
+                    });
+                }
+                '[' => {
+                    let mut inner = String::new();
+                    for next in chars.by_ref() {
+                        if next == ']' {
+                            break;
+                        }
+                        inner.push(next);
+                    }
+
+                    let inner = inner.trim();
+                    let segment = if inner == "*" {
+                        PathSegment::AnyIndex
+                    } else if let Ok(index) = inner.parse::<usize>() {
+                        PathSegment::Index(index)
+                    } else {
+                        let key = inner.trim_matches(|c| c == '\'' || c == '"');
+                        PathSegment::Key(key.to_string())
+                    };
+                    segments.push(segment);
+                }
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "Unexpected character '{}' in JSON path: {}",
+                        c,
+                        expression
+                    ));
+                }
+            }
+        }
+
+        if segments.is_empty() {
+            return Err(anyhow::anyhow!("JSON path has no segments: {}", expression));
+        }
+
+        Ok(Self { segments })
+    }
+
+    fn redact(&self, value: &mut Value, placeholder: &str) -> usize {
+        Self::redact_segments(&self.segments, value, placeholder)
+    }
+
+    fn redact_segments(segments: &[PathSegment], value: &mut Value, placeholder: &str) -> usize {
+        let Some((segment, rest)) = segments.split_first() else {
+            *value = Value::String(placeholder.to_string());
+            return 1;
+        };
+
+        match segment {
+            PathSegment::Key(key) => match value.get_mut(key.as_str()) {
+                Some(child) => Self::redact_segments(rest, child, placeholder),
+                None => 0,
+            },
+            PathSegment::AnyKey => match value {
+                Value::Object(map) => map
+                    .values_mut()
+                    .map(|child| Self::redact_segments(rest, child, placeholder))
+                    .sum(),
+                _ => 0,
+            },
+            PathSegment::Index(index) => match value.get_mut(*index) {
+                Some(child) => Self::redact_segments(rest, child, placeholder),
+                None => 0,
+            },
+            PathSegment::AnyIndex => match value {
+                Value::Array(items) => items
+                    .iter_mut()
+                    .map(|child| Self::redact_segments(rest, child, placeholder))
+                    .sum(),
+                _ => 0,
+            },
+            PathSegment::Descendant(key) => {
+                let mut count = 0;
+                match value {
+                    Value::Object(map) => {
+                        for (name, child) in map.iter_mut() {
+                            if name == key || key == "*" {
+                                count += Self::redact_segments(rest, child, placeholder);
+                            } else {
+                                count += Self::redact_segments(segments, child, placeholder);
+                            }
+                        }
+                    }
+                    Value::Array(items) => {
+                        for child in items.iter_mut() {
+                            count += Self::redact_segments(segments, child, placeholder);
+                        }
+                    }
+                    _ => {}
+                }
+                count
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RedactionRules {
+    enabled: bool,
+    headers: Vec<String>,
+    json_paths: Vec<JsonPath>,
+    patterns: Vec<Regex>,
+    placeholder: String,
+}
+
+impl Default for RedactionRules {
+    fn default() -> Self {
+        Self::compile(&RedactionConfig::default()).expect("default redaction rules must compile")
+    }
+}
+
+impl RedactionRules {
+    pub fn compile(config: &RedactionConfig) -> Result<Self> {
+        let json_paths = config
+            .json_paths
+            .iter()
+            .map(|path| JsonPath::parse(path))
+            .collect::<Result<Vec<_>>>()?;
+
+        let patterns = config
+            .patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .with_context(|| format!("Invalid redaction pattern: {}", pattern))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            enabled: config.enabled,
+            headers: config
+                .headers
+                .iter()
+                .map(|h| h.trim().to_ascii_lowercase())
+                .collect(),
+            json_paths,
+            patterns,
+            placeholder: config.placeholder.clone(),
+        })
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            headers: Vec::new(),
+            json_paths: Vec::new(),
+            patterns: Vec::new(),
+            placeholder: DEFAULT_REDACTION_PLACEHOLDER.to_string(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn placeholder(&self) -> &str {
+        &self.placeholder
+    }
+
+    pub fn is_sensitive_header(&self, name: &str) -> bool {
+        self.enabled && self.headers.iter().any(|h| h.eq_ignore_ascii_case(name))
+    }
+
+    pub fn redact_header_value(&self, name: &str, value: &str) -> String {
+        if self.is_sensitive_header(name) {
+            trace!("Redacting value of header {}", name);
+            return self.placeholder.clone();
+        }
+
+        self.redact_text(value)
+    }
+
+    pub fn redact_header_block(&self, block: &str) -> String {
+        if !self.enabled {
+            return block.to_string();
+        }
+
+        let mut redacted = String::with_capacity(block.len());
+
+        for (i, line) in block.split_inclusive('\n').enumerate() {
+            let content = line.trim_end_matches(['\r', '\n']);
+            let line_ending = &line[content.len()..];
+
+            if i == 0 {
+                redacted.push_str(&self.redact_text(content));
+            } else if let Some((name, value)) = content.split_once(':') {
+                redacted.push_str(name);
+                redacted.push_str(": ");
+                redacted.push_str(&self.redact_header_value(name.trim(), value.trim()));
+            } else {
+                redacted.push_str(content);
+            }
+
+            redacted.push_str(line_ending);
+        }
+
+        redacted
+    }
+
+    pub fn redact_json(&self, value: &mut Value) -> usize {
+        if !self.enabled {
+            return 0;
+        }
+
+        let mut count = 0;
+        for path in &self.json_paths {
+            count += path.redact(value, &self.placeholder);
+        }
+
+        if !self.patterns.is_empty() {
+            count += self.redact_json_strings(value);
+        }
+
+        count
+    }
+
+    fn redact_json_strings(&self, value: &mut Value) -> usize {
+        match value {
+            Value::String(s) => {
+                let redacted = self.redact_text(s);
+                if redacted != *s {
+                    *s = redacted;
+                    1
+                } else {
+                    0
+                }
+            }
+            Value::Array(items) => items.iter_mut().map(|v| self.redact_json_strings(v)).sum(),
+            Value::Object(map) => map.values_mut().map(|v| self.redact_json_strings(v)).sum(),
+            _ => 0,
+        }
+    }
+
+    pub fn redact_json_body(&self, body: &[u8]) -> Option<Vec<u8>> {
+        if !self.enabled || self.json_paths.is_empty() && self.patterns.is_empty() {
+            return None;
+        }
+
+        let text = std::str::from_utf8(body).ok()?;
+        let mut json = serde_json::from_str::<Value>(text.trim()).ok()?;
+
+        let count = self.redact_json(&mut json);
+        debug!("Redacted {} JSON values in body", count);
+
+        serde_json::to_vec(&json).ok()
+    }
+
+    pub fn redact_text(&self, text: &str) -> String {
+        if !self.enabled || self.patterns.is_empty() {
+            return text.to_string();
+        }
+
+        let mut current = text.to_string();
+
+        for pattern in &self.patterns {
+            if !pattern.is_match(&current) {
+                continue;
+            }
+
+            current = if pattern.capture_names().any(|n| n == Some(SECRET_GROUP)) {
+                Self::replace_secret_group(pattern, &current, &self.placeholder)
+            } else {
+                pattern
+                    .replace_all(&current, self.placeholder.as_str())
+                    .into_owned()
+            };
+        }
+
+        current
+    }
+
+    fn replace_secret_group(pattern: &Regex, text: &str, placeholder: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut last_end = 0;
+
+        for captures in pattern.captures_iter(text) {
+            if let Some(secret) = captures.name(SECRET_GROUP) {
+                result.push_str(&text[last_end..secret.start()]);
+                result.push_str(placeholder);
+                last_end = secret.end();
+            }
+        }
+
+        result.push_str(&text[last_end..]);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_redact_default_headers() {
+        let rules = RedactionRules::default();
+
+        assert_eq!(
+            rules.redact_header_value("Authorization", "Basic dXNlcjpwYXNz"),
+            DEFAULT_REDACTION_PLACEHOLDER
+        );
+        assert_eq!(rules.redact_header_value("Accept", "*/*"), "*/*");
+
+        let block =
+            "HTTP/1.1 200 OK\r\nSet-Cookie: session=abc\r\nContent-Type: text/plain\r\n\r\n";
+        let redacted = rules.redact_header_block(block);
+        assert!(redacted.contains("Set-Cookie: [REDACTED]\r\n"));
+        assert!(redacted.contains("Content-Type: text/plain\r\n"));
+        assert!(redacted.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_redact_json_paths() {
+        let rules = RedactionRules::compile(&RedactionConfig {
+            json_paths: vec![
+                "$..password".to_string(),
+                "$.user.ssn".to_string(),
+                "$.cards[*].number".to_string(),
+            ],
+            patterns: Vec::new(),
+            ..RedactionConfig::default()
+        })
+        .unwrap();
+
+        let mut value = json!({
+            "user": { "name": "alice", "ssn": "123-45-6789", "auth": { "password": "hunter2" } },
+            "cards": [{ "number": "4111" }, { "number": "5500" }],
+        });
+
+        assert_eq!(rules.redact_json(&mut value), 4);
+        assert_eq!(value["user"]["name"], "alice");
+        assert_eq!(value["user"]["ssn"], "[REDACTED]");
+        assert_eq!(value["user"]["auth"]["password"], "[REDACTED]");
+        assert_eq!(value["cards"][1]["number"], "[REDACTED]");
+    }
+
+    #[test]
+    fn test_redact_patterns() {
+        let rules = RedactionRules::default();
+
+        assert_eq!(
+            rules.redact_text("GET /callback?code=1&access_token=abc123&x=y HTTP/1.1"),
+            "GET /callback?code=1&access_token=[REDACTED]&x=y HTTP/1.1"
+        );
+        assert_eq!(
+            rules.redact_text("contact alice@example.com"),
+            "contact [REDACTED]"
+        );
+    }
+
+    #[test]
+    fn test_disabled_rules_keep_content() {
+        let rules = RedactionRules::compile(&RedactionConfig::disabled()).unwrap();
+
+        assert_eq!(
+            rules.redact_header_value("Authorization", "Bearer abc"),
+            "Bearer abc"
+        );
+        assert!(rules.redact_json_body(br#"{"password":"x"}"#).is_none());
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let config = RedactionConfig {
+            patterns: vec!["(".to_string()],
+            ..RedactionConfig::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = RedactionConfig {
+            json_paths: vec!["password".to_string()],
+            ..RedactionConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+}
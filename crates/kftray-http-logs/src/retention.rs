@@ -0,0 +1,696 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::pin::Pin;
+use std::sync::{
+    Arc,
+    Mutex,
+    RwLock,
+};
+use std::task::{
+    Context as TaskContext,
+    Poll,
+};
+use std::time::{
+    Duration,
+    SystemTime,
+};
+
+use anyhow::{
+    Context,
+    Result,
+};
+use chrono::{
+    DateTime,
+    Utc,
+};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use lazy_static::lazy_static;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tokio::fs::{
+    self,
+    File,
+    OpenOptions,
+};
+use tokio::io::{
+    AsyncWrite,
+    BufWriter,
+};
+use tokio::task::{
+    self,
+    JoinHandle,
+};
+use tracing::{
+    debug,
+    error,
+    info,
+    trace,
+};
+
+use crate::config::LogConfig;
+
+pub const DEFAULT_RETENTION_CHECK_INTERVAL_SECS: u64 = 15 * 60;
+
+pub const COMPRESSED_LOG_EXTENSION: &str = "gz";
+
+const LOG_WRITER_CAPACITY: usize = 64 * 1024;
+
+/// Scheduled retention of a log directory, with the config it applies,
+/// updated by the loggers started since.
+struct RetentionTask {
+    handle: JoinHandle<()>,
+    config: Arc<RwLock<LogConfig>>,
+}
+
+lazy_static! {
+    static ref RETENTION_TASKS: Mutex<BTreeMap<PathBuf, RetentionTask>> =
+        Mutex::new(BTreeMap::new());
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFileEntry {
+    pub path: PathBuf,
+    pub config_id: i64,
+    pub size: u64,
+    pub modified: SystemTime,
+    pub rotated: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigLogUsage {
+    pub config_id: i64,
+    pub total_bytes: u64,
+    pub active_bytes: u64,
+    pub rotated_bytes: u64,
+    pub file_count: usize,
+    pub rotated_file_count: usize,
+    pub quota_bytes: u64,
+    pub oldest_entry: Option<DateTime<Utc>>,
+    pub newest_entry: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogUsageReport {
+    pub log_dir: PathBuf,
+    pub total_bytes: u64,
+    pub max_log_size: u64,
+    pub retention_days: u64,
+    pub configs: Vec<ConfigLogUsage>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PruneReport {
+    pub expired_files: usize,
+    pub quota_files: usize,
+    pub freed_bytes: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct RetentionManager {
+    config: LogConfig,
+}
+
+impl RetentionManager {
+    pub fn new(config: LogConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn config(&self) -> &LogConfig {
+        &self.config
+    }
+
+    pub fn ensure_background_task(config: &LogConfig) {
+        if tokio::runtime::Handle::try_current().is_err() {
+            return;
+        }
+
+        let Ok(mut tasks) = RETENTION_TASKS.lock() else {
+            error!("Failed to lock HTTP log retention task registry");
+            return;
+        };
+
+        let log_dir = config.log_dir().to_path_buf();
+        if let Some(task) = tasks
+            .get(&log_dir)
+            .filter(|task| !task.handle.is_finished())
+        {
+            // Keeps the latest per-config quotas for the next run
+            if let Ok(mut current) = task.config.write() {
+                *current = config.clone();
+            }
+            return;
+        }
+
+        let shared_config = Arc::new(RwLock::new(config.clone()));
+        let task_config = shared_config.clone();
+        let handle = tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(DEFAULT_RETENTION_CHECK_INTERVAL_SECS));
+            loop {
+                interval.tick().await;
+                trace!("Running scheduled HTTP log retention");
+
+                let config = match task_config.read() {
+                    Ok(config) => config.clone(),
+                    Err(_) => {
+                        error!("Failed to read HTTP log retention config");
+                        continue;
+                    }
+                };
+
+                match Self::new(config).prune().await {
+                    Ok(report) if report.expired_files + report.quota_files > 0 => {
+                        info!(
+                            "HTTP log retention removed {} expired and {} over-quota files ({} bytes)",
+                            report.expired_files, report.quota_files, report.freed_bytes
+                        );
+                    }
+                    Ok(_) => {}
+                    Err(e) => error!("Failed to apply HTTP log retention: {:?}", e),
+                }
+            }
+        });
+
+        debug!("Started HTTP log retention task for {}", log_dir.display());
+        tasks.insert(
+            log_dir,
+            RetentionTask {
+                handle,
+                config: shared_config,
+            },
+        );
+    }
+
+    pub fn stop_background_tasks() {
+        if let Ok(mut tasks) = RETENTION_TASKS.lock() {
+            for (_, task) in std::mem::take(&mut *tasks) {
+                task.handle.abort();
+            }
+        }
+    }
+
+    /// Whether a log file on disk is due for rotation. Use `LogWriter::written`
+    /// for a file being written, as its size on disk lags behind the buffer.
+    pub async fn needs_rotation(&self, active_path: &Path) -> Result<bool> {
+        match fs::metadata(active_path).await {
+            Ok(metadata) => Ok(self.exceeds_max_log_size(metadata.len())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e).context("Failed to read log file metadata"),
+        }
+    }
+
+    pub fn exceeds_max_log_size(&self, size: u64) -> bool {
+        size >= self.config.max_log_size()
+    }
+
+    /// Moves the active log aside under a timestamped name. The caller must
+    /// have flushed and released its handle to `active_path` beforehand.
+    pub async fn rotate(&self, active_path: &Path) -> Result<PathBuf> {
+        let rotated_path = self.rotated_path_for(active_path).await;
+
+        fs::rename(active_path, &rotated_path)
+            .await
+            .with_context(|| format!("Failed to rotate log file {}", active_path.display()))?;
+
+        debug!(
+            "Rotated log file {} to {}",
+            active_path.display(),
+            rotated_path.display()
+        );
+
+        Ok(rotated_path)
+    }
+
+    pub async fn compress(&self, path: &Path) -> Result<PathBuf> {
+        let source = path.to_path_buf();
+        let target = compressed_path(path);
+        let target_clone = target.clone();
+
+        task::spawn_blocking(move || -> Result<()> {
+            let data = std::fs::read(&source).context("Failed to read rotated log file")?;
+            let file = std::fs::File::create(&target_clone)
+                .context("Failed to create compressed log file")?;
+            let mut encoder = GzEncoder::new(file, Compression::default());
+            encoder.write_all(&data)?;
+            encoder.finish()?.sync_all()?;
+            std::fs::remove_file(&source).context("Failed to remove uncompressed log file")?;
+            Ok(())
+        })
+        .await??;
+
+        debug!("Compressed rotated log file to {}", target.display());
+        Ok(target)
+    }
+
+    pub async fn rotate_and_compress(&self, active_path: &Path) -> Result<PathBuf> {
+        let rotated = self.rotate(active_path).await?;
+        self.compress(&rotated).await
+    }
+
+    pub async fn list_files(&self) -> Result<Vec<LogFileEntry>> {
+        let mut entries = Vec::new();
+
+        let mut dir = match fs::read_dir(self.config.log_dir()).await {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(entries),
+            Err(e) => return Err(e).context("Failed to read log directory"),
+        };
+
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            let Some((config_id, rotated)) = self.parse_log_file_name(&path) else {
+                continue;
+            };
+
+            let metadata = match entry.metadata().await {
+                Ok(metadata) if metadata.is_file() => metadata,
+                _ => continue,
+            };
+
+            entries.push(LogFileEntry {
+                path,
+                config_id,
+                size: metadata.len(),
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                rotated,
+            });
+        }
+
+        Ok(entries)
+    }
+
+    pub async fn prune(&self) -> Result<PruneReport> {
+        let mut report = self.prune_expired().await?;
+        let quota_report = self.enforce_quotas().await?;
+
+        report.quota_files = quota_report.quota_files;
+        report.freed_bytes += quota_report.freed_bytes;
+
+        Ok(report)
+    }
+
+    pub async fn prune_expired(&self) -> Result<PruneReport> {
+        let mut report = PruneReport::default();
+        let max_age = Duration::from_secs(self.config.retention_days() * 24 * 60 * 60);
+        let now = SystemTime::now();
+
+        for entry in self.list_files().await? {
+            let age = now.duration_since(entry.modified).unwrap_or_default();
+            if age <= max_age {
+                continue;
+            }
+
+            match fs::remove_file(&entry.path).await {
+                Ok(_) => {
+                    trace!("Removed expired log file {}", entry.path.display());
+                    report.expired_files += 1;
+                    report.freed_bytes += entry.size;
+                }
+                Err(e) => error!(
+                    "Failed to remove expired log file {}: {:?}",
+                    entry.path.display(),
+                    e
+                ),
+            }
+        }
+
+        Ok(report)
+    }
+
+    pub async fn enforce_quotas(&self) -> Result<PruneReport> {
+        let mut report = PruneReport::default();
+
+        for (config_id, mut files) in group_by_config(self.list_files().await?) {
+            let quota = self.config.config_disk_quota(config_id);
+            let mut total: u64 = files.iter().map(|f| f.size).sum();
+            if total <= quota {
+                continue;
+            }
+
+            debug!(
+                "HTTP logs for config {} use {} bytes, over quota of {} bytes",
+                config_id, total, quota
+            );
+
+            files.retain(|f| f.rotated);
+            files.sort_by_key(|f| f.modified);
+
+            for file in files {
+                if total <= quota {
+                    break;
+                }
+
+                match fs::remove_file(&file.path).await {
+                    Ok(_) => {
+                        total = total.saturating_sub(file.size);
+                        report.quota_files += 1;
+                        report.freed_bytes += file.size;
+                    }
+                    Err(e) => error!(
+                        "Failed to remove over-quota log file {}: {:?}",
+                        file.path.display(),
+                        e
+                    ),
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    pub async fn usage_report(&self) -> Result<LogUsageReport> {
+        let mut configs = Vec::new();
+
+        for (config_id, files) in group_by_config(self.list_files().await?) {
+            let mut usage = ConfigLogUsage {
+                config_id,
+                quota_bytes: self.config.config_disk_quota(config_id),
+                ..ConfigLogUsage::default()
+            };
+
+            for file in &files {
+                usage.total_bytes += file.size;
+                usage.file_count += 1;
+                if file.rotated {
+                    usage.rotated_bytes += file.size;
+                    usage.rotated_file_count += 1;
+                } else {
+                    usage.active_bytes += file.size;
+                }
+            }
+
+            usage.oldest_entry = files.iter().map(|f| f.modified).min().map(DateTime::from);
+            usage.newest_entry = files.iter().map(|f| f.modified).max().map(DateTime::from);
+
+            configs.push(usage);
+        }
+
+        Ok(LogUsageReport {
+            log_dir: self.config.log_dir().to_path_buf(),
+            total_bytes: configs.iter().map(|c| c.total_bytes).sum(),
+            max_log_size: self.config.max_log_size(),
+            retention_days: self.config.retention_days(),
+            configs,
+        })
+    }
+
+    pub async fn config_usage(&self, config_id: i64) -> Result<ConfigLogUsage> {
+        let report = self.usage_report().await?;
+
+        Ok(report
+            .configs
+            .into_iter()
+            .find(|usage| usage.config_id == config_id)
+            .unwrap_or(ConfigLogUsage {
+                config_id,
+                quota_bytes: self.config.config_disk_quota(config_id),
+                ..ConfigLogUsage::default()
+            }))
+    }
+
+    async fn rotated_path_for(&self, active_path: &Path) -> PathBuf {
+        let stem = active_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("http_log");
+        let timestamp = Utc::now().format("%Y%m%d_%H%M%S");
+        let extension = self.config.file_extension();
+        let dir = active_path.parent().unwrap_or(self.config.log_dir());
+
+        let mut candidate = dir.join(format!("{}_{}.{}", stem, timestamp, extension));
+        let mut suffix = 1;
+        while fs::try_exists(&candidate).await.unwrap_or(false)
+            || fs::try_exists(compressed_path(&candidate))
+                .await
+                .unwrap_or(false)
+        {
+            candidate = dir.join(format!("{}_{}_{}.{}", stem, timestamp, suffix, extension));
+            suffix += 1;
+        }
+
+        candidate
+    }
+
+    fn parse_log_file_name(&self, path: &Path) -> Option<(i64, bool)> {
+        let file_name = path.file_name()?.to_str()?;
+        let extension = format!(".{}", self.config.file_extension());
+
+        let (base, compressed) =
+            match file_name.strip_suffix(&format!(".{}", COMPRESSED_LOG_EXTENSION)) {
+                Some(base) => (base, true),
+                None => (file_name, false),
+            };
+        let base = base.strip_suffix(&extension)?;
+
+        let mut parts = base.split('_');
+        let config_id = parts.next()?.parse::<i64>().ok()?;
+        parts.next()?.parse::<u16>().ok()?;
+        let rotated = compressed || parts.next().is_some();
+
+        Some((config_id, rotated))
+    }
+}
+
+/// Buffered writer of an active log file, counting the bytes written through
+/// it, still buffered ones included, so rotation happens at the size limit
+/// rather than once the buffer reaches the disk.
+#[derive(Debug)]
+pub struct LogWriter {
+    inner: BufWriter<File>,
+    written: u64,
+}
+
+impl LogWriter {
+    /// Opens a log file for appending, counting from its current size.
+    pub async fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .await
+            .context("Failed to open log file")?;
+        let written = file
+            .metadata()
+            .await
+            .context("Failed to read log file metadata")?
+            .len();
+
+        Ok(Self {
+            inner: BufWriter::with_capacity(LOG_WRITER_CAPACITY, file),
+            written,
+        })
+    }
+
+    /// Size of the file once everything written is flushed.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn get_mut(&mut self) -> &mut File {
+        self.inner.get_mut()
+    }
+}
+
+impl AsyncWrite for LogWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            self.written += written as u64;
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+fn compressed_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(COMPRESSED_LOG_EXTENSION);
+    PathBuf::from(name)
+}
+
+fn group_by_config(files: Vec<LogFileEntry>) -> BTreeMap<i64, Vec<LogFileEntry>> {
+    let mut grouped: BTreeMap<i64, Vec<LogFileEntry>> = BTreeMap::new();
+    for file in files {
+        grouped.entry(file.config_id).or_default().push(file);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn manager(temp_dir: &TempDir, max_log_size: u64, quota: u64) -> RetentionManager {
+        RetentionManager::new(
+            LogConfig::builder(temp_dir.path().to_path_buf())
+                .max_log_size(max_log_size)
+                .max_config_disk_usage(quota)
+                .build(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_rotate_and_compress() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = manager(&temp_dir, 8, 1024);
+        let active = temp_dir.path().join("7_8080.http");
+        fs::write(&active, b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+
+        assert!(manager.needs_rotation(&active).await.unwrap());
+
+        let compressed = manager.rotate_and_compress(&active).await.unwrap();
+        assert!(!active.exists());
+        assert!(compressed.to_str().unwrap().ends_with(".http.gz"));
+
+        let mut decoder = flate2::read::GzDecoder::new(std::fs::File::open(&compressed).unwrap());
+        let mut content = String::new();
+        decoder.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "GET / HTTP/1.1\r\n\r\n");
+
+        let usage = manager.config_usage(7).await.unwrap();
+        assert_eq!(usage.rotated_file_count, 1);
+        assert_eq!(usage.active_bytes, 0);
+    }
+
+    #[tokio::test]
+    async fn test_enforce_quota_keeps_active_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = manager(&temp_dir, 1024, 150);
+
+        fs::write(temp_dir.path().join("1_80.http"), vec![b'a'; 100])
+            .await
+            .unwrap();
+        fs::write(
+            temp_dir.path().join("1_80_20240101_000000.http.gz"),
+            vec![b'b'; 100],
+        )
+        .await
+        .unwrap();
+        fs::write(temp_dir.path().join("2_80.http"), vec![b'c'; 100])
+            .await
+            .unwrap();
+        fs::write(temp_dir.path().join("notes.txt"), b"ignored")
+            .await
+            .unwrap();
+
+        let report = manager.enforce_quotas().await.unwrap();
+        assert_eq!(report.quota_files, 1);
+        assert_eq!(report.freed_bytes, 100);
+        assert!(temp_dir.path().join("1_80.http").exists());
+        assert!(temp_dir.path().join("notes.txt").exists());
+
+        let usage = manager.usage_report().await.unwrap();
+        assert_eq!(usage.configs.len(), 2);
+        assert_eq!(usage.total_bytes, 200);
+    }
+
+    #[tokio::test]
+    async fn test_enforce_per_config_quota() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = RetentionManager::new(
+            LogConfig::builder(temp_dir.path().to_path_buf())
+                .max_config_disk_usage(1000)
+                .config_disk_quotas(BTreeMap::from([(1, 150)]))
+                .build(),
+        );
+
+        for config_id in [1, 2] {
+            fs::write(
+                temp_dir.path().join(format!("{}_80.http", config_id)),
+                vec![b'a'; 100],
+            )
+            .await
+            .unwrap();
+            fs::write(
+                temp_dir
+                    .path()
+                    .join(format!("{}_80_20240101_000000.http.gz", config_id)),
+                vec![b'b'; 100],
+            )
+            .await
+            .unwrap();
+        }
+
+        let report = manager.enforce_quotas().await.unwrap();
+        assert_eq!(report.quota_files, 1);
+        assert!(!temp_dir
+            .path()
+            .join("1_80_20240101_000000.http.gz")
+            .exists());
+        assert!(temp_dir
+            .path()
+            .join("2_80_20240101_000000.http.gz")
+            .exists());
+
+        let usage = manager.usage_report().await.unwrap();
+        assert_eq!(usage.configs[0].quota_bytes, 150);
+        assert_eq!(usage.configs[1].quota_bytes, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_log_writer_counts_buffered_bytes() {
+        use tokio::io::AsyncWriteExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let manager = manager(&temp_dir, 64, 1024);
+        let path = temp_dir.path().join("5_8080.http");
+        fs::write(&path, vec![b'a'; 40]).await.unwrap();
+
+        let mut writer = LogWriter::open(&path).await.unwrap();
+        assert_eq!(writer.written(), 40);
+
+        writer.write_all(&[b'b'; 30]).await.unwrap();
+        assert_eq!(writer.written(), 70);
+        assert!(manager.exceeds_max_log_size(writer.written()));
+        assert!(!manager.needs_rotation(&path).await.unwrap());
+
+        writer.flush().await.unwrap();
+        assert!(manager.needs_rotation(&path).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_prune_expired() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = RetentionManager::new(
+            LogConfig::builder(temp_dir.path().to_path_buf())
+                .retention_days(0)
+                .build(),
+        );
+
+        let old_file = temp_dir.path().join("3_9000_20240101_000000.http.gz");
+        std::fs::write(&old_file, b"old").unwrap();
+        let file = std::fs::File::options()
+            .write(true)
+            .open(&old_file)
+            .unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(3600))
+            .unwrap();
+
+        let report = manager.prune_expired().await.unwrap();
+        assert_eq!(report.expired_files, 1);
+        assert!(!old_file.exists());
+    }
+}
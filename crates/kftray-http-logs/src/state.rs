@@ -1,4 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{
+    BTreeMap,
+    HashMap,
+};
+use std::path::PathBuf;
 use std::sync::atomic::{
     AtomicBool,
     Ordering,
@@ -23,6 +27,19 @@ use tracing::{
     trace,
 };
 
+use crate::cassette::{
+    CassetteConfig,
+    CassetteRecorder,
+};
+use crate::filter::LogFilterConfig;
+use crate::grpc::GrpcDecoder;
+use crate::mock::{
+    MockEngine,
+    MockRulesConfig,
+};
+use crate::protocols::LogProtocol;
+use crate::redaction::RedactionConfig;
+
 pub const DEFAULT_CLEANUP_INTERVAL_SECS: u64 = 3600;
 
 pub const DEFAULT_CONFIG_RETENTION_SECS: u64 = 24 * 60 * 60;
@@ -38,6 +55,13 @@ struct ConfigState {
     enabled: AtomicBool,
     last_updated: SystemTime,
     metadata: Option<String>,
+    redaction: Option<RedactionConfig>,
+    filter: Option<LogFilterConfig>,
+    grpc_descriptor_set: Option<PathBuf>,
+    protocol: Option<LogProtocol>,
+    mock_rules: Option<MockRulesConfig>,
+    cassette: Option<CassetteConfig>,
+    disk_quota: Option<u64>,
 }
 
 impl Clone for ConfigState {
@@ -46,6 +70,13 @@ impl Clone for ConfigState {
             enabled: AtomicBool::new(self.enabled.load(Ordering::SeqCst)),
             last_updated: self.last_updated,
             metadata: self.metadata.clone(),
+            redaction: self.redaction.clone(),
+            filter: self.filter.clone(),
+            grpc_descriptor_set: self.grpc_descriptor_set.clone(),
+            protocol: self.protocol,
+            mock_rules: self.mock_rules.clone(),
+            cassette: self.cassette.clone(),
+            disk_quota: self.disk_quota,
         }
     }
 }
@@ -56,6 +87,13 @@ impl ConfigState {
             enabled: AtomicBool::new(enabled),
             last_updated: SystemTime::now(),
             metadata,
+            redaction: None,
+            filter: None,
+            grpc_descriptor_set: None,
+            protocol: None,
+            mock_rules: None,
+            cassette: None,
+            disk_quota: None,
         }
     }
 
@@ -181,6 +219,227 @@ impl LogStateManager {
         Ok(())
     }
 
+    pub async fn set_redaction_config(
+        &self, config_id: i64, redaction: RedactionConfig,
+    ) -> Result<()> {
+        redaction.validate()?;
+
+        let mut state = self.state.lock().await;
+        debug!(
+            "Setting HTTP log redaction for config {} (enabled: {})",
+            config_id, redaction.enabled
+        );
+
+        if let Some(config_state) = state.get_mut(&config_id) {
+            config_state.redaction = Some(redaction);
+            config_state.touch();
+        } else {
+            let mut config_state = ConfigState::new(false, None);
+            config_state.redaction = Some(redaction);
+            state.insert(config_id, config_state);
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_redaction_config(&self, config_id: i64) -> Result<RedactionConfig> {
+        let state = self.state.lock().await;
+
+        Ok(state
+            .get(&config_id)
+            .and_then(|config_state| config_state.redaction.clone())
+            .unwrap_or_default())
+    }
+
+    pub async fn set_filter_config(&self, config_id: i64, filter: LogFilterConfig) -> Result<()> {
+        filter.validate()?;
+
+        let mut state = self.state.lock().await;
+        debug!("Setting HTTP log filter for config {}", config_id);
+
+        if let Some(config_state) = state.get_mut(&config_id) {
+            config_state.filter = Some(filter);
+            config_state.touch();
+        } else {
+            let mut config_state = ConfigState::new(false, None);
+            config_state.filter = Some(filter);
+            state.insert(config_id, config_state);
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_filter_config(&self, config_id: i64) -> Result<LogFilterConfig> {
+        let state = self.state.lock().await;
+
+        Ok(state
+            .get(&config_id)
+            .and_then(|config_state| config_state.filter.clone())
+            .unwrap_or_default())
+    }
+
+    pub async fn set_grpc_descriptor_set(
+        &self, config_id: i64, descriptor_set: Option<PathBuf>,
+    ) -> Result<()> {
+        if let Some(path) = &descriptor_set {
+            GrpcDecoder::from_descriptor_set(path)?;
+        }
+
+        let mut state = self.state.lock().await;
+        debug!(
+            "Setting gRPC descriptor set for config {}: {:?}",
+            config_id, descriptor_set
+        );
+
+        if let Some(config_state) = state.get_mut(&config_id) {
+            config_state.grpc_descriptor_set = descriptor_set;
+            config_state.touch();
+        } else {
+            let mut config_state = ConfigState::new(false, None);
+            config_state.grpc_descriptor_set = descriptor_set;
+            state.insert(config_id, config_state);
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_grpc_descriptor_set(&self, config_id: i64) -> Result<Option<PathBuf>> {
+        let state = self.state.lock().await;
+
+        Ok(state
+            .get(&config_id)
+            .and_then(|config_state| config_state.grpc_descriptor_set.clone()))
+    }
+
+    pub async fn set_log_protocol(&self, config_id: i64, protocol: LogProtocol) -> Result<()> {
+        let mut state = self.state.lock().await;
+        debug!(
+            "Setting log protocol for config {}: {}",
+            config_id, protocol
+        );
+
+        if let Some(config_state) = state.get_mut(&config_id) {
+            config_state.protocol = Some(protocol);
+            config_state.touch();
+        } else {
+            let mut config_state = ConfigState::new(false, None);
+            config_state.protocol = Some(protocol);
+            state.insert(config_id, config_state);
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_log_protocol(&self, config_id: i64) -> Result<LogProtocol> {
+        let state = self.state.lock().await;
+
+        Ok(state
+            .get(&config_id)
+            .and_then(|config_state| config_state.protocol)
+            .unwrap_or_default())
+    }
+
+    pub async fn set_mock_rules(&self, config_id: i64, rules: MockRulesConfig) -> Result<()> {
+        MockEngine::compile(&rules)?;
+
+        let mut state = self.state.lock().await;
+        debug!(
+            "Setting {} mock rules for config {}",
+            rules.rules.len(),
+            config_id
+        );
+
+        if let Some(config_state) = state.get_mut(&config_id) {
+            config_state.mock_rules = Some(rules);
+            config_state.touch();
+        } else {
+            let mut config_state = ConfigState::new(false, None);
+            config_state.mock_rules = Some(rules);
+            state.insert(config_id, config_state);
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_mock_rules(&self, config_id: i64) -> Result<MockRulesConfig> {
+        let state = self.state.lock().await;
+
+        Ok(state
+            .get(&config_id)
+            .and_then(|config_state| config_state.mock_rules.clone())
+            .unwrap_or_default())
+    }
+
+    pub async fn set_cassette(
+        &self, config_id: i64, cassette: Option<CassetteConfig>,
+    ) -> Result<()> {
+        if let Some(cassette) = &cassette {
+            cassette.validate()?;
+        }
+
+        let mut state = self.state.lock().await;
+        debug!("Setting cassette for config {}: {:?}", config_id, cassette);
+
+        let config_state = state
+            .entry(config_id)
+            .or_insert_with(|| ConfigState::new(false, None));
+        let previous = std::mem::replace(&mut config_state.cassette, cassette);
+        config_state.touch();
+        drop(state);
+
+        match previous {
+            Some(previous) => CassetteRecorder::release(&previous.path).await,
+            None => Ok(()),
+        }
+    }
+
+    pub async fn get_cassette(&self, config_id: i64) -> Result<Option<CassetteConfig>> {
+        let state = self.state.lock().await;
+
+        Ok(state
+            .get(&config_id)
+            .and_then(|config_state| config_state.cassette.clone()))
+    }
+
+    /// Sets the disk quota of a config's logs, or goes back to the global one
+    /// with `None`.
+    pub async fn set_disk_quota(&self, config_id: i64, quota: Option<u64>) -> Result<()> {
+        if quota == Some(0) {
+            anyhow::bail!("Disk quota must be greater than zero");
+        }
+
+        let mut state = self.state.lock().await;
+        debug!("Setting disk quota for config {}: {:?}", config_id, quota);
+
+        let config_state = state
+            .entry(config_id)
+            .or_insert_with(|| ConfigState::new(false, None));
+        config_state.disk_quota = quota;
+        config_state.touch();
+
+        Ok(())
+    }
+
+    pub async fn get_disk_quota(&self, config_id: i64) -> Result<Option<u64>> {
+        let state = self.state.lock().await;
+
+        Ok(state
+            .get(&config_id)
+            .and_then(|config_state| config_state.disk_quota))
+    }
+
+    /// Disk quotas of the configs that have their own.
+    pub async fn disk_quotas(&self) -> BTreeMap<i64, u64> {
+        let state = self.state.lock().await;
+
+        state
+            .iter()
+            .filter_map(|(config_id, config_state)| {
+                config_state.disk_quota.map(|quota| (*config_id, quota))
+            })
+            .collect()
+    }
+
     pub async fn config_count(&self) -> usize {
         let state = self.state.lock().await;
         state.len()
@@ -254,6 +513,11 @@ impl Drop for LogStateManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cassette::CassetteMode;
+    use crate::mock::{
+        MockResponse,
+        MockRule,
+    };
 
     #[tokio::test]
     async fn test_set_and_get_http_logs() {
@@ -312,4 +576,160 @@ mod tests {
 
         assert!(manager.get_http_logs(1).await.unwrap());
     }
+
+    #[tokio::test]
+    async fn test_redaction_config() {
+        let manager = LogStateManager::new();
+
+        assert_eq!(
+            manager.get_redaction_config(1).await.unwrap(),
+            RedactionConfig::default()
+        );
+
+        let custom = RedactionConfig {
+            headers: vec!["x-tenant-id".to_string()],
+            ..RedactionConfig::default()
+        };
+        manager
+            .set_redaction_config(1, custom.clone())
+            .await
+            .unwrap();
+        assert_eq!(manager.get_redaction_config(1).await.unwrap(), custom);
+        assert!(!manager.get_http_logs(1).await.unwrap());
+
+        let invalid = RedactionConfig {
+            patterns: vec!["[".to_string()],
+            ..RedactionConfig::default()
+        };
+        assert!(manager.set_redaction_config(1, invalid).await.is_err());
+        assert_eq!(manager.get_redaction_config(1).await.unwrap(), custom);
+    }
+
+    #[tokio::test]
+    async fn test_filter_config() {
+        let manager = LogStateManager::new();
+
+        assert_eq!(
+            manager.get_filter_config(1).await.unwrap(),
+            LogFilterConfig::default()
+        );
+
+        let filter = LogFilterConfig {
+            sample_rate: 0.25,
+            ..LogFilterConfig::default()
+        };
+        manager.set_filter_config(1, filter.clone()).await.unwrap();
+        assert_eq!(manager.get_filter_config(1).await.unwrap(), filter);
+
+        let invalid = LogFilterConfig {
+            sample_rate: -1.0,
+            ..LogFilterConfig::default()
+        };
+        assert!(manager.set_filter_config(1, invalid).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_grpc_descriptor_set() {
+        let manager = LogStateManager::new();
+
+        assert_eq!(manager.get_grpc_descriptor_set(1).await.unwrap(), None);
+        assert!(manager
+            .set_grpc_descriptor_set(1, Some(PathBuf::from("/nonexistent/descriptors.pb")))
+            .await
+            .is_err());
+
+        manager.set_grpc_descriptor_set(1, None).await.unwrap();
+        assert_eq!(manager.get_grpc_descriptor_set(1).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_log_protocol() {
+        let manager = LogStateManager::new();
+
+        assert_eq!(
+            manager.get_log_protocol(1).await.unwrap(),
+            LogProtocol::Http
+        );
+
+        manager
+            .set_log_protocol(1, LogProtocol::Redis)
+            .await
+            .unwrap();
+        assert_eq!(
+            manager.get_log_protocol(1).await.unwrap(),
+            LogProtocol::Redis
+        );
+        assert!(!manager.get_http_logs(1).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_mock_rules() {
+        let manager = LogStateManager::new();
+
+        assert_eq!(
+            manager.get_mock_rules(1).await.unwrap(),
+            MockRulesConfig::default()
+        );
+
+        let rules = MockRulesConfig {
+            rules: vec![MockRule {
+                name: "health".to_string(),
+                respond: Some(MockResponse::default()),
+                ..MockRule::default()
+            }],
+        };
+        manager.set_mock_rules(1, rules.clone()).await.unwrap();
+        assert_eq!(manager.get_mock_rules(1).await.unwrap(), rules);
+
+        let invalid = MockRulesConfig {
+            rules: vec![MockRule {
+                respond: Some(MockResponse {
+                    status: 42,
+                    ..MockResponse::default()
+                }),
+                ..MockRule::default()
+            }],
+        };
+        assert!(manager.set_mock_rules(1, invalid).await.is_err());
+        assert_eq!(manager.get_mock_rules(1).await.unwrap(), rules);
+    }
+
+    #[tokio::test]
+    async fn test_cassette() {
+        let manager = LogStateManager::new();
+
+        assert_eq!(manager.get_cassette(1).await.unwrap(), None);
+
+        let record = CassetteConfig {
+            mode: CassetteMode::Record,
+            path: PathBuf::from("/nonexistent/cassette.json"),
+        };
+        manager.set_cassette(1, Some(record.clone())).await.unwrap();
+        assert_eq!(manager.get_cassette(1).await.unwrap(), Some(record));
+
+        let replay = CassetteConfig {
+            mode: CassetteMode::Replay,
+            path: PathBuf::from("/nonexistent/cassette.json"),
+        };
+        assert!(manager.set_cassette(1, Some(replay)).await.is_err());
+
+        manager.set_cassette(1, None).await.unwrap();
+        assert_eq!(manager.get_cassette(1).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_disk_quota() {
+        let manager = LogStateManager::new();
+
+        assert_eq!(manager.get_disk_quota(1).await.unwrap(), None);
+        assert!(manager.set_disk_quota(1, Some(0)).await.is_err());
+
+        manager.set_disk_quota(1, Some(2048)).await.unwrap();
+        manager.set_http_logs(2, true).await.unwrap();
+        assert_eq!(manager.get_disk_quota(1).await.unwrap(), Some(2048));
+        assert_eq!(manager.disk_quotas().await, BTreeMap::from([(1, 2048)]));
+
+        manager.set_disk_quota(1, None).await.unwrap();
+        assert!(manager.disk_quotas().await.is_empty());
+    }
 }
@@ -0,0 +1,466 @@
+use std::fmt;
+
+use anyhow::{
+    anyhow,
+    Result,
+};
+use chrono::{
+    DateTime,
+    Utc,
+};
+use flate2::{
+    Decompress,
+    FlushDecompress,
+    Status,
+};
+
+const MAX_CAPTURED_PAYLOAD: usize = 64 * 1024;
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Returns the bytes that followed the headers of a `101 Switching Protocols`
+/// WebSocket upgrade response; they already belong to the frame stream.
+pub fn frames_after_upgrade(response: &[u8]) -> Option<&[u8]> {
+    let status_line_ok =
+        response.starts_with(b"HTTP/1.1 101") || response.starts_with(b"HTTP/1.0 101");
+    if !status_line_ok
+        || !crate::http_response_analyzer::HttpResponseAnalyzer::is_websocket_upgrade(response)
+    {
+        return None;
+    }
+
+    response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|end| &response[end + 4..])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDirection {
+    ClientToServer,
+    ServerToClient,
+}
+
+impl fmt::Display for FrameDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameDirection::ClientToServer => write!(f, "client -> server"),
+            FrameDirection::ServerToClient => write!(f, "server -> client"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+    Reserved(u8),
+}
+
+impl From<u8> for Opcode {
+    fn from(value: u8) -> Self {
+        match value {
+            0x0 => Opcode::Continuation,
+            0x1 => Opcode::Text,
+            0x2 => Opcode::Binary,
+            0x8 => Opcode::Close,
+            0x9 => Opcode::Ping,
+            0xa => Opcode::Pong,
+            other => Opcode::Reserved(other),
+        }
+    }
+}
+
+impl Opcode {
+    pub fn is_control(&self) -> bool {
+        match self {
+            Opcode::Close | Opcode::Ping | Opcode::Pong => true,
+            Opcode::Reserved(code) => *code >= 0x8,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Opcode::Continuation => write!(f, "continuation"),
+            Opcode::Text => write!(f, "text"),
+            Opcode::Binary => write!(f, "binary"),
+            Opcode::Close => write!(f, "close"),
+            Opcode::Ping => write!(f, "ping"),
+            Opcode::Pong => write!(f, "pong"),
+            Opcode::Reserved(code) => write!(f, "reserved (0x{:x})", code),
+        }
+    }
+}
+
+pub fn close_code_name(code: u16) -> &'static str {
+    match code {
+        1000 => "Normal Closure",
+        1001 => "Going Away",
+        1002 => "Protocol Error",
+        1003 => "Unsupported Data",
+        1005 => "No Status Received",
+        1006 => "Abnormal Closure",
+        1007 => "Invalid Payload Data",
+        1008 => "Policy Violation",
+        1009 => "Message Too Big",
+        1010 => "Mandatory Extension",
+        1011 => "Internal Error",
+        1012 => "Service Restart",
+        1013 => "Try Again Later",
+        1014 => "Bad Gateway",
+        1015 => "TLS Handshake",
+        3000..=3999 => "Registered",
+        4000..=4999 => "Application",
+        _ => "Unknown",
+    }
+}
+
+/// A complete message: a single frame, or the data frames of a fragmented
+/// message joined together.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebSocketMessage {
+    pub opcode: Opcode,
+    pub timestamp: DateTime<Utc>,
+    pub payload: Vec<u8>,
+    pub payload_len: u64,
+    pub fragments: usize,
+    pub compressed: bool,
+    pub truncated: bool,
+}
+
+impl WebSocketMessage {
+    fn new(opcode: Opcode, compressed: bool) -> Self {
+        Self {
+            opcode,
+            timestamp: Utc::now(),
+            payload: Vec::new(),
+            payload_len: 0,
+            fragments: 0,
+            compressed,
+            truncated: false,
+        }
+    }
+
+    /// Close code and reason of a close frame.
+    pub fn close_status(&self) -> Option<(u16, String)> {
+        if self.opcode != Opcode::Close || self.payload.len() < 2 {
+            return None;
+        }
+
+        let code = u16::from_be_bytes([self.payload[0], self.payload[1]]);
+        let reason = String::from_utf8_lossy(&self.payload[2..]).into_owned();
+        Some((code, reason))
+    }
+}
+
+#[derive(Debug)]
+struct FrameHeader {
+    fin: bool,
+    compressed: bool,
+    opcode: Opcode,
+    mask: Option<[u8; 4]>,
+    payload_len: u64,
+    len: usize,
+}
+
+impl FrameHeader {
+    /// Returns `None` until the whole header is available.
+    fn parse(buffer: &[u8]) -> Result<Option<Self>> {
+        if buffer.len() < 2 {
+            return Ok(None);
+        }
+
+        let (payload_len, mut len) = match buffer[1] & 0x7f {
+            126 => match buffer.get(2..4) {
+                Some(b) => (u16::from_be_bytes([b[0], b[1]]) as u64, 4),
+                None => return Ok(None),
+            },
+            127 => match buffer.get(2..10) {
+                Some(b) => (u64::from_be_bytes(b.try_into()?), 10),
+                None => return Ok(None),
+            },
+            payload_len => (payload_len as u64, 2),
+        };
+
+        let mask = if buffer[1] & 0x80 != 0 {
+            let Some(mask) = buffer.get(len..len + 4) else {
+                return Ok(None);
+            };
+            len += 4;
+            Some(mask.try_into()?)
+        } else {
+            None
+        };
+
+        Ok(Some(Self {
+            fin: buffer[0] & 0x80 != 0,
+            compressed: buffer[0] & 0x40 != 0,
+            opcode: Opcode::from(buffer[0] & 0x0f),
+            mask,
+            payload_len,
+            len,
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct FrameInProgress {
+    fin: bool,
+    opcode: Opcode,
+    mask: Option<[u8; 4]>,
+    remaining: u64,
+    offset: u64,
+    message: WebSocketMessage,
+}
+
+/// Incremental frame decoder for one direction of a WebSocket connection.
+#[derive(Debug, Default)]
+pub struct WebSocketDecoder {
+    buffer: Vec<u8>,
+    frame: Option<FrameInProgress>,
+    fragmented: Option<WebSocketMessage>,
+    inflater: Option<Decompress>,
+}
+
+impl WebSocketDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<WebSocketMessage>> {
+        let mut messages = Vec::new();
+        self.buffer.extend_from_slice(data);
+        let mut pos = 0;
+
+        loop {
+            if self.frame.is_none() {
+                let Some(header) = FrameHeader::parse(&self.buffer[pos..])? else {
+                    break;
+                };
+                pos += header.len;
+                self.start_frame(header)?;
+            }
+
+            let Some(frame) = self.frame.as_mut() else {
+                break;
+            };
+
+            let available = &self.buffer[pos..];
+            let take = frame.remaining.min(available.len() as u64) as usize;
+            let capture_room = MAX_CAPTURED_PAYLOAD.saturating_sub(frame.message.payload.len());
+            let captured = take.min(capture_room);
+
+            for (i, byte) in available[..captured].iter().enumerate() {
+                let unmasked = match frame.mask {
+                    Some(mask) => byte ^ mask[((frame.offset + i as u64) % 4) as usize],
+                    None => *byte,
+                };
+                frame.message.payload.push(unmasked);
+            }
+            if captured < take {
+                frame.message.truncated = true;
+            }
+
+            frame.offset += take as u64;
+            frame.remaining -= take as u64;
+            pos += take;
+
+            if frame.remaining > 0 {
+                break;
+            }
+
+            if let Some(frame) = self.frame.take() {
+                self.complete_frame(frame, &mut messages);
+            }
+        }
+
+        self.buffer.drain(..pos);
+        Ok(messages)
+    }
+
+    fn start_frame(&mut self, header: FrameHeader) -> Result<()> {
+        if header.opcode.is_control() && (!header.fin || header.payload_len > 125) {
+            return Err(anyhow!("Invalid WebSocket control frame"));
+        }
+
+        let message = match header.opcode {
+            Opcode::Continuation => self
+                .fragmented
+                .take()
+                .ok_or_else(|| anyhow!("Continuation frame without a started message"))?,
+            opcode => WebSocketMessage::new(opcode, header.compressed),
+        };
+
+        self.frame = Some(FrameInProgress {
+            fin: header.fin,
+            opcode: header.opcode,
+            mask: header.mask,
+            remaining: header.payload_len,
+            offset: 0,
+            message,
+        });
+
+        Ok(())
+    }
+
+    fn complete_frame(&mut self, frame: FrameInProgress, messages: &mut Vec<WebSocketMessage>) {
+        let mut message = frame.message;
+        message.payload_len += frame.offset;
+        message.fragments += 1;
+
+        if frame.opcode.is_control() {
+            messages.push(message);
+            return;
+        }
+
+        if !frame.fin {
+            self.fragmented = Some(message);
+            return;
+        }
+
+        if message.compressed {
+            self.inflate(&mut message);
+        }
+        messages.push(message);
+    }
+
+    /// Undoes permessage-deflate. The inflater is kept across messages for
+    /// context takeover and reset whenever a message cannot be inflated.
+    fn inflate(&mut self, message: &mut WebSocketMessage) {
+        if message.truncated {
+            self.inflater = None;
+            return;
+        }
+
+        let inflater = self.inflater.get_or_insert_with(|| Decompress::new(false));
+        let mut input = std::mem::take(&mut message.payload);
+        input.extend_from_slice(&DEFLATE_TAIL);
+
+        let mut output = Vec::with_capacity(input.len() * 4);
+        let mut consumed = 0;
+
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(input.len().max(1024));
+            }
+
+            let before_in = inflater.total_in();
+            let result =
+                inflater.decompress_vec(&input[consumed..], &mut output, FlushDecompress::Sync);
+            consumed += (inflater.total_in() - before_in) as usize;
+
+            match result {
+                Ok(Status::Ok) if consumed < input.len() || output.len() == output.capacity() => {
+                    if output.len() > MAX_CAPTURED_PAYLOAD * 16 {
+                        message.truncated = true;
+                        break;
+                    }
+                }
+                Ok(_) => break,
+                Err(_) => {
+                    self.inflater = None;
+                    input.truncate(input.len() - DEFLATE_TAIL.len());
+                    message.payload = input;
+                    return;
+                }
+            }
+        }
+
+        message.payload = output;
+        message.compressed = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(first: u8, mask: Option<[u8; 4]>, payload: &[u8]) -> Vec<u8> {
+        let mut out = vec![first];
+        let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+        if payload.len() < 126 {
+            out.push(mask_bit | payload.len() as u8);
+        } else {
+            out.push(mask_bit | 126);
+            out.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        }
+        match mask {
+            Some(mask) => {
+                out.extend_from_slice(&mask);
+                out.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+            }
+            None => out.extend_from_slice(payload),
+        }
+        out
+    }
+
+    #[test]
+    fn test_frames_after_upgrade() {
+        let response = b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: abc\r\n\r\n\x81\x02hi";
+        assert_eq!(frames_after_upgrade(response), Some(&b"\x81\x02hi"[..]));
+        assert_eq!(
+            frames_after_upgrade(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_decode_masked_and_split_frames() {
+        let mut decoder = WebSocketDecoder::new();
+        let mut data = frame(0x81, Some([1, 2, 3, 4]), b"hello");
+        data.extend(frame(0x89, Some([9, 9, 9, 9]), b"p"));
+
+        let (first, second) = data.split_at(4);
+        assert!(decoder.push(first).unwrap().is_empty());
+        let messages = decoder.push(second).unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].opcode, Opcode::Text);
+        assert_eq!(messages[0].payload, b"hello");
+        assert_eq!(messages[1].opcode, Opcode::Ping);
+        assert_eq!(messages[1].payload, b"p");
+    }
+
+    #[test]
+    fn test_fragmented_message_with_interleaved_control() {
+        let mut decoder = WebSocketDecoder::new();
+        let mut data = frame(0x02, None, &[1, 2]);
+        data.extend(frame(0x8a, None, b""));
+        data.extend(frame(0x80, None, &[3]));
+        let mut close = 1001u16.to_be_bytes().to_vec();
+        close.extend_from_slice(b"bye");
+        data.extend(frame(0x88, None, &close));
+
+        let messages = decoder.push(&data).unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].opcode, Opcode::Pong);
+        assert_eq!(messages[1].opcode, Opcode::Binary);
+        assert_eq!(messages[1].payload, vec![1, 2, 3]);
+        assert_eq!(messages[1].fragments, 2);
+        assert_eq!(messages[2].close_status(), Some((1001, "bye".to_string())));
+    }
+
+    #[test]
+    fn test_large_frame_is_truncated() {
+        let mut decoder = WebSocketDecoder::new();
+        let payload = vec![b'a'; MAX_CAPTURED_PAYLOAD + 10];
+        let mut data = vec![0x82, 127];
+        data.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+        data.extend_from_slice(&payload);
+
+        let messages: Vec<_> = data
+            .chunks(1000)
+            .flat_map(|chunk| decoder.push(chunk).unwrap())
+            .collect();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].truncated);
+        assert_eq!(messages[0].payload.len(), MAX_CAPTURED_PAYLOAD);
+        assert_eq!(messages[0].payload_len, payload.len() as u64);
+    }
+}
@@ -0,0 +1,71 @@
+use tokio::sync::Mutex;
+use tracing::error;
+
+use crate::websocket::{
+    FrameDirection,
+    WebSocketDecoder,
+};
+use crate::HttpLogger;
+
+#[derive(Default)]
+struct DirectionState {
+    decoder: WebSocketDecoder,
+    failed: bool,
+}
+
+/// Logs the frames of a WebSocket connection under the trace id of the HTTP
+/// upgrade request that opened it.
+pub struct WebSocketConnectionHandler {
+    logger: HttpLogger,
+    trace_id: String,
+    client: Mutex<DirectionState>,
+    server: Mutex<DirectionState>,
+}
+
+impl WebSocketConnectionHandler {
+    pub fn new(logger: HttpLogger, trace_id: String) -> Self {
+        Self {
+            logger,
+            trace_id,
+            client: Mutex::new(DirectionState::default()),
+            server: Mutex::new(DirectionState::default()),
+        }
+    }
+
+    pub async fn handle_client_data(&self, data: &[u8]) {
+        self.handle_data(FrameDirection::ClientToServer, data).await;
+    }
+
+    pub async fn handle_server_data(&self, data: &[u8]) {
+        self.handle_data(FrameDirection::ServerToClient, data).await;
+    }
+
+    async fn handle_data(&self, direction: FrameDirection, data: &[u8]) {
+        let state = match direction {
+            FrameDirection::ClientToServer => &self.client,
+            FrameDirection::ServerToClient => &self.server,
+        };
+        let mut state = state.lock().await;
+
+        if state.failed {
+            return;
+        }
+
+        match state.decoder.push(data) {
+            Ok(messages) => {
+                for message in messages {
+                    self.logger
+                        .log_websocket_message(&self.trace_id, direction, &message)
+                        .await;
+                }
+            }
+            Err(e) => {
+                error!(
+                    "Stopped decoding WebSocket frames ({}) for trace {}: {:?}",
+                    direction, self.trace_id, e
+                );
+                state.failed = true;
+            }
+        }
+    }
+}
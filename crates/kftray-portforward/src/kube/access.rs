@@ -0,0 +1,86 @@
+//! What the current user is allowed to do on a cluster, asked through
+//! SelfSubjectAccessReviews.
+
+use k8s_openapi::api::authorization::v1::{
+    ResourceAttributes,
+    SelfSubjectAccessReview,
+    SelfSubjectAccessReviewSpec,
+};
+use kube::api::{
+    Api,
+    PostParams,
+};
+use kube::Client;
+
+/// Answer of an access review.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Access {
+    Allowed,
+    /// Denied, with the reason given by the authorizer, or one describing the
+    /// request when it gives none.
+    Denied(String),
+}
+
+/// Asks whether the current user can `verb` a resource, in a namespace or
+/// across the cluster without one.
+pub(crate) async fn review_access(
+    client: &Client, namespace: Option<&str>, verb: &str, group: &str, resource: &str,
+    subresource: Option<&str>,
+) -> Result<Access, kube::Error> {
+    let review = SelfSubjectAccessReview {
+        spec: SelfSubjectAccessReviewSpec {
+            resource_attributes: Some(ResourceAttributes {
+                namespace: namespace.map(str::to_string),
+                verb: Some(verb.to_string()),
+                group: Some(group.to_string()),
+                resource: Some(resource.to_string()),
+                subresource: subresource.map(str::to_string),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let reviews: Api<SelfSubjectAccessReview> = Api::all(client.clone());
+    let status = reviews
+        .create(&PostParams::default(), &review)
+        .await?
+        .status
+        .unwrap_or_default();
+
+    if status.allowed {
+        return Ok(Access::Allowed);
+    }
+
+    let reason = status
+        .reason
+        .filter(|reason| !reason.is_empty())
+        .or(status.evaluation_error.filter(|error| !error.is_empty()))
+        .unwrap_or_else(|| denial_reason(namespace, verb, resource, subresource));
+
+    Ok(Access::Denied(reason))
+}
+
+/// Reason of a denial the authorizer didn't explain.
+pub(crate) fn denial_reason(
+    namespace: Option<&str>, verb: &str, resource: &str, subresource: Option<&str>,
+) -> String {
+    let resource = match subresource {
+        Some(subresource) => format!("{}/{}", resource, subresource),
+        None => resource.to_string(),
+    };
+    match namespace {
+        Some(namespace) => format!("cannot {} {} in namespace {}", verb, resource, namespace),
+        None => format!("cannot {} {} at the cluster scope", verb, resource),
+    }
+}
+
+/// Whether an error is the API server refusing the request for lack of
+/// permission.
+pub(crate) fn is_forbidden(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<kube::Error>(),
+        Some(kube::Error::Api(response)) if response.code == 403
+    )
+}
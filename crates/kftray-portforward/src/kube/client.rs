@@ -473,7 +473,7 @@ pub async fn get_services_with_annotation(
     Ok(results)
 }
 
-fn extract_ports_from_service(service: &Service) -> HashMap<String, i32> {
+pub(crate) fn extract_ports_from_service(service: &Service) -> HashMap<String, i32> {
     let mut ports = HashMap::new();
     if let Some(spec) = &service.spec {
         for port in spec.ports.as_ref().unwrap_or(&vec![]) {
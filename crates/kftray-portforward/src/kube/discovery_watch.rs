@@ -0,0 +1,478 @@
+//! Watch-based discovery, keeping the configs of a context's annotated
+//! services in sync with the cluster.
+//!
+//! A watch creates a config for every entry of an enabled service, updates
+//! it when the annotation changes and flags or deletes it once the service
+//! or the entry is gone. After every (re)connection the full list of services
+//! is compared with the configs the watch owns, so changes made while it was
+//! disconnected are caught up.
+
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+use std::sync::{
+    Arc,
+    Mutex,
+};
+
+use futures::StreamExt;
+use k8s_openapi::api::core::v1::Service;
+use kftray_commons::config::{
+    read_configs,
+    update_config,
+};
+use kftray_commons::config_state::get_configs_state;
+use kftray_commons::discovery::{
+    delete_discovered_config,
+    get_discovered_configs,
+    insert_discovered_config,
+    set_discovered_config_stale,
+};
+use kftray_commons::models::config_model::Config;
+use kftray_commons::models::discovery_model::DiscoveredConfig;
+use kftray_http_logs::HttpLogState;
+use kube::api::Api;
+use kube::Client;
+use kube_runtime::watcher::{
+    self,
+    Event,
+};
+use kube_runtime::WatchStreamExt;
+use lazy_static::lazy_static;
+use log::{
+    debug,
+    error,
+    info,
+    warn,
+};
+use tokio::task::JoinHandle;
+
+use crate::create_client_with_specific_context;
+use crate::kube::models::{
+    AnnotatedConfig,
+    DiscoveryWatchOptions,
+    RemovedConfigPolicy,
+};
+use crate::kube::service::service_configs;
+use crate::kube::stop::stop_port_forward;
+
+lazy_static! {
+    /// Running discovery watches keyed by context.
+    static ref DISCOVERY_WATCHES: Mutex<HashMap<String, JoinHandle<()>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Starts watching a context's services, replacing a watch already running
+/// for it.
+pub async fn start_discovery_watch(
+    options: DiscoveryWatchOptions, http_log_state: Arc<HttpLogState>,
+) -> Result<(), String> {
+    let (client_opt, _, _) =
+        create_client_with_specific_context(options.kubeconfig.clone(), Some(&options.context))
+            .await
+            .map_err(|e| e.to_string())?;
+    let client = client_opt.ok_or_else(|| "Client not created".to_string())?;
+
+    let context = options.context.clone();
+    let handle = tokio::spawn(async move {
+        run_watch(client, options, http_log_state).await;
+    });
+
+    let previous = DISCOVERY_WATCHES
+        .lock()
+        .unwrap()
+        .insert(context.clone(), handle);
+    if let Some(previous) = previous {
+        previous.abort();
+    }
+
+    info!("Started discovery watch for context {}", context);
+    Ok(())
+}
+
+/// Stops the watch of a context, telling whether one was running. Configs it
+/// created are kept.
+pub fn stop_discovery_watch(context: &str) -> bool {
+    match DISCOVERY_WATCHES.lock().unwrap().remove(context) {
+        Some(handle) => {
+            handle.abort();
+            info!("Stopped discovery watch for context {}", context);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Contexts with a running discovery watch.
+pub fn running_discovery_watches() -> Vec<String> {
+    let mut watches = DISCOVERY_WATCHES.lock().unwrap();
+    watches.retain(|_, handle| !handle.is_finished());
+    watches.keys().cloned().collect()
+}
+
+async fn run_watch(
+    client: Client, options: DiscoveryWatchOptions, http_log_state: Arc<HttpLogState>,
+) {
+    let services: Api<Service> = Api::all(client);
+    let mut events = watcher::watcher(services, watcher::Config::default())
+        .default_backoff()
+        .boxed();
+
+    // Services listed since the last (re)connection, by `{namespace}/{name}/`
+    let mut listed: Option<HashMap<String, Vec<AnnotatedConfig>>> = None;
+
+    while let Some(event) = events.next().await {
+        let result = match event {
+            Ok(Event::Init) => {
+                listed = Some(HashMap::new());
+                Ok(())
+            }
+            Ok(Event::InitApply(service)) => {
+                if let Some(listed) = listed.as_mut() {
+                    listed.insert(service_scope(&service), desired_configs(&service, &options));
+                }
+                Ok(())
+            }
+            Ok(Event::InitDone) => match listed.take() {
+                Some(listed) => {
+                    let desired = listed.into_values().flatten().collect();
+                    reconcile(&options, &http_log_state, None, desired).await
+                }
+                None => Ok(()),
+            },
+            Ok(Event::Apply(service)) => {
+                let scope = service_scope(&service);
+                let desired = desired_configs(&service, &options);
+                reconcile(&options, &http_log_state, Some(&scope), desired).await
+            }
+            Ok(Event::Delete(service)) => {
+                let scope = service_scope(&service);
+                reconcile(&options, &http_log_state, Some(&scope), Vec::new()).await
+            }
+            Err(e) => {
+                warn!("Discovery watch for {} failed: {}", options.context, e);
+                Ok(())
+            }
+        };
+
+        if let Err(e) = result {
+            error!(
+                "Failed to apply discovered configs for {}: {}",
+                options.context, e
+            );
+        }
+    }
+}
+
+fn desired_configs(service: &Service, options: &DiscoveryWatchOptions) -> Vec<AnnotatedConfig> {
+    service_configs(service, &options.context, options.kubeconfig.clone()).unwrap_or_default()
+}
+
+fn service_scope(service: &Service) -> String {
+    format!(
+        "{}/{}/",
+        service.metadata.namespace.as_deref().unwrap_or("default"),
+        service.metadata.name.as_deref().unwrap_or_default()
+    )
+}
+
+/// Key of the annotation entry a config comes from. Entries of a service
+/// can share an alias, like the ports of a service without
+/// `kftray.app/configs`, so the protocol and remote port are part of it.
+fn discovery_key(config: &Config) -> String {
+    format!(
+        "{}/{}/{}/{}/{}",
+        config.namespace,
+        config.service.as_deref().unwrap_or_default(),
+        config.alias.as_deref().unwrap_or_default(),
+        config.protocol,
+        config.remote_port.unwrap_or_default()
+    )
+}
+
+/// What reconciling a scope does with its tracked configs.
+#[derive(Default, Debug)]
+struct ReconcilePlan {
+    /// Tracked configs still wanted, with the entry they come from.
+    kept: Vec<(DiscoveredConfig, AnnotatedConfig)>,
+    /// Entries without a config yet, by key.
+    added: Vec<(String, AnnotatedConfig)>,
+    /// Tracked configs whose entry is gone, or that repeat another's key.
+    removed: Vec<DiscoveredConfig>,
+}
+
+/// Matches the desired entries of a scope, or of the whole context without
+/// one, with the configs tracked for it.
+fn plan_reconcile(
+    tracked: Vec<DiscoveredConfig>, scope: Option<&str>, desired: Vec<AnnotatedConfig>,
+) -> ReconcilePlan {
+    let mut tracked_by_key: HashMap<String, Vec<DiscoveredConfig>> = HashMap::new();
+    for discovered in tracked
+        .into_iter()
+        .filter(|discovered| scope.is_none_or(|scope| discovered.discovery_key.starts_with(scope)))
+    {
+        tracked_by_key
+            .entry(discovered.discovery_key.clone())
+            .or_default()
+            .push(discovered);
+    }
+
+    let mut plan = ReconcilePlan::default();
+    let mut seen = HashSet::new();
+    for annotated in desired {
+        let key = discovery_key(&annotated.config);
+        if !seen.insert(key.clone()) {
+            debug!("Ignoring repeated annotation entry {}", key);
+            continue;
+        }
+
+        match tracked_by_key
+            .get_mut(&key)
+            .filter(|configs| !configs.is_empty())
+        {
+            Some(configs) => plan.kept.push((configs.remove(0), annotated)),
+            None => plan.added.push((key, annotated)),
+        }
+    }
+
+    plan.removed = tracked_by_key.into_values().flatten().collect();
+    plan.removed.sort_by_key(|discovered| discovered.config_id);
+    plan
+}
+
+/// Brings the discovered configs of one service, or of the whole context
+/// without a scope, in line with the desired ones.
+async fn reconcile(
+    options: &DiscoveryWatchOptions, http_log_state: &HttpLogState, scope: Option<&str>,
+    desired: Vec<AnnotatedConfig>,
+) -> Result<(), String> {
+    let tracked = get_discovered_configs(Some(&options.context)).await?;
+    let plan = plan_reconcile(tracked, scope, desired);
+
+    let local: HashMap<i64, Config> = read_configs()
+        .await?
+        .into_iter()
+        .filter_map(|config| config.id.map(|id| (id, config)))
+        .collect();
+
+    let mut enabled = Vec::new();
+    for (discovered, annotated) in plan.kept {
+        let id = discovered.config_id;
+        if let Some(existing) = local.get(&id) {
+            let updated = with_annotation(existing, &annotated.config);
+            if updated != *existing {
+                update_config(updated).await?;
+                info!("Updated discovered config {}", discovered.discovery_key);
+            }
+        }
+        if discovered.stale {
+            set_discovered_config_stale(id, false).await?;
+        }
+        enabled.push((id, discovered.discovery_key, annotated.http_logs));
+    }
+
+    for (key, annotated) in plan.added {
+        let id = insert_discovered_config(&options.context, &key, annotated.config).await?;
+        info!("Added discovered config {}", key);
+        enabled.push((id, key, annotated.http_logs));
+    }
+
+    for (config_id, key, http_logs) in enabled {
+        if http_logs {
+            if let Err(e) = http_log_state.set_http_logs(config_id, true).await {
+                warn!("Failed to enable HTTP logs for {}: {}", key, e);
+            }
+        }
+    }
+
+    for discovered in plan.removed {
+        let key = &discovered.discovery_key;
+        match options.removed {
+            RemovedConfigPolicy::Flag => {
+                if !discovered.stale {
+                    set_discovered_config_stale(discovered.config_id, true).await?;
+                    info!("Flagged discovered config {} as stale", key);
+                }
+            }
+            RemovedConfigPolicy::Delete => {
+                stop_if_running(discovered.config_id).await;
+                delete_discovered_config(discovered.config_id).await?;
+                info!("Deleted discovered config {}", key);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The local config with the fields an annotation sets replaced, keeping the
+/// ones edited by hand that it leaves out.
+fn with_annotation(existing: &Config, annotated: &Config) -> Config {
+    let mut config = existing.clone();
+    config.local_port = annotated.local_port;
+    config.remote_port = annotated.remote_port;
+    config.protocol = annotated.protocol.clone();
+    if annotated.local_address.is_some() {
+        config.local_address = annotated.local_address.clone();
+    }
+    if annotated.domain_enabled.is_some() {
+        config.domain_enabled = annotated.domain_enabled;
+    }
+    config
+}
+
+async fn stop_if_running(config_id: i64) {
+    let running = get_configs_state()
+        .await
+        .map(|states| {
+            states
+                .iter()
+                .any(|state| state.config_id == config_id && state.is_running)
+        })
+        .unwrap_or_default();
+
+    if running {
+        if let Err(e) = stop_port_forward(config_id.to_string()).await {
+            debug!("Failed to stop forward of config {}: {}", config_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use k8s_openapi::api::core::v1::{
+        ServicePort,
+        ServiceSpec,
+    };
+    use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+    use kube::api::ObjectMeta;
+
+    use super::*;
+    use crate::kube::service::{
+        CONFIGS_ANNOTATION,
+        ENABLED_ANNOTATION,
+    };
+
+    fn service(configs: Option<&str>, ports: &[(&str, i32)]) -> Service {
+        let mut annotations =
+            BTreeMap::from([(ENABLED_ANNOTATION.to_string(), "true".to_string())]);
+        if let Some(configs) = configs {
+            annotations.insert(CONFIGS_ANNOTATION.to_string(), configs.to_string());
+        }
+
+        Service {
+            metadata: ObjectMeta {
+                name: Some("api".to_string()),
+                namespace: Some("apps".to_string()),
+                annotations: Some(annotations),
+                ..Default::default()
+            },
+            spec: Some(ServiceSpec {
+                ports: Some(
+                    ports
+                        .iter()
+                        .map(|(name, port)| ServicePort {
+                            name: Some(name.to_string()),
+                            port: *port,
+                            target_port: Some(IntOrString::Int(*port)),
+                            ..Default::default()
+                        })
+                        .collect(),
+                ),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn options() -> DiscoveryWatchOptions {
+        DiscoveryWatchOptions {
+            context: "kind".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Tracks the added configs as the database would, with fresh ids.
+    fn track(tracked: &mut Vec<DiscoveredConfig>, plan: &ReconcilePlan) {
+        for (key, _) in &plan.added {
+            tracked.push(DiscoveredConfig {
+                config_id: tracked.len() as i64 + 1,
+                context: "kind".to_string(),
+                discovery_key: key.clone(),
+                stale: false,
+            });
+        }
+    }
+
+    #[test]
+    fn test_multi_port_service_applied_twice() {
+        let service = service(None, &[("http", 80), ("metrics", 9090)]);
+        let scope = service_scope(&service);
+        let mut tracked = Vec::new();
+
+        let first = plan_reconcile(
+            tracked.clone(),
+            Some(&scope),
+            desired_configs(&service, &options()),
+        );
+        assert_eq!(first.added.len(), 2);
+        assert_ne!(first.added[0].0, first.added[1].0);
+        assert!(first.kept.is_empty() && first.removed.is_empty());
+        track(&mut tracked, &first);
+
+        let second = plan_reconcile(
+            tracked.clone(),
+            Some(&scope),
+            desired_configs(&service, &options()),
+        );
+        assert!(second.added.is_empty() && second.removed.is_empty());
+        assert_eq!(second.kept.len(), 2);
+        for (discovered, annotated) in &second.kept {
+            assert_eq!(discovered.discovery_key, discovery_key(&annotated.config));
+        }
+    }
+
+    #[test]
+    fn test_repeated_entries_are_added_once() {
+        let service = service(
+            Some("api-8080-80, api-8080-80, api-8081-80;protocol=udp"),
+            &[],
+        );
+        let plan = plan_reconcile(Vec::new(), None, desired_configs(&service, &options()));
+
+        let keys: Vec<&str> = plan.added.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["apps/api/api/tcp/80", "apps/api/api/udp/80"]);
+    }
+
+    #[test]
+    fn test_removed_entries_and_colliding_rows() {
+        let service = service(Some("api-8080-80"), &[]);
+        let discovered = |config_id: i64, discovery_key: &str| DiscoveredConfig {
+            config_id,
+            context: "kind".to_string(),
+            discovery_key: discovery_key.to_string(),
+            stale: false,
+        };
+        let tracked = vec![
+            discovered(1, "apps/api/api/tcp/80"),
+            discovered(2, "apps/api/api/tcp/80"),
+            discovered(3, "apps/api/old/tcp/81"),
+            discovered(4, "apps/other/other/tcp/80"),
+        ];
+
+        let plan = plan_reconcile(
+            tracked,
+            Some("apps/api/"),
+            desired_configs(&service, &options()),
+        );
+
+        assert!(plan.added.is_empty());
+        assert_eq!(plan.kept.len(), 1);
+        assert_eq!(plan.kept[0].0.config_id, 1);
+        let removed: Vec<i64> = plan.removed.iter().map(|d| d.config_id).collect();
+        assert_eq!(removed, [2, 3]);
+    }
+}
@@ -0,0 +1,448 @@
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use rand::Rng;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tokio::io::{
+    AsyncWrite,
+    AsyncWriteExt,
+};
+use tokio::time::{
+    sleep,
+    Instant,
+};
+use tracing::debug;
+
+const THROTTLE_INTERVAL: Duration = Duration::from_millis(100);
+const MIN_REORDER_DELAY: Duration = Duration::from_millis(20);
+
+lazy_static! {
+    static ref FAULT_PROFILES: DashMap<i64, FaultProfile> = DashMap::new();
+}
+
+/// Network conditions simulated on a forward. Latency and bandwidth apply to
+/// each direction separately. Packet loss and reordering only affect UDP
+/// forwards, connection resets only TCP forwards.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FaultProfile {
+    pub latency_ms: u64,
+    pub jitter_ms: u64,
+    pub bandwidth_bytes_per_sec: Option<u64>,
+    pub reset_probability: f64,
+    pub packet_loss: f64,
+    pub reorder_probability: f64,
+}
+
+impl FaultProfile {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (name, value) in [
+            ("reset_probability", self.reset_probability),
+            ("packet_loss", self.packet_loss),
+            ("reorder_probability", self.reorder_probability),
+        ] {
+            if !(0.0..=1.0).contains(&value) {
+                anyhow::bail!("{} must be between 0 and 1, got {}", name, value);
+            }
+        }
+
+        if self.bandwidth_bytes_per_sec == Some(0) {
+            anyhow::bail!("bandwidth_bytes_per_sec must be greater than 0");
+        }
+
+        Ok(())
+    }
+
+    pub fn is_noop(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn delay(&self) -> Duration {
+        if self.latency_ms == 0 && self.jitter_ms == 0 {
+            return Duration::ZERO;
+        }
+
+        let jitter = self.jitter_ms as i64;
+        let offset = if jitter > 0 {
+            rand::rng().random_range(-jitter..=jitter)
+        } else {
+            0
+        };
+
+        Duration::from_millis((self.latency_ms as i64 + offset).max(0) as u64)
+    }
+
+    fn roll(probability: f64) -> bool {
+        probability > 0.0 && rand::rng().random_bool(probability)
+    }
+}
+
+/// Named profiles offered by the UI surfaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FaultPreset {
+    Slow3g,
+    Edge,
+    Flaky,
+    Lossy,
+}
+
+impl FaultPreset {
+    pub const ALL: [FaultPreset; 4] = [
+        FaultPreset::Slow3g,
+        FaultPreset::Edge,
+        FaultPreset::Flaky,
+        FaultPreset::Lossy,
+    ];
+
+    pub fn profile(&self) -> FaultProfile {
+        match self {
+            FaultPreset::Slow3g => FaultProfile {
+                latency_ms: 200,
+                jitter_ms: 50,
+                bandwidth_bytes_per_sec: Some(50 * 1024),
+                ..Default::default()
+            },
+            FaultPreset::Edge => FaultProfile {
+                latency_ms: 400,
+                jitter_ms: 150,
+                bandwidth_bytes_per_sec: Some(30 * 1024),
+                ..Default::default()
+            },
+            FaultPreset::Flaky => FaultProfile {
+                latency_ms: 100,
+                jitter_ms: 100,
+                reset_probability: 0.02,
+                packet_loss: 0.05,
+                ..Default::default()
+            },
+            FaultPreset::Lossy => FaultProfile {
+                latency_ms: 50,
+                jitter_ms: 30,
+                packet_loss: 0.1,
+                reorder_probability: 0.1,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn matching(profile: &FaultProfile) -> Option<FaultPreset> {
+        Self::ALL
+            .into_iter()
+            .find(|preset| preset.profile() == *profile)
+    }
+}
+
+impl fmt::Display for FaultPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FaultPreset::Slow3g => write!(f, "slow 3g"),
+            FaultPreset::Edge => write!(f, "edge"),
+            FaultPreset::Flaky => write!(f, "flaky"),
+            FaultPreset::Lossy => write!(f, "lossy"),
+        }
+    }
+}
+
+/// Sets or clears the fault profile of a config. Running forwards pick up
+/// the change on the next chunk or packet they relay.
+pub fn set_fault_profile(config_id: i64, profile: Option<FaultProfile>) -> anyhow::Result<()> {
+    match profile {
+        Some(profile) if !profile.is_noop() => {
+            profile.validate()?;
+            debug!("Fault profile for config {}: {:?}", config_id, profile);
+            FAULT_PROFILES.insert(config_id, profile);
+        }
+        _ => {
+            debug!("Fault profile for config {} cleared", config_id);
+            FAULT_PROFILES.remove(&config_id);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn get_fault_profile(config_id: i64) -> Option<FaultProfile> {
+    FAULT_PROFILES
+        .get(&config_id)
+        .map(|profile| profile.value().clone())
+}
+
+/// Applies the fault profile of a config to one direction of a TCP
+/// connection.
+pub struct FaultInjector {
+    config_id: i64,
+    budget_started: Instant,
+    budget_used: u64,
+}
+
+impl FaultInjector {
+    pub fn new(config_id: i64) -> Self {
+        Self {
+            config_id,
+            budget_started: Instant::now(),
+            budget_used: 0,
+        }
+    }
+
+    /// Writes `data` after the configured delay, paced to the bandwidth
+    /// limit. Fails with `ConnectionReset` when a reset is injected.
+    pub async fn write_all<W: AsyncWrite + Unpin>(
+        &mut self, writer: &mut W, data: &[u8],
+    ) -> io::Result<()> {
+        let Some(profile) = get_fault_profile(self.config_id) else {
+            return writer.write_all(data).await;
+        };
+
+        if FaultProfile::roll(profile.reset_probability) {
+            debug!("Injecting connection reset for config {}", self.config_id);
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                "connection reset by fault injection",
+            ));
+        }
+
+        let delay = profile.delay();
+        if !delay.is_zero() {
+            sleep(delay).await;
+        }
+
+        let Some(rate) = profile.bandwidth_bytes_per_sec else {
+            return writer.write_all(data).await;
+        };
+
+        let slice_len = ((rate as u128 * THROTTLE_INTERVAL.as_millis() / 1000) as usize).max(1);
+        for slice in data.chunks(slice_len) {
+            self.pace(rate, slice.len() as u64).await;
+            writer.write_all(slice).await?;
+            writer.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn pace(&mut self, rate: u64, len: u64) {
+        let now = Instant::now();
+        if now.duration_since(self.budget_started) > Duration::from_secs(1) {
+            self.budget_started = now;
+            self.budget_used = 0;
+        }
+
+        self.budget_used += len;
+        let due =
+            self.budget_started + Duration::from_secs_f64(self.budget_used as f64 / rate as f64);
+        if due > now {
+            tokio::time::sleep_until(due).await;
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PacketFate {
+    Deliver,
+    DeliverAfter(Duration),
+    Drop,
+}
+
+/// Decides what happens to a single UDP packet of a config.
+pub fn packet_fate(config_id: i64) -> PacketFate {
+    let Some(profile) = get_fault_profile(config_id) else {
+        return PacketFate::Deliver;
+    };
+
+    if FaultProfile::roll(profile.packet_loss) {
+        return PacketFate::Drop;
+    }
+
+    let mut delay = profile.delay();
+    if FaultProfile::roll(profile.reorder_probability) {
+        delay += (delay * 2).max(MIN_REORDER_DELAY);
+    }
+
+    if delay.is_zero() {
+        PacketFate::Deliver
+    } else {
+        PacketFate::DeliverAfter(delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_bounds() {
+        assert!(FaultProfile::default().validate().is_ok());
+        assert!(FaultPreset::ALL
+            .iter()
+            .all(|preset| preset.profile().validate().is_ok()));
+
+        for profile in [
+            FaultProfile {
+                packet_loss: 1.5,
+                ..Default::default()
+            },
+            FaultProfile {
+                reset_probability: -0.1,
+                ..Default::default()
+            },
+            FaultProfile {
+                bandwidth_bytes_per_sec: Some(0),
+                ..Default::default()
+            },
+        ] {
+            assert!(profile.validate().is_err(), "{:?}", profile);
+        }
+    }
+
+    #[test]
+    fn test_matching_preset() {
+        for preset in FaultPreset::ALL {
+            assert_eq!(FaultPreset::matching(&preset.profile()), Some(preset));
+        }
+
+        let custom = FaultProfile {
+            latency_ms: 10,
+            ..Default::default()
+        };
+        assert_eq!(FaultPreset::matching(&custom), None);
+    }
+
+    #[test]
+    fn test_delay_stays_within_jitter() {
+        let fixed = FaultProfile {
+            latency_ms: 100,
+            ..Default::default()
+        };
+        assert_eq!(fixed.delay(), Duration::from_millis(100));
+
+        let jittery = FaultProfile {
+            latency_ms: 10,
+            jitter_ms: 50,
+            ..Default::default()
+        };
+        for _ in 0..100 {
+            assert!(jittery.delay() <= Duration::from_millis(60));
+        }
+    }
+
+    #[test]
+    fn test_set_noop_profile_clears() {
+        set_fault_profile(-1, Some(FaultPreset::Slow3g.profile())).unwrap();
+        assert_eq!(get_fault_profile(-1), Some(FaultPreset::Slow3g.profile()));
+
+        set_fault_profile(-1, Some(FaultProfile::default())).unwrap();
+        assert_eq!(get_fault_profile(-1), None);
+
+        let invalid = FaultProfile {
+            packet_loss: 2.0,
+            ..Default::default()
+        };
+        assert!(set_fault_profile(-1, Some(invalid)).is_err());
+        assert_eq!(get_fault_profile(-1), None);
+    }
+
+    #[test]
+    fn test_packet_fate() {
+        assert_eq!(packet_fate(-2), PacketFate::Deliver);
+
+        set_fault_profile(
+            -3,
+            Some(FaultProfile {
+                packet_loss: 1.0,
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+        assert_eq!(packet_fate(-3), PacketFate::Drop);
+
+        set_fault_profile(
+            -4,
+            Some(FaultProfile {
+                latency_ms: 30,
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+        assert_eq!(
+            packet_fate(-4),
+            PacketFate::DeliverAfter(Duration::from_millis(30))
+        );
+
+        set_fault_profile(
+            -5,
+            Some(FaultProfile {
+                reorder_probability: 1.0,
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+        assert_eq!(packet_fate(-5), PacketFate::DeliverAfter(MIN_REORDER_DELAY));
+
+        set_fault_profile(
+            -6,
+            Some(FaultProfile {
+                latency_ms: 30,
+                reorder_probability: 1.0,
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+        assert_eq!(
+            packet_fate(-6),
+            PacketFate::DeliverAfter(Duration::from_millis(90))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_injected_reset() {
+        let mut output = Vec::new();
+        FaultInjector::new(-7)
+            .write_all(&mut output, b"hello")
+            .await
+            .unwrap();
+        assert_eq!(output, b"hello");
+
+        set_fault_profile(
+            -8,
+            Some(FaultProfile {
+                reset_probability: 1.0,
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+        let error = FaultInjector::new(-8)
+            .write_all(&mut Vec::new(), b"hello")
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionReset);
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_pacing() {
+        set_fault_profile(
+            -9,
+            Some(FaultProfile {
+                bandwidth_bytes_per_sec: Some(10_000),
+                ..Default::default()
+            }),
+        )
+        .unwrap();
+
+        let started = Instant::now();
+        let mut output = Vec::new();
+        FaultInjector::new(-9)
+            .write_all(&mut output, &[0; 2_000])
+            .await
+            .unwrap();
+
+        assert_eq!(output.len(), 2_000);
+        assert!(started.elapsed() >= Duration::from_millis(150));
+    }
+}
@@ -0,0 +1,618 @@
+//! Leases keep proxy pods from outliving the desktop client.
+//!
+//! Every proxy pod carries a renewal timestamp and a TTL in its annotations.
+//! The client renews the timestamp while it uses the pod, and the server reads
+//! the annotations through a downward API volume and exits once the lease
+//! runs out, e.g. after the laptop went to sleep. Proxies deployed as a
+//! Deployment or Job carry the lease on the owning object instead, since their
+//! pods are replaced. Proxies whose lease expired are reported by
+//! [`find_orphaned_proxy_pods`].
+
+use std::collections::{
+    BTreeMap,
+    HashSet,
+};
+use std::fmt::Debug;
+use std::time::{
+    Duration,
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+use futures::stream::{
+    FuturesUnordered,
+    StreamExt,
+};
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::batch::v1::Job;
+use k8s_openapi::api::core::v1::{
+    DownwardAPIVolumeFile,
+    DownwardAPIVolumeSource,
+    EnvVar,
+    ObjectFieldSelector,
+    Pod,
+    Volume,
+    VolumeMount,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kftray_commons::config::read_configs;
+use kftray_commons::config_state::get_configs_state;
+use kube::api::{
+    Api,
+    DeleteParams,
+    ListParams,
+    Patch,
+    PatchParams,
+};
+use kube::{
+    Client,
+    Resource,
+};
+use log::{
+    debug,
+    error,
+    info,
+    warn,
+};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use tokio::task::JoinHandle;
+
+use crate::create_client_with_specific_context;
+use crate::kube::models::OrphanedPod;
+use crate::kube::proxy::clean_username;
+
+pub const LEASE_RENEWED_AT_ANNOTATION: &str = "kftray.app/lease-renewed-at";
+pub const LEASE_TTL_ANNOTATION: &str = "kftray.app/lease-ttl-seconds";
+pub const USER_LABEL: &str = "kftray.app/user";
+
+const PROXY_POD_PREFIX: &str = "kftray-forward-";
+const LEASE_TTL: Duration = Duration::from_secs(300);
+const RENEW_INTERVAL: Duration = Duration::from_secs(60);
+const LEASE_VOLUME: &str = "kftray-lease";
+const LEASE_MOUNT_PATH: &str = "/etc/kftray";
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Adds the lease annotations and the owner label to an object's metadata.
+pub(super) fn apply_lease_metadata(metadata: &mut ObjectMeta) {
+    let annotations = metadata.annotations.get_or_insert_with(BTreeMap::new);
+    annotations.insert(
+        LEASE_RENEWED_AT_ANNOTATION.to_string(),
+        unix_now().to_string(),
+    );
+    annotations.insert(
+        LEASE_TTL_ANNOTATION.to_string(),
+        LEASE_TTL.as_secs().to_string(),
+    );
+
+    metadata
+        .labels
+        .get_or_insert_with(BTreeMap::new)
+        .insert(USER_LABEL.to_string(), clean_username());
+}
+
+/// Adds the lease metadata and the downward API volume the server watches to
+/// a bare proxy pod.
+///
+/// The pod is switched to `restartPolicy: Never` so a server that exits on an
+/// expired lease stays down instead of being restarted.
+pub(super) fn apply_lease(pod: &mut Pod) {
+    apply_lease_metadata(&mut pod.metadata);
+
+    let Some(spec) = pod.spec.as_mut() else {
+        return;
+    };
+
+    spec.restart_policy = Some("Never".to_string());
+    spec.volumes.get_or_insert_with(Vec::new).push(Volume {
+        name: LEASE_VOLUME.to_string(),
+        downward_api: Some(DownwardAPIVolumeSource {
+            items: Some(vec![DownwardAPIVolumeFile {
+                path: "annotations".to_string(),
+                field_ref: Some(ObjectFieldSelector {
+                    field_path: "metadata.annotations".to_string(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    });
+
+    if let Some(container) = spec.containers.first_mut() {
+        container
+            .volume_mounts
+            .get_or_insert_with(Vec::new)
+            .push(VolumeMount {
+                name: LEASE_VOLUME.to_string(),
+                mount_path: LEASE_MOUNT_PATH.to_string(),
+                read_only: Some(true),
+                ..Default::default()
+            });
+        container.env.get_or_insert_with(Vec::new).push(EnvVar {
+            name: "LEASE_FILE".to_string(),
+            value: Some(format!("{}/annotations", LEASE_MOUNT_PATH)),
+            ..Default::default()
+        });
+    }
+}
+
+/// Renews the lease of a proxy pod, Deployment or Job until it is deleted.
+pub(super) fn spawn_lease_renewal<K>(api: Api<K>, name: String) -> JoinHandle<()>
+where
+    K: Resource + Clone + DeserializeOwned + Debug + Send + Sync + 'static,
+{
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(RENEW_INTERVAL).await;
+
+            let patch = json!({
+                "metadata": {
+                    "annotations": {
+                        LEASE_RENEWED_AT_ANNOTATION: unix_now().to_string(),
+                    }
+                }
+            });
+
+            match api
+                .patch(&name, &PatchParams::default(), &Patch::Merge(&patch))
+                .await
+            {
+                Ok(_) => debug!("Renewed lease of proxy {}", name),
+                Err(kube::Error::Api(e)) if e.code == 404 => {
+                    info!("Proxy {} is gone, stopping lease renewal", name);
+                    break;
+                }
+                Err(e) => warn!("Failed to renew lease of proxy {}: {}", name, e),
+            }
+        }
+    })
+}
+
+/// Aborts a lease renewal when dropped, tying it to the task of a forward.
+pub(super) struct LeaseGuard(pub(super) JoinHandle<()>);
+
+impl Drop for LeaseGuard {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Lists kftray proxies across all namespaces that nobody is using anymore.
+///
+/// A proxy is orphaned when its server already exited, when its lease was not
+/// renewed within its TTL, or, for proxies created before leases existed, when
+/// it belongs to the current user, is older than a lease TTL and its config is
+/// a local config of this context that is not running. Pods owned by a proxy
+/// Deployment or Job are reported through their owner. `user` narrows the
+/// search to proxies of one user.
+pub async fn find_orphaned_proxy_pods(
+    kubeconfig: Option<String>, context: Option<&str>, user: Option<String>,
+) -> Result<Vec<OrphanedPod>, String> {
+    let client = proxy_client(kubeconfig.clone(), context).await?;
+    let lp = ListParams::default().labels("config_id");
+
+    let pods = list_all::<Pod>(&client, &lp).await?;
+    let deployments = list_all::<Deployment>(&client, &lp).await?;
+    let jobs = list_all::<Job>(&client, &lp).await?;
+
+    let filter = OrphanFilter {
+        now: unix_now(),
+        current_user: clean_username(),
+        user: user.map(|u| u.to_lowercase()),
+        idle_configs: idle_local_configs(kubeconfig.as_deref(), context).await,
+    };
+
+    let mut orphans = Vec::new();
+
+    for pod in pods {
+        if pod
+            .metadata
+            .owner_references
+            .as_ref()
+            .is_some_and(|owners| !owners.is_empty())
+        {
+            continue;
+        }
+
+        let exited = pod
+            .status
+            .as_ref()
+            .and_then(|s| s.phase.as_deref())
+            .filter(|phase| matches!(*phase, "Succeeded" | "Failed"))
+            .map(|phase| format!("Proxy server exited ({})", phase));
+        orphans.extend(filter.check("Pod", &pod.metadata, exited));
+    }
+
+    for deployment in deployments {
+        orphans.extend(filter.check("Deployment", &deployment.metadata, None));
+    }
+
+    for job in jobs {
+        let finished = job
+            .status
+            .as_ref()
+            .and_then(|s| s.conditions.as_ref())
+            .and_then(|conditions| {
+                conditions.iter().find(|c| {
+                    matches!(c.type_.as_str(), "Complete" | "Failed") && c.status == "True"
+                })
+            })
+            .map(|c| format!("Proxy job finished ({})", c.type_));
+        orphans.extend(filter.check("Job", &job.metadata, finished));
+    }
+
+    Ok(orphans)
+}
+
+/// Deletes the orphaned proxies found by [`find_orphaned_proxy_pods`] and
+/// returns the ones that were removed. Pods of a deleted Deployment or Job are
+/// garbage collected through their owner references.
+pub async fn delete_orphaned_proxy_pods(
+    kubeconfig: Option<String>, context: Option<&str>, user: Option<String>,
+) -> Result<Vec<OrphanedPod>, String> {
+    let orphans = find_orphaned_proxy_pods(kubeconfig.clone(), context, user).await?;
+    let client = proxy_client(kubeconfig, context).await?;
+
+    let dp = DeleteParams {
+        grace_period_seconds: Some(0),
+        propagation_policy: Some(kube::api::PropagationPolicy::Background),
+        ..Default::default()
+    };
+
+    let mut deletions: FuturesUnordered<_> = orphans
+        .into_iter()
+        .map(|orphan| {
+            let client = client.clone();
+            let dp = dp.clone();
+            async move {
+                let namespace = orphan.namespace.as_str();
+                let result = match orphan.kind.as_str() {
+                    "Deployment" => Api::<Deployment>::namespaced(client, namespace)
+                        .delete(&orphan.name, &dp)
+                        .await
+                        .map(|_| ()),
+                    "Job" => Api::<Job>::namespaced(client, namespace)
+                        .delete(&orphan.name, &dp)
+                        .await
+                        .map(|_| ()),
+                    _ => Api::<Pod>::namespaced(client, namespace)
+                        .delete(&orphan.name, &dp)
+                        .await
+                        .map(|_| ()),
+                };
+
+                match result {
+                    Ok(_) => {
+                        info!(
+                            "Deleted orphaned proxy {} {}/{}",
+                            orphan.kind, orphan.namespace, orphan.name
+                        );
+                        Some(orphan)
+                    }
+                    Err(e) => {
+                        error!(
+                            "Failed to delete orphaned proxy {} {}/{}: {}",
+                            orphan.kind, orphan.namespace, orphan.name, e
+                        );
+                        None
+                    }
+                }
+            }
+        })
+        .collect();
+
+    let mut deleted = Vec::new();
+    while let Some(result) = deletions.next().await {
+        deleted.extend(result);
+    }
+
+    Ok(deleted)
+}
+
+/// Ids of the local configs of a context that are not running. Config ids of
+/// other machines or contexts mean nothing here, so proxies without a lease
+/// are only matched against these. Nothing is returned when the configs can't
+/// be read.
+async fn idle_local_configs(kubeconfig: Option<&str>, context: Option<&str>) -> HashSet<String> {
+    let (configs, states) = match (read_configs().await, get_configs_state().await) {
+        (Ok(configs), Ok(states)) => (configs, states),
+        (Err(e), _) | (_, Err(e)) => {
+            warn!(
+                "Failed to read configs, skipping proxies without a lease: {}",
+                e
+            );
+            return HashSet::new();
+        }
+    };
+
+    let running: HashSet<i64> = states
+        .into_iter()
+        .filter(|s| s.is_running)
+        .map(|s| s.config_id)
+        .collect();
+    let kubeconfig_path = |kubeconfig: Option<&str>| {
+        kubeconfig
+            .filter(|path| !path.is_empty() && *path != "default")
+            .map(str::to_string)
+    };
+
+    configs
+        .into_iter()
+        .filter(|config| {
+            Some(config.context.as_str()) == context
+                && kubeconfig_path(config.kubeconfig.as_deref()) == kubeconfig_path(kubeconfig)
+        })
+        .filter_map(|config| config.id)
+        .filter(|id| !running.contains(id))
+        .map(|id| id.to_string())
+        .collect()
+}
+
+async fn proxy_client(kubeconfig: Option<String>, context: Option<&str>) -> Result<Client, String> {
+    let (client, _, _) = create_client_with_specific_context(kubeconfig, context)
+        .await
+        .map_err(|e| {
+            error!("Failed to create Kubernetes client: {}", e);
+            e.to_string()
+        })?;
+
+    client.ok_or_else(|| "Client not created".to_string())
+}
+
+async fn list_all<K>(client: &Client, lp: &ListParams) -> Result<Vec<K>, String>
+where
+    K: Resource<DynamicType = ()> + Clone + DeserializeOwned + Debug,
+{
+    Api::<K>::all(client.clone())
+        .list(lp)
+        .await
+        .map(|list| list.items)
+        .map_err(|e| {
+            error!("Error listing {}: {}", K::plural(&()), e);
+            e.to_string()
+        })
+}
+
+struct OrphanFilter {
+    now: u64,
+    current_user: String,
+    user: Option<String>,
+    idle_configs: HashSet<String>,
+}
+
+impl OrphanFilter {
+    fn check(
+        &self, kind: &str, metadata: &ObjectMeta, finished: Option<String>,
+    ) -> Option<OrphanedPod> {
+        let name = metadata.name.clone()?;
+        if !name.starts_with(PROXY_POD_PREFIX) || metadata.deletion_timestamp.is_some() {
+            return None;
+        }
+
+        let labels = metadata.labels.clone().unwrap_or_default();
+        let owner = labels.get(USER_LABEL).cloned().or_else(|| {
+            name.strip_prefix(PROXY_POD_PREFIX)
+                .and_then(|rest| rest.split('-').next())
+                .map(str::to_string)
+        });
+        if self.user.is_some() && owner != self.user {
+            return None;
+        }
+
+        let config_id = labels.get("config_id").cloned();
+        let outlived_lease = metadata
+            .creation_timestamp
+            .as_ref()
+            .and_then(|created| u64::try_from(created.0.timestamp()).ok())
+            .is_some_and(|created| self.now > created.saturating_add(LEASE_TTL.as_secs()));
+        let reason = finished.or_else(|| {
+            self.lease_reason(metadata, || {
+                owner.as_deref() == Some(self.current_user.as_str())
+                    && outlived_lease
+                    && config_id
+                        .as_ref()
+                        .is_some_and(|id| self.idle_configs.contains(id))
+            })
+        })?;
+
+        Some(OrphanedPod {
+            kind: kind.to_string(),
+            namespace: metadata.namespace.clone().unwrap_or_default(),
+            name,
+            user: owner,
+            config_id,
+            reason,
+        })
+    }
+
+    fn lease_reason(
+        &self, metadata: &ObjectMeta, legacy_unused: impl FnOnce() -> bool,
+    ) -> Option<String> {
+        let annotations = metadata.annotations.clone().unwrap_or_default();
+        let renewed_at = annotations
+            .get(LEASE_RENEWED_AT_ANNOTATION)
+            .and_then(|v| v.parse::<u64>().ok());
+        let ttl = annotations
+            .get(LEASE_TTL_ANNOTATION)
+            .and_then(|v| v.parse::<u64>().ok());
+
+        match (renewed_at, ttl) {
+            (Some(renewed_at), Some(ttl)) if self.now > renewed_at.saturating_add(ttl) => {
+                Some(format!(
+                    "Lease expired {}s ago",
+                    self.now - renewed_at.saturating_add(ttl)
+                ))
+            }
+            (Some(_), Some(_)) => None,
+            _ if legacy_unused() => Some("No lease and config is not running".to_string()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use k8s_openapi::chrono::DateTime;
+
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn filter(user: Option<&str>) -> OrphanFilter {
+        OrphanFilter {
+            now: NOW,
+            current_user: "alice".to_string(),
+            user: user.map(str::to_string),
+            idle_configs: HashSet::from(["7".to_string()]),
+        }
+    }
+
+    fn time(secs_ago: u64) -> Time {
+        Time(DateTime::from_timestamp((NOW - secs_ago) as i64, 0).unwrap())
+    }
+
+    /// Metadata of a proxy of alice's config 7 created an hour ago.
+    fn proxy(name: &str) -> ObjectMeta {
+        ObjectMeta {
+            name: Some(name.to_string()),
+            namespace: Some("default".to_string()),
+            labels: Some(BTreeMap::from([
+                ("config_id".to_string(), "7".to_string()),
+                (USER_LABEL.to_string(), "alice".to_string()),
+            ])),
+            creation_timestamp: Some(time(3600)),
+            ..Default::default()
+        }
+    }
+
+    fn with_lease(mut metadata: ObjectMeta, renewed_secs_ago: u64) -> ObjectMeta {
+        metadata.annotations = Some(BTreeMap::from([
+            (
+                LEASE_RENEWED_AT_ANNOTATION.to_string(),
+                (NOW - renewed_secs_ago).to_string(),
+            ),
+            (
+                LEASE_TTL_ANNOTATION.to_string(),
+                LEASE_TTL.as_secs().to_string(),
+            ),
+        ]));
+        metadata
+    }
+
+    #[tokio::test]
+    async fn test_lease_guard_stops_renewal_with_its_task() {
+        let renewal = tokio::spawn(std::future::pending::<()>());
+        let renewal_handle = renewal.abort_handle();
+        let lease = LeaseGuard(renewal);
+
+        let forward = tokio::spawn(async move {
+            let _lease = lease;
+            std::future::pending::<()>().await
+        });
+        forward.abort();
+        let _ = forward.await;
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !renewal_handle.is_finished() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("lease renewal kept running");
+    }
+
+    #[test]
+    fn test_lease_reason_expired_and_valid() {
+        let filter = filter(None);
+        let expired = with_lease(proxy("kftray-forward-alice-1"), 400);
+        let valid = with_lease(proxy("kftray-forward-alice-1"), 60);
+
+        assert_eq!(
+            filter.lease_reason(&expired, || false),
+            Some("Lease expired 100s ago".to_string())
+        );
+        assert_eq!(filter.lease_reason(&valid, || true), None);
+    }
+
+    #[test]
+    fn test_check_reports_finished_proxies_with_a_valid_lease() {
+        let metadata = with_lease(proxy("kftray-forward-alice-1"), 60);
+
+        let orphan = filter(None)
+            .check(
+                "Job",
+                &metadata,
+                Some("Proxy job finished (Complete)".to_string()),
+            )
+            .unwrap();
+
+        assert_eq!(orphan.kind, "Job");
+        assert_eq!(orphan.namespace, "default");
+        assert_eq!(orphan.config_id.as_deref(), Some("7"));
+        assert_eq!(orphan.reason, "Proxy job finished (Complete)");
+    }
+
+    #[test]
+    fn test_check_skips_deleted_and_foreign_objects() {
+        let filter = filter(None);
+        let mut deleting = with_lease(proxy("kftray-forward-alice-1"), 400);
+        deleting.deletion_timestamp = Some(time(1));
+        let foreign = with_lease(proxy("my-app-7"), 400);
+
+        assert!(filter.check("Pod", &deleting, None).is_none());
+        assert!(filter.check("Pod", &foreign, None).is_none());
+    }
+
+    #[test]
+    fn test_check_filters_by_user() {
+        let labelled = with_lease(proxy("kftray-forward-alice-1"), 400);
+        let mut unlabelled = with_lease(proxy("kftray-forward-bob-1"), 400);
+        unlabelled.labels.as_mut().unwrap().remove(USER_LABEL);
+
+        assert!(filter(Some("bob")).check("Pod", &labelled, None).is_none());
+        assert!(filter(Some("alice"))
+            .check("Pod", &labelled, None)
+            .is_some());
+
+        let orphan = filter(Some("bob")).check("Pod", &unlabelled, None).unwrap();
+        assert_eq!(orphan.user.as_deref(), Some("bob"));
+    }
+
+    #[test]
+    fn test_check_legacy_proxies_without_a_lease() {
+        let filter = filter(None);
+        let legacy = proxy("kftray-forward-alice-1");
+
+        let orphan = filter.check("Pod", &legacy, None).unwrap();
+        assert_eq!(orphan.reason, "No lease and config is not running");
+
+        let mut other_config = legacy.clone();
+        other_config
+            .labels
+            .as_mut()
+            .unwrap()
+            .insert("config_id".to_string(), "8".to_string());
+        assert!(filter.check("Pod", &other_config, None).is_none());
+
+        let mut young = legacy.clone();
+        young.creation_timestamp = Some(time(60));
+        assert!(filter.check("Pod", &young, None).is_none());
+
+        let mut other_user = legacy;
+        other_user
+            .labels
+            .as_mut()
+            .unwrap()
+            .insert(USER_LABEL.to_string(), "bob".to_string());
+        assert!(filter.check("Pod", &other_user, None).is_none());
+    }
+}
@@ -0,0 +1,554 @@
+//! Configs generated from rendered manifests, like `helm template` or
+//! `kustomize build` output, without access to a cluster.
+//!
+//! Every Service found gets a config per port, or the configs of its
+//! `kftray.app/configs` annotation when it has one, so the result matches
+//! what discovery on the cluster would find once the manifests are applied.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use k8s_openapi::api::core::v1::{
+    ContainerPort,
+    PodTemplateSpec,
+    Service,
+    ServiceSpec,
+};
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use kftray_commons::models::config_model::Config;
+use log::{
+    debug,
+    info,
+    warn,
+};
+use serde::Deserialize;
+use serde_yaml::Value as YamlValue;
+
+use crate::kube::models::ManifestDiscoveryOptions;
+use crate::kube::service::{
+    parse_configs,
+    CONFIGS_ANNOTATION,
+};
+
+/// Generates configs from a manifest file, or from every `.yaml` and `.yml`
+/// file under a directory.
+pub fn discover_manifest_configs(
+    path: &Path, options: &ManifestDiscoveryOptions,
+) -> Result<Vec<Config>, String> {
+    let files = manifest_files(path)?;
+    if files.is_empty() {
+        return Err(format!("No manifests found in {}", path.display()));
+    }
+
+    let mut documents = Vec::new();
+    for file in &files {
+        let content = fs::read_to_string(file)
+            .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+        documents.extend(
+            parse_documents(&content)
+                .map_err(|e| format!("Failed to parse {}: {}", file.display(), e))?,
+        );
+    }
+
+    let configs = configs_from_documents(&documents, options);
+    info!(
+        "Generated {} configs from {} manifest files in {}",
+        configs.len(),
+        files.len(),
+        path.display()
+    );
+
+    Ok(configs)
+}
+
+/// Generates configs from manifests already read, e.g. the output of
+/// `helm template`.
+pub fn manifest_configs_from_str(
+    content: &str, options: &ManifestDiscoveryOptions,
+) -> Result<Vec<Config>, String> {
+    let documents =
+        parse_documents(content).map_err(|e| format!("Failed to parse manifests: {}", e))?;
+    Ok(configs_from_documents(&documents, options))
+}
+
+fn manifest_files(path: &Path) -> Result<Vec<PathBuf>, String> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files = Vec::new();
+    let entries =
+        fs::read_dir(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    for entry in entries {
+        let entry_path = entry.map_err(|e| e.to_string())?.path();
+        if entry_path.is_dir() {
+            files.extend(manifest_files(&entry_path)?);
+        } else if entry_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext == "yaml" || ext == "yml")
+        {
+            files.push(entry_path);
+        }
+    }
+
+    // Sorted so the generated configs come out in the same order every time
+    files.sort();
+    Ok(files)
+}
+
+/// Splits a multi document YAML stream into its objects, unpacking lists.
+fn parse_documents(content: &str) -> Result<Vec<YamlValue>, serde_yaml::Error> {
+    let mut documents = Vec::new();
+
+    for document in serde_yaml::Deserializer::from_str(content) {
+        let value = YamlValue::deserialize(document)?;
+        match kind(&value) {
+            Some(kind) if kind.ends_with("List") => {
+                if let Some(items) = value.get("items").and_then(YamlValue::as_sequence) {
+                    documents.extend(items.iter().cloned());
+                }
+            }
+            Some(_) => documents.push(value),
+            // Empty documents, like the ones between `---` separators of
+            // templates that rendered nothing
+            None => {}
+        }
+    }
+
+    Ok(documents)
+}
+
+fn configs_from_documents(
+    documents: &[YamlValue], options: &ManifestDiscoveryOptions,
+) -> Vec<Config> {
+    let pod_templates: Vec<(String, PodTemplateSpec)> = documents
+        .iter()
+        .filter_map(|document| {
+            let template = pod_template(document)?;
+            Some((document_namespace(document, options), template))
+        })
+        .collect();
+
+    documents
+        .iter()
+        .filter(|document| kind(document) == Some("Service"))
+        .filter_map(
+            |document| match serde_yaml::from_value::<Service>(document.clone()) {
+                Ok(service) => Some((document_namespace(document, options), service)),
+                Err(e) => {
+                    warn!("Skipping invalid Service manifest: {}", e);
+                    None
+                }
+            },
+        )
+        .flat_map(|(namespace, service)| {
+            service_configs(&service, &namespace, &pod_templates, options)
+        })
+        .collect()
+}
+
+fn service_configs(
+    service: &Service, source_namespace: &str, pod_templates: &[(String, PodTemplateSpec)],
+    options: &ManifestDiscoveryOptions,
+) -> Vec<Config> {
+    let (Some(service_name), Some(spec)) = (service.metadata.name.as_deref(), &service.spec) else {
+        return Vec::new();
+    };
+
+    if spec.type_.as_deref() == Some("ExternalName") {
+        debug!("Skipping ExternalName service: {}", service_name);
+        return Vec::new();
+    }
+
+    let namespace = render_template(
+        &options.namespace_template,
+        &[("namespace", source_namespace), ("service", service_name)],
+    );
+    let container_ports = selected_container_ports(spec, source_namespace, pod_templates);
+    let ports = service_ports(service_name, spec, &container_ports);
+
+    if let Some(configs_str) = service
+        .metadata
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(CONFIGS_ANNOTATION))
+    {
+        let ports: HashMap<String, i32> = ports.into_iter().collect();
+        return parse_configs(
+            configs_str,
+            &options.context,
+            &namespace,
+            service_name,
+            &ports,
+            options.kubeconfig.clone(),
+        )
+        .into_iter()
+        .map(|annotated| annotated.config)
+        .collect();
+    }
+
+    let mut alias_template = options.alias_template.clone();
+    if ports.len() > 1
+        && !alias_template.contains("{port_name}")
+        && !alias_template.contains("{port}")
+    {
+        alias_template.push_str("-{port_name}");
+    }
+
+    ports
+        .iter()
+        .map(|(port_name, port)| {
+            let alias = render_template(
+                &alias_template,
+                &[
+                    ("namespace", namespace.as_str()),
+                    ("service", service_name),
+                    ("port_name", port_name),
+                    ("port", &port.to_string()),
+                ],
+            );
+
+            Config {
+                id: None,
+                context: options.context.clone(),
+                kubeconfig: options.kubeconfig.clone(),
+                namespace: namespace.clone(),
+                service: Some(service_name.to_string()),
+                alias: Some(alias),
+                local_port: Some(*port as u16),
+                remote_port: Some(*port as u16),
+                protocol: "tcp".to_string(),
+                workload_type: Some("service".to_string()),
+                target: None,
+                local_address: None,
+                remote_address: None,
+                domain_enabled: None,
+                pod_template: None,
+            }
+        })
+        .collect()
+}
+
+/// Ports of a service with the port they reach on its pods, named after the
+/// service port.
+fn service_ports(
+    service_name: &str, spec: &ServiceSpec, container_ports: &[&ContainerPort],
+) -> Vec<(String, i32)> {
+    spec.ports
+        .iter()
+        .flatten()
+        .map(|port| {
+            let target_port = match &port.target_port {
+                Some(IntOrString::Int(target_port)) => *target_port,
+                Some(IntOrString::String(name)) => container_ports
+                    .iter()
+                    .find(|container_port| container_port.name.as_deref() == Some(name))
+                    .map(|container_port| container_port.container_port)
+                    .unwrap_or_else(|| {
+                        warn!(
+                            "Target port {} of service {} is not in the manifests, using port {}",
+                            name, service_name, port.port
+                        );
+                        port.port
+                    }),
+                None => port.port,
+            };
+
+            let name = port.name.clone().unwrap_or_else(|| target_port.to_string());
+            (name, target_port)
+        })
+        .collect()
+}
+
+/// Container ports of the pod templates a service's selector matches.
+fn selected_container_ports<'a>(
+    spec: &ServiceSpec, namespace: &str, pod_templates: &'a [(String, PodTemplateSpec)],
+) -> Vec<&'a ContainerPort> {
+    let Some(selector) = spec.selector.as_ref().filter(|s| !s.is_empty()) else {
+        return Vec::new();
+    };
+
+    pod_templates
+        .iter()
+        .filter(|(template_namespace, template)| {
+            let labels = template
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.labels.as_ref());
+            template_namespace == namespace
+                && labels.is_some_and(|labels| {
+                    selector
+                        .iter()
+                        .all(|(key, value)| labels.get(key) == Some(value))
+                })
+        })
+        .filter_map(|(_, template)| template.spec.as_ref())
+        .flat_map(|spec| &spec.containers)
+        .flat_map(|container| container.ports.iter().flatten())
+        .collect()
+}
+
+/// Pod template of a workload, or the pod itself.
+fn pod_template(document: &YamlValue) -> Option<PodTemplateSpec> {
+    let template = match kind(document)? {
+        "Pod" => document,
+        "Deployment" | "StatefulSet" | "DaemonSet" | "ReplicaSet" | "Job" => {
+            document.get("spec")?.get("template")?
+        }
+        "CronJob" => document
+            .get("spec")?
+            .get("jobTemplate")?
+            .get("spec")?
+            .get("template")?,
+        _ => return None,
+    };
+
+    serde_yaml::from_value(template.clone()).ok()
+}
+
+fn kind(document: &YamlValue) -> Option<&str> {
+    document.get("kind").and_then(YamlValue::as_str)
+}
+
+fn document_namespace(document: &YamlValue, options: &ManifestDiscoveryOptions) -> String {
+    document
+        .get("metadata")
+        .and_then(|metadata| metadata.get("namespace"))
+        .and_then(YamlValue::as_str)
+        .filter(|namespace| !namespace.is_empty())
+        .unwrap_or(&options.default_namespace)
+        .to_string()
+}
+
+fn render_template(template: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |rendered, (key, value)| {
+            rendered.replace(&format!("{{{}}}", key), value)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFESTS: &str = r#"
+---
+# Source: api/templates/service.yaml
+apiVersion: v1
+kind: Service
+metadata:
+  name: api
+spec:
+  selector:
+    app: api
+  ports:
+    - name: http
+      port: 80
+      targetPort: http
+    - name: grpc
+      port: 9090
+---
+---
+# Source: api/templates/deployment.yaml
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: api
+spec:
+  selector:
+    matchLabels:
+      app: api
+  template:
+    metadata:
+      labels:
+        app: api
+    spec:
+      containers:
+        - name: api
+          image: api:latest
+          ports:
+            - name: http
+              containerPort: 8080
+---
+apiVersion: v1
+kind: List
+items:
+  - apiVersion: v1
+    kind: Service
+    metadata:
+      name: db
+      namespace: data
+      annotations:
+        kftray.app/configs: "pg-15432-5432"
+    spec:
+      ports:
+        - port: 5432
+  - apiVersion: v1
+    kind: Service
+    metadata:
+      name: external
+    spec:
+      type: ExternalName
+      externalName: example.com
+"#;
+
+    fn options() -> ManifestDiscoveryOptions {
+        ManifestDiscoveryOptions {
+            context: "kind".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn summary(configs: &[Config]) -> Vec<(String, String, Option<u16>, Option<u16>)> {
+        configs
+            .iter()
+            .map(|config| {
+                (
+                    config.alias.clone().unwrap_or_default(),
+                    config.namespace.clone(),
+                    config.local_port,
+                    config.remote_port,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_manifest_services_become_configs() {
+        let configs = manifest_configs_from_str(MANIFESTS, &options()).unwrap();
+
+        assert_eq!(
+            summary(&configs),
+            [
+                (
+                    "api-http".to_string(),
+                    "default".to_string(),
+                    Some(8080),
+                    Some(8080)
+                ),
+                (
+                    "api-grpc".to_string(),
+                    "default".to_string(),
+                    Some(9090),
+                    Some(9090)
+                ),
+                (
+                    "pg".to_string(),
+                    "data".to_string(),
+                    Some(15432),
+                    Some(5432)
+                ),
+            ]
+        );
+
+        let api = &configs[0];
+        assert_eq!(api.context, "kind");
+        assert_eq!(api.service.as_deref(), Some("api"));
+        assert_eq!(api.workload_type.as_deref(), Some("service"));
+        assert_eq!(api.protocol, "tcp");
+    }
+
+    #[test]
+    fn test_manifest_templates() {
+        let options = ManifestDiscoveryOptions {
+            default_namespace: "shop".to_string(),
+            namespace_template: "{namespace}-dev".to_string(),
+            alias_template: "{namespace}-{service}-{port}".to_string(),
+            ..options()
+        };
+        let configs = manifest_configs_from_str(MANIFESTS, &options).unwrap();
+
+        assert_eq!(
+            summary(&configs),
+            [
+                (
+                    "shop-dev-api-8080".to_string(),
+                    "shop-dev".to_string(),
+                    Some(8080),
+                    Some(8080)
+                ),
+                (
+                    "shop-dev-api-9090".to_string(),
+                    "shop-dev".to_string(),
+                    Some(9090),
+                    Some(9090)
+                ),
+                (
+                    "pg".to_string(),
+                    "data-dev".to_string(),
+                    Some(15432),
+                    Some(5432)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_single_port_alias_keeps_template() {
+        let manifest = r#"
+apiVersion: v1
+kind: Service
+metadata:
+  name: web
+  namespace: front
+spec:
+  ports:
+    - name: http
+      port: 80
+      targetPort: 3000
+"#;
+        let configs = manifest_configs_from_str(manifest, &options()).unwrap();
+
+        assert_eq!(
+            summary(&configs),
+            [(
+                "web".to_string(),
+                "front".to_string(),
+                Some(3000),
+                Some(3000)
+            )]
+        );
+    }
+
+    #[test]
+    fn test_unknown_target_port_uses_service_port() {
+        let manifest = r#"
+apiVersion: v1
+kind: Service
+metadata:
+  name: web
+spec:
+  selector:
+    app: missing
+  ports:
+    - name: http
+      port: 80
+      targetPort: http
+"#;
+        let configs = manifest_configs_from_str(manifest, &options()).unwrap();
+
+        assert_eq!(configs[0].remote_port, Some(80));
+    }
+
+    #[test]
+    fn test_invalid_yaml_is_rejected() {
+        assert!(manifest_configs_from_str("kind: [Service", &options()).is_err());
+    }
+
+    #[test]
+    fn test_render_template() {
+        assert_eq!(
+            render_template(
+                "{namespace}/{service}/{unknown}",
+                &[("namespace", "data"), ("service", "db")]
+            ),
+            "data/db/{unknown}"
+        );
+    }
+}
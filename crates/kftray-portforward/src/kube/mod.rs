@@ -1,18 +1,56 @@
+mod access;
 pub mod client;
+mod discovery_watch;
+pub mod fault;
+mod lease;
+mod manifest;
 pub mod models;
 pub mod pod_finder;
+mod pod_template;
+mod preflight;
 mod proxy;
+mod proxy_workload;
+pub mod replay;
+mod reverse;
 mod service;
+mod shared_proxy;
 mod start;
 mod stop;
 pub mod tcp_forwarder;
+mod tunnel;
 pub mod udp_forwarder;
 
+pub use discovery_watch::{
+    running_discovery_watches,
+    start_discovery_watch,
+    stop_discovery_watch,
+};
+pub use lease::{
+    delete_orphaned_proxy_pods,
+    find_orphaned_proxy_pods,
+};
+pub use manifest::{
+    discover_manifest_configs,
+    manifest_configs_from_str,
+};
+pub use preflight::{
+    check_forward_permissions,
+    required_permissions,
+};
 pub use proxy::{
     deploy_and_forward_pod,
     stop_proxy_forward,
 };
-pub use service::retrieve_service_configs;
+pub use reverse::start_reverse_tunnel;
+pub use service::{
+    discover_context_services,
+    discover_service_configs,
+    retrieve_service_configs,
+};
+pub use shared_proxy::{
+    deploy_and_forward_shared,
+    stop_shared_proxy_forward,
+};
 pub use start::start_port_forward;
 pub use stop::{
     stop_all_port_forward,
@@ -6,6 +6,7 @@ use k8s_openapi::api::core::v1::{
     Service,
 };
 use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use kftray_commons::models::config_model::Config;
 use kube::api::Api;
 use serde::{
     Deserialize,
@@ -14,6 +15,8 @@ use serde::{
 use tokio::sync::Mutex;
 use tracing::debug;
 
+use crate::kube::access::denial_reason;
+
 impl NameSpace {
     pub fn name_any(&self) -> String {
         self.0.clone().unwrap_or_else(|| "default".to_string())
@@ -126,11 +129,258 @@ pub struct KubeServicePortInfo {
     pub port: Option<IntOrString>,
 }
 
+/// A config read from a service's `kftray.app/configs` annotation.
+#[derive(Clone, Debug)]
+pub struct AnnotatedConfig {
+    pub config: Config,
+    /// HTTP logging is turned on for the config once it exists.
+    pub http_logs: bool,
+}
+
+/// What a discovery watch does with a config whose service or annotation
+/// entry is gone.
+#[derive(Clone, Copy, Default, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RemovedConfigPolicy {
+    /// Keep the config, marked as stale.
+    #[default]
+    Flag,
+    /// Stop its forward and delete it.
+    Delete,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct DiscoveryWatchOptions {
+    pub context: String,
+    pub kubeconfig: Option<String>,
+    pub removed: RemovedConfigPolicy,
+}
+
+/// A namespace discovery didn't scan, and why.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct SkippedNamespace {
+    pub namespace: String,
+    pub reason: String,
+}
+
+/// Configs of the annotated services of a context, with the namespaces
+/// scanned and skipped to find them.
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct ServiceDiscovery {
+    pub configs: Vec<Config>,
+    /// Namespaces whose services were listed.
+    pub namespaces: Vec<String>,
+    pub skipped: Vec<SkippedNamespace>,
+}
+
+/// How discovery fans out over several contexts.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct MultiContextDiscoveryOptions {
+    /// Contexts to scan, every context of the kubeconfig when empty.
+    pub contexts: Vec<String>,
+    pub kubeconfig: Option<String>,
+    /// Namespaces to scan in every context. When empty, all namespaces are
+    /// scanned, or the context's namespace if they can't be listed.
+    pub namespaces: Vec<String>,
+    /// Contexts scanned at the same time.
+    pub concurrency: usize,
+    /// Seconds a context gets before its scan is given up.
+    pub timeout_secs: u64,
+}
+
+impl Default for MultiContextDiscoveryOptions {
+    fn default() -> Self {
+        Self {
+            contexts: Vec::new(),
+            kubeconfig: None,
+            namespaces: Vec::new(),
+            concurrency: 4,
+            timeout_secs: 30,
+        }
+    }
+}
+
+/// Outcome of discovery on one context.
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct ContextDiscovery {
+    pub context: String,
+    /// Configs matching no existing config, nor one found before them.
+    pub new_configs: Vec<Config>,
+    /// Configs already present locally.
+    pub existing: usize,
+    /// Configs found more than once, like services sharing an alias across
+    /// contexts.
+    pub duplicates: usize,
+    /// Namespaces left out, like the ones the user can't list services in.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_namespaces: Vec<SkippedNamespace>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Outcome of discovery on several contexts, in the order they were given.
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct DiscoveryReport {
+    pub contexts: Vec<ContextDiscovery>,
+}
+
+impl DiscoveryReport {
+    pub fn new_configs(&self) -> impl Iterator<Item = &Config> {
+        self.contexts
+            .iter()
+            .flat_map(|context| &context.new_configs)
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &ContextDiscovery> {
+        self.contexts
+            .iter()
+            .filter(|context| context.error.is_some())
+    }
+}
+
+/// How configs are generated from rendered manifests.
+///
+/// Templates take `{namespace}`, `{service}`, `{port_name}` and `{port}`.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct ManifestDiscoveryOptions {
+    /// Context written into the generated configs.
+    pub context: String,
+    pub kubeconfig: Option<String>,
+    /// Namespace of services whose manifest sets none, as in `helm template`
+    /// output rendered without `--namespace`.
+    pub default_namespace: String,
+    pub namespace_template: String,
+    /// Services with several ports get `-{port_name}` appended when the
+    /// template has neither port placeholder.
+    pub alias_template: String,
+}
+
+impl Default for ManifestDiscoveryOptions {
+    fn default() -> Self {
+        Self {
+            context: String::new(),
+            kubeconfig: None,
+            default_namespace: "default".to_string(),
+            namespace_template: "{namespace}".to_string(),
+            alias_template: "{service}".to_string(),
+        }
+    }
+}
+
 #[derive(Serialize, Debug)]
 pub struct PodInfo {
     pub labels_str: String,
 }
 
+#[derive(Serialize, Debug, Clone)]
+pub struct OrphanedPod {
+    pub kind: String,
+    pub namespace: String,
+    pub name: String,
+    pub user: Option<String>,
+    pub config_id: Option<String>,
+    pub reason: String,
+}
+
+/// A permission a forward needs on its namespace.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct RequiredPermission {
+    pub verb: String,
+    /// API group of the resource, empty for the core group.
+    pub group: String,
+    pub resource: String,
+    pub subresource: Option<String>,
+    /// What the forward does with it.
+    pub purpose: String,
+}
+
+impl RequiredPermission {
+    /// The resource as `kubectl auth can-i` takes it, like `pods/portforward`
+    /// or `deployments.apps`.
+    pub fn resource_name(&self) -> String {
+        let mut name = self.resource.clone();
+        if !self.group.is_empty() {
+            name = format!("{}.{}", name, self.group);
+        }
+        if let Some(subresource) = &self.subresource {
+            name = format!("{}/{}", name, subresource);
+        }
+        name
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct PermissionCheck {
+    pub permission: RequiredPermission,
+    pub allowed: bool,
+    /// Reason of a denial, given by the authorizer or describing the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Permissions of a config checked before starting its forward.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct PreflightReport {
+    pub config_id: Option<i64>,
+    pub context: String,
+    pub namespace: String,
+    pub checks: Vec<PermissionCheck>,
+}
+
+impl PreflightReport {
+    pub fn is_allowed(&self) -> bool {
+        self.checks.iter().all(|check| check.allowed)
+    }
+
+    pub fn denied(&self) -> impl Iterator<Item = &PermissionCheck> {
+        self.checks.iter().filter(|check| !check.allowed)
+    }
+
+    /// Explains the missing permissions and how to check them, or `None`
+    /// when nothing is missing.
+    pub fn explanation(&self) -> Option<String> {
+        let denied: Vec<&PermissionCheck> = self.denied().collect();
+        let first = denied.first()?;
+
+        let mut explanation = format!(
+            "Missing permissions in namespace '{}' of context '{}':",
+            self.namespace, self.context
+        );
+        for check in &denied {
+            let permission = &check.permission;
+            explanation.push_str(&format!(
+                "\n- {} {}, needed to {}",
+                permission.verb,
+                permission.resource_name(),
+                permission.purpose
+            ));
+            // A reason describing the request only repeats the line
+            let described = denial_reason(
+                Some(&self.namespace),
+                &permission.verb,
+                &permission.resource,
+                permission.subresource.as_deref(),
+            );
+            if let Some(reason) = check.reason.as_ref().filter(|reason| **reason != described) {
+                explanation.push_str(&format!(" ({})", reason));
+            }
+        }
+        explanation.push_str(&format!(
+            "\nCheck with `kubectl auth can-i {} {} -n {} --context {}`",
+            first.permission.verb,
+            first.permission.resource_name(),
+            self.namespace,
+            self.context
+        ));
+        explanation.push_str(" and ask a cluster admin for a Role granting them.");
+
+        Some(explanation)
+    }
+}
+
 #[derive(Clone, Debug)]
 #[allow(dead_code)]
 pub struct PortForward {
@@ -0,0 +1,574 @@
+//! Per-config customization of the proxy pod.
+//!
+//! A config's [`PodTemplate`] is layered over the global proxy manifest, and
+//! its security preset hardens the pod for the matching Pod Security
+//! Standards level. [`validate_proxy_pod`] checks the final pod against that
+//! level so a pod the admission controller would reject fails before it is
+//! created.
+
+use k8s_openapi::api::core::v1::{
+    Capabilities,
+    Container,
+    LocalObjectReference,
+    Pod,
+    PodSecurityContext,
+    SeccompProfile,
+    SecurityContext,
+    Sysctl,
+    Volume,
+};
+use kftray_commons::models::pod_template_model::{
+    PodTemplate,
+    SecurityPreset,
+};
+
+/// Unprivileged uid and gid the restricted preset runs the server as, since
+/// the image does not declare a user.
+const NON_ROOT_ID: i64 = 65534;
+
+/// Capabilities the baseline level allows containers to add.
+const BASELINE_CAPABILITIES: &[&str] = &[
+    "AUDIT_WRITE",
+    "CHOWN",
+    "DAC_OVERRIDE",
+    "FOWNER",
+    "FSETID",
+    "KILL",
+    "MKNOD",
+    "NET_BIND_SERVICE",
+    "SETFCAP",
+    "SETGID",
+    "SETPCAP",
+    "SETUID",
+    "SYS_CHROOT",
+];
+
+/// Sysctls the baseline level treats as safe.
+const SAFE_SYSCTLS: &[&str] = &[
+    "kernel.shm_rmid_forced",
+    "net.ipv4.ip_local_port_range",
+    "net.ipv4.ip_unprivileged_port_start",
+    "net.ipv4.tcp_syncookies",
+    "net.ipv4.ping_group_range",
+    "net.ipv4.ip_local_reserved_ports",
+    "net.ipv4.tcp_keepalive_time",
+    "net.ipv4.tcp_fin_timeout",
+    "net.ipv4.tcp_keepalive_intvl",
+    "net.ipv4.tcp_keepalive_probes",
+];
+
+/// Volume types the restricted level allows.
+const RESTRICTED_VOLUME_TYPES: &[&str] = &[
+    "configMap",
+    "csi",
+    "downwardAPI",
+    "emptyDir",
+    "ephemeral",
+    "persistentVolumeClaim",
+    "projected",
+    "secret",
+];
+
+/// Applies the overrides of a config's pod template to the rendered pod.
+///
+/// Labels and annotations of the manifest take precedence, so a template can
+/// not break the selectors kftray relies on. The security preset is applied
+/// separately by [`apply_security_preset`], after every other change.
+pub(super) fn apply_pod_template(pod: &mut Pod, template: &PodTemplate) {
+    let labels = pod.metadata.labels.get_or_insert_with(Default::default);
+    for (key, value) in &template.labels {
+        labels.entry(key.clone()).or_insert_with(|| value.clone());
+    }
+
+    let annotations = pod
+        .metadata
+        .annotations
+        .get_or_insert_with(Default::default);
+    for (key, value) in &template.annotations {
+        annotations
+            .entry(key.clone())
+            .or_insert_with(|| value.clone());
+    }
+
+    let Some(spec) = pod.spec.as_mut() else {
+        return;
+    };
+
+    if let Some(service_account) = template.service_account.as_ref().filter(|s| !s.is_empty()) {
+        spec.service_account_name = Some(service_account.clone());
+    }
+
+    if !template.image_pull_secrets.is_empty() {
+        spec.image_pull_secrets.get_or_insert_with(Vec::new).extend(
+            template
+                .image_pull_secrets
+                .iter()
+                .map(|name| LocalObjectReference { name: name.clone() }),
+        );
+    }
+
+    if !template.node_selector.is_empty() {
+        spec.node_selector
+            .get_or_insert_with(Default::default)
+            .extend(template.node_selector.clone());
+    }
+
+    if !template.tolerations.is_empty() {
+        spec.tolerations
+            .get_or_insert_with(Vec::new)
+            .extend(template.tolerations.clone());
+    }
+
+    if let Some(container) = spec.containers.first_mut() {
+        if let Some(image) = template.image.as_ref().filter(|i| !i.is_empty()) {
+            container.image = Some(image.clone());
+        }
+        if let Some(resources) = &template.resources {
+            container.resources = Some(resources.clone());
+        }
+    }
+}
+
+/// Hardens the pod so it satisfies the given Pod Security Standards level.
+///
+/// Both levels drop every capability, forbid privilege escalation and use the
+/// runtime's default seccomp profile. The restricted level also runs the
+/// server as an unprivileged user with a read-only root filesystem, and lets
+/// it bind ports below 1024 through a namespaced sysctl.
+pub(super) fn apply_security_preset(pod: &mut Pod, preset: SecurityPreset) {
+    let Some(spec) = pod.spec.as_mut() else {
+        return;
+    };
+
+    let pod_context = spec
+        .security_context
+        .get_or_insert_with(PodSecurityContext::default);
+    pod_context.seccomp_profile = Some(SeccompProfile {
+        type_: "RuntimeDefault".to_string(),
+        ..Default::default()
+    });
+
+    if preset == SecurityPreset::Restricted {
+        pod_context.run_as_non_root = Some(true);
+        pod_context.run_as_user.get_or_insert(NON_ROOT_ID);
+        pod_context.run_as_group.get_or_insert(NON_ROOT_ID);
+
+        let sysctls = pod_context.sysctls.get_or_insert_with(Vec::new);
+        if !sysctls
+            .iter()
+            .any(|s| s.name == "net.ipv4.ip_unprivileged_port_start")
+        {
+            sysctls.push(Sysctl {
+                name: "net.ipv4.ip_unprivileged_port_start".to_string(),
+                value: "0".to_string(),
+            });
+        }
+    }
+
+    let containers = spec
+        .containers
+        .iter_mut()
+        .chain(spec.init_containers.iter_mut().flatten());
+    for container in containers {
+        let context = container
+            .security_context
+            .get_or_insert_with(SecurityContext::default);
+        context.privileged = Some(false);
+        context.allow_privilege_escalation = Some(false);
+        context.capabilities = Some(Capabilities {
+            add: None,
+            drop: Some(vec!["ALL".to_string()]),
+        });
+
+        if preset == SecurityPreset::Restricted {
+            context.run_as_non_root = Some(true);
+            context.read_only_root_filesystem = Some(true);
+            if context.run_as_user == Some(0) {
+                context.run_as_user = None;
+            }
+        }
+    }
+}
+
+/// Checks a proxy pod against the Pod Security Standards level of its preset.
+///
+/// Without a preset only the basic shape of the pod is checked. Every
+/// violation is reported, not just the first one.
+pub(super) fn validate_proxy_pod(pod: &Pod, preset: Option<SecurityPreset>) -> Result<(), String> {
+    let spec = pod
+        .spec
+        .as_ref()
+        .ok_or_else(|| "Proxy pod manifest has no spec".to_string())?;
+    if spec.containers.is_empty() {
+        return Err("Proxy pod manifest has no containers".to_string());
+    }
+    if let Some(container) = spec
+        .containers
+        .iter()
+        .find(|c| c.image.as_deref().is_none_or(str::is_empty))
+    {
+        return Err(format!("Container {} has no image", container.name));
+    }
+
+    let Some(preset) = preset else {
+        return Ok(());
+    };
+
+    let mut violations = Vec::new();
+
+    for (field, enabled) in [
+        ("hostNetwork", spec.host_network),
+        ("hostPID", spec.host_pid),
+        ("hostIPC", spec.host_ipc),
+    ] {
+        if enabled == Some(true) {
+            violations.push(format!("{} must not be enabled", field));
+        }
+    }
+
+    let pod_context = spec.security_context.as_ref();
+    for sysctl in pod_context
+        .and_then(|c| c.sysctls.as_ref())
+        .into_iter()
+        .flatten()
+    {
+        if !SAFE_SYSCTLS.contains(&sysctl.name.as_str()) {
+            violations.push(format!("sysctl {} is not allowed", sysctl.name));
+        }
+    }
+
+    for volume in spec.volumes.iter().flatten() {
+        if volume.host_path.is_some() {
+            violations.push(format!("volume {} must not use hostPath", volume.name));
+        } else if preset == SecurityPreset::Restricted && !is_restricted_volume(volume) {
+            violations.push(format!("volume {} has a disallowed type", volume.name));
+        }
+    }
+
+    if preset == SecurityPreset::Restricted {
+        let seccomp = pod_context.and_then(|c| c.seccomp_profile.as_ref());
+        if pod_context.and_then(|c| c.run_as_user) == Some(0) {
+            violations.push("pod must not run as uid 0".to_string());
+        }
+
+        let containers = spec
+            .containers
+            .iter()
+            .chain(spec.init_containers.iter().flatten());
+        for container in containers {
+            let context = container.security_context.as_ref();
+            let non_root = context
+                .and_then(|c| c.run_as_non_root)
+                .or(pod_context.and_then(|c| c.run_as_non_root));
+            if non_root != Some(true) {
+                violations.push(format!(
+                    "container {} must set runAsNonRoot",
+                    container.name
+                ));
+            }
+            if context.and_then(|c| c.run_as_user) == Some(0) {
+                violations.push(format!(
+                    "container {} must not run as uid 0",
+                    container.name
+                ));
+            }
+            let profile = context
+                .and_then(|c| c.seccomp_profile.as_ref())
+                .or(seccomp)
+                .map(|p| p.type_.as_str());
+            if !matches!(profile, Some("RuntimeDefault" | "Localhost")) {
+                violations.push(format!(
+                    "container {} must use the RuntimeDefault or Localhost seccomp profile",
+                    container.name
+                ));
+            }
+        }
+    }
+
+    let containers = spec
+        .containers
+        .iter()
+        .chain(spec.init_containers.iter().flatten());
+    for container in containers {
+        check_container(container, preset, &mut violations);
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Proxy pod violates the {} Pod Security Standard: {}",
+            preset.as_str(),
+            violations.join("; ")
+        ))
+    }
+}
+
+fn check_container(container: &Container, preset: SecurityPreset, violations: &mut Vec<String>) {
+    let name = &container.name;
+    let context = container.security_context.as_ref();
+
+    if context.and_then(|c| c.privileged) == Some(true) {
+        violations.push(format!("container {} must not be privileged", name));
+    }
+
+    if container
+        .ports
+        .iter()
+        .flatten()
+        .any(|p| p.host_port.is_some_and(|port| port != 0))
+    {
+        violations.push(format!("container {} must not use host ports", name));
+    }
+
+    let capabilities = context.and_then(|c| c.capabilities.as_ref());
+    for capability in capabilities
+        .and_then(|c| c.add.as_ref())
+        .into_iter()
+        .flatten()
+    {
+        let allowed = match preset {
+            SecurityPreset::Baseline => BASELINE_CAPABILITIES.contains(&capability.as_str()),
+            SecurityPreset::Restricted => capability == "NET_BIND_SERVICE",
+        };
+        if !allowed {
+            violations.push(format!(
+                "container {} must not add capability {}",
+                name, capability
+            ));
+        }
+    }
+
+    if preset == SecurityPreset::Restricted {
+        let drops_all = capabilities
+            .and_then(|c| c.drop.as_ref())
+            .is_some_and(|drop| drop.iter().any(|c| c == "ALL"));
+        if !drops_all {
+            violations.push(format!("container {} must drop ALL capabilities", name));
+        }
+
+        if context.and_then(|c| c.allow_privilege_escalation) != Some(false) {
+            violations.push(format!(
+                "container {} must set allowPrivilegeEscalation to false",
+                name
+            ));
+        }
+    }
+}
+
+fn is_restricted_volume(volume: &Volume) -> bool {
+    let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(volume) else {
+        return false;
+    };
+
+    fields
+        .keys()
+        .filter(|key| key.as_str() != "name")
+        .all(|key| RESTRICTED_VOLUME_TYPES.contains(&key.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::Toleration;
+    use serde_json::json;
+
+    use super::*;
+
+    /// The proxy pod of the default manifest, rendered.
+    fn proxy_pod() -> Pod {
+        serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": "kftray-forward-abc",
+                "labels": {"app": "kftray-forward-abc", "config_id": "1"}
+            },
+            "spec": {
+                "containers": [{
+                    "name": "kftray-forward-abc",
+                    "image": "ghcr.io/hcavarsan/kftray-server:latest",
+                    "env": [
+                        {"name": "LOCAL_PORT", "value": "80"},
+                        {"name": "REMOTE_PORT", "value": "80"},
+                        {"name": "REMOTE_ADDRESS", "value": "db"},
+                        {"name": "PROXY_TYPE", "value": "tcp"}
+                    ]
+                }]
+            }
+        }))
+        .unwrap()
+    }
+
+    fn container_context(pod: &Pod) -> &SecurityContext {
+        pod.spec.as_ref().unwrap().containers[0]
+            .security_context
+            .as_ref()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_template_keeps_manifest_labels() {
+        let mut pod = proxy_pod();
+        let template = PodTemplate {
+            image: Some("registry.local/kftray-server:1.0".to_string()),
+            service_account: Some("proxy".to_string()),
+            image_pull_secrets: vec!["registry".to_string()],
+            tolerations: vec![Toleration {
+                key: Some("dedicated".to_string()),
+                operator: Some("Exists".to_string()),
+                ..Default::default()
+            }],
+            labels: [
+                ("app".to_string(), "other".to_string()),
+                ("team".to_string(), "payments".to_string()),
+            ]
+            .into(),
+            ..Default::default()
+        };
+
+        apply_pod_template(&mut pod, &template);
+
+        let labels = pod.metadata.labels.as_ref().unwrap();
+        assert_eq!(labels["app"], "kftray-forward-abc");
+        assert_eq!(labels["team"], "payments");
+
+        let spec = pod.spec.as_ref().unwrap();
+        assert_eq!(
+            spec.containers[0].image.as_deref(),
+            Some("registry.local/kftray-server:1.0")
+        );
+        assert_eq!(spec.service_account_name.as_deref(), Some("proxy"));
+        assert_eq!(
+            spec.image_pull_secrets.as_ref().unwrap()[0].name,
+            "registry"
+        );
+        assert_eq!(spec.tolerations.as_ref().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_default_pod_fails_restricted() {
+        let error = validate_proxy_pod(&proxy_pod(), Some(SecurityPreset::Restricted)).unwrap_err();
+
+        assert!(error.contains("restricted"));
+        assert!(error.contains("must set runAsNonRoot"));
+        assert!(error.contains("must drop ALL capabilities"));
+        assert!(error.contains("allowPrivilegeEscalation"));
+        assert!(error.contains("seccomp"));
+        assert!(validate_proxy_pod(&proxy_pod(), None).is_ok());
+    }
+
+    #[test]
+    fn test_restricted_preset_passes_validation() {
+        let mut pod = proxy_pod();
+        apply_security_preset(&mut pod, SecurityPreset::Restricted);
+
+        assert!(validate_proxy_pod(&pod, Some(SecurityPreset::Restricted)).is_ok());
+        assert!(validate_proxy_pod(&pod, Some(SecurityPreset::Baseline)).is_ok());
+
+        let pod_context = pod
+            .spec
+            .as_ref()
+            .unwrap()
+            .security_context
+            .as_ref()
+            .unwrap();
+        assert_eq!(pod_context.run_as_user, Some(NON_ROOT_ID));
+        assert!(pod_context
+            .sysctls
+            .as_ref()
+            .unwrap()
+            .iter()
+            .any(|s| s.name == "net.ipv4.ip_unprivileged_port_start" && s.value == "0"));
+        assert_eq!(
+            container_context(&pod).read_only_root_filesystem,
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_baseline_preset_passes_validation() {
+        let mut pod = proxy_pod();
+        apply_security_preset(&mut pod, SecurityPreset::Baseline);
+
+        assert!(validate_proxy_pod(&pod, Some(SecurityPreset::Baseline)).is_ok());
+        assert_eq!(container_context(&pod).run_as_non_root, None);
+    }
+
+    #[test]
+    fn test_preset_resets_root_user() {
+        let mut pod = proxy_pod();
+        pod.spec.as_mut().unwrap().containers[0].security_context = Some(SecurityContext {
+            run_as_user: Some(0),
+            ..Default::default()
+        });
+
+        apply_security_preset(&mut pod, SecurityPreset::Restricted);
+
+        assert_eq!(container_context(&pod).run_as_user, None);
+        assert!(validate_proxy_pod(&pod, Some(SecurityPreset::Restricted)).is_ok());
+    }
+
+    #[test]
+    fn test_override_breaking_restricted_is_rejected() {
+        let mut pod = proxy_pod();
+        apply_security_preset(&mut pod, SecurityPreset::Restricted);
+
+        let spec = pod.spec.as_mut().unwrap();
+        spec.host_network = Some(true);
+        spec.volumes = Some(vec![serde_json::from_value(json!({
+            "name": "docker",
+            "hostPath": {"path": "/var/run/docker.sock"}
+        }))
+        .unwrap()]);
+        let context = spec.containers[0].security_context.as_mut().unwrap();
+        context.run_as_user = Some(0);
+        context.capabilities = Some(Capabilities {
+            add: Some(vec!["NET_ADMIN".to_string()]),
+            drop: None,
+        });
+
+        let error = validate_proxy_pod(&pod, Some(SecurityPreset::Restricted)).unwrap_err();
+        for violation in [
+            "hostNetwork must not be enabled",
+            "volume docker must not use hostPath",
+            "must not run as uid 0",
+            "must not add capability NET_ADMIN",
+            "must drop ALL capabilities",
+        ] {
+            assert!(error.contains(violation), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_restricted_volume_types() {
+        let allowed: Volume = serde_json::from_value(json!({
+            "name": "tmp",
+            "emptyDir": {}
+        }))
+        .unwrap();
+        let disallowed: Volume = serde_json::from_value(json!({
+            "name": "nfs",
+            "nfs": {"server": "nfs.local", "path": "/"}
+        }))
+        .unwrap();
+
+        assert!(is_restricted_volume(&allowed));
+        assert!(!is_restricted_volume(&disallowed));
+
+        let mut pod = proxy_pod();
+        apply_security_preset(&mut pod, SecurityPreset::Restricted);
+        pod.spec.as_mut().unwrap().volumes = Some(vec![disallowed]);
+        assert!(validate_proxy_pod(&pod, Some(SecurityPreset::Baseline)).is_ok());
+        assert!(validate_proxy_pod(&pod, Some(SecurityPreset::Restricted)).is_err());
+    }
+
+    #[test]
+    fn test_pod_without_image_is_rejected() {
+        let mut pod = proxy_pod();
+        pod.spec.as_mut().unwrap().containers[0].image = None;
+
+        assert!(validate_proxy_pod(&pod, None)
+            .unwrap_err()
+            .contains("has no image"));
+    }
+}
@@ -0,0 +1,403 @@
+//! Permission checks run before a forward is started, so a missing RBAC
+//! permission is explained up front instead of failing deep in the
+//! connection with a raw API error.
+
+use std::collections::HashSet;
+
+use futures::future::try_join_all;
+use kftray_commons::models::config_model::Config;
+use kftray_commons::models::pod_template_model::ProxyController;
+use kube::Client;
+use log::{
+    debug,
+    warn,
+};
+
+use crate::create_client_with_specific_context;
+use crate::kube::access::{
+    review_access,
+    Access,
+};
+use crate::kube::models::{
+    PermissionCheck,
+    PreflightReport,
+    RequiredPermission,
+};
+
+/// Permissions the forward of a config needs, depending on its workload
+/// type and, for proxies, the controller running the proxy pod.
+pub fn required_permissions(config: &Config) -> Vec<RequiredPermission> {
+    let mut permissions = Vec::new();
+
+    match config.workload_type.as_deref() {
+        Some("pod") => {
+            permissions.push(permission(
+                "list",
+                "",
+                "pods",
+                "find a pod matching the target labels",
+            ));
+        }
+        Some("proxy") => {
+            let controller = config
+                .pod_template
+                .as_ref()
+                .and_then(|template| template.controller)
+                .unwrap_or_default();
+            let (group, resource) = match controller {
+                ProxyController::Pod => ("", "pods"),
+                ProxyController::Deployment => ("apps", "deployments"),
+                ProxyController::Job => ("batch", "jobs"),
+            };
+
+            permissions.push(permission("create", group, resource, "deploy the proxy"));
+            if controller != ProxyController::Pod {
+                permissions.push(permission(
+                    "get",
+                    group,
+                    resource,
+                    "replace a leftover proxy",
+                ));
+            }
+            permissions.extend([
+                permission("list", group, resource, "wait for the proxy to be ready"),
+                permission("watch", group, resource, "wait for the proxy to be ready"),
+                permission("patch", group, resource, "renew the proxy's lease"),
+                permission("delete", group, resource, "remove the proxy once stopped"),
+                // Stopping looks for proxy workloads whatever the controller
+                permission("list", "apps", "deployments", "find the proxy's deployment"),
+                permission("list", "batch", "jobs", "find the proxy's job"),
+                permission("get", "", "services", "look up the proxy's pods"),
+                permission("list", "", "pods", "find the proxy pod"),
+            ]);
+        }
+        Some("reverse") => {
+            permissions.extend([
+                permission("create", "", "pods", "deploy the tunnel pod"),
+                permission("list", "", "pods", "wait for the tunnel pod to be ready"),
+                permission("watch", "", "pods", "wait for the tunnel pod to be ready"),
+                permission("get", "", "pods", "check the tunnel pod is still running"),
+                permission("patch", "", "pods", "renew the tunnel pod's lease"),
+                permission("delete", "", "pods", "remove the tunnel pod once stopped"),
+            ]);
+            if config
+                .service
+                .as_deref()
+                .is_some_and(|name| !name.is_empty())
+            {
+                permissions.push(permission(
+                    "create",
+                    "",
+                    "services",
+                    "expose the tunnel as a service",
+                ));
+            }
+        }
+        Some("shared-proxy") => {
+            permissions.extend([
+                permission("list", "", "pods", "find a running shared proxy"),
+                permission("get", "", "pods", "check the shared proxy is still running"),
+                permission("create", "", "pods", "deploy the shared proxy"),
+                permission("watch", "", "pods", "wait for the shared proxy to be ready"),
+                permission("patch", "", "pods", "renew the shared proxy's lease"),
+                permission("delete", "", "pods", "remove the shared proxy once unused"),
+            ]);
+        }
+        _ => {
+            permissions.extend([
+                permission("get", "", "services", "look up the service's pod selector"),
+                permission("list", "", "pods", "find a ready pod behind the service"),
+            ]);
+        }
+    }
+
+    permissions.push(RequiredPermission {
+        subresource: Some("portforward".to_string()),
+        ..permission("create", "", "pods", "open the port forward")
+    });
+
+    let mut listed = HashSet::new();
+    permissions
+        .retain(|permission| listed.insert((permission.verb.clone(), permission.resource_name())));
+
+    permissions
+}
+
+/// Checks that the current user of a config's context has every permission
+/// its forward needs.
+pub async fn check_forward_permissions(config: &Config) -> Result<PreflightReport, String> {
+    let (client, _, _) =
+        create_client_with_specific_context(config.kubeconfig.clone(), Some(&config.context))
+            .await
+            .map_err(|e| e.to_string())?;
+    let client = client.ok_or_else(|| "Client not created".to_string())?;
+
+    review_permissions(&client, config)
+        .await
+        .map_err(|e| format!("Failed to review permissions: {}", e))
+}
+
+pub(crate) async fn review_permissions(
+    client: &Client, config: &Config,
+) -> Result<PreflightReport, kube::Error> {
+    let namespace = config.namespace.as_str();
+
+    let checks = try_join_all(required_permissions(config).into_iter().map(
+        |permission| async move {
+            let access = review_access(
+                client,
+                Some(namespace),
+                &permission.verb,
+                &permission.group,
+                &permission.resource,
+                permission.subresource.as_deref(),
+            )
+            .await?;
+
+            Ok::<_, kube::Error>(match access {
+                Access::Allowed => PermissionCheck {
+                    permission,
+                    allowed: true,
+                    reason: None,
+                },
+                Access::Denied(reason) => PermissionCheck {
+                    permission,
+                    allowed: false,
+                    reason: Some(reason),
+                },
+            })
+        },
+    ))
+    .await?;
+
+    Ok(PreflightReport {
+        config_id: config.id,
+        context: config.context.clone(),
+        namespace: namespace.to_string(),
+        checks,
+    })
+}
+
+/// Explanation of the permissions a config is missing, if any.
+///
+/// A failed review doesn't hold the forward back, as access reviews can be
+/// unavailable to users who can still forward.
+pub(crate) async fn preflight_denial(client: Option<&Client>, config: &Config) -> Option<String> {
+    let report = match client {
+        Some(client) => review_permissions(client, config)
+            .await
+            .map_err(|e| e.to_string()),
+        None => check_forward_permissions(config).await,
+    };
+
+    match report {
+        Ok(report) => {
+            let explanation = report.explanation();
+            if let Some(explanation) = &explanation {
+                warn!("{}", explanation);
+            }
+            explanation
+        }
+        Err(e) => {
+            debug!("Skipping permission preflight: {}", e);
+            None
+        }
+    }
+}
+
+fn permission(verb: &str, group: &str, resource: &str, purpose: &str) -> RequiredPermission {
+    RequiredPermission {
+        verb: verb.to_string(),
+        group: group.to_string(),
+        resource: resource.to_string(),
+        subresource: None,
+        purpose: purpose.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kftray_commons::models::pod_template_model::PodTemplate;
+
+    use super::*;
+
+    fn config(workload_type: &str, controller: Option<ProxyController>) -> Config {
+        Config {
+            workload_type: Some(workload_type.to_string()),
+            pod_template: controller.map(|controller| PodTemplate {
+                controller: Some(controller),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn permissions(config: &Config) -> Vec<String> {
+        required_permissions(config)
+            .iter()
+            .map(|permission| format!("{} {}", permission.verb, permission.resource_name()))
+            .collect()
+    }
+
+    #[test]
+    fn test_service_permissions() {
+        assert_eq!(
+            permissions(&config("service", None)),
+            ["get services", "list pods", "create pods/portforward"]
+        );
+    }
+
+    #[test]
+    fn test_pod_permissions() {
+        assert_eq!(
+            permissions(&config("pod", None)),
+            ["list pods", "create pods/portforward"]
+        );
+    }
+
+    #[test]
+    fn test_proxy_pod_permissions() {
+        let expected = [
+            "create pods",
+            "list pods",
+            "watch pods",
+            "patch pods",
+            "delete pods",
+            "list deployments.apps",
+            "list jobs.batch",
+            "get services",
+            "create pods/portforward",
+        ];
+
+        assert_eq!(permissions(&config("proxy", None)), expected);
+        assert_eq!(
+            permissions(&config("proxy", Some(ProxyController::Pod))),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_proxy_deployment_permissions() {
+        assert_eq!(
+            permissions(&config("proxy", Some(ProxyController::Deployment))),
+            [
+                "create deployments.apps",
+                "get deployments.apps",
+                "list deployments.apps",
+                "watch deployments.apps",
+                "patch deployments.apps",
+                "delete deployments.apps",
+                "list jobs.batch",
+                "get services",
+                "list pods",
+                "create pods/portforward",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_proxy_job_permissions() {
+        assert_eq!(
+            permissions(&config("proxy", Some(ProxyController::Job))),
+            [
+                "create jobs.batch",
+                "get jobs.batch",
+                "list jobs.batch",
+                "watch jobs.batch",
+                "patch jobs.batch",
+                "delete jobs.batch",
+                "list deployments.apps",
+                "get services",
+                "list pods",
+                "create pods/portforward",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reverse_permissions() {
+        let mut reverse = config("reverse", None);
+        reverse.service = None;
+        let expected = [
+            "create pods",
+            "list pods",
+            "watch pods",
+            "get pods",
+            "patch pods",
+            "delete pods",
+            "create pods/portforward",
+        ];
+        assert_eq!(permissions(&reverse), expected);
+
+        reverse.service = Some("webhook".to_string());
+        let permissions = permissions(&reverse);
+        assert_eq!(permissions[..6], expected[..6]);
+        assert_eq!(
+            permissions[6..],
+            ["create services", "create pods/portforward"]
+        );
+    }
+
+    #[test]
+    fn test_shared_proxy_permissions() {
+        assert_eq!(
+            permissions(&config("shared-proxy", None)),
+            [
+                "list pods",
+                "get pods",
+                "create pods",
+                "watch pods",
+                "patch pods",
+                "delete pods",
+                "create pods/portforward",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_explanation() {
+        let report = PreflightReport {
+            config_id: Some(1),
+            context: "prod".to_string(),
+            namespace: "payments".to_string(),
+            checks: required_permissions(&config("service", None))
+                .into_iter()
+                .map(|permission| {
+                    let reason = match permission.verb.as_str() {
+                        "get" => Some("RBAC: no rule for services".to_string()),
+                        "list" => Some("cannot list pods in namespace payments".to_string()),
+                        _ => None,
+                    };
+                    PermissionCheck {
+                        allowed: reason.is_none(),
+                        permission,
+                        reason,
+                    }
+                })
+                .collect(),
+        };
+
+        assert_eq!(
+            report.explanation().as_deref(),
+            Some(
+                "Missing permissions in namespace 'payments' of context 'prod':\n\
+                 - get services, needed to look up the service's pod selector \
+                 (RBAC: no rule for services)\n\
+                 - list pods, needed to find a ready pod behind the service\n\
+                 Check with `kubectl auth can-i get services -n payments --context prod` \
+                 and ask a cluster admin for a Role granting them."
+            )
+        );
+    }
+
+    #[test]
+    fn test_permissions_keep_the_first_purpose() {
+        let permissions = required_permissions(&config("proxy", None));
+        let list_pods = permissions
+            .iter()
+            .find(|permission| permission.verb == "list" && permission.resource == "pods")
+            .unwrap();
+
+        assert_eq!(list_pods.purpose, "wait for the proxy to be ready");
+    }
+}
@@ -13,6 +13,10 @@ use k8s_openapi::api::core::v1::Pod;
 use kftray_commons::{
     models::{
         config_model::Config,
+        pod_template_model::{
+            PodTemplate,
+            ProxyController,
+        },
         response::CustomResponse,
     },
     utils::config_dir::get_pod_manifest_path,
@@ -36,6 +40,21 @@ use rand::distr::{
 };
 
 use crate::create_client_with_specific_context;
+use crate::kube::lease::{
+    apply_lease,
+    spawn_lease_renewal,
+};
+use crate::kube::pod_template::{
+    apply_pod_template,
+    apply_security_preset,
+    validate_proxy_pod,
+};
+use crate::kube::preflight::preflight_denial;
+use crate::kube::proxy_workload::{
+    delete_proxy_workloads,
+    deploy_proxy_workload,
+    proxy_workload_name,
+};
 
 pub async fn deploy_and_forward_pod(
     configs: Vec<Config>, http_log_state: Arc<HttpLogState>,
@@ -54,29 +73,21 @@ pub async fn deploy_and_forward_pod(
 
         let client = client.ok_or_else(|| "Client not created".to_string())?;
 
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map_err(|e| e.to_string())?
-            .as_secs();
-
-        let random_string: String = Alphanumeric
-            .sample_string(&mut rand::rng(), 6)
-            .chars()
-            .map(|c| c.to_ascii_lowercase())
-            .collect();
-
-        let username = whoami::username().to_lowercase();
-        let clean_username: String = username.chars().filter(|c| c.is_alphanumeric()).collect();
-
-        info!("Cleaned username: {}", clean_username);
+        if let Some(explanation) = preflight_denial(Some(&client), &config).await {
+            return Err(explanation);
+        }
 
         let protocol = config.protocol.to_string().to_lowercase();
-
-        let hashed_name = format!(
-            "kftray-forward-{}-{}-{}-{}",
-            clean_username, protocol, timestamp, random_string
-        )
-        .to_lowercase();
+        let controller = config
+            .pod_template
+            .as_ref()
+            .and_then(|template| template.controller)
+            .unwrap_or_default();
+        let hashed_name = match (controller, config.id) {
+            (ProxyController::Pod, _) => proxy_pod_name(&protocol)?,
+            (_, Some(config_id)) => proxy_workload_name(&protocol, config_id),
+            (_, None) => return Err("Config id is required to deploy a proxy workload".into()),
+        };
 
         let config_id_str = config
             .id
@@ -98,33 +109,64 @@ pub async fn deploy_and_forward_pod(
         values.insert("local_port", config.remote_port.expect("None").to_string());
         values.insert("protocol", protocol.clone());
 
-        let manifest_path = get_pod_manifest_path().map_err(|e| e.to_string())?;
-        let mut file = File::open(manifest_path).map_err(|e| e.to_string())?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .map_err(|e| e.to_string())?;
+        let mut pod = render_proxy_pod(&values, config.pod_template.as_ref())?;
+
+        if controller != ProxyController::Pod {
+            if !matches!(protocol.as_str(), "tcp" | "udp") {
+                return Err("Unsupported proxy type".to_string());
+            }
 
-        let rendered_json = render_json_template(&contents, &values);
-        let pod: Pod = serde_json::from_str(&rendered_json).map_err(|e| e.to_string())?;
+            let active_deadline_seconds = config
+                .pod_template
+                .as_ref()
+                .and_then(|template| template.active_deadline_seconds);
+            deploy_proxy_workload(
+                client.clone(),
+                &config.namespace,
+                pod,
+                controller,
+                active_deadline_seconds,
+            )
+            .await?;
+
+            config.service = Some(hashed_name.clone());
+
+            match super::start::start_port_forward(
+                vec![config.clone()],
+                &protocol,
+                http_log_state.clone(),
+            )
+            .await
+            {
+                Ok(mut port_forward_responses) => {
+                    let response = port_forward_responses
+                        .pop()
+                        .ok_or("No response received from port forwarding")?;
+                    responses.push(response);
+                }
+                Err(e) => {
+                    if let Some(config_id) = config.id {
+                        let _ = delete_proxy_workloads(
+                            client.clone(),
+                            Some(&config.namespace),
+                            config_id,
+                        )
+                        .await;
+                    }
+                    return Err(format!("Failed to start port forwarding {}", e));
+                }
+            }
+
+            continue;
+        }
 
+        apply_lease(&mut pod);
         let pods: Api<Pod> = Api::namespaced(client.clone(), &config.namespace);
 
         match pods.create(&PostParams::default(), &pod).await {
             Ok(_) => {
-                if let Err(e) = kube_runtime::wait::await_condition(
-                    pods.clone(),
-                    &hashed_name,
-                    conditions::is_pod_running(),
-                )
-                .await
-                {
-                    let dp = DeleteParams {
-                        grace_period_seconds: Some(0),
-                        ..DeleteParams::default()
-                    };
-                    let _ = pods.delete(&hashed_name, &dp).await;
-                    return Err(e.to_string());
-                }
+                wait_for_proxy_pod(&pods, &hashed_name).await?;
+                spawn_lease_renewal(pods.clone(), hashed_name.clone());
 
                 config.service = Some(hashed_name.clone());
 
@@ -200,6 +242,8 @@ pub async fn stop_proxy_forward(
 
     let client = client.ok_or_else(|| "Client not created".to_string())?;
 
+    delete_proxy_workloads(client.clone(), Some(namespace), config_id).await?;
+
     let pods: Api<Pod> = Api::namespaced(client, namespace);
 
     let lp = ListParams::default().labels(&format!("config_id={}", config_id));
@@ -215,6 +259,10 @@ pub async fn stop_proxy_forward(
     debug!("Looking for pods with prefix: {}", pod_prefix);
 
     for pod in pod_list.items {
+        if pod.metadata.owner_references.is_some() {
+            continue;
+        }
+
         if let Some(pod_name) = pod.metadata.name {
             if pod_name.starts_with(&pod_prefix) {
                 info!("Found pod to stop: {}", pod_name);
@@ -266,3 +314,84 @@ fn render_json_template(template: &str, values: &HashMap<&str, String>) -> Strin
 
     rendered_template
 }
+
+/// Builds a unique proxy pod name that `stop_proxy_forward` can find again
+/// through its `kftray-forward-{user}` prefix.
+pub(super) fn proxy_pod_name(protocol: &str) -> Result<String, String> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_secs();
+
+    let random_string: String = Alphanumeric
+        .sample_string(&mut rand::rng(), 6)
+        .chars()
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+
+    let clean_username = clean_username();
+
+    info!("Cleaned username: {}", clean_username);
+
+    Ok(format!(
+        "kftray-forward-{}-{}-{}-{}",
+        clean_username, protocol, timestamp, random_string
+    )
+    .to_lowercase())
+}
+
+/// Lowercase alphanumeric form of the local username, safe for pod names and
+/// label values.
+pub(super) fn clean_username() -> String {
+    whoami::username()
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+/// Renders the proxy pod manifest template with the given placeholder values
+/// and layers the config's pod template over it.
+///
+/// The result is validated against the template's security preset, so a pod
+/// the cluster would refuse is rejected before `pods.create`.
+pub(super) fn render_proxy_pod(
+    values: &HashMap<&str, String>, template: Option<&PodTemplate>,
+) -> Result<Pod, String> {
+    let manifest_path = get_pod_manifest_path().map_err(|e| e.to_string())?;
+    let mut file = File::open(manifest_path).map_err(|e| e.to_string())?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
+        .map_err(|e| e.to_string())?;
+
+    let rendered_json = render_json_template(&contents, values);
+    let mut pod: Pod = serde_json::from_str(&rendered_json).map_err(|e| e.to_string())?;
+
+    if let Some(template) = template {
+        apply_pod_template(&mut pod, template);
+    }
+
+    let preset = template.and_then(|t| t.security_preset);
+    if let Some(preset) = preset {
+        apply_security_preset(&mut pod, preset);
+    }
+    validate_proxy_pod(&pod, preset)?;
+
+    Ok(pod)
+}
+
+/// Waits for a freshly created proxy pod to run, deleting it when it does not.
+pub(super) async fn wait_for_proxy_pod(pods: &Api<Pod>, name: &str) -> Result<(), String> {
+    if let Err(e) =
+        kube_runtime::wait::await_condition(pods.clone(), name, conditions::is_pod_running()).await
+    {
+        let dp = DeleteParams {
+            grace_period_seconds: Some(0),
+            ..DeleteParams::default()
+        };
+        let _ = pods.delete(name, &dp).await;
+        return Err(e.to_string());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,452 @@
+//! Proxies deployed as a Deployment or Job instead of a bare pod.
+//!
+//! The controller recreates the proxy pod when its node is drained, and the
+//! forwarder follows whichever pod currently carries the `app={name}` label.
+//! The pods are owned by the controller, so deleting it with background
+//! propagation cleans them up.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::time::Duration;
+
+use k8s_openapi::api::apps::v1::{
+    Deployment,
+    DeploymentSpec,
+};
+use k8s_openapi::api::batch::v1::{
+    Job,
+    JobSpec,
+};
+use k8s_openapi::api::core::v1::{
+    Pod,
+    PodTemplateSpec,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{
+    LabelSelector,
+    ObjectMeta,
+};
+use k8s_openapi::NamespaceResourceScope;
+use kftray_commons::models::pod_template_model::ProxyController;
+use kube::api::{
+    Api,
+    DeleteParams,
+    ListParams,
+    PostParams,
+};
+use kube::{
+    Client,
+    Resource,
+    ResourceExt,
+};
+use kube_runtime::wait::{
+    await_condition,
+    conditions,
+    Condition,
+};
+use log::{
+    error,
+    info,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::time::timeout;
+
+use crate::kube::lease::{
+    apply_lease_metadata,
+    spawn_lease_renewal,
+};
+use crate::kube::proxy::clean_username;
+
+/// Deadline of proxy Jobs whose template does not set one.
+const DEFAULT_ACTIVE_DEADLINE_SECONDS: i64 = 12 * 60 * 60;
+/// Finished proxy Jobs are removed after this many seconds.
+const JOB_TTL_AFTER_FINISHED_SECONDS: i32 = 60;
+const READY_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Builds the stable name of a config's proxy Deployment or Job, keeping the
+/// `kftray-forward-{user}` prefix of proxy pods.
+pub(super) fn proxy_workload_name(protocol: &str, config_id: i64) -> String {
+    format!(
+        "kftray-forward-{}-{}-{}",
+        clean_username(),
+        protocol,
+        config_id
+    )
+    .to_lowercase()
+}
+
+/// Deploys a rendered proxy pod as a Deployment or Job named after the pod and
+/// waits for one of its pods to become ready.
+///
+/// A leftover workload with the same name is replaced. The lease lives on the
+/// workload rather than on its pods, since those come and go.
+pub(super) async fn deploy_proxy_workload(
+    client: Client, namespace: &str, pod: Pod, controller: ProxyController,
+    active_deadline_seconds: Option<i64>,
+) -> Result<(), String> {
+    match controller {
+        ProxyController::Deployment => {
+            let deployment = proxy_deployment(pod)?;
+            let name = deployment.name_any();
+
+            let deployments: Api<Deployment> = Api::namespaced(client, namespace);
+            replace_workload(&deployments, &name, &deployment).await?;
+
+            let ready = |deployment: Option<&Deployment>| {
+                deployment
+                    .and_then(|d| d.status.as_ref())
+                    .and_then(|s| s.ready_replicas)
+                    .unwrap_or_default()
+                    >= 1
+            };
+            wait_until_ready(&deployments, &name, ready).await?;
+
+            info!("Proxy deployment {} is ready", name);
+            spawn_lease_renewal(deployments, name);
+            Ok(())
+        }
+        ProxyController::Job => {
+            let job = proxy_job(pod, active_deadline_seconds)?;
+            let name = job.name_any();
+
+            let jobs: Api<Job> = Api::namespaced(client, namespace);
+            replace_workload(&jobs, &name, &job).await?;
+
+            let ready = |job: Option<&Job>| {
+                job.and_then(|j| j.status.as_ref())
+                    .and_then(|s| s.ready)
+                    .unwrap_or_default()
+                    >= 1
+            };
+            wait_until_ready(&jobs, &name, ready).await?;
+
+            info!("Proxy job {} is ready", name);
+            spawn_lease_renewal(jobs, name);
+            Ok(())
+        }
+        ProxyController::Pod => Err("Bare proxy pods are not deployed as a workload".to_string()),
+    }
+}
+
+/// Builds the single replica Deployment running a proxy pod.
+fn proxy_deployment(pod: Pod) -> Result<Deployment, String> {
+    let (name, metadata, mut template) = workload_parts(pod)?;
+    if let Some(spec) = template.spec.as_mut() {
+        spec.restart_policy = Some("Always".to_string());
+    }
+
+    Ok(Deployment {
+        metadata,
+        spec: Some(DeploymentSpec {
+            replicas: Some(1),
+            selector: LabelSelector {
+                match_labels: Some(BTreeMap::from([("app".to_string(), name)])),
+                ..Default::default()
+            },
+            template,
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/// Builds the Job running a proxy pod, which stops at its deadline and is
+/// removed shortly after finishing.
+fn proxy_job(pod: Pod, active_deadline_seconds: Option<i64>) -> Result<Job, String> {
+    let (_, metadata, mut template) = workload_parts(pod)?;
+    if let Some(spec) = template.spec.as_mut() {
+        spec.restart_policy = Some("OnFailure".to_string());
+    }
+
+    Ok(Job {
+        metadata,
+        spec: Some(JobSpec {
+            active_deadline_seconds: Some(
+                active_deadline_seconds.unwrap_or(DEFAULT_ACTIVE_DEADLINE_SECONDS),
+            ),
+            ttl_seconds_after_finished: Some(JOB_TTL_AFTER_FINISHED_SECONDS),
+            template,
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/// Splits a proxy pod into the name, metadata and pod template of the
+/// workload running it.
+fn workload_parts(pod: Pod) -> Result<(String, ObjectMeta, PodTemplateSpec), String> {
+    let name = pod
+        .metadata
+        .name
+        .clone()
+        .ok_or_else(|| "Proxy pod manifest has no name".to_string())?;
+
+    // The selector and the forwarder both find the current pod through `app`
+    let mut labels = pod.metadata.labels.unwrap_or_default();
+    labels.insert("app".to_string(), name.clone());
+
+    let mut metadata = ObjectMeta {
+        name: Some(name.clone()),
+        labels: Some(labels.clone()),
+        ..Default::default()
+    };
+    apply_lease_metadata(&mut metadata);
+
+    let template = PodTemplateSpec {
+        metadata: Some(ObjectMeta {
+            labels: Some(labels),
+            annotations: pod.metadata.annotations,
+            ..Default::default()
+        }),
+        spec: Some(pod.spec.unwrap_or_default()),
+    };
+
+    Ok((name, metadata, template))
+}
+
+/// Deletes the proxy Deployments and Jobs of a config, in one namespace or
+/// in all of them. Their pods are garbage collected through their owner
+/// references.
+pub(super) async fn delete_proxy_workloads(
+    client: Client, namespace: Option<&str>, config_id: i64,
+) -> Result<(), String> {
+    delete_workloads::<Deployment>(client.clone(), namespace, config_id).await?;
+    delete_workloads::<Job>(client, namespace, config_id).await
+}
+
+async fn delete_workloads<K>(
+    client: Client, namespace: Option<&str>, config_id: i64,
+) -> Result<(), String>
+where
+    K: Resource<DynamicType = (), Scope = NamespaceResourceScope>
+        + Clone
+        + DeserializeOwned
+        + Debug,
+{
+    let lp = ListParams::default().labels(&format!("config_id={}", config_id));
+    let prefix = format!("kftray-forward-{}", clean_username());
+
+    let api: Api<K> = match namespace {
+        Some(namespace) => Api::namespaced(client.clone(), namespace),
+        None => Api::all(client.clone()),
+    };
+    let workloads = api.list(&lp).await.map_err(|e| e.to_string())?;
+
+    for workload in workloads {
+        let name = workload.name_any();
+        let Some(namespace) = workload.namespace() else {
+            continue;
+        };
+        if !name.starts_with(&prefix) {
+            continue;
+        }
+
+        match Api::<K>::namespaced(client.clone(), &namespace)
+            .delete(&name, &background_delete())
+            .await
+        {
+            Ok(_) => info!("Deleted proxy {} {}/{}", K::kind(&()), namespace, name),
+            Err(e) => {
+                error!(
+                    "Failed to delete proxy {} {}/{}: {}",
+                    K::kind(&()),
+                    namespace,
+                    name,
+                    e
+                );
+                return Err(e.to_string());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates a workload, first deleting a leftover one with the same name since
+/// the pod template of a Job can not be updated in place.
+async fn replace_workload<K>(api: &Api<K>, name: &str, workload: &K) -> Result<(), String>
+where
+    K: Resource + Clone + DeserializeOwned + Serialize + Debug + Send + 'static,
+{
+    if let Some(existing) = api.get_opt(name).await.map_err(|e| e.to_string())? {
+        info!("Replacing leftover proxy workload {}", name);
+        api.delete(name, &background_delete())
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let uid = existing.uid().unwrap_or_default();
+        await_condition(api.clone(), name, conditions::is_deleted(&uid))
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    api.create(&PostParams::default(), workload)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Waits for a workload to have a ready pod, deleting it when it does not.
+async fn wait_until_ready<K>(
+    api: &Api<K>, name: &str, ready: impl Condition<K>,
+) -> Result<(), String>
+where
+    K: Resource + Clone + DeserializeOwned + Debug + Send + 'static,
+{
+    let error = match timeout(READY_TIMEOUT, await_condition(api.clone(), name, ready)).await {
+        Ok(Ok(_)) => return Ok(()),
+        Ok(Err(e)) => e.to_string(),
+        Err(_) => format!(
+            "Proxy {} did not become ready within {}s",
+            name,
+            READY_TIMEOUT.as_secs()
+        ),
+    };
+
+    let _ = api.delete(name, &background_delete()).await;
+    Err(error)
+}
+
+fn background_delete() -> DeleteParams {
+    DeleteParams {
+        grace_period_seconds: Some(0),
+        propagation_policy: Some(kube::api::PropagationPolicy::Background),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::{
+        Container,
+        PodSpec,
+    };
+
+    use super::*;
+    use crate::kube::lease::{
+        LEASE_RENEWED_AT_ANNOTATION,
+        LEASE_TTL_ANNOTATION,
+        USER_LABEL,
+    };
+
+    fn proxy_pod() -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some("kftray-forward-alice-tcp-7".to_string()),
+                labels: Some(BTreeMap::from([("config_id".to_string(), "7".to_string())])),
+                ..Default::default()
+            },
+            spec: Some(PodSpec {
+                containers: vec![Container {
+                    name: "kftray-server".to_string(),
+                    image: Some("ghcr.io/hcavarsan/kftray-server:latest".to_string()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn template_labels(template: &PodTemplateSpec) -> BTreeMap<String, String> {
+        template
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.labels.clone())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_proxy_workload_name() {
+        let name = proxy_workload_name("UDP", 42);
+
+        assert_eq!(name, name.to_lowercase());
+        assert!(name.starts_with(&format!("kftray-forward-{}-", clean_username())));
+        assert!(name.ends_with("-udp-42"));
+        assert_eq!(name, proxy_workload_name("udp", 42));
+        assert_ne!(name, proxy_workload_name("udp", 43));
+    }
+
+    #[test]
+    fn test_proxy_deployment_spec() {
+        let deployment = proxy_deployment(proxy_pod()).unwrap();
+        let spec = deployment.spec.unwrap();
+
+        assert_eq!(
+            deployment.metadata.name.as_deref(),
+            Some("kftray-forward-alice-tcp-7")
+        );
+        assert_eq!(spec.replicas, Some(1));
+        assert_eq!(
+            spec.selector.match_labels,
+            Some(BTreeMap::from([(
+                "app".to_string(),
+                "kftray-forward-alice-tcp-7".to_string()
+            )]))
+        );
+
+        let labels = template_labels(&spec.template);
+        assert_eq!(
+            labels.get("app").map(String::as_str),
+            Some("kftray-forward-alice-tcp-7")
+        );
+        assert_eq!(labels.get("config_id").map(String::as_str), Some("7"));
+
+        let pod_spec = spec.template.spec.unwrap();
+        assert_eq!(pod_spec.restart_policy.as_deref(), Some("Always"));
+        assert_eq!(pod_spec.containers[0].name, "kftray-server");
+    }
+
+    #[test]
+    fn test_proxy_job_spec() {
+        let job = proxy_job(proxy_pod(), None).unwrap();
+        let spec = job.spec.unwrap();
+
+        assert_eq!(
+            job.metadata.name.as_deref(),
+            Some("kftray-forward-alice-tcp-7")
+        );
+        assert_eq!(
+            spec.active_deadline_seconds,
+            Some(DEFAULT_ACTIVE_DEADLINE_SECONDS)
+        );
+        assert_eq!(
+            spec.ttl_seconds_after_finished,
+            Some(JOB_TTL_AFTER_FINISHED_SECONDS)
+        );
+        assert_eq!(
+            template_labels(&spec.template)
+                .get("app")
+                .map(String::as_str),
+            Some("kftray-forward-alice-tcp-7")
+        );
+        assert_eq!(
+            spec.template.spec.unwrap().restart_policy.as_deref(),
+            Some("OnFailure")
+        );
+
+        let job = proxy_job(proxy_pod(), Some(600)).unwrap();
+        assert_eq!(job.spec.unwrap().active_deadline_seconds, Some(600));
+    }
+
+    #[test]
+    fn test_workload_carries_lease() {
+        let deployment = proxy_deployment(proxy_pod()).unwrap();
+        let labels = deployment.metadata.labels.unwrap();
+        let annotations = deployment.metadata.annotations.unwrap();
+
+        assert_eq!(labels.get(USER_LABEL), Some(&clean_username()));
+        assert!(annotations.contains_key(LEASE_RENEWED_AT_ANNOTATION));
+        assert!(annotations.contains_key(LEASE_TTL_ANNOTATION));
+    }
+
+    #[test]
+    fn test_workload_requires_pod_name() {
+        let mut pod = proxy_pod();
+        pod.metadata.name = None;
+
+        assert!(proxy_deployment(pod.clone()).is_err());
+        assert!(proxy_job(pod, None).is_err());
+    }
+}
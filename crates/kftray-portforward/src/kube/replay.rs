@@ -0,0 +1,115 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Context;
+use kftray_http_logs::cassette::request_len;
+use kftray_http_logs::CassettePlayer;
+use tokio::io::{
+    AsyncReadExt,
+    AsyncWriteExt,
+};
+use tokio::net::{
+    TcpListener,
+    TcpStream,
+};
+use tokio::task::JoinHandle;
+use tracing::{
+    debug,
+    error,
+    info,
+    trace,
+};
+
+use crate::kube::fault::FaultInjector;
+
+const BUFFER_SIZE: usize = 131072;
+const MAX_REQUEST_SIZE: usize = 16 * 1024 * 1024;
+
+/// Serves a local port from a recorded cassette instead of a cluster.
+pub struct ReplayServer;
+
+impl ReplayServer {
+    pub async fn bind_and_serve(
+        config_id: i64, local_address: String, local_port: u16, player: CassettePlayer,
+    ) -> anyhow::Result<(u16, JoinHandle<()>)> {
+        if let Err(e) = crate::network_utils::ensure_loopback_address(&local_address).await {
+            error!(
+                "Failed to configure loopback address {}: {:?}",
+                local_address, e
+            );
+            return Err(anyhow::anyhow!(
+                "Failed to configure loopback address: {}",
+                e
+            ));
+        }
+
+        let addr = format!("{}:{}", local_address, local_port)
+            .parse::<SocketAddr>()
+            .context("Invalid local address")?;
+        let listener = TcpListener::bind(addr).await?;
+        let port = listener.local_addr()?.port();
+
+        info!(
+            "Replaying {} recorded responses for config {} on port {}",
+            player.len(),
+            config_id,
+            port
+        );
+
+        let player = Arc::new(player);
+        let handle = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer_addr)) => {
+                        trace!(%peer_addr, "new replay connection");
+                        let player = Arc::clone(&player);
+                        tokio::spawn(async move {
+                            if let Err(e) = Self::serve_connection(config_id, stream, &player).await
+                            {
+                                debug!("Replay connection closed with error: {:?}", e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        error!("Failed to accept replay connection: {:?}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok((port, handle))
+    }
+
+    async fn serve_connection(
+        config_id: i64, mut stream: TcpStream, player: &CassettePlayer,
+    ) -> anyhow::Result<()> {
+        stream.set_nodelay(true)?;
+        let (mut reader, mut writer) = stream.split();
+
+        let mut faults = FaultInjector::new(config_id);
+        let mut buffer = [0; BUFFER_SIZE];
+        let mut pending = Vec::new();
+
+        loop {
+            while let Some(len) = request_len(&pending)? {
+                let response = player.respond(&pending[..len]);
+                faults.write_all(&mut writer, &response).await?;
+                pending.drain(..len);
+            }
+
+            if pending.len() > MAX_REQUEST_SIZE {
+                anyhow::bail!("Request exceeds {} bytes", MAX_REQUEST_SIZE);
+            }
+
+            let n = reader.read(&mut buffer).await?;
+            if n == 0 {
+                break;
+            }
+            pending.extend_from_slice(&buffer[..n]);
+        }
+
+        writer.shutdown().await?;
+        Ok(())
+    }
+}
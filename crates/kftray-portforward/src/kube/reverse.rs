@@ -0,0 +1,460 @@
+use std::collections::{
+    BTreeMap,
+    HashMap,
+};
+use std::time::Duration;
+
+use k8s_openapi::api::core::v1::{
+    EnvVar,
+    Pod,
+    Service,
+    ServicePort,
+    ServiceSpec,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{
+    ObjectMeta,
+    OwnerReference,
+};
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use kftray_commons::{
+    models::{
+        config_model::Config,
+        config_state_model::ConfigState,
+        response::CustomResponse,
+    },
+    utils::config_state::update_config_state,
+};
+use kube::api::{
+    Api,
+    DeleteParams,
+    PostParams,
+};
+use log::{
+    debug,
+    error,
+    info,
+    trace,
+    warn,
+};
+use tokio::io::{
+    AsyncRead,
+    AsyncWrite,
+    ReadHalf,
+};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+use crate::create_client_with_specific_context;
+use crate::kube::lease::{
+    apply_lease,
+    spawn_lease_renewal,
+    LeaseGuard,
+};
+use crate::kube::preflight::preflight_denial;
+use crate::kube::proxy::{
+    proxy_pod_name,
+    render_proxy_pod,
+    wait_for_proxy_pod,
+};
+use crate::kube::tunnel::{
+    pump_from_tunnel,
+    pump_to_tunnel,
+    read_frame,
+    spawn_keepalive,
+    write_frames,
+    Frame,
+    FrameKind,
+};
+use crate::port_forward::CHILD_PROCESSES;
+
+/// Port the reverse proxy pod accepts the tunnel connection on.
+pub const TUNNEL_PORT: u16 = 9999;
+
+const FRAME_QUEUE_SIZE: usize = 256;
+const STREAM_QUEUE_SIZE: usize = 64;
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Exposes a local port inside the cluster.
+///
+/// For every config a `kftray-server` pod is deployed in reverse mode,
+/// listening on `remote_port` in the cluster. When `service` is set, a
+/// Service with that name is created in front of it, owned by the pod so it
+/// goes away together with it. Connections accepted in the cluster are carried
+/// back over a single port forward to `local_address:local_port`.
+pub async fn start_reverse_tunnel(configs: Vec<Config>) -> Result<Vec<CustomResponse>, String> {
+    let mut responses = Vec::new();
+
+    for config in configs.into_iter() {
+        let context_name = Some(config.context.as_str());
+        let (client, _, _) =
+            create_client_with_specific_context(config.kubeconfig.clone(), context_name)
+                .await
+                .map_err(|e| {
+                    error!("Failed to create Kubernetes client: {}", e);
+                    e.to_string()
+                })?;
+
+        let client = client.ok_or_else(|| "Client not created".to_string())?;
+
+        if let Some(explanation) = preflight_denial(Some(&client), &config).await {
+            return Err(explanation);
+        }
+
+        let config_id = config
+            .id
+            .ok_or_else(|| "Config id is required".to_string())?;
+        let remote_port = config
+            .remote_port
+            .ok_or_else(|| "Remote port is required".to_string())?;
+        if remote_port == TUNNEL_PORT {
+            return Err(format!(
+                "Remote port {} is reserved for the tunnel",
+                TUNNEL_PORT
+            ));
+        }
+
+        let local_port = config.local_port.unwrap_or(remote_port);
+        let local_address = config
+            .local_address
+            .clone()
+            .filter(|address| !address.is_empty())
+            .unwrap_or_else(|| "127.0.0.1".to_string());
+        let local_target = format!("{}:{}", local_address, local_port);
+
+        let hashed_name = proxy_pod_name("reverse")?;
+        let service_name = config.service.clone().filter(|name| !name.is_empty());
+
+        let mut values: HashMap<&str, String> = HashMap::new();
+        values.insert("hashed_name", hashed_name.clone());
+        values.insert("config_id", config_id.to_string());
+        values.insert(
+            "service_name",
+            service_name.clone().unwrap_or_else(|| hashed_name.clone()),
+        );
+        values.insert("remote_address", "127.0.0.1".to_string());
+        values.insert("remote_port", TUNNEL_PORT.to_string());
+        values.insert("local_port", remote_port.to_string());
+        values.insert("protocol", "reverse".to_string());
+
+        let mut pod = render_proxy_pod(&values, config.pod_template.as_ref())?;
+        apply_lease(&mut pod);
+        prepare_reverse_pod(&mut pod, &hashed_name);
+
+        let pods: Api<Pod> = Api::namespaced(client.clone(), &config.namespace);
+        let created = pods
+            .create(&PostParams::default(), &pod)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let exposed = async {
+            wait_for_proxy_pod(&pods, &hashed_name).await?;
+
+            if let Some(service_name) = &service_name {
+                let services: Api<Service> = Api::namespaced(client.clone(), &config.namespace);
+                let service = tunnel_service(service_name, &created, &hashed_name, remote_port);
+                services
+                    .create(&PostParams::default(), &service)
+                    .await
+                    .map_err(|e| format!("Failed to create service {}: {}", service_name, e))?;
+            }
+
+            Ok::<(), String>(())
+        }
+        .await;
+        if let Err(e) = exposed {
+            let _ = pods.delete(&hashed_name, &DeleteParams::default()).await;
+            return Err(e);
+        }
+
+        // Held by the tunnel task, so stopping the forward ends the renewal
+        let lease = LeaseGuard(spawn_lease_renewal(pods.clone(), hashed_name.clone()));
+        let handle = tokio::spawn({
+            let pods = pods.clone();
+            let hashed_name = hashed_name.clone();
+            async move {
+                let _lease = lease;
+                run_tunnel(pods, hashed_name, local_target).await
+            }
+        });
+
+        let exposed_name = service_name.clone().unwrap_or_else(|| hashed_name.clone());
+        let handle_key = format!("{}_{}", config_id, exposed_name);
+        CHILD_PROCESSES.lock().unwrap().insert(handle_key, handle);
+
+        let config_state = ConfigState {
+            id: None,
+            config_id,
+            is_running: true,
+        };
+        if let Err(e) = update_config_state(&config_state).await {
+            error!("Failed to update config state: {}", e);
+        }
+
+        info!(
+            "Reverse tunnel from {}:{} to {}:{} is set up",
+            exposed_name, remote_port, local_address, local_port
+        );
+
+        responses.push(CustomResponse {
+            id: config.id,
+            service: exposed_name.clone(),
+            namespace: config.namespace.clone(),
+            local_port,
+            remote_port,
+            context: config.context.clone(),
+            protocol: "tcp".to_string(),
+            stdout: format!(
+                "Reverse tunnel from {}:{} -> {}:{}",
+                exposed_name, remote_port, local_address, local_port
+            ),
+            stderr: String::new(),
+            status: 0,
+        });
+    }
+
+    Ok(responses)
+}
+
+/// Points the rendered proxy pod at the tunnel port and makes sure the
+/// Service selector has a label to match, even with a custom manifest.
+fn prepare_reverse_pod(pod: &mut Pod, hashed_name: &str) {
+    pod.metadata
+        .labels
+        .get_or_insert_with(BTreeMap::new)
+        .entry("app".to_string())
+        .or_insert_with(|| hashed_name.to_string());
+
+    if let Some(container) = pod
+        .spec
+        .as_mut()
+        .and_then(|spec| spec.containers.first_mut())
+    {
+        container.env.get_or_insert_with(Vec::new).push(EnvVar {
+            name: "TUNNEL_PORT".to_string(),
+            value: Some(TUNNEL_PORT.to_string()),
+            ..Default::default()
+        });
+    }
+}
+
+fn tunnel_service(name: &str, pod: &Pod, hashed_name: &str, port: u16) -> Service {
+    let owner = OwnerReference {
+        api_version: "v1".to_string(),
+        kind: "Pod".to_string(),
+        name: hashed_name.to_string(),
+        uid: pod.metadata.uid.clone().unwrap_or_default(),
+        ..Default::default()
+    };
+
+    Service {
+        metadata: ObjectMeta {
+            name: Some(name.to_string()),
+            labels: pod.metadata.labels.clone(),
+            owner_references: Some(vec![owner]),
+            ..Default::default()
+        },
+        spec: Some(ServiceSpec {
+            selector: Some(BTreeMap::from([(
+                "app".to_string(),
+                hashed_name.to_string(),
+            )])),
+            ports: Some(vec![ServicePort {
+                port: port as i32,
+                target_port: Some(IntOrString::Int(port as i32)),
+                protocol: Some("TCP".to_string()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Keeps the tunnel to the proxy pod open, reconnecting until the pod is gone.
+async fn run_tunnel(pods: Api<Pod>, pod_name: String, local_target: String) {
+    loop {
+        match pods.portforward(&pod_name, &[TUNNEL_PORT]).await {
+            Ok(mut forwarder) => match forwarder.take_stream(TUNNEL_PORT) {
+                Some(stream) => match serve_tunnel(stream, &local_target).await {
+                    Ok(()) => info!("Tunnel to {} closed", pod_name),
+                    Err(e) => warn!("Tunnel to {} failed: {}", pod_name, e),
+                },
+                None => error!("Tunnel port not found in forwarder for {}", pod_name),
+            },
+            Err(e) => warn!("Failed to open tunnel to {}: {}", pod_name, e),
+        }
+
+        match pods.get_opt(&pod_name).await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                info!("Proxy pod {} is gone, stopping reverse tunnel", pod_name);
+                break;
+            }
+            Err(e) => debug!("Failed to check proxy pod {}: {}", pod_name, e),
+        }
+
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Dispatches frames from the proxy pod, opening a local connection for every
+/// stream it announces.
+async fn serve_tunnel<S>(stream: S, local_target: &str) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut reader, writer) = tokio::io::split(stream);
+    let (frames_tx, frames_rx) = mpsc::channel(FRAME_QUEUE_SIZE);
+
+    let writer_task = tokio::spawn(write_frames(writer, frames_rx));
+    let ping_task = spawn_keepalive(frames_tx.clone());
+
+    let mut streams: HashMap<u32, mpsc::Sender<Vec<u8>>> = HashMap::new();
+    let result = read_frames(&mut reader, &mut streams, &frames_tx, local_target).await;
+
+    writer_task.abort();
+    ping_task.abort();
+    result
+}
+
+async fn read_frames<S>(
+    reader: &mut ReadHalf<S>, streams: &mut HashMap<u32, mpsc::Sender<Vec<u8>>>,
+    frames: &mpsc::Sender<Frame>, local_target: &str,
+) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    while let Some(frame) = read_frame(reader).await? {
+        match frame.kind {
+            FrameKind::Open => {
+                let (tx, rx) = mpsc::channel(STREAM_QUEUE_SIZE);
+                streams.insert(frame.stream_id, tx);
+                tokio::spawn(carry_stream(
+                    frame.stream_id,
+                    local_target.to_string(),
+                    frames.clone(),
+                    rx,
+                ));
+            }
+            FrameKind::Data => {
+                if let Some(sender) = streams.get(&frame.stream_id) {
+                    if sender.send(frame.payload).await.is_err() {
+                        streams.remove(&frame.stream_id);
+                    }
+                }
+            }
+            FrameKind::Close => {
+                streams.remove(&frame.stream_id);
+            }
+            FrameKind::Ping => trace!("Tunnel ping"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Connects a tunnel stream to the local target and pipes it both ways.
+async fn carry_stream(
+    stream_id: u32, local_target: String, frames: mpsc::Sender<Frame>,
+    payloads: mpsc::Receiver<Vec<u8>>,
+) {
+    let stream = match TcpStream::connect(&local_target).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            warn!("Failed to connect to {}: {}", local_target, e);
+            let _ = frames.send(Frame::close(stream_id)).await;
+            return;
+        }
+    };
+
+    let _ = stream.set_nodelay(true);
+    let (reader, writer) = stream.into_split();
+
+    if let Err(e) = tokio::try_join!(
+        pump_to_tunnel(stream_id, reader, &frames),
+        pump_from_tunnel(writer, payloads)
+    ) {
+        debug!("Stream {} closed with error: {}", stream_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{
+        AsyncReadExt,
+        AsyncWriteExt,
+        DuplexStream,
+    };
+    use tokio::net::TcpListener;
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::kube::tunnel::write_frame;
+
+    async fn next_frame(proxy: &mut DuplexStream) -> Frame {
+        timeout(Duration::from_secs(5), read_frame(proxy))
+            .await
+            .expect("no frame from the tunnel")
+            .unwrap()
+            .expect("tunnel closed")
+    }
+
+    /// Local target echoing what it receives until the peer closes.
+    async fn echo_target() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            loop {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => stream.write_all(&buf[..n]).await.unwrap(),
+                }
+            }
+        });
+        addr.to_string()
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_carries_a_stream_in_the_server_layout() {
+        let target = echo_target().await;
+        let (upstream, mut proxy) = tokio::io::duplex(65536);
+        let tunnel = tokio::spawn(async move { serve_tunnel(upstream, &target).await });
+
+        // Open and data frames as the server writes them
+        proxy.write_all(&[1, 0, 0, 0, 5, 0, 0, 0, 0]).await.unwrap();
+        proxy
+            .write_all(&[2, 0, 0, 0, 5, 0, 0, 0, 4, b'p', b'i', b'n', b'g'])
+            .await
+            .unwrap();
+
+        let mut echoed = Vec::new();
+        while echoed.len() < 4 {
+            let frame = next_frame(&mut proxy).await;
+            assert_eq!((frame.kind, frame.stream_id), (FrameKind::Data, 5));
+            echoed.extend(frame.payload);
+        }
+        assert_eq!(echoed, b"ping");
+
+        proxy.write_all(&[3, 0, 0, 0, 5, 0, 0, 0, 0]).await.unwrap();
+        assert_eq!(next_frame(&mut proxy).await, Frame::close(5));
+
+        drop(proxy);
+        let result = timeout(Duration::from_secs(5), tunnel).await.unwrap();
+        assert!(result.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_closes_streams_it_cannot_connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let (upstream, mut proxy) = tokio::io::duplex(65536);
+        tokio::spawn(async move { serve_tunnel(upstream, &target).await });
+
+        write_frame(&mut proxy, &Frame::open(9)).await.unwrap();
+
+        assert_eq!(next_frame(&mut proxy).await, Frame::close(9));
+    }
+}
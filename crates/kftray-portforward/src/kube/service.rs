@@ -1,38 +1,80 @@
-use std::collections::HashMap;
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+use std::net::IpAddr;
+use std::time::Duration;
 
 use futures::stream::{
     self,
     StreamExt,
 };
+use k8s_openapi::api::core::v1::Service;
+use kftray_commons::config::read_configs;
+use kftray_commons::config_merge::config_identity;
 use kftray_commons::models::config_model::Config;
+use kube::Client;
 use log::{
     debug,
     error,
     info,
+    warn,
 };
 
 use crate::create_client_with_specific_context;
 use crate::get_services_with_annotation;
+use crate::kube::access::{
+    is_forbidden,
+    review_access,
+    Access,
+};
+use crate::kube::client::extract_ports_from_service;
+use crate::kube::models::{
+    AnnotatedConfig,
+    ContextDiscovery,
+    DiscoveryReport,
+    MultiContextDiscoveryOptions,
+    ServiceDiscovery,
+    SkippedNamespace,
+};
 use crate::list_all_namespaces;
+use crate::list_kube_contexts;
+
+pub(crate) const ENABLED_ANNOTATION: &str = "kftray.app/enabled";
+pub(crate) const CONFIGS_ANNOTATION: &str = "kftray.app/configs";
+
 pub async fn retrieve_service_configs(
     context: &str, kubeconfig: Option<String>,
 ) -> Result<Vec<Config>, String> {
+    discover_context_services(context, kubeconfig, &[])
+        .await
+        .map(|discovery| discovery.configs)
+}
+
+/// Finds the configs of a context's annotated services, reporting the
+/// namespaces that were skipped and why.
+///
+/// Without an allowlist every namespace is scanned. When the user can't list
+/// namespaces, only the context's namespace is. Namespaces of the allowlist or
+/// the fallback are first checked with an access review, so the ones the user
+/// can't list services in are reported instead of failing.
+pub async fn discover_context_services(
+    context: &str, kubeconfig: Option<String>, namespaces: &[String],
+) -> Result<ServiceDiscovery, String> {
     let (client_opt, _, _) = create_client_with_specific_context(kubeconfig.clone(), Some(context))
         .await
         .map_err(|e| e.to_string())?;
 
     let client = client_opt.ok_or_else(|| "Client not created".to_string())?;
-    let annotation = "kftray.app/configs";
+    let annotation = CONFIGS_ANNOTATION;
 
-    let namespaces = list_all_namespaces(client.clone())
-        .await
-        .map_err(|e| e.to_string())?;
+    let (namespaces, mut skipped) = discovery_namespaces(&client, namespaces).await?;
 
     debug!("Found {} namespaces", namespaces.len());
 
     let concurrency_limit = 10;
 
-    stream::iter(namespaces)
+    let results: Vec<(String, Result<Vec<Config>, String>)> = stream::iter(namespaces)
         .map(|namespace| {
             let client = client.clone();
             let context = context.to_string();
@@ -42,9 +84,13 @@ pub async fn retrieve_service_configs(
             async move {
                 info!("Processing namespace: {}", namespace);
                 let services =
-                    get_services_with_annotation(client.clone(), &namespace, &annotation)
+                    match get_services_with_annotation(client.clone(), &namespace, &annotation)
                         .await
-                        .map_err(|e| e.to_string())?;
+                        .map_err(|e| e.to_string())
+                    {
+                        Ok(services) => services,
+                        Err(e) => return (namespace, Err(e)),
+                    };
 
                 let mut namespace_configs = Vec::new();
 
@@ -54,14 +100,18 @@ pub async fn retrieve_service_configs(
                         service_name, namespace
                     );
                     if let Some(configs_str) = annotations.get(&annotation) {
-                        namespace_configs.extend(parse_configs(
-                            configs_str,
-                            &context,
-                            &namespace,
-                            &service_name,
-                            &ports,
-                            kubeconfig.clone(),
-                        ));
+                        namespace_configs.extend(
+                            parse_configs(
+                                configs_str,
+                                &context,
+                                &namespace,
+                                &service_name,
+                                &ports,
+                                kubeconfig.clone(),
+                            )
+                            .into_iter()
+                            .map(|annotated| annotated.config),
+                        );
                     } else {
                         namespace_configs.extend(create_default_configs(
                             &context,
@@ -73,76 +123,360 @@ pub async fn retrieve_service_configs(
                     }
                 }
 
-                Ok(namespace_configs)
+                (namespace, Ok(namespace_configs))
             }
         })
         .buffer_unordered(concurrency_limit)
-        .fold(
-            Ok(Vec::new()),
-            |mut acc: Result<Vec<Config>, String>, result: Result<Vec<Config>, String>| async {
-                match (&mut acc, result) {
-                    (Ok(configs), Ok(mut namespace_configs)) => {
-                        configs.append(&mut namespace_configs);
-                        acc
-                    }
-                    (Ok(_), Err(e)) => {
-                        error!("Error processing namespace: {}", e);
-                        acc
-                    }
-                    (Err(_), _) => acc,
-                }
-            },
-        )
-        .await
+        .collect()
+        .await;
+
+    let mut discovery = ServiceDiscovery::default();
+    for (namespace, result) in results {
+        match result {
+            Ok(mut namespace_configs) => {
+                discovery.configs.append(&mut namespace_configs);
+                discovery.namespaces.push(namespace);
+            }
+            Err(e) => {
+                error!("Error processing namespace {}: {}", namespace, e);
+                skipped.push(SkippedNamespace {
+                    namespace,
+                    reason: e,
+                });
+            }
+        }
+    }
+    discovery.namespaces.sort();
+    skipped.sort_by(|a, b| a.namespace.cmp(&b.namespace));
+    discovery.skipped = skipped;
+
+    Ok(discovery)
+}
+
+/// Namespaces discovery scans, with the ones it leaves out.
+async fn discovery_namespaces(
+    client: &Client, allowlist: &[String],
+) -> Result<(Vec<String>, Vec<SkippedNamespace>), String> {
+    let allowlist = allowlisted_namespaces(allowlist);
+    let candidates = if allowlist.is_empty() {
+        match fallback_namespaces(
+            list_all_namespaces(client.clone()).await,
+            client.default_namespace(),
+        )? {
+            NamespaceCandidates::Listed(namespaces) => return Ok((namespaces, Vec::new())),
+            NamespaceCandidates::Review(namespaces) => namespaces,
+        }
+    } else {
+        allowlist
+    };
+
+    let mut reviews = Vec::new();
+    for namespace in candidates {
+        let access = review_access(client, Some(&namespace), "list", "", "services", None).await;
+        reviews.push((namespace, access));
+    }
+
+    Ok(reviewed_namespaces(reviews))
 }
 
-fn parse_configs(
+/// Namespaces discovery may scan, before their access is reviewed.
+#[derive(PartialEq, Debug)]
+enum NamespaceCandidates {
+    /// Every namespace of the cluster, scanned without a review.
+    Listed(Vec<String>),
+    /// Namespaces to review first.
+    Review(Vec<String>),
+}
+
+/// The allowlist trimmed, without blanks or repeats, in the order given.
+fn allowlisted_namespaces(allowlist: &[String]) -> Vec<String> {
+    let mut listed = HashSet::new();
+    allowlist
+        .iter()
+        .map(|namespace| namespace.trim())
+        .filter(|namespace| !namespace.is_empty() && listed.insert(*namespace))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Candidates without an allowlist: every namespace, or only the context's
+/// namespace when the user can't list them.
+fn fallback_namespaces(
+    listed: anyhow::Result<Vec<String>>, default_namespace: &str,
+) -> Result<NamespaceCandidates, String> {
+    match listed {
+        Ok(namespaces) => Ok(NamespaceCandidates::Listed(namespaces)),
+        Err(e) if is_forbidden(&e) => {
+            warn!(
+                "Not allowed to list namespaces, only scanning namespace {}: {}",
+                default_namespace, e
+            );
+            Ok(NamespaceCandidates::Review(vec![
+                default_namespace.to_string()
+            ]))
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Splits reviewed namespaces into the ones to scan and the ones denied,
+/// keeping their order.
+fn reviewed_namespaces(
+    reviews: Vec<(String, Result<Access, kube::Error>)>,
+) -> (Vec<String>, Vec<SkippedNamespace>) {
+    let mut namespaces = Vec::new();
+    let mut skipped = Vec::new();
+    for (namespace, access) in reviews {
+        match access {
+            Ok(Access::Allowed) => namespaces.push(namespace),
+            Ok(Access::Denied(reason)) => {
+                info!("Skipping namespace {}: {}", namespace, reason);
+                skipped.push(SkippedNamespace { namespace, reason });
+            }
+            // Access reviews can be unavailable, listing the services tells
+            Err(e) => {
+                debug!("Access review failed for namespace {}: {}", namespace, e);
+                namespaces.push(namespace);
+            }
+        }
+    }
+
+    (namespaces, skipped)
+}
+
+/// Runs discovery on several contexts at once, reporting for each the
+/// configs that are not configured yet.
+///
+/// A context failing or timing out is reported without stopping the others.
+/// Configs are told apart by their identity, as imports and Git syncs do.
+pub async fn discover_service_configs(
+    options: &MultiContextDiscoveryOptions,
+) -> Result<DiscoveryReport, String> {
+    let mut contexts = if options.contexts.is_empty() {
+        list_kube_contexts(options.kubeconfig.clone())
+            .await?
+            .into_iter()
+            .map(|info| info.name)
+            .collect()
+    } else {
+        options.contexts.clone()
+    };
+    let mut listed = HashSet::new();
+    contexts.retain(|context| listed.insert(context.clone()));
+
+    info!(
+        "Discovering services in {} contexts, {} at a time",
+        contexts.len(),
+        options.concurrency.max(1)
+    );
+
+    let timeout = Duration::from_secs(options.timeout_secs.max(1));
+    let results: Vec<(String, Result<ServiceDiscovery, String>)> = stream::iter(contexts)
+        .map(|context| {
+            let kubeconfig = options.kubeconfig.clone();
+            let namespaces = &options.namespaces;
+            async move {
+                let result = tokio::time::timeout(
+                    timeout,
+                    discover_context_services(&context, kubeconfig, namespaces),
+                )
+                .await
+                .unwrap_or_else(|_| Err(format!("Timed out after {}s", timeout.as_secs())));
+                (context, result)
+            }
+        })
+        .buffered(options.concurrency.max(1))
+        .collect()
+        .await;
+
+    let existing: HashSet<String> = read_configs().await?.iter().map(config_identity).collect();
+    let mut seen = HashSet::new();
+    let mut report = DiscoveryReport::default();
+
+    for (context, result) in results {
+        let mut discovery = ContextDiscovery {
+            context,
+            ..Default::default()
+        };
+
+        match result {
+            Ok(found) => {
+                discovery.skipped_namespaces = found.skipped;
+                classify_configs(&mut discovery, found.configs, &existing, &mut seen);
+            }
+            Err(e) => {
+                warn!("Discovery failed for context {}: {}", discovery.context, e);
+                discovery.error = Some(e);
+            }
+        }
+
+        report.contexts.push(discovery);
+    }
+
+    Ok(report)
+}
+
+/// Sorts the configs found on a context into new ones, ones already present
+/// locally and ones an earlier context found too. `seen` holds the identities
+/// of the new configs of the contexts before.
+fn classify_configs(
+    discovery: &mut ContextDiscovery, configs: Vec<Config>, existing: &HashSet<String>,
+    seen: &mut HashSet<String>,
+) {
+    for config in configs {
+        let identity = config_identity(&config);
+        if existing.contains(&identity) {
+            discovery.existing += 1;
+        } else if !seen.insert(identity) {
+            discovery.duplicates += 1;
+        } else {
+            discovery.new_configs.push(config);
+        }
+    }
+}
+
+/// Parses a `kftray.app/configs` annotation.
+///
+/// Entries are separated by commas and read `alias-local_port-target_port`,
+/// where the target port is a number or the name of a service port. Options
+/// can follow, separated by semicolons: `protocol=udp`,
+/// `local_address=127.0.0.2`, `domain=true` and `http_logs=true`, e.g.
+/// `api-8080-http;domain=true;http_logs=true, dns-5353-53;protocol=udp`.
+pub(crate) fn parse_configs(
     configs_str: &str, context: &str, namespace: &str, service_name: &str,
     ports: &HashMap<String, i32>, kubeconfig: Option<String>,
-) -> Vec<Config> {
+) -> Vec<AnnotatedConfig> {
     configs_str
         .split(',')
+        .filter(|config_str| !config_str.trim().is_empty())
         .filter_map(|config_str| {
-            let parts: Vec<&str> = config_str.trim().split('-').collect();
-            if parts.len() != 3 {
+            let mut options = config_str.trim().split(';');
+            let entry = options.next().unwrap_or_default().trim();
+
+            // Split from the right so aliases can contain dashes
+            let parts: Vec<&str> = entry.rsplitn(3, '-').collect();
+            let [target, local_port, alias] = parts.as_slice() else {
+                debug!("Invalid config format: {}", config_str);
+                return None;
+            };
+            if alias.is_empty() {
                 debug!("Invalid config format: {}", config_str);
                 return None;
             }
 
-            let alias = parts[0].to_string();
-            let local_port: u16 = match parts[1].parse() {
+            let local_port: u16 = match local_port.parse() {
                 Ok(port) => port,
                 Err(e) => {
-                    debug!("Failed to parse local port '{}': {}", parts[1], e);
+                    debug!("Failed to parse local port '{}': {}", local_port, e);
                     return None;
                 }
             };
 
-            let target_port = parts[2]
+            let target_port = target
                 .parse()
                 .ok()
-                .or_else(|| ports.get(parts[2]).cloned())?;
-
-            Some(Config {
-                id: None,
-                context: context.to_string(),
-                kubeconfig: kubeconfig.clone(),
-                namespace: namespace.to_string(),
-                service: Some(service_name.to_string()),
-                alias: Some(alias),
-                local_port: Some(local_port),
-                remote_port: Some(target_port as u16),
-                protocol: "tcp".to_string(),
-                workload_type: Some("service".to_string()),
-                target: None,
-                local_address: None,
-                remote_address: None,
-                domain_enabled: None,
-            })
+                .or_else(|| ports.get(*target).cloned())?;
+
+            let mut annotated = AnnotatedConfig {
+                config: Config {
+                    id: None,
+                    context: context.to_string(),
+                    kubeconfig: kubeconfig.clone(),
+                    namespace: namespace.to_string(),
+                    service: Some(service_name.to_string()),
+                    alias: Some(alias.to_string()),
+                    local_port: Some(local_port),
+                    remote_port: Some(target_port as u16),
+                    protocol: "tcp".to_string(),
+                    workload_type: Some("service".to_string()),
+                    target: None,
+                    local_address: None,
+                    remote_address: None,
+                    domain_enabled: None,
+                    pod_template: None,
+                },
+                http_logs: false,
+            };
+
+            for option in options.map(str::trim).filter(|o| !o.is_empty()) {
+                if !apply_option(&mut annotated, option) {
+                    debug!("Ignoring invalid option '{}' of {}", option, alias);
+                }
+            }
+
+            Some(annotated)
         })
         .collect()
 }
 
+/// Applies a `key=value` option of an annotation entry, telling whether it
+/// was valid.
+fn apply_option(annotated: &mut AnnotatedConfig, option: &str) -> bool {
+    let Some((key, value)) = option.split_once('=') else {
+        return false;
+    };
+    let value = value.trim();
+
+    match key.trim() {
+        "protocol" => match value.to_lowercase().as_str() {
+            protocol @ ("tcp" | "udp") => annotated.config.protocol = protocol.to_string(),
+            _ => return false,
+        },
+        "local_address" => {
+            if value.parse::<IpAddr>().is_err() {
+                return false;
+            }
+            annotated.config.local_address = Some(value.to_string());
+        }
+        "domain" => match value.parse() {
+            Ok(enabled) => annotated.config.domain_enabled = Some(enabled),
+            Err(_) => return false,
+        },
+        "http_logs" => match value.parse() {
+            Ok(enabled) => annotated.http_logs = enabled,
+            Err(_) => return false,
+        },
+        _ => return false,
+    }
+
+    true
+}
+
+/// Configs of a service discovery picks up, none unless the service has the
+/// `kftray.app/enabled` annotation.
+pub(crate) fn service_configs(
+    service: &Service, context: &str, kubeconfig: Option<String>,
+) -> Option<Vec<AnnotatedConfig>> {
+    let annotations = service.metadata.annotations.as_ref()?;
+    if annotations.get(ENABLED_ANNOTATION).map(String::as_str) != Some("true") {
+        return None;
+    }
+
+    let service_name = service.metadata.name.as_deref()?;
+    let namespace = service.metadata.namespace.as_deref().unwrap_or("default");
+    let ports = extract_ports_from_service(service);
+
+    let configs = match annotations.get(CONFIGS_ANNOTATION) {
+        Some(configs_str) => parse_configs(
+            configs_str,
+            context,
+            namespace,
+            service_name,
+            &ports,
+            kubeconfig,
+        ),
+        None => create_default_configs(context, namespace, service_name, &ports, kubeconfig)
+            .into_iter()
+            .map(|config| AnnotatedConfig {
+                config,
+                http_logs: false,
+            })
+            .collect(),
+    };
+
+    Some(configs)
+}
+
 fn create_default_configs(
     context: &str, namespace: &str, service_name: &str, ports: &HashMap<String, i32>,
     kubeconfig: Option<String>,
@@ -164,6 +498,246 @@ fn create_default_configs(
             local_address: None,
             remote_address: None,
             domain_enabled: None,
+            pod_template: None,
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ports() -> HashMap<String, i32> {
+        HashMap::from([("http".to_string(), 8080), ("metrics".to_string(), 9090)])
+    }
+
+    fn parse(configs_str: &str) -> Vec<AnnotatedConfig> {
+        parse_configs(configs_str, "kind", "apps", "api", &ports(), None)
+    }
+
+    fn config(alias: Option<&str>, context: &str, remote_port: u16) -> Config {
+        Config {
+            alias: alias.map(str::to_string),
+            context: context.to_string(),
+            namespace: "apps".to_string(),
+            service: Some("api".to_string()),
+            remote_port: Some(remote_port),
+            protocol: "tcp".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_classify_configs() {
+        let existing = HashSet::from([config_identity(&config(Some("api"), "kind", 80))]);
+        let mut seen = HashSet::new();
+
+        let mut first = ContextDiscovery::default();
+        classify_configs(
+            &mut first,
+            vec![
+                config(Some("api"), "kind", 80),
+                config(Some("db"), "kind", 5432),
+                config(None, "kind", 9090),
+                config(Some("db"), "kind", 5432),
+            ],
+            &existing,
+            &mut seen,
+        );
+        assert_eq!(first.existing, 1);
+        assert_eq!(first.duplicates, 1);
+        assert_eq!(first.new_configs.len(), 2);
+        assert_eq!(first.new_configs[0].alias.as_deref(), Some("db"));
+        assert_eq!(first.new_configs[1].remote_port, Some(9090));
+
+        let mut second = ContextDiscovery::default();
+        classify_configs(
+            &mut second,
+            vec![
+                config(Some("api"), "prod", 80),
+                config(Some("db"), "prod", 5432),
+                config(None, "prod", 9090),
+            ],
+            &existing,
+            &mut seen,
+        );
+        assert_eq!(second.existing, 1);
+        assert_eq!(second.duplicates, 1);
+        assert_eq!(second.new_configs.len(), 1);
+        assert_eq!(second.new_configs[0].context, "prod");
+        assert_eq!(second.new_configs[0].remote_port, Some(9090));
+    }
+
+    fn api_error(code: u16) -> kube::Error {
+        kube::Error::Api(kube::error::ErrorResponse {
+            status: "Failure".to_string(),
+            message: "namespaces is forbidden".to_string(),
+            reason: "Forbidden".to_string(),
+            code,
+        })
+    }
+
+    fn namespaces(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_allowlisted_namespaces() {
+        let allowlist = namespaces(&[" apps ", "", "kube-system", "apps", "  ", "default"]);
+
+        assert_eq!(
+            allowlisted_namespaces(&allowlist),
+            namespaces(&["apps", "kube-system", "default"])
+        );
+        assert!(allowlisted_namespaces(&namespaces(&[" ", ""])).is_empty());
+    }
+
+    #[test]
+    fn test_fallback_namespaces() {
+        assert_eq!(
+            fallback_namespaces(Ok(namespaces(&["apps", "default"])), "team-a"),
+            Ok(NamespaceCandidates::Listed(namespaces(&[
+                "apps", "default"
+            ])))
+        );
+        assert_eq!(
+            fallback_namespaces(Err(api_error(403).into()), "team-a"),
+            Ok(NamespaceCandidates::Review(namespaces(&["team-a"])))
+        );
+        assert!(fallback_namespaces(Err(api_error(500).into()), "team-a").is_err());
+        assert!(fallback_namespaces(Err(anyhow::anyhow!("connection refused")), "team-a").is_err());
+    }
+
+    #[test]
+    fn test_reviewed_namespaces() {
+        let (scanned, skipped) = reviewed_namespaces(vec![
+            ("apps".to_string(), Ok(Access::Allowed)),
+            (
+                "kube-system".to_string(),
+                Ok(Access::Denied("RBAC: no rule".to_string())),
+            ),
+            ("monitoring".to_string(), Err(api_error(500))),
+            (
+                "team-b".to_string(),
+                Ok(Access::Denied(
+                    "cannot list services in namespace team-b".to_string(),
+                )),
+            ),
+            ("default".to_string(), Ok(Access::Allowed)),
+        ]);
+
+        assert_eq!(scanned, namespaces(&["apps", "monitoring", "default"]));
+        assert_eq!(
+            skipped,
+            vec![
+                SkippedNamespace {
+                    namespace: "kube-system".to_string(),
+                    reason: "RBAC: no rule".to_string(),
+                },
+                SkippedNamespace {
+                    namespace: "team-b".to_string(),
+                    reason: "cannot list services in namespace team-b".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_configs() {
+        let configs = parse("api-8080-80, db-5432-5432");
+
+        assert_eq!(configs.len(), 2);
+        let config = &configs[0].config;
+        assert_eq!(config.alias.as_deref(), Some("api"));
+        assert_eq!(config.local_port, Some(8080));
+        assert_eq!(config.remote_port, Some(80));
+        assert_eq!(config.context, "kind");
+        assert_eq!(config.namespace, "apps");
+        assert_eq!(config.service.as_deref(), Some("api"));
+        assert_eq!(config.protocol, "tcp");
+        assert_eq!(config.workload_type.as_deref(), Some("service"));
+        assert!(!configs[0].http_logs);
+        assert_eq!(configs[1].config.alias.as_deref(), Some("db"));
+    }
+
+    #[test]
+    fn test_parse_configs_alias_with_dashes() {
+        let configs = parse("my-api-v2-8080-80");
+
+        assert_eq!(configs.len(), 1);
+        assert_eq!(configs[0].config.alias.as_deref(), Some("my-api-v2"));
+        assert_eq!(configs[0].config.local_port, Some(8080));
+        assert_eq!(configs[0].config.remote_port, Some(80));
+    }
+
+    #[test]
+    fn test_parse_configs_named_target_port() {
+        let configs = parse("api-8000-http, api-metrics-9000-metrics, api-9001-grpc");
+
+        assert_eq!(configs.len(), 2);
+        assert_eq!(configs[0].config.remote_port, Some(8080));
+        assert_eq!(configs[1].config.remote_port, Some(9090));
+    }
+
+    #[test]
+    fn test_parse_configs_options() {
+        let configs = parse(
+            "api-8080-http; domain=true; http_logs=true, \
+             dns-5353-53;protocol=UDP;local_address=127.0.0.2",
+        );
+
+        assert_eq!(configs.len(), 2);
+        assert_eq!(configs[0].config.domain_enabled, Some(true));
+        assert!(configs[0].http_logs);
+        assert_eq!(configs[0].config.protocol, "tcp");
+        assert_eq!(configs[1].config.protocol, "udp");
+        assert_eq!(
+            configs[1].config.local_address.as_deref(),
+            Some("127.0.0.2")
+        );
+        assert_eq!(configs[1].config.domain_enabled, None);
+        assert!(!configs[1].http_logs);
+    }
+
+    #[test]
+    fn test_parse_configs_ignores_invalid_options() {
+        let configs = parse(
+            "api-8080-80;protocol=sctp;local_address=localhost;domain=yes;http_logs=1;color=blue;udp",
+        );
+
+        assert_eq!(configs.len(), 1);
+        let annotated = &configs[0];
+        assert_eq!(annotated.config.protocol, "tcp");
+        assert_eq!(annotated.config.local_address, None);
+        assert_eq!(annotated.config.domain_enabled, None);
+        assert!(!annotated.http_logs);
+    }
+
+    #[test]
+    fn test_parse_configs_rejects_invalid_entries() {
+        let configs =
+            parse("bad-x-1, -1-2, api-80, 8080-80, api-70000-80, api-8080-grpc, , ok-1-2");
+
+        assert_eq!(configs.len(), 1);
+        assert_eq!(configs[0].config.alias.as_deref(), Some("ok"));
+    }
+
+    #[test]
+    fn test_apply_option() {
+        let mut annotated = parse("api-8080-80").remove(0);
+
+        assert!(apply_option(&mut annotated, "protocol = Udp"));
+        assert_eq!(annotated.config.protocol, "udp");
+        assert!(apply_option(&mut annotated, "local_address=::1"));
+        assert_eq!(annotated.config.local_address.as_deref(), Some("::1"));
+        assert!(apply_option(&mut annotated, "domain=false"));
+        assert_eq!(annotated.config.domain_enabled, Some(false));
+        assert!(apply_option(&mut annotated, "http_logs=true"));
+        assert!(annotated.http_logs);
+
+        assert!(!apply_option(&mut annotated, "protocol"));
+        assert!(!apply_option(&mut annotated, "protocol=http"));
+        assert!(!apply_option(&mut annotated, "retries=3"));
+        assert_eq!(annotated.config.protocol, "udp");
+    }
+}
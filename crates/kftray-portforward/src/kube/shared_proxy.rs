@@ -0,0 +1,899 @@
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+use std::net::SocketAddr;
+use std::sync::atomic::{
+    AtomicBool,
+    AtomicU32,
+    Ordering,
+};
+use std::sync::{
+    Arc,
+    Mutex as StdMutex,
+};
+
+use anyhow::Context;
+use k8s_openapi::api::core::v1::Pod;
+use kftray_commons::{
+    models::{
+        config_model::Config,
+        config_state_model::ConfigState,
+        pod_template_model::PodTemplate,
+        response::CustomResponse,
+    },
+    utils::config_state::update_config_state,
+};
+use kube::api::{
+    Api,
+    DeleteParams,
+    ListParams,
+    PostParams,
+};
+use lazy_static::lazy_static;
+use log::{
+    debug,
+    error,
+    info,
+    trace,
+    warn,
+};
+use tokio::io::{
+    AsyncRead,
+    AsyncWrite,
+};
+use tokio::net::{
+    TcpListener,
+    TcpStream,
+    UdpSocket,
+};
+use tokio::sync::{
+    mpsc,
+    Mutex,
+    OwnedMutexGuard,
+};
+use tokio::task::JoinHandle;
+use tokio::time::{
+    interval,
+    Instant,
+};
+
+use crate::create_client_with_specific_context;
+use crate::kube::lease::{
+    apply_lease,
+    spawn_lease_renewal,
+};
+use crate::kube::preflight::preflight_denial;
+use crate::kube::proxy::{
+    clean_username,
+    proxy_pod_name,
+    render_proxy_pod,
+    wait_for_proxy_pod,
+};
+use crate::kube::tunnel::{
+    pump_from_tunnel,
+    pump_to_tunnel,
+    read_frame,
+    spawn_keepalive,
+    write_frames,
+    Destination,
+    DestinationProtocol,
+    Frame,
+    FrameKind,
+};
+use crate::kube::udp_forwarder::{
+    MAX_SESSIONS,
+    SESSION_IDLE_TIMEOUT,
+    SESSION_SWEEP_INTERVAL,
+};
+use crate::port_forward::CHILD_PROCESSES;
+
+/// Port the shared proxy pod accepts multiplexed sessions on.
+pub const SHARED_PROXY_PORT: u16 = 9998;
+
+const SHARED_PROXY_LABEL: &str = "kftray.app/shared-proxy";
+const FRAME_QUEUE_SIZE: usize = 256;
+const STREAM_QUEUE_SIZE: usize = 64;
+const BUFFER_SIZE: usize = 65536;
+
+lazy_static! {
+    /// Shared proxies keyed by `{context}/{namespace}`. Each has its own lock,
+    /// so deploying or reconnecting one doesn't hold up the others.
+    static ref SHARED_PROXIES: StdMutex<HashMap<String, Arc<Mutex<SharedProxy>>>> =
+        StdMutex::new(HashMap::new());
+}
+
+#[derive(Default)]
+struct SharedProxy {
+    pod: Option<ProxyPod>,
+    session: Option<Arc<MuxSession>>,
+    users: HashSet<i64>,
+    template: Option<PodTemplate>,
+}
+
+/// The pod a shared proxy forwards through, with its lease renewal.
+struct ProxyPod {
+    pods: Api<Pod>,
+    name: String,
+    lease: JoinHandle<()>,
+}
+
+/// Multiplexed session over a single port forward to a shared proxy pod.
+struct MuxSession {
+    frames: mpsc::Sender<Frame>,
+    streams: Arc<StdMutex<HashMap<u32, mpsc::Sender<Vec<u8>>>>>,
+    next_stream_id: AtomicU32,
+    closed: Arc<AtomicBool>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl MuxSession {
+    async fn connect(pods: &Api<Pod>, pod_name: &str) -> anyhow::Result<Self> {
+        let mut forwarder = pods.portforward(pod_name, &[SHARED_PROXY_PORT]).await?;
+        let stream = forwarder
+            .take_stream(SHARED_PROXY_PORT)
+            .ok_or_else(|| anyhow::anyhow!("port not found in forwarder"))?;
+
+        Ok(Self::start(stream, pod_name))
+    }
+
+    /// Runs a session over a connection to the proxy.
+    fn start<S>(stream: S, pod_name: &str) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (mut reader, writer) = tokio::io::split(stream);
+        let (frames_tx, frames_rx) = mpsc::channel(FRAME_QUEUE_SIZE);
+        let streams: Arc<StdMutex<HashMap<u32, mpsc::Sender<Vec<u8>>>>> = Arc::default();
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let writer_task = tokio::spawn(async move {
+            if let Err(e) = write_frames(writer, frames_rx).await {
+                debug!("Shared proxy writer stopped: {}", e);
+            }
+        });
+        let keepalive_task = spawn_keepalive(frames_tx.clone());
+
+        let reader_task = tokio::spawn({
+            let streams = streams.clone();
+            let closed = closed.clone();
+            let pod_name = pod_name.to_string();
+            async move {
+                loop {
+                    let frame = match read_frame(&mut reader).await {
+                        Ok(Some(frame)) => frame,
+                        Ok(None) => break,
+                        Err(e) => {
+                            warn!("Shared proxy session to {} failed: {}", pod_name, e);
+                            break;
+                        }
+                    };
+
+                    match frame.kind {
+                        FrameKind::Data => {
+                            let sender = streams.lock().unwrap().get(&frame.stream_id).cloned();
+                            if let Some(sender) = sender {
+                                if sender.send(frame.payload).await.is_err() {
+                                    streams.lock().unwrap().remove(&frame.stream_id);
+                                }
+                            }
+                        }
+                        FrameKind::Close => {
+                            streams.lock().unwrap().remove(&frame.stream_id);
+                        }
+                        FrameKind::Ping | FrameKind::Open => {
+                            trace!("Shared proxy frame: {:?}", frame.kind)
+                        }
+                    }
+                }
+
+                closed.store(true, Ordering::SeqCst);
+                streams.lock().unwrap().clear();
+                info!("Shared proxy session to {} closed", pod_name);
+            }
+        });
+
+        Self {
+            frames: frames_tx,
+            streams,
+            next_stream_id: AtomicU32::new(1),
+            closed,
+            tasks: vec![writer_task, keepalive_task, reader_task],
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    async fn open_stream(
+        &self, destination: &Destination,
+    ) -> anyhow::Result<(u32, mpsc::Receiver<Vec<u8>>)> {
+        let stream_id = self.next_stream_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel(STREAM_QUEUE_SIZE);
+        self.streams.lock().unwrap().insert(stream_id, tx);
+
+        if self
+            .frames
+            .send(Frame::open_to(stream_id, destination))
+            .await
+            .is_err()
+        {
+            self.finish_stream(stream_id);
+            anyhow::bail!("Shared proxy session closed");
+        }
+
+        Ok((stream_id, rx))
+    }
+
+    fn finish_stream(&self, stream_id: u32) {
+        self.streams.lock().unwrap().remove(&stream_id);
+    }
+}
+
+impl Drop for MuxSession {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Forwards configs through one proxy pod per context and namespace.
+///
+/// Instead of deploying a pod per config like `deploy_and_forward_pod`, the
+/// first config of a namespace deploys a `kftray-server` pod in mux mode and
+/// every other config reuses it. Each local connection becomes a stream over
+/// a single port forward, naming its own TCP or UDP destination.
+pub async fn deploy_and_forward_shared(
+    configs: Vec<Config>,
+) -> Result<Vec<CustomResponse>, String> {
+    let mut responses = Vec::new();
+
+    for config in configs.into_iter() {
+        let context_name = Some(config.context.as_str());
+        let (client, _, _) =
+            create_client_with_specific_context(config.kubeconfig.clone(), context_name)
+                .await
+                .map_err(|e| {
+                    error!("Failed to create Kubernetes client: {}", e);
+                    e.to_string()
+                })?;
+
+        let client = client.ok_or_else(|| "Client not created".to_string())?;
+
+        if let Some(explanation) = preflight_denial(Some(&client), &config).await {
+            return Err(explanation);
+        }
+
+        let pods: Api<Pod> = Api::namespaced(client, &config.namespace);
+        let key = format!("{}/{}", config.context, config.namespace);
+
+        let config_id = config
+            .id
+            .ok_or_else(|| "Config id is required".to_string())?;
+        let remote_port = config
+            .remote_port
+            .ok_or_else(|| "Remote port is required".to_string())?;
+        let host = config
+            .remote_address
+            .clone()
+            .filter(|address| !address.is_empty())
+            .or_else(|| config.service.clone())
+            .ok_or_else(|| "Remote address or service is required".to_string())?;
+        let protocol = match config.protocol.to_lowercase().as_str() {
+            "udp" => DestinationProtocol::Udp,
+            _ => DestinationProtocol::Tcp,
+        };
+        let destination = Destination {
+            protocol,
+            host: host.clone(),
+            port: remote_port,
+        };
+
+        acquire_session(&key, &pods, config.pod_template.as_ref()).await?;
+
+        let local_address = config
+            .local_address
+            .clone()
+            .filter(|address| !address.is_empty())
+            .unwrap_or_else(|| "127.0.0.1".to_string());
+        if let Err(e) = crate::network_utils::ensure_loopback_address(&local_address).await {
+            return Err(format!("Failed to configure loopback address: {}", e));
+        }
+        let local_port = config.local_port.unwrap_or(remote_port);
+
+        let served = match protocol {
+            DestinationProtocol::Tcp => {
+                serve_tcp(key.clone(), pods, &local_address, local_port, destination).await
+            }
+            DestinationProtocol::Udp => {
+                serve_udp(key.clone(), pods, &local_address, local_port, destination).await
+            }
+        };
+        let (actual_local_port, handle) = served.map_err(|e| {
+            error!("Failed to serve shared proxy forward: {:?}", e);
+            e.to_string()
+        })?;
+
+        lock_proxy(&key).await.users.insert(config_id);
+
+        let service = config.service.clone().unwrap_or_else(|| host.clone());
+        let handle_key = format!("{}_{}", config_id, service);
+        CHILD_PROCESSES.lock().unwrap().insert(handle_key, handle);
+
+        let config_state = ConfigState {
+            id: None,
+            config_id,
+            is_running: true,
+        };
+        if let Err(e) = update_config_state(&config_state).await {
+            error!("Failed to update config state: {}", e);
+        }
+
+        responses.push(CustomResponse {
+            id: config.id,
+            service,
+            namespace: config.namespace.clone(),
+            local_port: actual_local_port,
+            remote_port,
+            context: config.context.clone(),
+            protocol: config.protocol.clone(),
+            stdout: format!(
+                "{} forwarding from {}:{} -> {}:{} through shared proxy",
+                config.protocol.to_uppercase(),
+                local_address,
+                actual_local_port,
+                host,
+                remote_port
+            ),
+            stderr: String::new(),
+            status: 0,
+        });
+    }
+
+    Ok(responses)
+}
+
+/// Stops a shared proxy forward, deleting the pod once no config uses it.
+pub async fn stop_shared_proxy_forward(config_id: i64) -> Result<CustomResponse, String> {
+    let response = super::stop::stop_port_forward(config_id.to_string()).await?;
+
+    let proxies: Vec<(String, Arc<Mutex<SharedProxy>>)> = SHARED_PROXIES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(key, proxy)| (key.clone(), proxy.clone()))
+        .collect();
+
+    for (key, proxy) in proxies {
+        let mut proxy_guard = proxy.lock().await;
+        if !(proxy_guard.users.remove(&config_id) && proxy_guard.users.is_empty()) {
+            continue;
+        }
+
+        unregister(&key, &proxy);
+
+        if let Some(pod) = proxy_guard.pod.take() {
+            delete_shared_pod(&pod).await;
+        }
+    }
+
+    Ok(response)
+}
+
+/// Removes the shared proxy of a key, unless another one replaced it.
+fn unregister(key: &str, proxy: &Arc<Mutex<SharedProxy>>) {
+    let mut proxies = SHARED_PROXIES.lock().unwrap();
+    if proxies
+        .get(key)
+        .is_some_and(|current| Arc::ptr_eq(current, proxy))
+    {
+        proxies.remove(key);
+    }
+}
+
+/// Deletes every shared proxy pod started by this process.
+pub async fn shutdown_shared_proxies() {
+    let proxies: Vec<Arc<Mutex<SharedProxy>>> = SHARED_PROXIES
+        .lock()
+        .unwrap()
+        .drain()
+        .map(|(_, proxy)| proxy)
+        .collect();
+
+    for proxy in proxies {
+        if let Some(pod) = proxy.lock().await.pod.take() {
+            delete_shared_pod(&pod).await;
+        }
+    }
+}
+
+async fn delete_shared_pod(pod: &ProxyPod) {
+    pod.lease.abort();
+    delete_pod(&pod.pods, &pod.name).await;
+}
+
+async fn delete_pod(pods: &Api<Pod>, pod_name: &str) {
+    let dp = DeleteParams {
+        grace_period_seconds: Some(0),
+        propagation_policy: Some(kube::api::PropagationPolicy::Background),
+        ..Default::default()
+    };
+
+    match pods.delete(pod_name, &dp).await {
+        Ok(_) => info!("Deleted shared proxy pod {}", pod_name),
+        Err(e) => error!("Failed to delete shared proxy pod {}: {}", pod_name, e),
+    }
+}
+
+/// Locks the shared proxy of a key, adding an empty one when there's none.
+///
+/// A proxy released while waiting for its lock is no longer registered, so
+/// the lock is taken again on the one that replaced it.
+async fn lock_proxy(key: &str) -> OwnedMutexGuard<SharedProxy> {
+    loop {
+        let proxy = SHARED_PROXIES
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+        let guard = proxy.clone().lock_owned().await;
+
+        let registered = SHARED_PROXIES
+            .lock()
+            .unwrap()
+            .get(key)
+            .is_some_and(|current| Arc::ptr_eq(current, &proxy));
+        if registered {
+            return guard;
+        }
+    }
+}
+
+/// Returns the live session of a shared proxy, deploying its pod or
+/// reconnecting the port forward when needed.
+///
+/// Only the proxy of the key is locked while doing so. A redeployed pod keeps
+/// the pod template it was first deployed with unless a new one is given, and
+/// a pod deployed here is deleted again when the session can't be opened.
+async fn acquire_session(
+    key: &str, pods: &Api<Pod>, template: Option<&PodTemplate>,
+) -> Result<Arc<MuxSession>, String> {
+    let mut proxy = lock_proxy(key).await;
+    let template = template.cloned().or_else(|| proxy.template.clone());
+
+    if let Some(session) = proxy.session.as_ref() {
+        if !session.is_closed() {
+            return Ok(session.clone());
+        }
+    }
+
+    let known_pod = match &proxy.pod {
+        Some(pod) => match pod.pods.get_opt(&pod.name).await {
+            Ok(Some(_)) => Some(pod.name.clone()),
+            _ => None,
+        },
+        None => None,
+    };
+    let (pod_name, deployed) = match known_pod {
+        Some(pod_name) => (pod_name, false),
+        None => find_or_deploy_pod(pods, template.as_ref()).await?,
+    };
+
+    let session = match MuxSession::connect(pods, &pod_name).await {
+        Ok(session) => Arc::new(session),
+        Err(e) => {
+            if deployed {
+                delete_pod(pods, &pod_name).await;
+            }
+            return Err(format!(
+                "Failed to connect to shared proxy {}: {}",
+                pod_name, e
+            ));
+        }
+    };
+
+    if proxy.pod.as_ref().is_none_or(|pod| pod.name != pod_name) {
+        if let Some(previous) = proxy.pod.take() {
+            previous.lease.abort();
+        }
+        proxy.pod = Some(ProxyPod {
+            pods: pods.clone(),
+            name: pod_name.clone(),
+            lease: spawn_lease_renewal(pods.clone(), pod_name),
+        });
+    }
+    proxy.session = Some(session.clone());
+    proxy.template = template;
+
+    Ok(session)
+}
+
+/// Finds a running shared proxy pod of the user or deploys one, telling
+/// whether it was deployed.
+async fn find_or_deploy_pod(
+    pods: &Api<Pod>, template: Option<&PodTemplate>,
+) -> Result<(String, bool), String> {
+    let username = clean_username();
+    let lp = ListParams::default().labels(&format!("{}={}", SHARED_PROXY_LABEL, username));
+    let existing = pods.list(&lp).await.map_err(|e| e.to_string())?;
+
+    for pod in existing.items {
+        let running = pod
+            .status
+            .as_ref()
+            .and_then(|status| status.phase.as_deref())
+            == Some("Running");
+        if running && pod.metadata.deletion_timestamp.is_none() {
+            if let Some(name) = pod.metadata.name {
+                info!("Reusing shared proxy pod {}", name);
+                return Ok((name, false));
+            }
+        }
+    }
+
+    let hashed_name = proxy_pod_name("mux")?;
+
+    let mut values: HashMap<&str, String> = HashMap::new();
+    values.insert("hashed_name", hashed_name.clone());
+    values.insert("config_id", "shared".to_string());
+    values.insert("service_name", hashed_name.clone());
+    values.insert("remote_address", "127.0.0.1".to_string());
+    values.insert("remote_port", "0".to_string());
+    values.insert("local_port", SHARED_PROXY_PORT.to_string());
+    values.insert("protocol", "mux".to_string());
+
+    let mut pod = render_proxy_pod(&values, template)?;
+    apply_lease(&mut pod);
+    pod.metadata
+        .labels
+        .get_or_insert_with(Default::default)
+        .insert(SHARED_PROXY_LABEL.to_string(), username);
+
+    pods.create(&PostParams::default(), &pod)
+        .await
+        .map_err(|e| e.to_string())?;
+    if let Err(e) = wait_for_proxy_pod(pods, &hashed_name).await {
+        delete_pod(pods, &hashed_name).await;
+        return Err(e);
+    }
+
+    info!("Deployed shared proxy pod {}", hashed_name);
+    Ok((hashed_name, true))
+}
+
+async fn serve_tcp(
+    key: String, pods: Api<Pod>, local_address: &str, local_port: u16, destination: Destination,
+) -> anyhow::Result<(u16, JoinHandle<()>)> {
+    let addr = format!("{}:{}", local_address, local_port)
+        .parse::<SocketAddr>()
+        .context("Invalid local address")?;
+    let listener = TcpListener::bind(addr).await?;
+    let port = listener.local_addr()?.port();
+
+    let handle = tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer_addr)) => {
+                    trace!("New shared proxy connection from {}", peer_addr);
+                    let key = key.clone();
+                    let pods = pods.clone();
+                    let destination = destination.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = carry_tcp(&key, &pods, stream, &destination).await {
+                            debug!("Shared proxy connection closed with error: {}", e);
+                        }
+                    });
+                }
+                Err(e) => {
+                    error!("Failed to accept shared proxy connection: {:?}", e);
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok((port, handle))
+}
+
+async fn carry_tcp(
+    key: &str, pods: &Api<Pod>, stream: TcpStream, destination: &Destination,
+) -> anyhow::Result<()> {
+    let session = acquire_session(key, pods, None)
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+    let (stream_id, payloads) = session.open_stream(destination).await?;
+
+    let _ = stream.set_nodelay(true);
+    let (reader, writer) = stream.into_split();
+    let result = tokio::try_join!(
+        pump_to_tunnel(stream_id, reader, &session.frames),
+        pump_from_tunnel(writer, payloads)
+    );
+
+    session.finish_stream(stream_id);
+    result.map(|_| ())
+}
+
+/// A local UDP client of a shared proxy forward, with the stream it uses.
+struct UdpPeer {
+    session: Arc<MuxSession>,
+    stream_id: u32,
+    last_active: Instant,
+    replies: JoinHandle<()>,
+}
+
+impl UdpPeer {
+    fn is_open(&self) -> bool {
+        !self.session.is_closed() && !self.replies.is_finished()
+    }
+
+    async fn close(self) {
+        self.replies.abort();
+        self.session.finish_stream(self.stream_id);
+        let _ = self.session.frames.send(Frame::close(self.stream_id)).await;
+    }
+}
+
+/// Maps local UDP clients to the streams they use, like `UdpSessions` does
+/// for a forward of its own.
+#[derive(Default)]
+struct UdpPeers {
+    by_addr: HashMap<SocketAddr, UdpPeer>,
+}
+
+impl UdpPeers {
+    /// Returns the stream of a peer, when it has one that is still open.
+    async fn stream_of(&mut self, addr: SocketAddr) -> Option<(Arc<MuxSession>, u32)> {
+        match self.by_addr.get_mut(&addr) {
+            Some(peer) if peer.is_open() => {
+                peer.last_active = Instant::now();
+                Some((peer.session.clone(), peer.stream_id))
+            }
+            Some(_) => {
+                if let Some(peer) = self.by_addr.remove(&addr) {
+                    peer.close().await;
+                }
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Opens a stream for a peer, sending what comes back on it to `replies`.
+    /// The least recently used peer is evicted when the limit is reached.
+    async fn open(
+        &mut self, addr: SocketAddr, session: Arc<MuxSession>, destination: &Destination,
+        replies: &mpsc::Sender<(SocketAddr, Vec<u8>)>,
+    ) -> anyhow::Result<u32> {
+        if self.by_addr.len() >= MAX_SESSIONS {
+            let oldest = self
+                .by_addr
+                .iter()
+                .min_by_key(|(_, peer)| peer.last_active)
+                .map(|(&addr, _)| addr);
+            if let Some(peer) = oldest.and_then(|addr| self.by_addr.remove(&addr)) {
+                debug!(
+                    "UDP session limit reached, evicting stream {}",
+                    peer.stream_id
+                );
+                peer.close().await;
+            }
+        }
+
+        let (stream_id, mut payloads) = session.open_stream(destination).await?;
+        let replies = replies.clone();
+        let relay = tokio::spawn(async move {
+            while let Some(payload) = payloads.recv().await {
+                if replies.send((addr, payload)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        debug!("Opening shared proxy UDP stream {} for {}", stream_id, addr);
+        self.by_addr.insert(
+            addr,
+            UdpPeer {
+                session,
+                stream_id,
+                last_active: Instant::now(),
+                replies: relay,
+            },
+        );
+        Ok(stream_id)
+    }
+
+    fn touch(&mut self, addr: SocketAddr) {
+        if let Some(peer) = self.by_addr.get_mut(&addr) {
+            peer.last_active = Instant::now();
+        }
+    }
+
+    /// Closes the streams of peers idle for longer than
+    /// [`SESSION_IDLE_TIMEOUT`].
+    async fn expire_idle(&mut self) {
+        let expired: Vec<SocketAddr> = self
+            .by_addr
+            .iter()
+            .filter(|(_, peer)| peer.last_active.elapsed() >= SESSION_IDLE_TIMEOUT)
+            .map(|(&addr, _)| addr)
+            .collect();
+        for addr in expired {
+            if let Some(peer) = self.by_addr.remove(&addr) {
+                debug!("Shared proxy UDP stream {} expired", peer.stream_id);
+                peer.close().await;
+            }
+        }
+    }
+
+    async fn close_all(&mut self) {
+        for (_, peer) in self.by_addr.drain() {
+            peer.close().await;
+        }
+    }
+}
+
+/// Relays a local UDP socket with one stream per source address, so replies
+/// go back to the client that sent the request. Streams idle for a minute are
+/// closed, the least recently used one is evicted once [`MAX_SESSIONS`] are
+/// open, and a stream is reopened when the session drops.
+async fn serve_udp(
+    key: String, pods: Api<Pod>, local_address: &str, local_port: u16, destination: Destination,
+) -> anyhow::Result<(u16, JoinHandle<()>)> {
+    let socket = UdpSocket::bind(format!("{}:{}", local_address, local_port))
+        .await
+        .context("Failed to bind local UDP socket")?;
+    let port = socket.local_addr()?.port();
+
+    let handle = tokio::spawn(async move {
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        let mut peers = UdpPeers::default();
+        let (replies_tx, mut replies) = mpsc::channel(STREAM_QUEUE_SIZE);
+        let mut sweep = interval(SESSION_SWEEP_INTERVAL);
+
+        loop {
+            tokio::select! {
+                result = socket.recv_from(&mut buffer) => {
+                    let (len, src) = match result {
+                        Ok(received) => received,
+                        Err(e) => {
+                            error!("Failed to receive UDP packet: {:?}", e);
+                            break;
+                        }
+                    };
+
+                    let (session, stream_id) = match peers.stream_of(src).await {
+                        Some(stream) => stream,
+                        None => {
+                            let session = match acquire_session(&key, &pods, None).await {
+                                Ok(session) => session,
+                                Err(e) => {
+                                    warn!("Failed to reach shared proxy: {}", e);
+                                    continue;
+                                }
+                            };
+                            match peers.open(src, session.clone(), &destination, &replies_tx).await {
+                                Ok(stream_id) => (session, stream_id),
+                                Err(e) => {
+                                    warn!("Failed to open shared proxy UDP stream: {}", e);
+                                    continue;
+                                }
+                            }
+                        }
+                    };
+
+                    let frame = Frame::data(stream_id, buffer[..len].to_vec());
+                    if session.frames.send(frame).await.is_err() {
+                        debug!("Shared proxy session closed, dropping UDP packet from {}", src);
+                    }
+                }
+                Some((peer, payload)) = replies.recv() => {
+                    peers.touch(peer);
+                    if let Err(e) = socket.send_to(&payload, peer).await {
+                        error!("Failed to send UDP packet to {}: {:?}", peer, e);
+                    }
+                }
+                _ = sweep.tick() => peers.expire_idle().await,
+            }
+        }
+
+        peers.close_all().await;
+    });
+
+    Ok((port, handle))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::kube::tunnel::write_frame;
+
+    fn destination() -> Destination {
+        Destination {
+            protocol: DestinationProtocol::Udp,
+            host: "dns".to_string(),
+            port: 53,
+        }
+    }
+
+    async fn next_frame(proxy: &mut tokio::io::DuplexStream) -> Frame {
+        timeout(Duration::from_secs(5), read_frame(proxy))
+            .await
+            .expect("no frame from the session")
+            .unwrap()
+            .expect("session closed")
+    }
+
+    #[tokio::test]
+    async fn test_udp_peers_get_a_stream_each() {
+        let (upstream, mut proxy) = tokio::io::duplex(BUFFER_SIZE);
+        let session = Arc::new(MuxSession::start(upstream, "test"));
+        let (replies_tx, mut replies) = mpsc::channel(STREAM_QUEUE_SIZE);
+        let mut peers = UdpPeers::default();
+        let first: SocketAddr = "127.0.0.1:5001".parse().unwrap();
+        let second: SocketAddr = "127.0.0.1:5002".parse().unwrap();
+
+        let first_id = peers
+            .open(first, session.clone(), &destination(), &replies_tx)
+            .await
+            .unwrap();
+        let second_id = peers
+            .open(second, session.clone(), &destination(), &replies_tx)
+            .await
+            .unwrap();
+        assert_ne!(first_id, second_id);
+        assert_eq!(next_frame(&mut proxy).await.kind, FrameKind::Open);
+        assert_eq!(next_frame(&mut proxy).await.kind, FrameKind::Open);
+        assert_eq!(
+            peers.stream_of(first).await.map(|(_, id)| id),
+            Some(first_id)
+        );
+
+        write_frame(&mut proxy, &Frame::data(second_id, b"pong".to_vec()))
+            .await
+            .unwrap();
+        assert_eq!(replies.recv().await, Some((second, b"pong".to_vec())));
+
+        peers.close_all().await;
+        let mut closed = vec![
+            next_frame(&mut proxy).await.stream_id,
+            next_frame(&mut proxy).await.stream_id,
+        ];
+        closed.sort();
+        assert_eq!(closed, [first_id, second_id]);
+    }
+
+    #[tokio::test]
+    async fn test_udp_peer_stream_is_dropped_once_the_proxy_closes_it() {
+        let (upstream, mut proxy) = tokio::io::duplex(BUFFER_SIZE);
+        let session = Arc::new(MuxSession::start(upstream, "test"));
+        let (replies_tx, _replies) = mpsc::channel(STREAM_QUEUE_SIZE);
+        let mut peers = UdpPeers::default();
+        let addr: SocketAddr = "127.0.0.1:5001".parse().unwrap();
+
+        let id = peers
+            .open(addr, session.clone(), &destination(), &replies_tx)
+            .await
+            .unwrap();
+        next_frame(&mut proxy).await;
+
+        write_frame(&mut proxy, &Frame::close(id)).await.unwrap();
+        timeout(Duration::from_secs(5), async {
+            while peers.by_addr.get(&addr).is_some_and(UdpPeer::is_open) {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("stream was not closed");
+
+        assert!(peers.stream_of(addr).await.is_none());
+        assert!(peers.by_addr.is_empty());
+    }
+}
@@ -8,7 +8,11 @@ use kftray_commons::{
     },
     utils::config_state::update_config_state,
 };
-use kftray_http_logs::HttpLogState;
+use kftray_http_logs::{
+    CassetteMode,
+    CassettePlayer,
+    HttpLogState,
+};
 use log::{
     debug,
     error,
@@ -27,6 +31,8 @@ use crate::{
         Target,
         TargetSelector,
     },
+    kube::preflight::preflight_denial,
+    kube::replay::ReplayServer,
     port_forward::CHILD_PROCESSES,
 };
 
@@ -60,134 +66,68 @@ pub async fn start_port_forward(
 
         let local_address_clone = config.local_address.clone();
 
-        let port_forward_result: Result<PortForward, anyhow::Error> = PortForward::new(
-            target,
-            config.local_port,
-            local_address_clone,
-            context_name,
-            kubeconfig.flatten(),
-            config.id.unwrap_or_default(),
-            config.workload_type.clone().unwrap_or_default(),
-        )
-        .await;
-
-        match port_forward_result {
-            Ok(port_forward) => {
-                let forward_result = match protocol {
-                    "udp" => port_forward.clone().port_forward_udp().await,
-                    "tcp" => {
-                        port_forward
-                            .clone()
-                            .port_forward_tcp(http_log_state.clone())
-                            .await
-                    }
-                    _ => Err(anyhow::anyhow!("Unsupported protocol")),
-                };
+        let replay = match replay_player(&http_log_state, config, protocol).await {
+            Ok(replay) => replay,
+            Err(e) => {
+                let error_message = format!(
+                    "Failed to load cassette for {}: {}",
+                    config.service.clone().unwrap_or_default(),
+                    e
+                );
+                error!("{}", &error_message);
+                errors.push(error_message);
+                continue;
+            }
+        };
 
-                match forward_result {
-                    Ok((actual_local_port, handle)) => {
-                        info!(
-                            "{} port forwarding is set up on local port: {:?} for {}: {:?}",
-                            protocol.to_uppercase(),
-                            actual_local_port,
-                            if config.workload_type.as_deref() == Some("pod") {
-                                "pod label"
-                            } else {
-                                "service"
-                            },
-                            &config.service
-                        );
+        // Proxies are checked before their pod is deployed
+        if replay.is_none() && config.workload_type.as_deref() != Some("proxy") {
+            if let Some(explanation) = preflight_denial(None, config).await {
+                errors.push(explanation);
+                continue;
+            }
+        }
 
+        let forward_result = match replay {
+            Some(player) => {
+                ReplayServer::bind_and_serve(
+                    config.id.unwrap_or_default(),
+                    local_address_clone.unwrap_or_else(|| "127.0.0.1".to_string()),
+                    config.local_port.unwrap_or_default(),
+                    player,
+                )
+                .await
+            }
+            None => {
+                let port_forward_result: Result<PortForward, anyhow::Error> = PortForward::new(
+                    target,
+                    config.local_port,
+                    local_address_clone,
+                    context_name,
+                    kubeconfig.flatten(),
+                    config.id.unwrap_or_default(),
+                    config.workload_type.clone().unwrap_or_default(),
+                )
+                .await;
+
+                match port_forward_result {
+                    Ok(port_forward) => {
                         debug!("Port forwarding details: {:?}", port_forward);
-                        debug!("Actual local port: {:?}", actual_local_port);
 
-                        let handle_key = format!(
-                            "{}_{}",
-                            config.id.unwrap(),
-                            config.service.clone().unwrap_or_default()
-                        );
-                        CHILD_PROCESSES
-                            .lock()
-                            .unwrap()
-                            .insert(handle_key.clone(), handle);
-                        child_handles.push(handle_key.clone());
-
-                        if config.domain_enabled.unwrap_or_default() {
-                            if let Some(service_name) = &config.service {
-                                if let Some(local_address) = &config.local_address {
-                                    match local_address.parse::<std::net::IpAddr>() {
-                                        Ok(ip_addr) => {
-                                            let entry_id =
-                                                format!("{}", config.id.unwrap_or_default());
-
-                                            let host_entry = HostEntry {
-                                                ip: ip_addr,
-                                                hostname: config.alias.clone().unwrap_or_default(),
-                                            };
-
-                                            if let Err(e) = add_host_entry(entry_id, host_entry) {
-                                                let error_message = format!(
-                                                    "Failed to write to the hostfile for {}: {}",
-                                                    service_name, e
-                                                );
-                                                error!("{}", &error_message);
-                                                errors.push(error_message);
-
-                                                if let Some(handle) = CHILD_PROCESSES
-                                                    .lock()
-                                                    .unwrap()
-                                                    .remove(&handle_key)
-                                                {
-                                                    handle.abort();
-                                                }
-                                                continue;
-                                            }
-                                        }
-                                        Err(_) => {
-                                            let warning_message = format!(
-                                                "Invalid IP address format: {}",
-                                                local_address
-                                            );
-                                            warn!("{}", &warning_message);
-                                            errors.push(warning_message);
-                                        }
-                                    }
-                                }
+                        match protocol {
+                            "udp" => port_forward.clone().port_forward_udp().await,
+                            "tcp" => {
+                                port_forward
+                                    .clone()
+                                    .port_forward_tcp(http_log_state.clone())
+                                    .await
                             }
+                            _ => Err(anyhow::anyhow!("Unsupported protocol")),
                         }
-
-                        let config_state = ConfigState {
-                            id: None,
-                            config_id: config.id.unwrap(),
-                            is_running: true,
-                        };
-                        if let Err(e) = update_config_state(&config_state).await {
-                            error!("Failed to update config state: {}", e);
-                        }
-
-                        responses.push(CustomResponse {
-                            id: config.id,
-                            service: config.service.clone().unwrap(),
-                            namespace: namespace.clone(),
-                            local_port: actual_local_port,
-                            remote_port: config.remote_port.unwrap_or_default(),
-                            context: config.context.clone(),
-                            protocol: config.protocol.clone(),
-                            stdout: format!(
-                                "{} forwarding from 127.0.0.1:{} -> {:?}:{}",
-                                protocol.to_uppercase(),
-                                actual_local_port,
-                                config.remote_port.unwrap_or_default(),
-                                config.service.clone().unwrap()
-                            ),
-                            stderr: String::new(),
-                            status: 0,
-                        });
                     }
                     Err(e) => {
                         let error_message = format!(
-                            "Failed to start {} port forwarding for {} {}: {}",
-                            protocol.to_uppercase(),
+                            "Failed to create PortForward for {} {}: {}",
                             if config.workload_type.as_deref() == Some("pod") {
                                 "pod label"
                             } else {
@@ -198,12 +138,110 @@ pub async fn start_port_forward(
                         );
                         error!("{}", &error_message);
                         errors.push(error_message);
+                        continue;
+                    }
+                }
+            }
+        };
+
+        match forward_result {
+            Ok((actual_local_port, handle)) => {
+                info!(
+                    "{} port forwarding is set up on local port: {:?} for {}: {:?}",
+                    protocol.to_uppercase(),
+                    actual_local_port,
+                    if config.workload_type.as_deref() == Some("pod") {
+                        "pod label"
+                    } else {
+                        "service"
+                    },
+                    &config.service
+                );
+
+                debug!("Actual local port: {:?}", actual_local_port);
+
+                let handle_key = format!(
+                    "{}_{}",
+                    config.id.unwrap(),
+                    config.service.clone().unwrap_or_default()
+                );
+                CHILD_PROCESSES
+                    .lock()
+                    .unwrap()
+                    .insert(handle_key.clone(), handle);
+                child_handles.push(handle_key.clone());
+
+                if config.domain_enabled.unwrap_or_default() {
+                    if let Some(service_name) = &config.service {
+                        if let Some(local_address) = &config.local_address {
+                            match local_address.parse::<std::net::IpAddr>() {
+                                Ok(ip_addr) => {
+                                    let entry_id = format!("{}", config.id.unwrap_or_default());
+
+                                    let host_entry = HostEntry {
+                                        ip: ip_addr,
+                                        hostname: config.alias.clone().unwrap_or_default(),
+                                    };
+
+                                    if let Err(e) = add_host_entry(entry_id, host_entry) {
+                                        let error_message = format!(
+                                            "Failed to write to the hostfile for {}: {}",
+                                            service_name, e
+                                        );
+                                        error!("{}", &error_message);
+                                        errors.push(error_message);
+
+                                        if let Some(handle) =
+                                            CHILD_PROCESSES.lock().unwrap().remove(&handle_key)
+                                        {
+                                            handle.abort();
+                                        }
+                                        continue;
+                                    }
+                                }
+                                Err(_) => {
+                                    let warning_message =
+                                        format!("Invalid IP address format: {}", local_address);
+                                    warn!("{}", &warning_message);
+                                    errors.push(warning_message);
+                                }
+                            }
+                        }
                     }
                 }
+
+                let config_state = ConfigState {
+                    id: None,
+                    config_id: config.id.unwrap(),
+                    is_running: true,
+                };
+                if let Err(e) = update_config_state(&config_state).await {
+                    error!("Failed to update config state: {}", e);
+                }
+
+                responses.push(CustomResponse {
+                    id: config.id,
+                    service: config.service.clone().unwrap(),
+                    namespace: namespace.clone(),
+                    local_port: actual_local_port,
+                    remote_port: config.remote_port.unwrap_or_default(),
+                    context: config.context.clone(),
+                    protocol: config.protocol.clone(),
+                    stdout: format!(
+                        "{} forwarding from 127.0.0.1:{} -> {:?}:{}",
+                        protocol.to_uppercase(),
+                        actual_local_port,
+                        config.remote_port.unwrap_or_default(),
+                        config.service.clone().unwrap()
+                    ),
+                    stderr: String::new(),
+                    status: 0,
+                });
             }
             Err(e) => {
                 let error_message = format!(
-                    "Failed to create PortForward for {} {}: {}",
+                    "Failed to start {} port forwarding for {} {}: {}",
+                    protocol.to_uppercase(),
                     if config.workload_type.as_deref() == Some("pod") {
                         "pod label"
                     } else {
@@ -229,3 +267,24 @@ pub async fn start_port_forward(
 
     Ok(responses)
 }
+
+/// Loads the cassette of a TCP config in replay mode, which is then served
+/// without creating a cluster client.
+async fn replay_player(
+    http_log_state: &HttpLogState, config: &Config, protocol: &str,
+) -> anyhow::Result<Option<CassettePlayer>> {
+    if protocol != "tcp" {
+        return Ok(None);
+    }
+
+    match http_log_state
+        .get_cassette(config.id.unwrap_or_default())
+        .await?
+    {
+        Some(cassette) if cassette.mode == CassetteMode::Replay => {
+            info!("Replaying cassette {}", cassette.path.display());
+            CassettePlayer::load(&cassette.path).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
@@ -33,8 +33,10 @@ use crate::hostsfile::{
     remove_all_host_entries,
     remove_host_entry,
 };
+use crate::kube::proxy_workload::delete_proxy_workloads;
 use crate::port_forward::{
-    CANCEL_NOTIFIER,
+    cancel_all_connections,
+    cancel_notifier,
     CHILD_PROCESSES,
 };
 
@@ -42,7 +44,7 @@ pub async fn stop_all_port_forward() -> Result<Vec<CustomResponse>, String> {
     info!("Attempting to stop all port forwards");
 
     let mut responses = Vec::with_capacity(1024);
-    CANCEL_NOTIFIER.notify_waiters();
+    cancel_all_connections();
 
     let handle_map: HashMap<String, JoinHandle<()>> = {
         let mut processes = CHILD_PROCESSES.lock().unwrap();
@@ -153,7 +155,11 @@ pub async fn stop_all_port_forward() -> Result<Vec<CustomResponse>, String> {
         .iter()
         .filter(|config| running_configs_state.contains(&config.id.unwrap_or_default()))
         .filter(|config| {
-            config.protocol == "udp" || matches!(config.workload_type.as_deref(), Some("proxy"))
+            config.protocol == "udp"
+                || matches!(
+                    config.workload_type.as_deref(),
+                    Some("proxy") | Some("reverse")
+                )
         })
         .filter_map(|config| {
             config
@@ -171,6 +177,15 @@ pub async fn stop_all_port_forward() -> Result<Vec<CustomResponse>, String> {
                 .await
                 {
                     Ok((Some(client), _, _)) => {
+                        if let Err(e) =
+                            delete_proxy_workloads(client.clone(), None, config_id_str).await
+                        {
+                            error!(
+                                "Failed to delete proxy workloads for config_id {}: {}",
+                                config_id_str, e
+                            );
+                        }
+
                         let pods: Api<Pod> = Api::all(client.clone());
                         let lp =
                             ListParams::default().labels(&format!("config_id={}", config_id_str));
@@ -229,6 +244,7 @@ pub async fn stop_all_port_forward() -> Result<Vec<CustomResponse>, String> {
         .collect();
 
     pod_deletion_tasks.collect::<Vec<_>>().await;
+    super::shared_proxy::shutdown_shared_proxies().await;
 
     let update_config_tasks: FuturesUnordered<_> = configs
         .iter()
@@ -267,8 +283,9 @@ pub async fn stop_all_port_forward() -> Result<Vec<CustomResponse>, String> {
 }
 
 pub async fn stop_port_forward(config_id: String) -> Result<CustomResponse, String> {
-    let cancellation_notifier = CANCEL_NOTIFIER.clone();
-    cancellation_notifier.notify_waiters();
+    if let Ok(id) = config_id.parse::<i64>() {
+        cancel_notifier(id).notify_waiters();
+    }
 
     let composite_key = {
         let child_processes = CHILD_PROCESSES.lock().unwrap();
@@ -1,23 +1,75 @@
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
-use kftray_http_logs::HttpLogState;
+use kftray_http_logs::http2::detect_http2;
+use kftray_http_logs::mock::{
+    rewrite_head,
+    PendingBody,
+};
+use kftray_http_logs::websocket::frames_after_upgrade;
+use kftray_http_logs::{
+    CassetteMode,
+    HeaderRewrite,
+    Http2ConnectionHandler,
+    HttpLogState,
+    LogConfig,
+    LogFilter,
+    MockDecision,
+    MockEngine,
+    ProtocolConnectionHandler,
+    WebSocketConnectionHandler,
+};
 use tokio::io::{
     AsyncReadExt,
     AsyncWriteExt,
 };
 use tokio::net::TcpStream;
+use tokio::sync::mpsc;
 use tokio::sync::Mutex;
 use tokio::sync::Notify;
-use tokio::time::timeout;
+use tokio::time::{
+    sleep,
+    timeout,
+};
 use tracing::{
     debug,
     error,
 };
 
+use crate::kube::fault::FaultInjector;
 use crate::Logger;
 
 const BUFFER_SIZE: usize = 131072;
+const MAX_PENDING_DETECTION_BYTES: usize = 65536;
+
+/// Protocol spoken on a logged connection, decided from the first bytes the
+/// client sends. Server bytes seen before the decision are held back so an
+/// HTTP/2 session can be fed from the start of the stream. HTTP/1 connections
+/// switch to WebSocket after a successful upgrade. Configs logged as a
+/// database or cache protocol skip detection and use a `Decoder`.
+enum ConnectionProtocol {
+    Detecting { client: Vec<u8>, server: Vec<u8> },
+    Http1,
+    Http2(Arc<Http2ConnectionHandler>),
+    WebSocket(Arc<WebSocketConnectionHandler>),
+    Decoder(Arc<ProtocolConnectionHandler>),
+    Passthrough,
+}
+
+enum ProtocolDecision {
+    Pending,
+    Http1 { buffered: Option<Vec<u8>> },
+    Handled,
+}
+
+/// Mock rules of a connection. Canned responses are handed to the upstream
+/// to client task, which owns the client writer.
+struct ConnectionMocks {
+    engine: MockEngine,
+    responses: mpsc::UnboundedSender<Vec<u8>>,
+    response_rewrite: Mutex<Option<HeaderRewrite>>,
+}
 
 #[derive(Clone)]
 pub struct TcpForwarder {
@@ -42,16 +94,35 @@ impl TcpForwarder {
             return Ok(());
         }
 
-        match http_log_state.get_http_logs(self.config_id).await {
+        let cassette = self.recording_cassette(http_log_state).await;
+        let capture = http_log_state
+            .get_http_logs(self.config_id)
+            .await
+            .map(|enabled| enabled || cassette.is_some());
+
+        match capture {
             Ok(true) => {
                 if self.logger.is_none() {
                     debug!(
                         "Initializing HTTP logger for config_id {} on port {}",
                         self.config_id, local_port
                     );
-                    let logger =
-                        kftray_http_logs::HttpLogger::for_config(self.config_id, local_port)
-                            .await?;
+                    let log_config = LogConfig::builder(LogConfig::default_log_directory()?)
+                        .redaction(http_log_state.get_redaction_config(self.config_id).await?)
+                        .grpc_descriptor_set(
+                            http_log_state
+                                .get_grpc_descriptor_set(self.config_id)
+                                .await?,
+                        )
+                        .cassette(cassette)
+                        .config_disk_quotas(http_log_state.disk_quotas().await)
+                        .build();
+                    let logger = kftray_http_logs::HttpLogger::for_config_with_log_config(
+                        self.config_id,
+                        local_port,
+                        log_config,
+                    )
+                    .await?;
                     self.logger = Some(logger);
                 }
             }
@@ -72,6 +143,29 @@ impl TcpForwarder {
         Ok(())
     }
 
+    /// Cassette path of a config in record mode. Recording needs the HTTP
+    /// logger even when HTTP logs are turned off.
+    async fn recording_cassette(&self, http_log_state: &HttpLogState) -> Option<PathBuf> {
+        match http_log_state.get_cassette(self.config_id).await {
+            Ok(Some(cassette)) if cassette.mode == CassetteMode::Record => Some(cassette.path),
+            Ok(_) => None,
+            Err(e) => {
+                error!("Failed to get cassette: {:?}", e);
+                None
+            }
+        }
+    }
+
+    async fn capture_enabled(&self, http_log_state: &HttpLogState) -> bool {
+        match http_log_state.get_http_logs(self.config_id).await {
+            Ok(enabled) => enabled || self.recording_cassette(http_log_state).await.is_some(),
+            Err(e) => {
+                error!("Failed to check HTTP logging state: {:?}", e);
+                false
+            }
+        }
+    }
+
     pub async fn update_logger_state(
         &mut self, http_log_state: &HttpLogState, local_port: u16,
     ) -> anyhow::Result<()> {
@@ -84,6 +178,20 @@ impl TcpForwarder {
         http_log_state: Arc<HttpLogState>, cancel_notifier: Arc<Notify>, _local_port: u16,
     ) -> anyhow::Result<()> {
         let request_id = Arc::new(Mutex::new(None));
+        let log_filter = Arc::new(self.load_log_filter(&http_log_state).await);
+        let protocol = Arc::new(Mutex::new(self.initial_protocol(&http_log_state).await));
+        let (mocks, mock_responses) = match self.load_mock_engine(&http_log_state).await {
+            Some(engine) => {
+                let (responses, mock_responses) = mpsc::unbounded_channel();
+                let mocks = ConnectionMocks {
+                    engine,
+                    responses,
+                    response_rewrite: Mutex::new(None),
+                };
+                (Some(Arc::new(mocks)), Some(mock_responses))
+            }
+            None => (None, None),
+        };
 
         let mut client_conn_guard = client_conn.lock().await;
         client_conn_guard.set_nodelay(true)?;
@@ -97,6 +205,9 @@ impl TcpForwarder {
             self.logger.clone(),
             &http_log_state,
             Arc::clone(&request_id),
+            Arc::clone(&log_filter),
+            Arc::clone(&protocol),
+            mocks.clone(),
             cancel_notifier.clone(),
         );
 
@@ -106,14 +217,31 @@ impl TcpForwarder {
             self.logger.clone(),
             &http_log_state,
             Arc::clone(&request_id),
+            Arc::clone(&log_filter),
+            Arc::clone(&protocol),
+            mocks,
+            mock_responses,
             cancel_notifier.clone(),
         );
 
-        match tokio::try_join!(client_to_upstream, upstream_to_client) {
+        let result = tokio::try_join!(client_to_upstream, upstream_to_client);
+        drop((client_reader, client_writer));
+
+        if let ConnectionProtocol::Http2(handler) = &*protocol.lock().await {
+            handler.finish().await;
+        }
+
+        match result {
             Ok(_) => {
                 debug!("Connection closed normally");
             }
             Err(e) => {
+                if Self::is_connection_reset(&e) {
+                    // Close with an RST so the client sees a reset.
+                    if let Err(e) = client_conn_guard.set_linger(Some(Duration::ZERO)) {
+                        debug!("Failed to set linger on client connection: {:?}", e);
+                    }
+                }
                 error!(
                     error = e.as_ref() as &dyn std::error::Error,
                     "Connection closed with error"
@@ -125,23 +253,246 @@ impl TcpForwarder {
         Ok(())
     }
 
+    fn is_connection_reset(error: &anyhow::Error) -> bool {
+        error
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::ConnectionReset)
+    }
+
+    async fn load_log_filter(&self, http_log_state: &HttpLogState) -> LogFilter {
+        let filter_config = match http_log_state.get_filter_config(self.config_id).await {
+            Ok(filter_config) => filter_config,
+            Err(e) => {
+                error!("Failed to get HTTP log filter: {:?}", e);
+                return LogFilter::default();
+            }
+        };
+
+        LogFilter::compile(&filter_config).unwrap_or_else(|e| {
+            error!("Invalid HTTP log filter, logging everything: {:?}", e);
+            LogFilter::default()
+        })
+    }
+
+    async fn load_mock_engine(&self, http_log_state: &HttpLogState) -> Option<MockEngine> {
+        let rules = match http_log_state.get_mock_rules(self.config_id).await {
+            Ok(rules) => rules,
+            Err(e) => {
+                error!("Failed to get mock rules: {:?}", e);
+                return None;
+            }
+        };
+
+        match MockEngine::compile(&rules) {
+            Ok(engine) if !engine.is_empty() => Some(engine),
+            Ok(_) => None,
+            Err(e) => {
+                error!("Invalid mock rules, forwarding unchanged: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// Only HTTP/1 request heads are matched against mock rules.
+    async fn evaluate_mock(
+        mocks: &ConnectionMocks, protocol: &Mutex<ConnectionProtocol>, request: &[u8],
+    ) -> Option<MockDecision> {
+        if detect_http2(request) != Some(false) {
+            return None;
+        }
+
+        if !matches!(
+            &*protocol.lock().await,
+            ConnectionProtocol::Detecting { .. } | ConnectionProtocol::Http1
+        ) {
+            return None;
+        }
+
+        mocks.engine.evaluate(request)
+    }
+
+    async fn next_mock_response(
+        responses: &mut Option<mpsc::UnboundedReceiver<Vec<u8>>>,
+    ) -> Option<Vec<u8>> {
+        match responses {
+            Some(responses) => responses.recv().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    async fn initial_protocol(&self, http_log_state: &HttpLogState) -> ConnectionProtocol {
+        let detecting = ConnectionProtocol::Detecting {
+            client: Vec::new(),
+            server: Vec::new(),
+        };
+
+        let Some(logger) = &self.logger else {
+            return detecting;
+        };
+
+        let log_protocol = match http_log_state.get_log_protocol(self.config_id).await {
+            Ok(log_protocol) => log_protocol,
+            Err(e) => {
+                error!("Failed to get log protocol: {:?}", e);
+                return detecting;
+            }
+        };
+
+        match log_protocol.decoder() {
+            Some(decoder) => {
+                debug!("Logging connection as {}", log_protocol);
+                ConnectionProtocol::Decoder(Arc::new(ProtocolConnectionHandler::new(
+                    logger.clone(),
+                    decoder,
+                )))
+            }
+            None => detecting,
+        }
+    }
+
+    async fn observe_client_protocol(
+        protocol: &Mutex<ConnectionProtocol>, data: &[u8], logger: &Logger,
+        log_filter: &Arc<LogFilter>,
+    ) -> ProtocolDecision {
+        let mut guard = protocol.lock().await;
+
+        let (client, server) = match &mut *guard {
+            ConnectionProtocol::Http1 => return ProtocolDecision::Http1 { buffered: None },
+            ConnectionProtocol::Http2(handler) => {
+                let handler = Arc::clone(handler);
+                drop(guard);
+                handler.handle_client_data(data).await;
+                return ProtocolDecision::Handled;
+            }
+            ConnectionProtocol::WebSocket(handler) => {
+                let handler = Arc::clone(handler);
+                drop(guard);
+                handler.handle_client_data(data).await;
+                return ProtocolDecision::Handled;
+            }
+            ConnectionProtocol::Decoder(handler) => {
+                let handler = Arc::clone(handler);
+                drop(guard);
+                handler.handle_client_data(data).await;
+                return ProtocolDecision::Handled;
+            }
+            ConnectionProtocol::Passthrough => return ProtocolDecision::Handled,
+            ConnectionProtocol::Detecting { client, server } => {
+                client.extend_from_slice(data);
+                (client, server)
+            }
+        };
+
+        match detect_http2(client) {
+            None if client.len() < MAX_PENDING_DETECTION_BYTES => ProtocolDecision::Pending,
+            Some(true) => {
+                debug!("Detected HTTP/2 connection preface");
+                let handler = Arc::new(Http2ConnectionHandler::new(
+                    logger.clone(),
+                    Arc::clone(log_filter),
+                ));
+                let client = std::mem::take(client);
+                let server = std::mem::take(server);
+                *guard = ConnectionProtocol::Http2(Arc::clone(&handler));
+                drop(guard);
+
+                handler.handle_client_data(&client).await;
+                handler.handle_server_data(&server).await;
+                ProtocolDecision::Handled
+            }
+            _ => {
+                let buffered = (client.len() > data.len()).then(|| std::mem::take(client));
+                *guard = ConnectionProtocol::Http1;
+                ProtocolDecision::Http1 { buffered }
+            }
+        }
+    }
+
+    async fn observe_server_protocol(
+        protocol: &Mutex<ConnectionProtocol>, data: &[u8],
+    ) -> ProtocolDecision {
+        let mut guard = protocol.lock().await;
+
+        match &mut *guard {
+            ConnectionProtocol::Http1 => ProtocolDecision::Http1 { buffered: None },
+            ConnectionProtocol::Http2(handler) => {
+                let handler = Arc::clone(handler);
+                drop(guard);
+                handler.handle_server_data(data).await;
+                ProtocolDecision::Handled
+            }
+            ConnectionProtocol::WebSocket(handler) => {
+                let handler = Arc::clone(handler);
+                drop(guard);
+                handler.handle_server_data(data).await;
+                ProtocolDecision::Handled
+            }
+            ConnectionProtocol::Decoder(handler) => {
+                let handler = Arc::clone(handler);
+                drop(guard);
+                handler.handle_server_data(data).await;
+                ProtocolDecision::Handled
+            }
+            ConnectionProtocol::Passthrough => ProtocolDecision::Handled,
+            ConnectionProtocol::Detecting { server, .. } => {
+                if server.len() + data.len() <= MAX_PENDING_DETECTION_BYTES {
+                    server.extend_from_slice(data);
+                }
+                ProtocolDecision::Pending
+            }
+        }
+    }
+
+    /// Switches the connection to WebSocket frame logging once the response
+    /// to an upgrade request has been handled.
+    async fn switch_after_upgrade(
+        protocol: &Mutex<ConnectionProtocol>, response: &[u8], logger: &Logger,
+        trace_id: Option<&str>,
+    ) -> bool {
+        let Some(frames) = frames_after_upgrade(response) else {
+            return false;
+        };
+
+        let mut guard = protocol.lock().await;
+        match trace_id {
+            Some(trace_id) => {
+                debug!(
+                    "WebSocket upgrade completed, logging frames for trace {}",
+                    trace_id
+                );
+                let handler = Arc::new(WebSocketConnectionHandler::new(
+                    logger.clone(),
+                    trace_id.to_string(),
+                ));
+                *guard = ConnectionProtocol::WebSocket(Arc::clone(&handler));
+                drop(guard);
+
+                if !frames.is_empty() {
+                    handler.handle_server_data(frames).await;
+                }
+            }
+            None => *guard = ConnectionProtocol::Passthrough,
+        }
+
+        true
+    }
+
+    #[allow(clippy::too_many_arguments)]
     async fn handle_client_to_upstream<'a>(
         &'a self, client_reader: &'a mut (impl AsyncReadExt + Unpin),
         upstream_writer: &'a mut (impl AsyncWriteExt + Unpin), logger: Option<Logger>,
         http_log_state: &HttpLogState, request_id: Arc<Mutex<Option<String>>>,
-        cancel_notifier: Arc<Notify>,
+        log_filter: Arc<LogFilter>, protocol: Arc<Mutex<ConnectionProtocol>>,
+        mocks: Option<Arc<ConnectionMocks>>, cancel_notifier: Arc<Notify>,
     ) -> anyhow::Result<()> {
         let mut buffer = [0; BUFFER_SIZE];
         let mut timeout_duration = Duration::from_secs(600);
         let mut request_buffer = Vec::new();
+        let mut request_passes_filter = true;
+        let mut faults = FaultInjector::new(self.config_id);
+        let mut mocked_body = PendingBody::None;
 
-        let logging_enabled = match http_log_state.get_http_logs(self.config_id).await {
-            Ok(enabled) => enabled,
-            Err(e) => {
-                error!("Failed to check HTTP logging state: {:?}", e);
-                false
-            }
-        };
+        let logging_enabled = self.capture_enabled(http_log_state).await;
 
         // Only proceed with logging if both enabled and logger is available
         let should_log = logging_enabled && logger.is_some();
@@ -171,17 +522,83 @@ impl TcpForwarder {
                     debug!("Read {} bytes from client", n);
                     request_buffer.extend_from_slice(&buffer[..n]);
 
+                    if let Some(mocks) = &mocks {
+                        if mocked_body.is_pending() {
+                            request_buffer = mocked_body.consume(&request_buffer).to_vec();
+                            if request_buffer.is_empty() {
+                                continue;
+                            }
+                        }
+
+                        if let Some(decision) = Self::evaluate_mock(mocks, &protocol, &request_buffer).await {
+                            if let Some(delay) = decision.delay {
+                                sleep(delay).await;
+                            }
+
+                            if let Some(response) = decision.response {
+                                debug!("Answering request with mock rule '{}'", decision.rule);
+                                if should_log {
+                                    if let Some(logger) = &logger {
+                                        if log_filter.should_log_request(&request_buffer) != Some(false) {
+                                            let mock_request_id = logger.log_request(request_buffer.clone().into()).await;
+                                            logger.log_mocked_response(response.clone().into(), mock_request_id, &decision.rule).await;
+                                        }
+                                    }
+                                }
+
+                                mocked_body = PendingBody::after_request(&request_buffer);
+                                request_buffer.clear();
+                                if mocks.responses.send(response).is_err() {
+                                    debug!("Client writer closed, dropping mocked response");
+                                    break;
+                                }
+                                continue;
+                            }
+
+                            if let Some(rewritten) = rewrite_head(&request_buffer, &decision.request_headers) {
+                                request_buffer = rewritten;
+                            }
+                            *mocks.response_rewrite.lock().await =
+                                (!decision.response_headers.is_empty()).then_some(decision.response_headers);
+                        }
+                    }
+
                     // Only log if HTTP logging is enabled and we have a logger
                     if should_log {
                         if let Some(logger) = &logger {
+                            let log_buffer = match Self::observe_client_protocol(&protocol, &request_buffer, logger, &log_filter).await {
+                                ProtocolDecision::Http1 { buffered } => buffered.unwrap_or_else(|| request_buffer.clone()),
+                                ProtocolDecision::Pending | ProtocolDecision::Handled => {
+                                    if let Err(e) = faults.write_all(upstream_writer, &request_buffer).await {
+                                        error!("Error writing to upstream: {:?}", e);
+                                        return Err(e.into());
+                                    }
+                                    request_buffer.clear();
+                                    continue;
+                                }
+                            };
+
+                            if let Some(passes) = log_filter.should_log_request(&log_buffer) {
+                                request_passes_filter = passes;
+                            }
+
                             let mut req_id_guard = request_id.lock().await;
-                            let new_request_id = logger.log_request(request_buffer.clone().into()).await;
-                            debug!("Generated new request ID: {}", new_request_id);
-                            *req_id_guard = Some(new_request_id);
+                            if request_passes_filter {
+                                let new_request_id = if log_filter.has_response_rules() {
+                                    logger.log_request_deferred(log_buffer.into()).await
+                                } else {
+                                    logger.log_request(log_buffer.into()).await
+                                };
+                                debug!("Generated new request ID: {}", new_request_id);
+                                *req_id_guard = Some(new_request_id);
+                            } else {
+                                debug!("Request skipped by HTTP log filter");
+                                *req_id_guard = logger.skip_request(log_buffer.into());
+                            }
                         }
                     }
 
-                    if let Err(e) = upstream_writer.write_all(&request_buffer).await {
+                    if let Err(e) = faults.write_all(upstream_writer, &request_buffer).await {
                         error!("Error writing to upstream: {:?}", e);
                         return Err(e.into());
                     }
@@ -203,15 +620,19 @@ impl TcpForwarder {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn handle_upstream_to_client<'a>(
         &'a self, upstream_reader: &'a mut (impl AsyncReadExt + Unpin),
         client_writer: &'a mut (impl AsyncWriteExt + Unpin), logger: Option<Logger>,
         http_log_state: &HttpLogState, request_id: Arc<Mutex<Option<String>>>,
-        cancel_notifier: Arc<Notify>,
+        log_filter: Arc<LogFilter>, protocol: Arc<Mutex<ConnectionProtocol>>,
+        mocks: Option<Arc<ConnectionMocks>>,
+        mut mock_responses: Option<mpsc::UnboundedReceiver<Vec<u8>>>, cancel_notifier: Arc<Notify>,
     ) -> anyhow::Result<()> {
         let mut buffer = [0; BUFFER_SIZE];
         let mut timeout_duration = Duration::from_secs(600);
         let mut response_buffer = Vec::new();
+        let mut faults = FaultInjector::new(self.config_id);
 
         let mut is_chunked = false;
         let mut found_end_marker = false;
@@ -223,13 +644,7 @@ impl TcpForwarder {
         let mut first_chunk_time: Option<tokio::time::Instant> = None;
         let mut force_log_time: Option<tokio::time::Instant> = None;
 
-        let logging_enabled = match http_log_state.get_http_logs(self.config_id).await {
-            Ok(enabled) => enabled,
-            Err(e) => {
-                error!("Failed to check HTTP logging state: {:?}", e);
-                false
-            }
-        };
+        let logging_enabled = self.capture_enabled(http_log_state).await;
 
         let should_log = logging_enabled && logger.is_some();
         if should_log {
@@ -256,11 +671,16 @@ impl TcpForwarder {
                             if let Some(logger) = &logger {
                                 let req_id_guard = request_id.lock().await;
                                 if let Some(req_id) = &*req_id_guard {
-                                    debug!("Connection closed, logging final response data for request ID: {}", req_id);
-                                    let buffer_for_logging = response_buffer.clone();
-                                    logger
-                                        .log_response(buffer_for_logging.into(), req_id.clone())
-                                        .await;
+                                    if !logger.is_skipped(req_id) && log_filter.should_log_response(&response_buffer) {
+                                        debug!("Connection closed, logging final response data for request ID: {}", req_id);
+                                        let buffer_for_logging = response_buffer.clone();
+                                        logger
+                                            .log_response(buffer_for_logging.into(), req_id.clone())
+                                            .await;
+                                    } else {
+                                        debug!("Response for request ID {} skipped by HTTP log filter", req_id);
+                                        logger.skip_response(response_buffer.clone().into(), req_id).await;
+                                    }
                                 }
                                 drop(req_id_guard);
                             }
@@ -270,6 +690,28 @@ impl TcpForwarder {
 
                     debug!("Read {} bytes from upstream", n);
 
+                    let rewritten = match &mocks {
+                        Some(mocks) if buffer[..n].starts_with(b"HTTP/") => mocks
+                            .response_rewrite
+                            .lock()
+                            .await
+                            .take()
+                            .and_then(|rewrite| rewrite_head(&buffer[..n], &rewrite)),
+                        _ => None,
+                    };
+                    let data = rewritten.as_deref().unwrap_or(&buffer[..n]);
+
+                    if should_log {
+                        let decision = Self::observe_server_protocol(&protocol, data).await;
+                        if !matches!(decision, ProtocolDecision::Http1 { .. }) {
+                            if let Err(e) = faults.write_all(client_writer, data).await {
+                                error!("Error writing to client: {:?}", e);
+                                return Err(e.into());
+                            }
+                            continue;
+                        }
+                    }
+
                     let req_id_guard = request_id.lock().await;
                     let current_req_id = req_id_guard.clone();
                     drop(req_id_guard);
@@ -280,6 +722,12 @@ impl TcpForwarder {
                         _ => false
                     };
 
+                    if current_req_id.is_none() && current_response_id.is_some() {
+                        debug!("Current request is not being logged, ignoring its response");
+                        current_response_id = None;
+                        response_buffer.clear();
+                    }
+
                     if is_new_response {
                         debug!("Detected new response for request ID: {:?}", current_req_id);
                         response_buffer.clear();
@@ -297,20 +745,20 @@ impl TcpForwarder {
                             first_chunk_time = Some(tokio::time::Instant::now());
                         }
 
-                        is_chunked = kftray_http_logs::http_response_analyzer::HttpResponseAnalyzer::detect_chunked_encoding(&buffer[..n]);
+                        is_chunked = kftray_http_logs::http_response_analyzer::HttpResponseAnalyzer::detect_chunked_encoding(data);
                         if is_chunked {
                             debug!("Detected chunked encoding in response");
                         }
                     }
 
                     kftray_http_logs::http_response_analyzer::HttpResponseAnalyzer::process_chunk(
-                        &buffer[..n],
+                        data,
                         is_chunked,
                         &mut found_end_marker,
                         &mut total_chunks_received
                     );
 
-                    response_buffer.extend_from_slice(&buffer[..n]);
+                    response_buffer.extend_from_slice(data);
 
                     if !current_response_logged && should_log {
                         if let Some(logger) = &logger {
@@ -373,13 +821,30 @@ impl TcpForwarder {
 
                                 current_response_logged = true;
 
-                                let buffer_for_logging = response_buffer.clone();
+                                let response_logged = !logger.is_skipped(&response_id)
+                                    && log_filter.should_log_response(&response_buffer);
+                                if response_logged {
+                                    let buffer_for_logging = response_buffer.clone();
 
-                                logger
-                                    .log_response(buffer_for_logging.into(), response_id.clone())
-                                    .await;
+                                    logger
+                                        .log_response(buffer_for_logging.into(), response_id.clone())
+                                        .await;
 
-                                debug!("Response successfully logged for ID: {}", response_id);
+                                    debug!("Response successfully logged for ID: {}", response_id);
+                                } else {
+                                    debug!("Response for request ID {} skipped by HTTP log filter", response_id);
+                                    logger.skip_response(response_buffer.clone().into(), &response_id).await;
+                                }
+
+                                let trace_id = response_logged.then_some(response_id.as_str());
+                                if Self::switch_after_upgrade(&protocol, &response_buffer, logger, trace_id).await {
+                                    response_buffer.clear();
+                                    if let Err(e) = faults.write_all(client_writer, data).await {
+                                        error!("Error writing to client: {:?}", e);
+                                        return Err(e.into());
+                                    }
+                                    continue;
+                                }
 
 
                                 let can_clear_buffer = if is_chunked {
@@ -403,11 +868,17 @@ impl TcpForwarder {
                         }
                     }
 
-                    if let Err(e) = client_writer.write_all(&buffer[..n]).await {
+                    if let Err(e) = faults.write_all(client_writer, data).await {
                         error!("Error writing to client: {:?}", e);
                         return Err(e.into());
                     }
                 },
+                Some(response) = Self::next_mock_response(&mut mock_responses) => {
+                    if let Err(e) = faults.write_all(client_writer, &response).await {
+                        error!("Error writing mocked response to client: {:?}", e);
+                        return Err(e.into());
+                    }
+                },
                 _ = cancel_notifier.notified() => {
                     debug!("Upstream to client task cancelled");
                     break;
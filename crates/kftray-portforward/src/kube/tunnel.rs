@@ -0,0 +1,352 @@
+//! Frame codec of the reverse tunnel and the shared proxy, kept in sync with
+//! `kftray-server/src/proxy/tunnel.rs`.
+//!
+//! Every frame is a 9 byte header (kind, stream id and payload length, both
+//! big endian `u32`) followed by the payload. Shared proxy streams name their
+//! destination in the open frame as protocol (1) + port (2) + host (UTF-8).
+//! UDP proxy sessions use the same frames, with the session id as stream id.
+
+use std::io;
+use std::time::Duration;
+
+use tokio::io::{
+    AsyncRead,
+    AsyncReadExt,
+    AsyncWrite,
+    AsyncWriteExt,
+    WriteHalf,
+};
+use tokio::net::tcp::{
+    OwnedReadHalf,
+    OwnedWriteHalf,
+};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+const FRAME_HEADER_SIZE: usize = 9;
+const MAX_FRAME_PAYLOAD: usize = 1024 * 1024;
+const BUFFER_SIZE: usize = 65536;
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Open = 1,
+    Data = 2,
+    Close = 3,
+    Ping = 4,
+}
+
+impl TryFrom<u8> for FrameKind {
+    type Error = io::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(FrameKind::Open),
+            2 => Ok(FrameKind::Data),
+            3 => Ok(FrameKind::Close),
+            4 => Ok(FrameKind::Ping),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown frame kind: {}", other),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DestinationProtocol {
+    Tcp = 1,
+    Udp = 2,
+}
+
+/// Target of a shared proxy stream, sent as the payload of its open frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Destination {
+    pub protocol: DestinationProtocol,
+    pub host: String,
+    pub port: u16,
+}
+
+impl Destination {
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(3 + self.host.len());
+        buf.push(self.protocol as u8);
+        buf.extend_from_slice(&self.port.to_be_bytes());
+        buf.extend_from_slice(self.host.as_bytes());
+        buf
+    }
+
+    #[cfg(test)]
+    fn decode(payload: &[u8]) -> io::Result<Self> {
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        if payload.len() < 4 {
+            return Err(invalid("Destination too short"));
+        }
+
+        let protocol = match payload[0] {
+            1 => DestinationProtocol::Tcp,
+            2 => DestinationProtocol::Udp,
+            _ => return Err(invalid("Unknown destination protocol")),
+        };
+        let port = u16::from_be_bytes([payload[1], payload[2]]);
+        let host = String::from_utf8(payload[3..].to_vec())
+            .map_err(|_| invalid("Destination host is not UTF-8"))?;
+
+        Ok(Self {
+            protocol,
+            host,
+            port,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub stream_id: u32,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn open(stream_id: u32) -> Self {
+        Self {
+            kind: FrameKind::Open,
+            stream_id,
+            payload: Vec::new(),
+        }
+    }
+
+    pub fn open_to(stream_id: u32, destination: &Destination) -> Self {
+        Self {
+            kind: FrameKind::Open,
+            stream_id,
+            payload: destination.encode(),
+        }
+    }
+
+    pub fn data(stream_id: u32, payload: Vec<u8>) -> Self {
+        Self {
+            kind: FrameKind::Data,
+            stream_id,
+            payload,
+        }
+    }
+
+    pub fn close(stream_id: u32) -> Self {
+        Self {
+            kind: FrameKind::Close,
+            stream_id,
+            payload: Vec::new(),
+        }
+    }
+
+    pub fn ping() -> Self {
+        Self {
+            kind: FrameKind::Ping,
+            stream_id: 0,
+            payload: Vec::new(),
+        }
+    }
+}
+
+/// Reads the next frame, returning `None` when the tunnel closed cleanly.
+pub async fn read_frame<R>(reader: &mut R) -> io::Result<Option<Frame>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut header = [0u8; FRAME_HEADER_SIZE];
+    match reader.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let kind = FrameKind::try_from(header[0])?;
+    let stream_id = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+    let len = u32::from_be_bytes([header[5], header[6], header[7], header[8]]) as usize;
+
+    if len > MAX_FRAME_PAYLOAD {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Frame payload too large: {} bytes", len),
+        ));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+
+    Ok(Some(Frame {
+        kind,
+        stream_id,
+        payload,
+    }))
+}
+
+pub async fn write_frame<W>(writer: &mut W, frame: &Frame) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = Vec::with_capacity(FRAME_HEADER_SIZE + frame.payload.len());
+    buf.push(frame.kind as u8);
+    buf.extend_from_slice(&frame.stream_id.to_be_bytes());
+    buf.extend_from_slice(&(frame.payload.len() as u32).to_be_bytes());
+    buf.extend_from_slice(&frame.payload);
+
+    writer.write_all(&buf).await?;
+    writer.flush().await
+}
+
+pub async fn write_frames<S>(
+    mut writer: WriteHalf<S>, mut frames: mpsc::Receiver<Frame>,
+) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    while let Some(frame) = frames.recv().await {
+        write_frame(&mut writer, &frame).await?;
+    }
+
+    Ok(())
+}
+
+/// Queues a ping every 30 seconds so idle port forwards are not dropped.
+pub fn spawn_keepalive(frames: mpsc::Sender<Frame>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PING_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if frames.send(Frame::ping()).await.is_err() {
+                break;
+            }
+        }
+    })
+}
+
+pub async fn pump_to_tunnel(
+    stream_id: u32, mut reader: OwnedReadHalf, frames: &mpsc::Sender<Frame>,
+) -> anyhow::Result<()> {
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+
+    loop {
+        let n = match reader.read(&mut buffer).await {
+            Ok(n) => n,
+            Err(e) => {
+                let _ = frames.send(Frame::close(stream_id)).await;
+                return Err(e.into());
+            }
+        };
+
+        let frame = if n == 0 {
+            Frame::close(stream_id)
+        } else {
+            Frame::data(stream_id, buffer[..n].to_vec())
+        };
+
+        frames
+            .send(frame)
+            .await
+            .map_err(|_| anyhow::anyhow!("Tunnel closed"))?;
+
+        if n == 0 {
+            return Ok(());
+        }
+    }
+}
+
+pub async fn pump_from_tunnel(
+    mut writer: OwnedWriteHalf, mut payloads: mpsc::Receiver<Vec<u8>>,
+) -> anyhow::Result<()> {
+    while let Some(payload) = payloads.recv().await {
+        writer.write_all(&payload).await?;
+    }
+
+    let _ = writer.shutdown().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn encode(frame: &Frame) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, frame).await.unwrap();
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_frame_layout_matches_server() {
+        let frame = Frame::data(0x0102_0304, b"hi".to_vec());
+
+        let bytes = encode(&frame).await;
+
+        // kind, stream id (big endian u32), payload length (big endian u32)
+        assert_eq!(bytes, [2, 1, 2, 3, 4, 0, 0, 0, 2, b'h', b'i']);
+        assert_eq!(
+            read_frame(&mut bytes.as_slice()).await.unwrap(),
+            Some(frame)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_frames_round_trip() {
+        let frames = vec![
+            Frame::open(1),
+            Frame::data(1, vec![0; 70_000]),
+            Frame::close(1),
+            Frame::ping(),
+        ];
+        let mut bytes = Vec::new();
+        for frame in &frames {
+            bytes.extend(encode(frame).await);
+        }
+
+        let mut reader = bytes.as_slice();
+        for frame in frames {
+            assert_eq!(read_frame(&mut reader).await.unwrap(), Some(frame));
+        }
+        assert_eq!(read_frame(&mut reader).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_read_frame_rejects_invalid_headers() {
+        let unknown_kind = [9, 0, 0, 0, 1, 0, 0, 0, 0];
+        let oversized = [2, 0, 0, 0, 1, 0, 0x10, 0, 1];
+
+        assert!(read_frame(&mut unknown_kind.as_slice()).await.is_err());
+        assert!(read_frame(&mut oversized.as_slice()).await.is_err());
+    }
+
+    #[test]
+    fn test_destination_layout_matches_server() {
+        let destination = Destination {
+            protocol: DestinationProtocol::Udp,
+            host: "dns".to_string(),
+            port: 53,
+        };
+
+        let frame = Frame::open_to(7, &destination);
+
+        // protocol (Tcp=1, Udp=2), port (big endian u16), UTF-8 host
+        assert_eq!(frame.payload, [2, 0, 53, b'd', b'n', b's']);
+        assert_eq!(Destination::decode(&frame.payload).unwrap(), destination);
+    }
+
+    #[test]
+    fn test_destination_round_trip() {
+        let destination = Destination {
+            protocol: DestinationProtocol::Tcp,
+            host: "api.default.svc.cluster.local".to_string(),
+            port: 8443,
+        };
+
+        let payload = destination.encode();
+
+        assert_eq!(&payload[..3], [1, 0x20, 0xfb]);
+        assert_eq!(Destination::decode(&payload).unwrap(), destination);
+        assert!(Destination::decode(&[3, 0, 80, b'x']).is_err());
+        assert!(Destination::decode(&[1, 0]).is_err());
+    }
+}
@@ -1,4 +1,7 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Context;
 use tokio::io::{
@@ -6,108 +9,262 @@ use tokio::io::{
     AsyncWriteExt,
 };
 use tokio::net::UdpSocket as TokioUdpSocket;
-use tokio::sync::Mutex;
+use tokio::sync::{
+    mpsc,
+    Mutex,
+};
+use tokio::time::{
+    interval,
+    sleep,
+    timeout,
+    Instant,
+};
 use tracing::{
+    debug,
     error,
     info,
+    warn,
+};
+
+use crate::kube::fault::{
+    packet_fate,
+    PacketFate,
+};
+use crate::kube::tunnel::{
+    read_frame,
+    write_frames,
+    Frame,
+    FrameKind,
 };
 
 const BUFFER_SIZE: usize = 131072;
+/// Handshake of the session framing, kept in sync with
+/// `kftray-server/src/proxy/udp.rs`.
+const SESSION_MAGIC: [u8; 4] = *b"KFUS";
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+pub(super) const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+pub(super) const SESSION_SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+pub(super) const MAX_SESSIONS: usize = 256;
+const FRAME_QUEUE_SIZE: usize = 256;
+
+/// A local UDP client, identified by its source address.
+struct UdpSession {
+    peer: SocketAddr,
+    last_active: Instant,
+}
+
+/// Maps local UDP clients to the session ids they use on the tunnel.
+#[derive(Default)]
+struct UdpSessions {
+    by_peer: HashMap<SocketAddr, u32>,
+    by_id: HashMap<u32, UdpSession>,
+    next_id: u32,
+}
+
+impl UdpSessions {
+    /// Returns the session of a peer, opening one when it has none yet. The
+    /// least recently used session is evicted when the limit is reached.
+    fn session_for(&mut self, peer: SocketAddr, frames: &mut Vec<Frame>) -> u32 {
+        if let Some(&id) = self.by_peer.get(&peer) {
+            if let Some(session) = self.by_id.get_mut(&id) {
+                session.last_active = Instant::now();
+            }
+            return id;
+        }
+
+        if self.by_id.len() >= MAX_SESSIONS {
+            let oldest = self
+                .by_id
+                .iter()
+                .min_by_key(|(_, session)| session.last_active)
+                .map(|(&id, _)| id);
+            if let Some(id) = oldest {
+                debug!("UDP session limit reached, evicting session {}", id);
+                self.remove(id);
+                frames.push(Frame::close(id));
+            }
+        }
+
+        let id = loop {
+            self.next_id = self.next_id.wrapping_add(1);
+            if self.next_id != 0 && !self.by_id.contains_key(&self.next_id) {
+                break self.next_id;
+            }
+        };
+
+        debug!("Opening UDP session {} for {}", id, peer);
+        self.by_peer.insert(peer, id);
+        self.by_id.insert(
+            id,
+            UdpSession {
+                peer,
+                last_active: Instant::now(),
+            },
+        );
+        frames.push(Frame::open(id));
+        id
+    }
+
+    fn peer(&mut self, id: u32) -> Option<SocketAddr> {
+        let session = self.by_id.get_mut(&id)?;
+        session.last_active = Instant::now();
+        Some(session.peer)
+    }
+
+    fn remove(&mut self, id: u32) {
+        if let Some(session) = self.by_id.remove(&id) {
+            self.by_peer.remove(&session.peer);
+        }
+    }
+
+    /// Removes the sessions idle for longer than [`SESSION_IDLE_TIMEOUT`],
+    /// returning their ids.
+    fn expire_idle(&mut self) -> Vec<u32> {
+        let expired: Vec<u32> = self
+            .by_id
+            .iter()
+            .filter(|(_, session)| session.last_active.elapsed() >= SESSION_IDLE_TIMEOUT)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in &expired {
+            self.remove(*id);
+        }
+        expired
+    }
+}
 
 pub struct UdpForwarder;
 
 impl UdpForwarder {
-    pub async fn bind_and_forward(
-        local_address: String, local_port: u16,
-        upstream_conn: impl tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
-    ) -> anyhow::Result<(u16, tokio::task::JoinHandle<()>)> {
+    /// Binds the local UDP socket of a forward. The socket outlives the
+    /// tunnels to the pod, which are reopened when one closes.
+    pub async fn bind(local_address: &str, local_port: u16) -> anyhow::Result<Arc<TokioUdpSocket>> {
         let local_udp_addr = format!("{}:{}", local_address, local_port);
 
-        let local_udp_socket = Arc::new(
-            TokioUdpSocket::bind(&local_udp_addr)
-                .await
-                .context("Failed to bind local UDP socket")?,
-        );
-
-        let local_port = local_udp_socket.local_addr()?.port();
+        let local_udp_socket = TokioUdpSocket::bind(&local_udp_addr)
+            .await
+            .context("Failed to bind local UDP socket")?;
 
         info!("Local UDP socket bound to {}", local_udp_addr);
 
+        Ok(Arc::new(local_udp_socket))
+    }
+
+    /// Forwards a local UDP socket over a connection using the legacy
+    /// framing, where replies go to the last sender.
+    ///
+    /// Returns once the connection closes, or with an error when the local
+    /// socket fails.
+    pub async fn forward(
+        config_id: i64, local_udp_socket: Arc<TokioUdpSocket>,
+        upstream_conn: impl tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+    ) -> anyhow::Result<()> {
         let (tcp_read, tcp_write) = tokio::io::split(upstream_conn);
         let tcp_read = Arc::new(Mutex::new(tcp_read));
         let tcp_write = Arc::new(Mutex::new(tcp_write));
 
-        let local_udp_socket_read = local_udp_socket.clone();
-        let local_udp_socket_write = local_udp_socket;
-
-        let handle = tokio::spawn({
-            let tcp_read = tcp_read.clone();
-            let tcp_write = tcp_write.clone();
-            async move {
-                let mut udp_buffer = [0u8; BUFFER_SIZE];
-                let mut peer: Option<std::net::SocketAddr> = None;
-
-                loop {
-                    tokio::select! {
-                        result = local_udp_socket_read.recv_from(&mut udp_buffer) => {
-                            match result {
-                                Ok((len, src)) => {
-                                    peer = Some(src);
-                                    let mut writer = tcp_write.lock().await;
-
-                                    let packet_len = (len as u32).to_be_bytes();
-                                    if let Err(e) = writer.write_all(&packet_len).await {
-                                        error!("Failed to write packet length to TCP stream: {:?}", e);
-                                        break;
-                                    }
-                                    if let Err(e) = writer.write_all(&udp_buffer[..len]).await {
-                                        error!("Failed to write UDP packet to TCP stream: {:?}", e);
-                                        break;
-                                    }
-                                    if let Err(e) = writer.flush().await {
-                                        error!("Failed to flush TCP stream: {:?}", e);
-                                        break;
+        let mut udp_buffer = [0u8; BUFFER_SIZE];
+        let mut peer: Option<std::net::SocketAddr> = None;
+
+        let result = loop {
+            tokio::select! {
+                result = local_udp_socket.recv_from(&mut udp_buffer) => {
+                    match result {
+                        Ok((len, src)) => {
+                            peer = Some(src);
+
+                            match packet_fate(config_id) {
+                                PacketFate::Drop => {
+                                    debug!("Dropping UDP packet from {} by fault injection", src);
+                                }
+                                PacketFate::Deliver => {
+                                    if let Err(e) = Self::write_tcp_packet(&tcp_write, &udp_buffer[..len]).await {
+                                        error!("{:?}", e);
+                                        break Ok(());
                                     }
-                                },
-                                Err(e) => {
-                                    error!("Failed to receive from UDP socket: {:?}", e);
-                                    break;
+                                }
+                                PacketFate::DeliverAfter(delay) => {
+                                    let tcp_write = tcp_write.clone();
+                                    let packet = udp_buffer[..len].to_vec();
+                                    tokio::spawn(async move {
+                                        sleep(delay).await;
+                                        if let Err(e) = Self::write_tcp_packet(&tcp_write, &packet).await {
+                                            error!("{:?}", e);
+                                        }
+                                    });
                                 }
                             }
                         },
-                        result = async {
-                            let mut reader = tcp_read.lock().await;
-                            Self::read_tcp_length_and_packet(&mut *reader).await
-                        } => {
-                            match result {
-                                Ok(Some(packet)) => {
-                                    if let Some(peer) = peer {
-                                        if let Err(e) = local_udp_socket_write.send_to(&packet, &peer).await {
-                                            error!("Failed to send UDP packet to peer: {:?}", e);
-                                            break;
+                        Err(e) => {
+                            break Err(anyhow::Error::new(e).context("Failed to receive from UDP socket"));
+                        }
+                    }
+                },
+                result = async {
+                    let mut reader = tcp_read.lock().await;
+                    Self::read_tcp_length_and_packet(&mut *reader).await
+                } => {
+                    match result {
+                        Ok(Some(packet)) => {
+                            if let Some(peer) = peer {
+                                match packet_fate(config_id) {
+                                    PacketFate::Drop => {
+                                        debug!("Dropping UDP packet to {} by fault injection", peer);
+                                    }
+                                    PacketFate::Deliver => {
+                                        if let Err(e) = local_udp_socket.send_to(&packet, &peer).await {
+                                            break Err(anyhow::Error::new(e).context("Failed to send UDP packet to peer"));
                                         }
-                                    } else {
-                                        error!("No UDP peer to send to");
-                                        break;
                                     }
-                                },
-                                Ok(None) => break,
-                                Err(e) => {
-                                    error!("Failed to read from TCP stream: {:?}", e);
-                                    break;
+                                    PacketFate::DeliverAfter(delay) => {
+                                        let socket = local_udp_socket.clone();
+                                        tokio::spawn(async move {
+                                            sleep(delay).await;
+                                            if let Err(e) = socket.send_to(&packet, &peer).await {
+                                                error!("Failed to send delayed UDP packet to peer: {:?}", e);
+                                            }
+                                        });
+                                    }
                                 }
+                            } else {
+                                error!("No UDP peer to send to");
+                                break Ok(());
                             }
+                        },
+                        Ok(None) => break Ok(()),
+                        Err(e) => {
+                            error!("Failed to read from TCP stream: {:?}", e);
+                            break Ok(());
                         }
                     }
                 }
-
-                if let Err(e) = tcp_write.lock().await.shutdown().await {
-                    error!("Error shutting down TCP writer: {:?}", e);
-                }
             }
-        });
+        };
+
+        if let Err(e) = tcp_write.lock().await.shutdown().await {
+            error!("Error shutting down TCP writer: {:?}", e);
+        }
 
-        Ok((local_port, handle))
+        result
+    }
+
+    async fn write_tcp_packet(
+        tcp_write: &Mutex<impl AsyncWriteExt + Unpin>, packet: &[u8],
+    ) -> anyhow::Result<()> {
+        let mut writer = tcp_write.lock().await;
+
+        let packet_len = (packet.len() as u32).to_be_bytes();
+        writer
+            .write_all(&packet_len)
+            .await
+            .context("Failed to write packet length to TCP stream")?;
+        writer
+            .write_all(packet)
+            .await
+            .context("Failed to write UDP packet to TCP stream")?;
+        writer.flush().await.context("Failed to flush TCP stream")?;
+
+        Ok(())
     }
 
     async fn read_tcp_length_and_packet(
@@ -129,4 +286,223 @@ impl UdpForwarder {
 
         Ok(Some(packet))
     }
+
+    /// Asks the proxy server to switch the connection to session framing.
+    ///
+    /// Returns `false` when the server predates UDP sessions. Such a server
+    /// closes the connection, so the caller has to open a new one for the
+    /// legacy framing.
+    pub async fn negotiate_sessions(
+        upstream_conn: &mut (impl tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin),
+    ) -> anyhow::Result<bool> {
+        upstream_conn
+            .write_all(&SESSION_MAGIC)
+            .await
+            .context("Failed to write UDP session handshake")?;
+        upstream_conn.flush().await?;
+
+        let mut reply = [0u8; 4];
+        match timeout(HANDSHAKE_TIMEOUT, upstream_conn.read_exact(&mut reply)).await {
+            Ok(Ok(_)) => Ok(reply == SESSION_MAGIC),
+            Ok(Err(e)) => {
+                debug!("UDP session handshake failed: {:?}", e);
+                Ok(false)
+            }
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Forwards a local UDP socket over a connection already switched to
+    /// session framing by [`Self::negotiate_sessions`].
+    ///
+    /// Every source address gets its own session, so replies go back to the
+    /// client that sent the request even when several processes share the
+    /// forward. Sessions idle for a minute are closed, and the least recently
+    /// used one is evicted once [`MAX_SESSIONS`] are open.
+    ///
+    /// Returns once the connection closes, or with an error when the local
+    /// socket fails.
+    pub async fn forward_sessions(
+        config_id: i64, local_udp_socket: Arc<TokioUdpSocket>,
+        upstream_conn: impl tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+    ) -> anyhow::Result<()> {
+        let (mut tcp_read, tcp_write) = tokio::io::split(upstream_conn);
+        let (frames_tx, frames_rx) = mpsc::channel(FRAME_QUEUE_SIZE);
+        let writer = tokio::spawn(write_frames(tcp_write, frames_rx));
+
+        // Frames are read on their own task since a partially read frame
+        // would be lost if the read was cancelled by the select below
+        let (incoming_tx, mut incoming) = mpsc::channel(FRAME_QUEUE_SIZE);
+        let reader = tokio::spawn(async move {
+            loop {
+                match read_frame(&mut tcp_read).await {
+                    Ok(Some(frame)) => {
+                        if incoming_tx.send(frame).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        error!("Failed to read from TCP stream: {:?}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        let mut sessions = UdpSessions::default();
+        let mut sweep = interval(SESSION_SWEEP_INTERVAL);
+        let mut udp_buffer = vec![0u8; BUFFER_SIZE];
+
+        let result = loop {
+            tokio::select! {
+                result = local_udp_socket.recv_from(&mut udp_buffer) => {
+                    let (len, src) = match result {
+                        Ok(received) => received,
+                        Err(e) => {
+                            break Err(anyhow::Error::new(e).context("Failed to receive from UDP socket"));
+                        }
+                    };
+
+                    let mut frames = Vec::new();
+                    let id = sessions.session_for(src, &mut frames);
+
+                    match packet_fate(config_id) {
+                        PacketFate::Drop => {
+                            debug!("Dropping UDP packet from {} by fault injection", src);
+                        }
+                        PacketFate::Deliver => {
+                            frames.push(Frame::data(id, udp_buffer[..len].to_vec()));
+                        }
+                        PacketFate::DeliverAfter(delay) => {
+                            let frames_tx = frames_tx.clone();
+                            let packet = udp_buffer[..len].to_vec();
+                            tokio::spawn(async move {
+                                sleep(delay).await;
+                                let _ = frames_tx.send(Frame::data(id, packet)).await;
+                            });
+                        }
+                    }
+
+                    for frame in frames {
+                        if frames_tx.send(frame).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                frame = incoming.recv() => {
+                    let Some(frame) = frame else {
+                        break Ok(());
+                    };
+
+                    match frame.kind {
+                        FrameKind::Data => {
+                            let Some(peer) = sessions.peer(frame.stream_id) else {
+                                debug!("Dropping UDP packet for closed session {}", frame.stream_id);
+                                continue;
+                            };
+
+                            match packet_fate(config_id) {
+                                PacketFate::Drop => {
+                                    debug!("Dropping UDP packet to {} by fault injection", peer);
+                                }
+                                PacketFate::Deliver => {
+                                    if let Err(e) = local_udp_socket.send_to(&frame.payload, peer).await {
+                                        warn!("Failed to send UDP packet to {}: {:?}", peer, e);
+                                    }
+                                }
+                                PacketFate::DeliverAfter(delay) => {
+                                    let socket = local_udp_socket.clone();
+                                    tokio::spawn(async move {
+                                        sleep(delay).await;
+                                        if let Err(e) = socket.send_to(&frame.payload, peer).await {
+                                            error!("Failed to send delayed UDP packet to peer: {:?}", e);
+                                        }
+                                    });
+                                }
+                            }
+                        }
+                        FrameKind::Close => {
+                            debug!("UDP session {} closed by the proxy", frame.stream_id);
+                            sessions.remove(frame.stream_id);
+                        }
+                        FrameKind::Open | FrameKind::Ping => {}
+                    }
+                }
+                _ = sweep.tick() => {
+                    for id in sessions.expire_idle() {
+                        debug!("UDP session {} expired", id);
+                        let _ = frames_tx.send(Frame::close(id)).await;
+                    }
+                }
+            }
+        };
+
+        reader.abort();
+        drop(frames_tx);
+        if let Ok(Err(e)) = writer.await {
+            error!("Error writing to TCP stream: {:?}", e);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_forward_returns_when_tunnel_closes() {
+        let socket = UdpForwarder::bind("127.0.0.1", 0).await.unwrap();
+
+        let (upstream, proxy) = tokio::io::duplex(1024);
+        drop(proxy);
+        let result = timeout(
+            Duration::from_secs(5),
+            UdpForwarder::forward(-1, socket.clone(), upstream),
+        )
+        .await
+        .expect("forward did not return");
+        assert!(result.is_ok());
+
+        let (upstream, proxy) = tokio::io::duplex(1024);
+        drop(proxy);
+        let result = timeout(
+            Duration::from_secs(5),
+            UdpForwarder::forward_sessions(-1, socket, upstream),
+        )
+        .await
+        .expect("forward_sessions did not return");
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_socket_outlives_tunnel() {
+        let socket = UdpForwarder::bind("127.0.0.1", 0).await.unwrap();
+        let local_addr = socket.local_addr().unwrap();
+
+        let (upstream, proxy) = tokio::io::duplex(1024);
+        drop(proxy);
+        UdpForwarder::forward_sessions(-1, socket.clone(), upstream)
+            .await
+            .unwrap();
+
+        // A reopened tunnel picks up packets sent to the same local port
+        let (upstream, mut proxy) = tokio::io::duplex(BUFFER_SIZE);
+        let forward = tokio::spawn(UdpForwarder::forward_sessions(-1, socket, upstream));
+
+        let client = TokioUdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.send_to(b"ping", local_addr).await.unwrap();
+
+        let open = read_frame(&mut proxy).await.unwrap().unwrap();
+        assert_eq!(open.kind, FrameKind::Open);
+        assert_eq!(
+            read_frame(&mut proxy).await.unwrap(),
+            Some(Frame::data(open.stream_id, b"ping".to_vec()))
+        );
+
+        drop(proxy);
+        assert!(timeout(Duration::from_secs(5), forward).await.is_ok());
+    }
 }
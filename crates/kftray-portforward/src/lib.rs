@@ -9,17 +9,44 @@ pub use kftray_http_logs::{
 };
 pub use kube::client::*;
 pub use kube::models::{
+    AnnotatedConfig,
+    ContextDiscovery,
+    DiscoveryReport,
+    DiscoveryWatchOptions,
     KubeContextInfo,
     KubeNamespaceInfo,
     KubeServiceInfo,
     KubeServicePortInfo,
+    ManifestDiscoveryOptions,
+    MultiContextDiscoveryOptions,
+    OrphanedPod,
+    PermissionCheck,
     PodInfo,
+    PreflightReport,
+    RemovedConfigPolicy,
+    RequiredPermission,
+    ServiceDiscovery,
+    SkippedNamespace,
 };
 pub use kube::{
+    check_forward_permissions,
+    delete_orphaned_proxy_pods,
     deploy_and_forward_pod,
+    deploy_and_forward_shared,
+    discover_context_services,
+    discover_manifest_configs,
+    discover_service_configs,
+    find_orphaned_proxy_pods,
+    manifest_configs_from_str,
+    required_permissions,
     retrieve_service_configs,
+    running_discovery_watches,
+    start_discovery_watch,
     start_port_forward,
+    start_reverse_tunnel,
     stop_all_port_forward,
+    stop_discovery_watch,
     stop_port_forward,
     stop_proxy_forward,
+    stop_shared_proxy_forward,
 };
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::Mutex as StdMutex;
+use std::time::Duration;
 
 use futures::TryStreamExt;
 use kftray_http_logs::HttpLogState;
@@ -10,8 +11,13 @@ use kube::{
     Client,
 };
 use lazy_static::lazy_static;
+use tokio::io::{
+    AsyncRead,
+    AsyncWrite,
+};
 use tokio::sync::Mutex;
 use tokio::sync::Notify;
+use tokio::time::sleep;
 use tokio::{
     net::TcpListener,
     task::JoinHandle,
@@ -20,6 +26,7 @@ use tokio_stream::wrappers::TcpListenerStream;
 use tracing::{
     error,
     trace,
+    warn,
 };
 
 use crate::kube::client::create_client_with_specific_context;
@@ -30,10 +37,33 @@ use crate::kube::models::{
 use crate::kube::pod_finder::TargetPodFinder;
 use crate::kube::tcp_forwarder::TcpForwarder;
 use crate::kube::udp_forwarder::UdpForwarder;
+
+/// Delay before a closed UDP tunnel is reopened.
+const UDP_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
 lazy_static! {
     pub static ref CHILD_PROCESSES: Arc<StdMutex<HashMap<String, JoinHandle<()>>>> =
         Arc::new(StdMutex::new(HashMap::new()));
-    pub static ref CANCEL_NOTIFIER: Arc<Notify> = Arc::new(Notify::new());
+    static ref CANCEL_NOTIFIERS: StdMutex<HashMap<i64, Arc<Notify>>> =
+        StdMutex::new(HashMap::new());
+}
+
+/// Returns the notifier that cancels the open connections of a config's
+/// forward, so stopping one forward leaves the connections of others alone.
+pub fn cancel_notifier(config_id: i64) -> Arc<Notify> {
+    CANCEL_NOTIFIERS
+        .lock()
+        .unwrap()
+        .entry(config_id)
+        .or_insert_with(|| Arc::new(Notify::new()))
+        .clone()
+}
+
+/// Cancels the open connections of every forward.
+pub fn cancel_all_connections() {
+    for notifier in CANCEL_NOTIFIERS.lock().unwrap().values() {
+        notifier.notify_waiters();
+    }
 }
 
 impl PortForward {
@@ -106,7 +136,7 @@ impl PortForward {
         trace!(port, "Bound to local address and port");
 
         let server = {
-            let cancel_notifier = CANCEL_NOTIFIER.clone();
+            let cancel_notifier = cancel_notifier(self.config_id);
             let http_log_state = http_log_state.clone();
             TcpListenerStream::new(bind).try_for_each(move |client_conn| {
                 let pf = self.clone();
@@ -191,14 +221,6 @@ impl PortForward {
     }
 
     pub async fn port_forward_udp(self) -> anyhow::Result<(u16, JoinHandle<()>)> {
-        let target = self.finder().find(&self.target).await?;
-        let (pod_name, pod_port) = target.into_parts();
-
-        let mut port_forwarder = self.pod_api.portforward(&pod_name, &[pod_port]).await?;
-        let upstream_conn = port_forwarder
-            .take_stream(pod_port)
-            .ok_or_else(|| anyhow::anyhow!("port not found in forwarder"))?;
-
         let local_addr = self
             .local_address
             .clone()
@@ -215,8 +237,67 @@ impl PortForward {
             ));
         }
 
-        let local_port = self.local_port();
+        let (mut sessions, mut upstream_conn) = self.open_udp_tunnel().await?;
+        let socket = UdpForwarder::bind(&local_addr, self.local_port()).await?;
+        let port = socket.local_addr()?.port();
+
+        // The socket stays bound while the pod behind the target changes, and
+        // a closed tunnel is reopened to whichever pod the target resolves to
+        let handle = tokio::spawn(async move {
+            loop {
+                let result = if sessions {
+                    UdpForwarder::forward_sessions(self.config_id, socket.clone(), upstream_conn)
+                        .await
+                } else {
+                    UdpForwarder::forward(self.config_id, socket.clone(), upstream_conn).await
+                };
+
+                if let Err(e) = result {
+                    error!("UDP forward on port {} stopped: {:?}", port, e);
+                    break;
+                }
+
+                warn!("UDP tunnel on port {} closed, reconnecting", port);
+                (sessions, upstream_conn) = loop {
+                    sleep(UDP_RECONNECT_DELAY).await;
+                    match self.open_udp_tunnel().await {
+                        Ok(tunnel) => break tunnel,
+                        Err(e) => warn!("Failed to reopen UDP tunnel on port {}: {:?}", port, e),
+                    }
+                };
+            }
+        });
+
+        Ok((port, handle))
+    }
+
+    /// Opens a tunnel to the pod the target currently resolves to, returning
+    /// whether the proxy switched it to per-client sessions.
+    async fn open_udp_tunnel(
+        &self,
+    ) -> anyhow::Result<(bool, impl AsyncRead + AsyncWrite + Unpin + Send + 'static)> {
+        let target = self.finder().find(&self.target).await?;
+        let (pod_name, pod_port) = target.into_parts();
+
+        let mut port_forwarder = self.pod_api.portforward(&pod_name, &[pod_port]).await?;
+        let mut upstream_conn = port_forwarder
+            .take_stream(pod_port)
+            .ok_or_else(|| anyhow::anyhow!("port not found in forwarder"))?;
+
+        if UdpForwarder::negotiate_sessions(&mut upstream_conn).await? {
+            return Ok((true, upstream_conn));
+        }
+
+        // Older proxy servers close the connection on the handshake
+        warn!(
+            "Proxy pod {} does not support UDP sessions, replies go to the last sender",
+            pod_name
+        );
+        let mut port_forwarder = self.pod_api.portforward(&pod_name, &[pod_port]).await?;
+        let upstream_conn = port_forwarder
+            .take_stream(pod_port)
+            .ok_or_else(|| anyhow::anyhow!("port not found in forwarder"))?;
 
-        UdpForwarder::bind_and_forward(local_addr, local_port, upstream_conn).await
+        Ok((false, upstream_conn))
     }
 }
@@ -2,6 +2,7 @@ mod proxy;
 
 use std::env;
 use std::net::ToSocketAddrs;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use log::{
@@ -17,6 +18,7 @@ use crate::proxy::{
         ProxyType,
     },
     error::ProxyError,
+    lease,
     server::ProxyServer,
 };
 
@@ -26,11 +28,55 @@ use crate::proxy::{
 /// * `Result<ProxyConfig, ProxyError>` - Parsed configuration or error details
 ///
 /// # Environment Variables
-/// * `REMOTE_ADDRESS` - Target server hostname/IP
-/// * `REMOTE_PORT` - Target server port
+/// * `REMOTE_ADDRESS` - Target server hostname/IP (not used by "reverse" and
+///   "mux")
+/// * `REMOTE_PORT` - Target server port (not used by "reverse" and "mux")
 /// * `LOCAL_PORT` - Local proxy listening port
-/// * `PROXY_TYPE` - Protocol type ("tcp" or "udp")
+/// * `PROXY_TYPE` - Protocol type ("tcp", "udp", "reverse" or "mux")
+/// * `TUNNEL_PORT` - Port the desktop client connects to ("reverse" only)
 fn load_config() -> Result<ProxyConfig, ProxyError> {
+    let proxy_type = match env::var("PROXY_TYPE")
+        .map_err(|_| ProxyError::Configuration("PROXY_TYPE not set".into()))?
+        .to_lowercase()
+        .as_str()
+    {
+        "tcp" => ProxyType::Tcp,
+        "udp" => ProxyType::Udp,
+        "reverse" => ProxyType::Reverse,
+        "mux" => ProxyType::Mux,
+        t => {
+            return Err(ProxyError::Configuration(format!(
+                "Invalid proxy type: {}",
+                t
+            )))
+        }
+    };
+
+    let proxy_port = env::var("LOCAL_PORT")
+        .map_err(|_| ProxyError::Configuration("LOCAL_PORT not set".into()))?
+        .parse()
+        .map_err(|_| ProxyError::Configuration("Invalid LOCAL_PORT".into()))?;
+
+    if let ProxyType::Mux = proxy_type {
+        return Ok(ProxyConfig::builder()
+            .proxy_port(proxy_port)
+            .proxy_type(proxy_type)
+            .build()?);
+    }
+
+    if let ProxyType::Reverse = proxy_type {
+        let tunnel_port = env::var("TUNNEL_PORT")
+            .map_err(|_| ProxyError::Configuration("TUNNEL_PORT not set".into()))?
+            .parse()
+            .map_err(|_| ProxyError::Configuration("Invalid TUNNEL_PORT".into()))?;
+
+        return Ok(ProxyConfig::builder()
+            .proxy_port(proxy_port)
+            .tunnel_port(tunnel_port)
+            .proxy_type(proxy_type)
+            .build()?);
+    }
+
     let target_host = env::var("REMOTE_ADDRESS")
         .map_err(|_| ProxyError::Configuration("REMOTE_ADDRESS not set".into()))?;
 
@@ -65,26 +111,6 @@ fn load_config() -> Result<ProxyConfig, ProxyError> {
         .parse()
         .map_err(|_| ProxyError::Configuration("Invalid REMOTE_PORT".into()))?;
 
-    let proxy_port = env::var("LOCAL_PORT")
-        .map_err(|_| ProxyError::Configuration("LOCAL_PORT not set".into()))?
-        .parse()
-        .map_err(|_| ProxyError::Configuration("Invalid LOCAL_PORT".into()))?;
-
-    let proxy_type = match env::var("PROXY_TYPE")
-        .map_err(|_| ProxyError::Configuration("PROXY_TYPE not set".into()))?
-        .to_lowercase()
-        .as_str()
-    {
-        "tcp" => ProxyType::Tcp,
-        "udp" => ProxyType::Udp,
-        t => {
-            return Err(ProxyError::Configuration(format!(
-                "Invalid proxy type: {}",
-                t
-            )))
-        }
-    };
-
     Ok(ProxyConfig::builder()
         .target_host(socket_addr.ip().to_string())
         .target_port(target_port)
@@ -96,7 +122,9 @@ fn load_config() -> Result<ProxyConfig, ProxyError> {
 /// Main entry point for the proxy server application
 ///
 /// Sets up logging, loads configuration, starts the proxy server,
-/// and handles shutdown signals (Ctrl+C and SIGTERM)
+/// and handles shutdown signals (Ctrl+C and SIGTERM). When `LEASE_FILE`
+/// points at the pod's annotations, the server also stops once the desktop
+/// client's lease expires.
 #[tokio::main]
 async fn main() -> Result<(), ProxyError> {
     env_logger::init();
@@ -117,6 +145,14 @@ async fn main() -> Result<(), ProxyError> {
                 info!("Received SIGTERM signal");
             }
         } => {}
+        _ = async {
+            match env::var("LEASE_FILE") {
+                Ok(path) => lease::wait_for_expiry(PathBuf::from(path)).await,
+                Err(_) => std::future::pending().await,
+            }
+        } => {
+            info!("Shutting down after lease expiry");
+        }
     }
 
     server.shutdown();
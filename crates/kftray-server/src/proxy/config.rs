@@ -7,8 +7,10 @@ pub struct ProxyConfig {
     pub target_port: u16,
     /// Local port number the proxy listens on
     pub proxy_port: u16,
-    /// Type of proxy protocol (TCP or UDP)
+    /// Type of proxy protocol (TCP, UDP, reverse tunnel or multiplexed)
     pub proxy_type: ProxyType,
+    /// Port the desktop client connects to in reverse tunnel mode
+    pub tunnel_port: Option<u16>,
 }
 
 /// Builder pattern implementation for creating ProxyConfig instances
@@ -18,6 +20,7 @@ pub struct ProxyConfigBuilder {
     target_port: Option<u16>,
     proxy_port: Option<u16>,
     proxy_type: Option<ProxyType>,
+    tunnel_port: Option<u16>,
 }
 
 impl ProxyConfigBuilder {
@@ -45,13 +48,12 @@ impl ProxyConfigBuilder {
         self
     }
 
+    pub fn tunnel_port(mut self, port: u16) -> Self {
+        self.tunnel_port = Some(port);
+        self
+    }
+
     pub fn build(self) -> Result<ProxyConfig, String> {
-        let target_host = self
-            .target_host
-            .ok_or_else(|| "target_host is required".to_string())?;
-        let target_port = self
-            .target_port
-            .ok_or_else(|| "target_port is required".to_string())?;
         let proxy_port = self
             .proxy_port
             .ok_or_else(|| "proxy_port is required".to_string())?;
@@ -59,11 +61,33 @@ impl ProxyConfigBuilder {
             .proxy_type
             .ok_or_else(|| "proxy_type is required".to_string())?;
 
+        // Reverse and mux modes have no fixed upstream target: a reverse tunnel
+        // carries connections back to the desktop client and a mux stream
+        // names its own destination
+        let (target_host, target_port) = match proxy_type {
+            ProxyType::Reverse | ProxyType::Mux => {
+                if matches!(proxy_type, ProxyType::Reverse) && self.tunnel_port.is_none() {
+                    return Err("tunnel_port is required".to_string());
+                }
+                (
+                    self.target_host.unwrap_or_default(),
+                    self.target_port.unwrap_or_default(),
+                )
+            }
+            _ => (
+                self.target_host
+                    .ok_or_else(|| "target_host is required".to_string())?,
+                self.target_port
+                    .ok_or_else(|| "target_port is required".to_string())?,
+            ),
+        };
+
         Ok(ProxyConfig {
             target_host,
             target_port,
             proxy_port,
             proxy_type,
+            tunnel_port: self.tunnel_port,
         })
     }
 }
@@ -81,4 +105,8 @@ pub enum ProxyType {
     Tcp,
     /// UDP proxy mode
     Udp,
+    /// Reverse tunnel mode, exposes a port on the desktop client
+    Reverse,
+    /// Multiplexed mode, each stream names its own TCP or UDP destination
+    Mux,
 }
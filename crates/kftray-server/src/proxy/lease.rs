@@ -0,0 +1,138 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{
+        Duration,
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+
+use log::{
+    debug,
+    info,
+    warn,
+};
+
+/// Annotation holding the unix time of the last lease renewal
+pub const LEASE_RENEWED_AT_ANNOTATION: &str = "kftray.app/lease-renewed-at";
+/// Annotation holding how many seconds a renewal stays valid
+pub const LEASE_TTL_ANNOTATION: &str = "kftray.app/lease-ttl-seconds";
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Parses a downward API annotations file, one `key="value"` pair per line
+///
+/// # Parameters
+/// * `content` - Contents of the annotations file
+///
+/// # Returns
+/// * `HashMap<String, String>` - Annotation values by key
+pub fn parse_annotations(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            (key.trim().to_string(), value.replace("\\\"", "\""))
+        })
+        .collect()
+}
+
+/// Checks whether the lease described by the annotations has run out
+///
+/// # Parameters
+/// * `annotations` - Pod annotations
+/// * `now` - Current unix time in seconds
+///
+/// # Returns
+/// * `bool` - True when the lease was not renewed within its TTL. Pods without
+///   lease annotations never expire.
+pub fn lease_expired(annotations: &HashMap<String, String>, now: u64) -> bool {
+    let renewed_at = annotations
+        .get(LEASE_RENEWED_AT_ANNOTATION)
+        .and_then(|v| v.parse::<u64>().ok());
+    let ttl = annotations
+        .get(LEASE_TTL_ANNOTATION)
+        .and_then(|v| v.parse::<u64>().ok());
+
+    match (renewed_at, ttl) {
+        (Some(renewed_at), Some(ttl)) => now > renewed_at.saturating_add(ttl),
+        _ => false,
+    }
+}
+
+/// Resolves once the lease in the annotations file expires
+///
+/// The desktop client renews the lease annotation while it uses the pod, and
+/// the kubelet keeps the mounted file in sync. When the laptop sleeps or
+/// crashes the renewals stop and the server shuts itself down.
+///
+/// # Parameters
+/// * `path` - Path of the downward API annotations file
+pub async fn wait_for_expiry(path: PathBuf) {
+    info!("Watching lease in {}", path.display());
+
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+
+        let content = match tokio::fs::read_to_string(&path).await {
+            Ok(content) => content,
+            Err(e) => {
+                warn!("Failed to read lease file {}: {}", path.display(), e);
+                continue;
+            }
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        if lease_expired(&parse_annotations(&content), now) {
+            info!("Lease expired, the client stopped renewing it");
+            return;
+        }
+
+        debug!("Lease still valid");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_annotations() {
+        // Arrange
+        let content = "kftray.app/lease-renewed-at=\"1700000000\"\n\
+                       kftray.app/lease-ttl-seconds=\"300\"\n\
+                       note=\"say \\\"hi\\\"\"\n";
+
+        // Act
+        let annotations = parse_annotations(content);
+
+        // Assert
+        assert_eq!(annotations[LEASE_RENEWED_AT_ANNOTATION], "1700000000");
+        assert_eq!(annotations[LEASE_TTL_ANNOTATION], "300");
+        assert_eq!(annotations["note"], "say \"hi\"");
+    }
+
+    #[test]
+    fn test_lease_expired() {
+        // Arrange
+        let annotations = parse_annotations(
+            "kftray.app/lease-renewed-at=\"1000\"\nkftray.app/lease-ttl-seconds=\"300\"\n",
+        );
+
+        // Act & Assert
+        assert!(!lease_expired(&annotations, 1200));
+        assert!(!lease_expired(&annotations, 1300));
+        assert!(lease_expired(&annotations, 1301));
+        assert!(!lease_expired(&HashMap::new(), u64::MAX));
+    }
+}
@@ -1,8 +1,12 @@
 pub mod config;
 pub mod error;
+pub mod lease;
+pub mod mux;
+pub mod reverse;
 pub mod server;
 pub mod tcp;
 pub mod traits;
+pub mod tunnel;
 pub mod udp;
 
 #[cfg(test)]
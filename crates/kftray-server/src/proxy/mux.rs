@@ -0,0 +1,397 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use log::{
+    debug,
+    error,
+    info,
+    warn,
+};
+use tokio::{
+    net::{
+        TcpListener,
+        TcpStream,
+        UdpSocket,
+    },
+    sync::{
+        mpsc,
+        Notify,
+    },
+    task::JoinSet,
+    time::timeout,
+};
+
+use crate::proxy::{
+    config::ProxyConfig,
+    error::ProxyError,
+    traits::ProxyHandler,
+    tunnel::{
+        pump_from_tunnel,
+        pump_to_tunnel,
+        read_frame,
+        write_frames,
+        Destination,
+        DestinationProtocol,
+        Frame,
+        FrameKind,
+    },
+};
+
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+const FRAME_QUEUE_SIZE: usize = 256;
+const STREAM_QUEUE_SIZE: usize = 64;
+const MAX_UDP_PAYLOAD_SIZE: usize = 65507;
+
+/// Multiplexing proxy that lets a single pod serve many configs.
+///
+/// Every client connection is a session carrying any number of streams. A
+/// stream starts with an open frame naming its destination, after which its
+/// data frames are relayed to a TCP connection or a connected UDP socket.
+/// It only listens on loopback, where the client's port forward arrives, so
+/// other pods can't use it to reach destinations they are kept from.
+#[derive(Clone)]
+pub struct MuxProxy;
+
+impl MuxProxy {
+    /// Creates a new mux proxy instance
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Serves one client session until its connection closes
+    ///
+    /// # Parameters
+    /// * `stream` - Connection from the desktop client
+    async fn handle_session(stream: TcpStream) -> Result<(), ProxyError> {
+        let _ = stream.set_nodelay(true);
+        let (mut reader, writer) = stream.into_split();
+        let (frames_tx, frames_rx) = mpsc::channel(FRAME_QUEUE_SIZE);
+        let writer_handle = tokio::spawn(write_frames(writer, frames_rx));
+
+        let mut streams: HashMap<u32, mpsc::Sender<Vec<u8>>> = HashMap::new();
+        // Dropping the set when the session ends aborts its remaining streams
+        let mut tasks = JoinSet::new();
+
+        let result = async {
+            while let Some(frame) = read_frame(&mut reader).await? {
+                let stream_id = frame.stream_id;
+
+                match frame.kind {
+                    FrameKind::Open => {
+                        let destination = match Destination::decode(&frame.payload) {
+                            Ok(destination) => destination,
+                            Err(e) => {
+                                warn!("Rejecting stream {}: {}", stream_id, e);
+                                let _ = frames_tx.send(Frame::close(stream_id)).await;
+                                continue;
+                            }
+                        };
+
+                        let (tx, rx) = mpsc::channel(STREAM_QUEUE_SIZE);
+                        streams.insert(stream_id, tx);
+
+                        let frames = frames_tx.clone();
+                        tasks.spawn(async move {
+                            if let Err(e) = Self::relay(stream_id, destination, frames, rx).await {
+                                error!("Stream {} error: {}", stream_id, e);
+                            }
+                        });
+                    }
+                    FrameKind::Data => {
+                        if let Some(sender) = streams.get(&stream_id) {
+                            if sender.send(frame.payload).await.is_err() {
+                                streams.remove(&stream_id);
+                            }
+                        }
+                    }
+                    FrameKind::Close => {
+                        streams.remove(&stream_id);
+                    }
+                    FrameKind::Ping => debug!("Mux session ping"),
+                }
+            }
+
+            Ok(())
+        }
+        .await;
+
+        writer_handle.abort();
+        result
+    }
+
+    /// Relays one stream to its destination
+    ///
+    /// # Parameters
+    /// * `stream_id` - Stream being relayed
+    /// * `destination` - Target named by the open frame
+    /// * `frames` - Queue of frames for the session writer
+    /// * `payloads` - Payloads the client sent on this stream
+    async fn relay(
+        stream_id: u32, destination: Destination, frames: mpsc::Sender<Frame>,
+        payloads: mpsc::Receiver<Vec<u8>>,
+    ) -> Result<(), ProxyError> {
+        let result = match destination.protocol {
+            DestinationProtocol::Tcp => {
+                Self::relay_tcp(stream_id, &destination, &frames, payloads).await
+            }
+            DestinationProtocol::Udp => {
+                Self::relay_udp(stream_id, &destination, &frames, payloads).await
+            }
+        };
+
+        if let Err(ProxyError::Connection(_)) = result {
+            let _ = frames.send(Frame::close(stream_id)).await;
+        }
+        result
+    }
+
+    /// Connects a TCP stream and pipes it both ways
+    async fn relay_tcp(
+        stream_id: u32, destination: &Destination, frames: &mpsc::Sender<Frame>,
+        payloads: mpsc::Receiver<Vec<u8>>,
+    ) -> Result<(), ProxyError> {
+        let connect = TcpStream::connect((destination.host.as_str(), destination.port));
+        let stream = match timeout(CONNECTION_TIMEOUT, connect).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(e)) => {
+                return Err(ProxyError::Connection(format!(
+                    "Failed to connect to {}:{}: {}",
+                    destination.host, destination.port, e
+                )))
+            }
+            Err(_) => return Err(ProxyError::Connection("Connection timeout".into())),
+        };
+
+        info!(
+            "Stream {} connected to {}:{}",
+            stream_id, destination.host, destination.port
+        );
+
+        let (reader, writer) = stream.into_split();
+        tokio::try_join!(
+            pump_to_tunnel(stream_id, reader, frames),
+            pump_from_tunnel(writer, payloads)
+        )?;
+
+        Ok(())
+    }
+
+    /// Relays datagrams of a UDP stream, one data frame per datagram
+    async fn relay_udp(
+        stream_id: u32, destination: &Destination, frames: &mpsc::Sender<Frame>,
+        mut payloads: mpsc::Receiver<Vec<u8>>,
+    ) -> Result<(), ProxyError> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket
+            .connect((destination.host.as_str(), destination.port))
+            .await
+            .map_err(|e| {
+                ProxyError::Connection(format!(
+                    "Failed to resolve {}:{}: {}",
+                    destination.host, destination.port, e
+                ))
+            })?;
+
+        info!(
+            "Stream {} relaying UDP to {}:{}",
+            stream_id, destination.host, destination.port
+        );
+
+        let mut buffer = vec![0u8; MAX_UDP_PAYLOAD_SIZE];
+        loop {
+            tokio::select! {
+                payload = payloads.recv() => {
+                    match payload {
+                        Some(payload) => {
+                            socket.send(&payload).await?;
+                        }
+                        None => break,
+                    }
+                }
+                result = socket.recv(&mut buffer) => {
+                    match result {
+                        Ok(n) => {
+                            if frames.send(Frame::data(stream_id, buffer[..n].to_vec())).await.is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => debug!("UDP receive error on stream {}: {}", stream_id, e),
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ProxyHandler for MuxProxy {
+    /// Starts the mux proxy with the given configuration and shutdown signal.
+    ///
+    /// # Parameters
+    /// * `config` - Configuration containing the proxy port
+    /// * `shutdown` - Notification mechanism to signal when the proxy should
+    ///   stop
+    ///
+    /// # Returns
+    /// * `Result<(), ProxyError>` - Success if proxy runs and shuts down
+    ///   cleanly, or error details
+    async fn start(&self, config: ProxyConfig, shutdown: Arc<Notify>) -> Result<(), ProxyError> {
+        let addr: SocketAddr = format!("127.0.0.1:{}", config.proxy_port).parse()?;
+        let listener = TcpListener::bind(addr).await?;
+
+        info!("Mux proxy started on port {}", config.proxy_port);
+
+        loop {
+            tokio::select! {
+                accept_result = listener.accept() => {
+                    match accept_result {
+                        Ok((stream, addr)) => {
+                            info!("Accepted mux session from {}", addr);
+                            tokio::spawn(async move {
+                                if let Err(e) = Self::handle_session(stream).await {
+                                    error!("Mux session error for {}: {}", addr, e);
+                                }
+                                info!("Mux session from {} closed", addr);
+                            });
+                        }
+                        Err(e) => error!("Failed to accept connection: {}", e),
+                    }
+                }
+                _ = shutdown.notified() => {
+                    info!("Shutdown signal received, stopping mux proxy");
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::{
+        config::ProxyType,
+        test_utils,
+        tunnel::write_frame,
+    };
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    async fn setup_proxy() -> (Arc<Notify>, SocketAddr) {
+        let proxy = MuxProxy::new();
+        let shutdown = Arc::new(Notify::new());
+        let shutdown_clone = shutdown.clone();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let config = ProxyConfig::builder()
+            .proxy_port(addr.port())
+            .proxy_type(ProxyType::Mux)
+            .build()
+            .unwrap();
+
+        tokio::spawn(async move {
+            let _ = proxy.start(config, shutdown).await;
+        });
+
+        assert!(
+            test_utils::wait_for_port(addr).await,
+            "Proxy failed to start"
+        );
+
+        (shutdown_clone, addr)
+    }
+
+    async fn next_frame(session: &mut TcpStream) -> Frame {
+        timeout(TEST_TIMEOUT, read_frame(session))
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_mux_proxy_tcp_and_udp_streams() {
+        // Arrange
+        let tcp_server = test_utils::setup_test_tcp_echo_server().await;
+        let udp_server = test_utils::setup_test_udp_echo_server().await;
+        let (shutdown, proxy_addr) = setup_proxy().await;
+        let mut session = TcpStream::connect(proxy_addr).await.unwrap();
+
+        let tcp_destination = Destination {
+            protocol: DestinationProtocol::Tcp,
+            host: tcp_server.addr().ip().to_string(),
+            port: tcp_server.addr().port(),
+        };
+        let udp_destination = Destination {
+            protocol: DestinationProtocol::Udp,
+            host: udp_server.addr().ip().to_string(),
+            port: udp_server.addr().port(),
+        };
+
+        // Act
+        for frame in [
+            Frame::open_to(1, &tcp_destination),
+            Frame::open_to(2, &udp_destination),
+            Frame::data(1, b"over tcp".to_vec()),
+            Frame::data(2, b"over udp".to_vec()),
+        ] {
+            write_frame(&mut session, &frame).await.unwrap();
+        }
+
+        let mut replies = HashMap::new();
+        while replies.len() < 2 {
+            let frame = next_frame(&mut session).await;
+            assert_eq!(frame.kind, FrameKind::Data);
+            replies.insert(frame.stream_id, frame.payload);
+        }
+
+        // Assert
+        assert_eq!(replies[&1], b"over tcp");
+        assert_eq!(replies[&2], b"over udp");
+
+        // Cleanup
+        shutdown.notify_one();
+        tcp_server.shutdown();
+        udp_server.shutdown();
+    }
+
+    #[tokio::test]
+    async fn test_mux_proxy_closes_unreachable_stream() {
+        // Arrange
+        let (shutdown, proxy_addr) = setup_proxy().await;
+        let unused = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let unused_port = unused.local_addr().unwrap().port();
+        drop(unused);
+
+        let mut session = TcpStream::connect(proxy_addr).await.unwrap();
+        let destination = Destination {
+            protocol: DestinationProtocol::Tcp,
+            host: "127.0.0.1".to_string(),
+            port: unused_port,
+        };
+
+        // Act
+        write_frame(&mut session, &Frame::open_to(5, &destination))
+            .await
+            .unwrap();
+        let frame = next_frame(&mut session).await;
+
+        // Assert
+        assert_eq!(frame, Frame::close(5));
+
+        // Cleanup
+        shutdown.notify_one();
+    }
+}
@@ -0,0 +1,494 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{
+            AtomicU32,
+            AtomicU64,
+            Ordering,
+        },
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use log::{
+    debug,
+    error,
+    info,
+    warn,
+};
+use tokio::{
+    net::{
+        tcp::OwnedReadHalf,
+        TcpListener,
+        TcpStream,
+    },
+    sync::{
+        mpsc,
+        Mutex,
+        Notify,
+    },
+};
+
+use crate::proxy::{
+    config::ProxyConfig,
+    error::ProxyError,
+    traits::ProxyHandler,
+    tunnel::{
+        pump_from_tunnel,
+        pump_to_tunnel,
+        read_frame,
+        write_frames,
+        Frame,
+        FrameKind,
+    },
+};
+
+const FRAME_QUEUE_SIZE: usize = 256;
+const STREAM_QUEUE_SIZE: usize = 64;
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+/// A tunnel silent for this long is dropped, both sides ping every
+/// [`PING_INTERVAL`]
+const TUNNEL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Reverse tunnel proxy that exposes a port on the developer's machine inside
+/// the cluster.
+///
+/// The desktop client keeps a single connection open on the tunnel port and
+/// every connection accepted on the proxy port is carried over it as its own
+/// multiplexed stream. The tunnel port only listens on loopback, where the
+/// client's port forward arrives, so other pods can't take the tunnel over.
+#[derive(Clone)]
+pub struct ReverseProxy {
+    /// Attached tunnel, cleared once it closes so the client can reconnect
+    tunnel: Arc<Mutex<Option<TunnelSession>>>,
+    /// Counter used to tell tunnel sessions apart
+    sessions: Arc<AtomicU64>,
+}
+
+/// State of one tunnel connection from the desktop client
+#[derive(Clone)]
+struct TunnelSession {
+    id: u64,
+    /// Frames queued for the tunnel writer task
+    frames: mpsc::Sender<Frame>,
+    /// Write side of every open stream, keyed by stream id
+    streams: Arc<Mutex<HashMap<u32, mpsc::Sender<Vec<u8>>>>>,
+    next_stream_id: Arc<AtomicU32>,
+}
+
+impl TunnelSession {
+    /// Hands payload received from the tunnel to the matching stream
+    ///
+    /// # Parameters
+    /// * `stream_id` - Stream the payload belongs to
+    /// * `payload` - Bytes to write to the in-cluster connection
+    async fn deliver(&self, stream_id: u32, payload: Vec<u8>) {
+        let sender = self.streams.lock().await.get(&stream_id).cloned();
+        match sender {
+            Some(sender) => {
+                if sender.send(payload).await.is_err() {
+                    self.streams.lock().await.remove(&stream_id);
+                }
+            }
+            None => debug!("Dropping data for unknown stream {}", stream_id),
+        }
+    }
+}
+
+impl ReverseProxy {
+    /// Creates a new reverse proxy instance
+    pub fn new() -> Self {
+        Self {
+            tunnel: Arc::new(Mutex::new(None)),
+            sessions: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Attaches a new tunnel connection, refused while another one is
+    /// attached
+    ///
+    /// # Parameters
+    /// * `stream` - Connection from the desktop client
+    async fn attach_tunnel(&self, stream: TcpStream) {
+        let mut current = self.tunnel.lock().await;
+        if let Some(attached) = current.as_ref() {
+            warn!(
+                "Tunnel session {} is attached, refusing another tunnel",
+                attached.id
+            );
+            return;
+        }
+
+        let _ = stream.set_nodelay(true);
+        let (reader, writer) = stream.into_split();
+        let (frames_tx, frames_rx) = mpsc::channel(FRAME_QUEUE_SIZE);
+
+        let session = TunnelSession {
+            id: self.sessions.fetch_add(1, Ordering::Relaxed) + 1,
+            frames: frames_tx,
+            streams: Arc::new(Mutex::new(HashMap::new())),
+            next_stream_id: Arc::new(AtomicU32::new(1)),
+        };
+        *current = Some(session.clone());
+        drop(current);
+
+        let writer_handle = tokio::spawn(write_frames(writer, frames_rx));
+        let frames = session.frames.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PING_INTERVAL);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if frames.send(Frame::ping()).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let proxy = self.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = Self::read_frames(reader, &session).await {
+                error!("Tunnel session {} failed: {}", session.id, e);
+            }
+
+            writer_handle.abort();
+            session.streams.lock().await.clear();
+
+            let mut current = proxy.tunnel.lock().await;
+            if current.as_ref().map(|s| s.id) == Some(session.id) {
+                *current = None;
+            }
+            info!("Tunnel session {} closed", session.id);
+        });
+    }
+
+    /// Dispatches frames received from the desktop client
+    ///
+    /// # Parameters
+    /// * `reader` - Tunnel read half
+    /// * `session` - Session the frames belong to
+    async fn read_frames(
+        mut reader: OwnedReadHalf, session: &TunnelSession,
+    ) -> Result<(), ProxyError> {
+        loop {
+            let frame = tokio::time::timeout(TUNNEL_IDLE_TIMEOUT, read_frame(&mut reader))
+                .await
+                .map_err(|_| ProxyError::Connection("Tunnel went silent".into()))??;
+            let Some(frame) = frame else {
+                break;
+            };
+
+            match frame.kind {
+                FrameKind::Data => session.deliver(frame.stream_id, frame.payload).await,
+                FrameKind::Close => {
+                    session.streams.lock().await.remove(&frame.stream_id);
+                }
+                FrameKind::Ping => debug!("Tunnel session {} ping", session.id),
+                FrameKind::Open => warn!(
+                    "Ignoring open frame from client for stream {}",
+                    frame.stream_id
+                ),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Carries an in-cluster connection over the tunnel as a new stream
+    ///
+    /// # Parameters
+    /// * `session` - Tunnel session to use
+    /// * `stream` - Connection accepted on the proxy port
+    async fn carry_stream(session: TunnelSession, stream: TcpStream) -> Result<(), ProxyError> {
+        let stream_id = session.next_stream_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel(STREAM_QUEUE_SIZE);
+        session.streams.lock().await.insert(stream_id, tx);
+
+        if session.frames.send(Frame::open(stream_id)).await.is_err() {
+            session.streams.lock().await.remove(&stream_id);
+            return Err(ProxyError::Connection("Tunnel closed".into()));
+        }
+
+        let (reader, writer) = stream.into_split();
+        let result = tokio::try_join!(
+            pump_to_tunnel(stream_id, reader, &session.frames),
+            pump_from_tunnel(writer, rx)
+        );
+
+        session.streams.lock().await.remove(&stream_id);
+        debug!("Stream {} finished", stream_id);
+        result.map(|_| ())
+    }
+}
+
+#[async_trait]
+impl ProxyHandler for ReverseProxy {
+    /// Starts the reverse proxy with the given configuration and shutdown
+    /// signal.
+    ///
+    /// # Parameters
+    /// * `config` - Configuration containing the proxy and tunnel ports
+    /// * `shutdown` - Notification mechanism to signal when the proxy should
+    ///   stop
+    ///
+    /// # Returns
+    /// * `Result<(), ProxyError>` - Success if proxy runs and shuts down
+    ///   cleanly, or error details
+    async fn start(&self, config: ProxyConfig, shutdown: Arc<Notify>) -> Result<(), ProxyError> {
+        let tunnel_port = config.tunnel_port.ok_or_else(|| {
+            ProxyError::Configuration("tunnel_port is required for reverse proxy".into())
+        })?;
+
+        let tunnel_addr: SocketAddr = format!("127.0.0.1:{}", tunnel_port).parse()?;
+        let tunnel_listener = TcpListener::bind(tunnel_addr).await?;
+        let addr: SocketAddr = format!("0.0.0.0:{}", config.proxy_port).parse()?;
+        let listener = TcpListener::bind(addr).await?;
+
+        info!(
+            "Reverse proxy started on port {} with tunnel port {}",
+            config.proxy_port, tunnel_port
+        );
+
+        loop {
+            tokio::select! {
+                accept_result = tunnel_listener.accept() => {
+                    match accept_result {
+                        Ok((stream, addr)) => {
+                            info!("Tunnel connected from {}", addr);
+                            self.attach_tunnel(stream).await;
+                        }
+                        Err(e) => error!("Failed to accept tunnel connection: {}", e),
+                    }
+                }
+                accept_result = listener.accept() => {
+                    match accept_result {
+                        Ok((stream, addr)) => {
+                            let session = self.tunnel.lock().await.clone();
+                            match session {
+                                Some(session) => {
+                                    info!("Accepted connection from {}", addr);
+                                    tokio::spawn(async move {
+                                        if let Err(e) = Self::carry_stream(session, stream).await {
+                                            error!("Connection error for {}: {}", addr, e);
+                                        }
+                                    });
+                                }
+                                None => warn!("No tunnel connected, dropping connection from {}", addr),
+                            }
+                        }
+                        Err(e) => error!("Failed to accept connection: {}", e),
+                    }
+                }
+                _ = shutdown.notified() => {
+                    info!("Shutdown signal received, stopping reverse proxy");
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{
+        AsyncReadExt,
+        AsyncWriteExt,
+    };
+
+    use super::*;
+    use crate::proxy::{
+        config::ProxyType,
+        test_utils,
+        tunnel::write_frame,
+    };
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    async fn free_port() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    async fn setup_proxy() -> (Arc<Notify>, SocketAddr, SocketAddr) {
+        let proxy = ReverseProxy::new();
+        let shutdown = Arc::new(Notify::new());
+        let shutdown_clone = shutdown.clone();
+
+        let proxy_addr: SocketAddr = format!("127.0.0.1:{}", free_port().await).parse().unwrap();
+        let tunnel_addr: SocketAddr = format!("127.0.0.1:{}", free_port().await).parse().unwrap();
+
+        let config = ProxyConfig::builder()
+            .proxy_port(proxy_addr.port())
+            .tunnel_port(tunnel_addr.port())
+            .proxy_type(ProxyType::Reverse)
+            .build()
+            .unwrap();
+
+        tokio::spawn(async move {
+            let _ = proxy.start(config, shutdown).await;
+        });
+
+        assert!(
+            test_utils::wait_for_port(proxy_addr).await,
+            "Proxy failed to start"
+        );
+
+        (shutdown_clone, proxy_addr, tunnel_addr)
+    }
+
+    /// Connects in-cluster clients until the tunnel reports the open stream
+    async fn open_stream(tunnel: &mut TcpStream, proxy_addr: SocketAddr) -> (TcpStream, u32) {
+        for _ in 0..50 {
+            let client = TcpStream::connect(proxy_addr).await.unwrap();
+            if let Ok(Ok(Some(frame))) =
+                tokio::time::timeout(Duration::from_millis(100), read_frame(tunnel)).await
+            {
+                assert_eq!(frame.kind, FrameKind::Open);
+                return (client, frame.stream_id);
+            }
+        }
+        panic!("Tunnel never received an open frame");
+    }
+
+    #[tokio::test]
+    async fn test_reverse_proxy_carries_stream() {
+        // Arrange
+        let (shutdown, proxy_addr, tunnel_addr) = setup_proxy().await;
+        let mut tunnel = TcpStream::connect(tunnel_addr).await.unwrap();
+        let (mut client, stream_id) = open_stream(&mut tunnel, proxy_addr).await;
+        let mut response = [0u8; 5];
+
+        // Act
+        client.write_all(b"hello").await.unwrap();
+        let request = tokio::time::timeout(TEST_TIMEOUT, read_frame(&mut tunnel))
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        write_frame(&mut tunnel, &Frame::data(stream_id, b"world".to_vec()))
+            .await
+            .unwrap();
+        write_frame(&mut tunnel, &Frame::close(stream_id))
+            .await
+            .unwrap();
+
+        tokio::time::timeout(TEST_TIMEOUT, client.read_exact(&mut response))
+            .await
+            .unwrap()
+            .unwrap();
+        let eof = tokio::time::timeout(TEST_TIMEOUT, client.read(&mut [0u8; 1]))
+            .await
+            .unwrap()
+            .unwrap();
+
+        drop(client);
+        let close = tokio::time::timeout(TEST_TIMEOUT, read_frame(&mut tunnel))
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        // Assert
+        assert_eq!(request, Frame::data(stream_id, b"hello".to_vec()));
+        assert_eq!(&response, b"world");
+        assert_eq!(eof, 0);
+        assert_eq!(close, Frame::close(stream_id));
+
+        // Cleanup
+        shutdown.notify_one();
+    }
+
+    #[tokio::test]
+    async fn test_reverse_proxy_assigns_distinct_streams() {
+        // Arrange
+        let (shutdown, proxy_addr, tunnel_addr) = setup_proxy().await;
+        let mut tunnel = TcpStream::connect(tunnel_addr).await.unwrap();
+
+        // Act
+        let (_first, first_id) = open_stream(&mut tunnel, proxy_addr).await;
+        let (_second, second_id) = open_stream(&mut tunnel, proxy_addr).await;
+
+        // Assert
+        assert_ne!(first_id, second_id);
+
+        // Cleanup
+        shutdown.notify_one();
+    }
+
+    #[tokio::test]
+    async fn test_reverse_proxy_keeps_the_attached_tunnel() {
+        // Arrange
+        let (shutdown, proxy_addr, tunnel_addr) = setup_proxy().await;
+        let mut tunnel = TcpStream::connect(tunnel_addr).await.unwrap();
+        let (_first, _) = open_stream(&mut tunnel, proxy_addr).await;
+
+        // Act
+        let mut intruder = TcpStream::connect(tunnel_addr).await.unwrap();
+        let refused = tokio::time::timeout(TEST_TIMEOUT, intruder.read(&mut [0u8; 1]))
+            .await
+            .unwrap();
+        let (_second, stream_id) = open_stream(&mut tunnel, proxy_addr).await;
+
+        // Assert
+        assert!(matches!(refused, Ok(0) | Err(_)));
+        assert!(stream_id > 0);
+
+        // Cleanup
+        shutdown.notify_one();
+    }
+
+    #[tokio::test]
+    async fn test_reverse_proxy_accepts_a_tunnel_after_the_first_closes() {
+        // Arrange
+        let (shutdown, proxy_addr, tunnel_addr) = setup_proxy().await;
+        let mut first = TcpStream::connect(tunnel_addr).await.unwrap();
+        let _ = open_stream(&mut first, proxy_addr).await;
+
+        // Act
+        drop(first);
+        let mut reconnected = None;
+        for _ in 0..50 {
+            let mut tunnel = TcpStream::connect(tunnel_addr).await.unwrap();
+            let client = TcpStream::connect(proxy_addr).await.unwrap();
+            if let Ok(Ok(Some(frame))) =
+                tokio::time::timeout(Duration::from_millis(100), read_frame(&mut tunnel)).await
+            {
+                reconnected = Some((client, frame));
+                break;
+            }
+        }
+
+        // Assert
+        let (_client, frame) = reconnected.expect("Reconnected tunnel never got a stream");
+        assert_eq!(frame.kind, FrameKind::Open);
+
+        // Cleanup
+        shutdown.notify_one();
+    }
+
+    #[tokio::test]
+    async fn test_reverse_proxy_drops_connections_without_tunnel() {
+        // Arrange
+        let (shutdown, proxy_addr, _tunnel_addr) = setup_proxy().await;
+
+        // Act
+        let mut client = TcpStream::connect(proxy_addr).await.unwrap();
+        let result = tokio::time::timeout(TEST_TIMEOUT, client.read(&mut [0u8; 1]))
+            .await
+            .unwrap();
+
+        // Assert
+        assert!(matches!(result, Ok(0) | Err(_)));
+
+        // Cleanup
+        shutdown.notify_one();
+    }
+}
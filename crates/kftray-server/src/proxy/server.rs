@@ -9,6 +9,8 @@ use crate::proxy::{
         ProxyType,
     },
     error::ProxyError,
+    mux::MuxProxy,
+    reverse::ReverseProxy,
     tcp::TcpProxy,
     traits::ProxyHandler,
     udp::UdpProxy,
@@ -20,7 +22,8 @@ pub struct ProxyServer {
     config: ProxyConfig,
     /// Shutdown signal notifier
     shutdown: Arc<Notify>,
-    /// Protocol-specific proxy handler (TCP or UDP)
+    /// Protocol-specific proxy handler (TCP, UDP, reverse tunnel or
+    /// multiplexed)
     handler: Box<dyn ProxyHandler>,
 }
 
@@ -33,6 +36,8 @@ impl ProxyServer {
         let handler: Box<dyn ProxyHandler> = match config.proxy_type {
             ProxyType::Tcp => Box::new(TcpProxy::new()),
             ProxyType::Udp => Box::new(UdpProxy::new()),
+            ProxyType::Reverse => Box::new(ReverseProxy::new()),
+            ProxyType::Mux => Box::new(MuxProxy::new()),
         };
 
         Self {
@@ -0,0 +1,361 @@
+use tokio::{
+    io::{
+        AsyncRead,
+        AsyncReadExt,
+        AsyncWrite,
+        AsyncWriteExt,
+    },
+    net::tcp::{
+        OwnedReadHalf,
+        OwnedWriteHalf,
+    },
+    sync::mpsc,
+};
+
+use crate::proxy::error::ProxyError;
+
+/// Size of the fixed frame header: kind (1) + stream id (4) + length (4)
+pub const FRAME_HEADER_SIZE: usize = 9;
+/// Largest payload a single frame may carry
+pub const MAX_FRAME_PAYLOAD: usize = 1024 * 1024;
+/// Read buffer size used when pumping a connection into data frames
+const BUFFER_SIZE: usize = 65536;
+
+/// Frame types exchanged over a reverse tunnel connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    /// Opens a stream. In reverse mode the server announces an accepted
+    /// in-cluster connection, in mux mode the client names a [`Destination`]
+    Open = 1,
+    /// Payload bytes for an open stream
+    Data = 2,
+    /// The sender will not write to this stream anymore
+    Close = 3,
+    /// Keepalive, carries no stream
+    Ping = 4,
+}
+
+impl TryFrom<u8> for FrameKind {
+    type Error = ProxyError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(FrameKind::Open),
+            2 => Ok(FrameKind::Data),
+            3 => Ok(FrameKind::Close),
+            4 => Ok(FrameKind::Ping),
+            other => Err(ProxyError::InvalidData(format!(
+                "Unknown frame kind: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Transport used to reach a mux mode destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DestinationProtocol {
+    Tcp = 1,
+    Udp = 2,
+}
+
+/// Target of a mux mode stream, carried in the payload of its open frame as
+/// protocol (1) + port (2, big endian) + host (UTF-8)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Destination {
+    pub protocol: DestinationProtocol,
+    pub host: String,
+    pub port: u16,
+}
+
+impl Destination {
+    /// The server only decodes destinations, the desktop client encodes them
+    #[cfg(test)]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(3 + self.host.len());
+        buf.push(self.protocol as u8);
+        buf.extend_from_slice(&self.port.to_be_bytes());
+        buf.extend_from_slice(self.host.as_bytes());
+        buf
+    }
+
+    pub fn decode(payload: &[u8]) -> Result<Self, ProxyError> {
+        if payload.len() < 4 {
+            return Err(ProxyError::InvalidData("Destination too short".into()));
+        }
+
+        let protocol = match payload[0] {
+            1 => DestinationProtocol::Tcp,
+            2 => DestinationProtocol::Udp,
+            other => {
+                return Err(ProxyError::InvalidData(format!(
+                    "Unknown destination protocol: {}",
+                    other
+                )))
+            }
+        };
+        let port = u16::from_be_bytes([payload[1], payload[2]]);
+        let host = String::from_utf8(payload[3..].to_vec())
+            .map_err(|_| ProxyError::InvalidData("Destination host is not UTF-8".into()))?;
+
+        Ok(Self {
+            protocol,
+            host,
+            port,
+        })
+    }
+}
+
+/// A single multiplexed frame on the tunnel connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub stream_id: u32,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn open(stream_id: u32) -> Self {
+        Self {
+            kind: FrameKind::Open,
+            stream_id,
+            payload: Vec::new(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn open_to(stream_id: u32, destination: &Destination) -> Self {
+        Self {
+            kind: FrameKind::Open,
+            stream_id,
+            payload: destination.encode(),
+        }
+    }
+
+    pub fn data(stream_id: u32, payload: Vec<u8>) -> Self {
+        Self {
+            kind: FrameKind::Data,
+            stream_id,
+            payload,
+        }
+    }
+
+    pub fn close(stream_id: u32) -> Self {
+        Self {
+            kind: FrameKind::Close,
+            stream_id,
+            payload: Vec::new(),
+        }
+    }
+
+    pub fn ping() -> Self {
+        Self {
+            kind: FrameKind::Ping,
+            stream_id: 0,
+            payload: Vec::new(),
+        }
+    }
+}
+
+/// Reads the next frame from the tunnel
+///
+/// # Parameters
+/// * `reader` - Tunnel read half
+///
+/// # Returns
+/// * `Result<Option<Frame>, ProxyError>` - The frame, or `None` when the tunnel
+///   was closed on a frame boundary
+pub async fn read_frame<R>(reader: &mut R) -> Result<Option<Frame>, ProxyError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut header = [0u8; FRAME_HEADER_SIZE];
+    match reader.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(ProxyError::Io(e)),
+    }
+
+    let kind = FrameKind::try_from(header[0])?;
+    let stream_id = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+    let len = u32::from_be_bytes([header[5], header[6], header[7], header[8]]) as usize;
+
+    if len > MAX_FRAME_PAYLOAD {
+        return Err(ProxyError::InvalidData(format!(
+            "Frame payload too large: {} bytes",
+            len
+        )));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+
+    Ok(Some(Frame {
+        kind,
+        stream_id,
+        payload,
+    }))
+}
+
+/// Writes a frame to the tunnel
+///
+/// # Parameters
+/// * `writer` - Tunnel write half
+/// * `frame` - Frame to send
+pub async fn write_frame<W>(writer: &mut W, frame: &Frame) -> Result<(), ProxyError>
+where
+    W: AsyncWrite + Unpin,
+{
+    if frame.payload.len() > MAX_FRAME_PAYLOAD {
+        return Err(ProxyError::InvalidData(format!(
+            "Frame payload too large: {} bytes",
+            frame.payload.len()
+        )));
+    }
+
+    let mut buf = Vec::with_capacity(FRAME_HEADER_SIZE + frame.payload.len());
+    buf.push(frame.kind as u8);
+    buf.extend_from_slice(&frame.stream_id.to_be_bytes());
+    buf.extend_from_slice(&(frame.payload.len() as u32).to_be_bytes());
+    buf.extend_from_slice(&frame.payload);
+
+    writer.write_all(&buf).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Serializes queued frames onto the tunnel connection
+///
+/// # Parameters
+/// * `writer` - Tunnel write half
+/// * `frames` - Queue of frames produced by the stream tasks
+pub async fn write_frames(
+    mut writer: OwnedWriteHalf, mut frames: mpsc::Receiver<Frame>,
+) -> Result<(), ProxyError> {
+    while let Some(frame) = frames.recv().await {
+        write_frame(&mut writer, &frame).await?;
+    }
+
+    Ok(())
+}
+
+/// Forwards bytes read from a connection as data frames, ending the stream
+/// with a close frame
+///
+/// # Parameters
+/// * `stream_id` - Stream the connection is carried on
+/// * `reader` - Read half of the connection
+/// * `frames` - Queue of frames for the tunnel writer
+pub async fn pump_to_tunnel(
+    stream_id: u32, mut reader: OwnedReadHalf, frames: &mpsc::Sender<Frame>,
+) -> Result<(), ProxyError> {
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+
+    loop {
+        let n = match reader.read(&mut buffer).await {
+            Ok(n) => n,
+            Err(e) => {
+                let _ = frames.send(Frame::close(stream_id)).await;
+                return Err(ProxyError::Io(e));
+            }
+        };
+
+        let frame = if n == 0 {
+            Frame::close(stream_id)
+        } else {
+            Frame::data(stream_id, buffer[..n].to_vec())
+        };
+
+        frames
+            .send(frame)
+            .await
+            .map_err(|_| ProxyError::Connection("Tunnel closed".into()))?;
+
+        if n == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// Writes payload received from the tunnel to a connection, shutting down its
+/// write side once the peer closes the stream
+///
+/// # Parameters
+/// * `writer` - Write half of the connection
+/// * `payloads` - Payloads delivered for the stream
+pub async fn pump_from_tunnel(
+    mut writer: OwnedWriteHalf, mut payloads: mpsc::Receiver<Vec<u8>>,
+) -> Result<(), ProxyError> {
+    while let Some(payload) = payloads.recv().await {
+        writer.write_all(&payload).await?;
+    }
+
+    let _ = writer.shutdown().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frame_roundtrip() {
+        // Arrange
+        let frames = vec![
+            Frame::open(7),
+            Frame::data(7, b"hello tunnel".to_vec()),
+            Frame::close(7),
+            Frame::ping(),
+        ];
+        let mut buf = Vec::new();
+
+        // Act
+        for frame in &frames {
+            write_frame(&mut buf, frame).await.unwrap();
+        }
+        let mut reader = buf.as_slice();
+        let mut decoded = Vec::new();
+        while let Some(frame) = read_frame(&mut reader).await.unwrap() {
+            decoded.push(frame);
+        }
+
+        // Assert
+        assert_eq!(decoded, frames);
+    }
+
+    #[test]
+    fn test_destination_roundtrip() {
+        // Arrange
+        let destination = Destination {
+            protocol: DestinationProtocol::Udp,
+            host: "kube-dns.kube-system.svc".to_string(),
+            port: 53,
+        };
+
+        // Act
+        let frame = Frame::open_to(3, &destination);
+        let decoded = Destination::decode(&frame.payload).unwrap();
+
+        // Assert
+        assert_eq!(frame.kind, FrameKind::Open);
+        assert_eq!(decoded, destination);
+        assert!(Destination::decode(&[1, 0]).is_err());
+        assert!(Destination::decode(&[7, 0, 80, b'a']).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_frame_rejects_invalid_input() {
+        // Arrange
+        let unknown_kind = [9u8, 0, 0, 0, 1, 0, 0, 0, 0];
+        let mut oversized = vec![2u8, 0, 0, 0, 1];
+        oversized.extend_from_slice(&((MAX_FRAME_PAYLOAD as u32) + 1).to_be_bytes());
+
+        // Act
+        let kind_result = read_frame(&mut unknown_kind.as_slice()).await;
+        let size_result = read_frame(&mut oversized.as_slice()).await;
+
+        // Assert
+        assert!(matches!(kind_result, Err(ProxyError::InvalidData(_))));
+        assert!(matches!(size_result, Err(ProxyError::InvalidData(_))));
+    }
+}
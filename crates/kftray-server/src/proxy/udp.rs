@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     sync::Arc,
     time::Duration,
 };
@@ -8,6 +9,7 @@ use log::{
     debug,
     error,
     info,
+    warn,
 };
 use tokio::{
     io::{
@@ -19,26 +21,56 @@ use tokio::{
         TcpStream,
         UdpSocket,
     },
-    sync::Notify,
+    sync::{
+        mpsc,
+        Notify,
+    },
+    task::JoinSet,
+    time::sleep,
 };
 
 use crate::proxy::{
     config::ProxyConfig,
     error::ProxyError,
     traits::ProxyHandler,
+    tunnel::{
+        read_frame,
+        write_frames,
+        Frame,
+        FrameKind,
+    },
 };
 
 const UDP_TIMEOUT: Duration = Duration::from_secs(5);
 const MAX_UDP_PAYLOAD_SIZE: usize = 65507;
+/// Sent by a client as the first bytes of a connection to switch it to
+/// session framing. Read as a legacy length prefix it exceeds
+/// [`MAX_UDP_PAYLOAD_SIZE`], so older servers reject it.
+pub const SESSION_MAGIC: [u8; 4] = *b"KFUS";
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_SESSIONS: usize = 256;
+const FRAME_QUEUE_SIZE: usize = 256;
+const SESSION_QUEUE_SIZE: usize = 64;
 
 /// UDP proxy implementation that tunnels UDP traffic over TCP connections
+///
+/// A connection either carries a single UDP client with length-prefixed
+/// packets, or, after the client sent [`SESSION_MAGIC`], many UDP clients as
+/// tunnel frames whose stream id is the session id. Each session gets its own
+/// UDP socket so replies reach the client that sent the request.
 #[derive(Clone)]
-pub struct UdpProxy;
+pub struct UdpProxy {
+    session_idle_timeout: Duration,
+    max_sessions: usize,
+}
 
 impl UdpProxy {
     /// Creates a new UDP proxy instance
     pub fn new() -> Self {
-        Self
+        Self {
+            session_idle_timeout: SESSION_IDLE_TIMEOUT,
+            max_sessions: MAX_SESSIONS,
+        }
     }
 
     /// Creates and connects a UDP socket to the target server
@@ -63,53 +95,81 @@ impl UdpProxy {
 
     /// Handles a TCP connection carrying tunneled UDP traffic
     ///
-    /// Forwards UDP packets between the TCP client and target UDP server
+    /// Picks the framing from the first four bytes the client sends
     ///
     /// # Parameters
     /// * `tcp_stream` - Client TCP connection
     /// * `config` - Proxy configuration
-    async fn handle_udp_connection(
+    async fn handle_connection(
         &self, mut tcp_stream: TcpStream, config: &ProxyConfig,
+    ) -> Result<(), ProxyError> {
+        let mut first = [0u8; 4];
+        match tcp_stream.read_exact(&mut first).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                debug!("TCP connection closed");
+                return Ok(());
+            }
+            Err(e) => return Err(ProxyError::Io(e)),
+        }
+
+        if first == SESSION_MAGIC {
+            tcp_stream.write_all(&SESSION_MAGIC).await?;
+            tcp_stream.flush().await?;
+            return self.handle_udp_sessions(tcp_stream, config).await;
+        }
+
+        self.handle_udp_connection(tcp_stream, config, first).await
+    }
+
+    /// Forwards length-prefixed UDP packets of a single client, waiting for
+    /// one response per packet
+    ///
+    /// # Parameters
+    /// * `tcp_stream` - Client TCP connection
+    /// * `config` - Proxy configuration
+    /// * `size_buf` - Length prefix of the first packet, already read
+    async fn handle_udp_connection(
+        &self, mut tcp_stream: TcpStream, config: &ProxyConfig, mut size_buf: [u8; 4],
     ) -> Result<(), ProxyError> {
         let udp_socket = self.create_udp_socket(config).await?;
-        let mut size_buf = [0u8; 4];
 
         loop {
-            match tcp_stream.read_exact(&mut size_buf).await {
-                Ok(_) => {
-                    let size = u32::from_be_bytes(size_buf);
-                    debug!("Read size: {}", size);
-
-                    if size as usize > MAX_UDP_PAYLOAD_SIZE {
-                        let err = ProxyError::InvalidData(format!(
-                            "UDP packet size {} exceeds maximum allowed {}",
-                            size, MAX_UDP_PAYLOAD_SIZE
-                        ));
-                        tcp_stream.write_all(&0u32.to_be_bytes()).await?;
-                        tcp_stream.flush().await?;
-                        return Err(err);
-                    }
+            let size = u32::from_be_bytes(size_buf);
+            debug!("Read size: {}", size);
+
+            if size as usize > MAX_UDP_PAYLOAD_SIZE {
+                let err = ProxyError::InvalidData(format!(
+                    "UDP packet size {} exceeds maximum allowed {}",
+                    size, MAX_UDP_PAYLOAD_SIZE
+                ));
+                tcp_stream.write_all(&0u32.to_be_bytes()).await?;
+                tcp_stream.flush().await?;
+                return Err(err);
+            }
 
-                    let mut buffer = vec![0u8; size as usize];
-                    match tcp_stream.read_exact(&mut buffer).await {
-                        Ok(_) => {
-                            debug!("Received {} bytes from TCP", size);
-                            udp_socket.send(&buffer).await?;
-                            debug!("Sent {} bytes to UDP", size);
+            let mut buffer = vec![0u8; size as usize];
+            match tcp_stream.read_exact(&mut buffer).await {
+                Ok(_) => {
+                    debug!("Received {} bytes from TCP", size);
+                    udp_socket.send(&buffer).await?;
+                    debug!("Sent {} bytes to UDP", size);
 
-                            self.handle_udp_response(&udp_socket, &mut tcp_stream)
-                                .await?;
-                        }
-                        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-                            debug!("TCP connection closed while reading payload");
-                            break;
-                        }
-                        Err(e) => {
-                            error!("Error reading TCP payload: {}", e);
-                            return Err(ProxyError::Io(e));
-                        }
-                    }
+                    self.handle_udp_response(&udp_socket, &mut tcp_stream)
+                        .await?;
                 }
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    debug!("TCP connection closed while reading payload");
+                    break;
+                }
+                Err(e) => {
+                    error!("Error reading TCP payload: {}", e);
+                    return Err(ProxyError::Io(e));
+                }
+            }
+
+            match tcp_stream.read_exact(&mut size_buf).await {
+                Ok(_) => {}
                 Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
                     debug!("TCP connection closed");
                     break;
@@ -124,6 +184,143 @@ impl UdpProxy {
         Ok(())
     }
 
+    /// Serves the UDP sessions multiplexed over one connection
+    ///
+    /// The client opens a session per UDP source address. Sessions are closed
+    /// by either side, and by the server once they have been idle for the idle
+    /// timeout. Opens beyond the session limit are answered with a close.
+    ///
+    /// # Parameters
+    /// * `tcp_stream` - Client TCP connection, past the handshake
+    /// * `config` - Proxy configuration
+    async fn handle_udp_sessions(
+        &self, tcp_stream: TcpStream, config: &ProxyConfig,
+    ) -> Result<(), ProxyError> {
+        let _ = tcp_stream.set_nodelay(true);
+        let (mut reader, writer) = tcp_stream.into_split();
+        let (frames_tx, frames_rx) = mpsc::channel(FRAME_QUEUE_SIZE);
+        let writer_handle = tokio::spawn(write_frames(writer, frames_rx));
+
+        let mut sessions: HashMap<u32, mpsc::Sender<Vec<u8>>> = HashMap::new();
+        // Dropping the set when the connection ends aborts its remaining
+        // sessions
+        let mut tasks = JoinSet::new();
+
+        let result = async {
+            while let Some(frame) = read_frame(&mut reader).await? {
+                let session_id = frame.stream_id;
+
+                match frame.kind {
+                    FrameKind::Open => {
+                        sessions.retain(|_, sender| !sender.is_closed());
+                        if sessions.len() >= self.max_sessions {
+                            warn!(
+                                "Rejecting UDP session {}: limit of {} sessions reached",
+                                session_id, self.max_sessions
+                            );
+                            let _ = frames_tx.send(Frame::close(session_id)).await;
+                            continue;
+                        }
+
+                        let socket = match self.create_udp_socket(config).await {
+                            Ok(socket) => socket,
+                            Err(e) => {
+                                warn!("Rejecting UDP session {}: {}", session_id, e);
+                                let _ = frames_tx.send(Frame::close(session_id)).await;
+                                continue;
+                            }
+                        };
+
+                        let (tx, rx) = mpsc::channel(SESSION_QUEUE_SIZE);
+                        sessions.insert(session_id, tx);
+
+                        let frames = frames_tx.clone();
+                        let idle_timeout = self.session_idle_timeout;
+                        tasks.spawn(async move {
+                            if let Err(e) =
+                                Self::relay_session(session_id, socket, idle_timeout, frames, rx)
+                                    .await
+                            {
+                                error!("UDP session {} error: {}", session_id, e);
+                            }
+                        });
+                    }
+                    FrameKind::Data => {
+                        if frame.payload.len() > MAX_UDP_PAYLOAD_SIZE {
+                            debug!(
+                                "Dropping {} byte packet on UDP session {}",
+                                frame.payload.len(),
+                                session_id
+                            );
+                            continue;
+                        }
+                        if let Some(sender) = sessions.get(&session_id) {
+                            if sender.send(frame.payload).await.is_err() {
+                                sessions.remove(&session_id);
+                            }
+                        }
+                    }
+                    FrameKind::Close => {
+                        sessions.remove(&session_id);
+                    }
+                    FrameKind::Ping => debug!("UDP session ping"),
+                }
+            }
+
+            Ok(())
+        }
+        .await;
+
+        writer_handle.abort();
+        result
+    }
+
+    /// Relays the datagrams of one session until either side closes it or it
+    /// stays idle for `idle_timeout`
+    ///
+    /// # Parameters
+    /// * `session_id` - Session being relayed
+    /// * `socket` - UDP socket connected to the target
+    /// * `idle_timeout` - Time without traffic after which the session expires
+    /// * `frames` - Queue of frames for the connection writer
+    /// * `payloads` - Packets the client sent on this session
+    async fn relay_session(
+        session_id: u32, socket: UdpSocket, idle_timeout: Duration, frames: mpsc::Sender<Frame>,
+        mut payloads: mpsc::Receiver<Vec<u8>>,
+    ) -> Result<(), ProxyError> {
+        let mut buffer = vec![0u8; MAX_UDP_PAYLOAD_SIZE];
+
+        loop {
+            tokio::select! {
+                payload = payloads.recv() => {
+                    match payload {
+                        Some(payload) => {
+                            socket.send(&payload).await?;
+                        }
+                        None => break,
+                    }
+                }
+                result = socket.recv(&mut buffer) => {
+                    match result {
+                        Ok(n) => {
+                            if frames.send(Frame::data(session_id, buffer[..n].to_vec())).await.is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => debug!("UDP receive error on session {}: {}", session_id, e),
+                    }
+                }
+                _ = sleep(idle_timeout) => {
+                    debug!("UDP session {} expired after {:?} idle", session_id, idle_timeout);
+                    let _ = frames.send(Frame::close(session_id)).await;
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     async fn handle_udp_response(
         &self, udp_socket: &UdpSocket, tcp_stream: &mut TcpStream,
     ) -> Result<(), ProxyError> {
@@ -168,7 +365,7 @@ impl ProxyHandler for UdpProxy {
                             let proxy = self.clone();
 
                             tokio::spawn(async move {
-                                if let Err(e) = proxy.handle_udp_connection(stream, &config).await {
+                                if let Err(e) = proxy.handle_connection(stream, &config).await {
                                     error!("Error handling client: {}", e);
                                 }
                             });
@@ -209,13 +406,17 @@ mod tests {
             self,
             TestServer,
         },
+        tunnel::write_frame,
     };
 
     const TEST_TIMEOUT: Duration = Duration::from_secs(5);
 
     async fn setup_proxy() -> (TestServer, Arc<Notify>, SocketAddr) {
+        setup_proxy_with(UdpProxy::new()).await
+    }
+
+    async fn setup_proxy_with(proxy: UdpProxy) -> (TestServer, Arc<Notify>, SocketAddr) {
         let echo_server = test_utils::setup_test_udp_echo_server().await;
-        let proxy = UdpProxy::new();
         let shutdown = Arc::new(Notify::new());
         let shutdown_clone = shutdown.clone();
 
@@ -266,6 +467,23 @@ mod tests {
         Ok(response)
     }
 
+    async fn open_sessions(proxy_addr: SocketAddr) -> TcpStream {
+        let mut stream = TcpStream::connect(proxy_addr).await.unwrap();
+        stream.write_all(&SESSION_MAGIC).await.unwrap();
+        let mut ack = [0u8; 4];
+        stream.read_exact(&mut ack).await.unwrap();
+        assert_eq!(ack, SESSION_MAGIC);
+        stream
+    }
+
+    async fn next_frame(stream: &mut TcpStream) -> Frame {
+        tokio::time::timeout(TEST_TIMEOUT, read_frame(stream))
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap()
+    }
+
     #[tokio::test]
     async fn test_udp_proxy_echo() {
         // Arrange
@@ -351,4 +569,68 @@ mod tests {
         shutdown.notify_one();
         echo_server.shutdown();
     }
+
+    #[tokio::test]
+    async fn test_udp_proxy_sessions_are_kept_apart() {
+        // Arrange
+        let (echo_server, shutdown, proxy_addr) = setup_proxy().await;
+        let mut stream = open_sessions(proxy_addr).await;
+
+        // Act
+        for session_id in [1, 2] {
+            write_frame(&mut stream, &Frame::open(session_id))
+                .await
+                .unwrap();
+            let payload = format!("session {}", session_id).into_bytes();
+            write_frame(&mut stream, &Frame::data(session_id, payload))
+                .await
+                .unwrap();
+        }
+        let mut replies = HashMap::new();
+        for _ in 0..2 {
+            let frame = next_frame(&mut stream).await;
+            assert_eq!(frame.kind, FrameKind::Data);
+            replies.insert(frame.stream_id, frame.payload);
+        }
+
+        // Assert
+        assert_eq!(replies[&1], b"session 1");
+        assert_eq!(replies[&2], b"session 2");
+
+        // Cleanup
+        shutdown.notify_one();
+        echo_server.shutdown();
+    }
+
+    #[tokio::test]
+    async fn test_udp_proxy_session_limit_and_idle_expiry() {
+        // Arrange
+        let proxy = UdpProxy {
+            session_idle_timeout: Duration::from_millis(200),
+            max_sessions: 1,
+        };
+        let (echo_server, shutdown, proxy_addr) = setup_proxy_with(proxy).await;
+        let mut stream = open_sessions(proxy_addr).await;
+
+        // Act
+        write_frame(&mut stream, &Frame::open(1)).await.unwrap();
+        write_frame(&mut stream, &Frame::open(2)).await.unwrap();
+        let rejected = next_frame(&mut stream).await;
+        let expired = next_frame(&mut stream).await;
+        write_frame(&mut stream, &Frame::open(3)).await.unwrap();
+        write_frame(&mut stream, &Frame::data(3, b"after expiry".to_vec()))
+            .await
+            .unwrap();
+        let reply = next_frame(&mut stream).await;
+
+        // Assert
+        assert_eq!((rejected.kind, rejected.stream_id), (FrameKind::Close, 2));
+        assert_eq!((expired.kind, expired.stream_id), (FrameKind::Close, 1));
+        assert_eq!((reply.kind, reply.stream_id), (FrameKind::Data, 3));
+        assert_eq!(reply.payload, b"after expiry");
+
+        // Cleanup
+        shutdown.notify_one();
+        echo_server.shutdown();
+    }
 }
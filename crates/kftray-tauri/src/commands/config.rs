@@ -9,7 +9,23 @@ use kftray_commons::config::{
     insert_config,
     update_config,
 };
+use kftray_commons::config_bundle::{
+    export_encrypted_configs,
+    generate_bundle_identity,
+    import_encrypted_configs,
+    signing_public_key,
+};
+use kftray_commons::config_merge::merge_configs;
+use kftray_commons::models::bundle_model::{
+    BundleEncryption,
+    BundleIdentity,
+    BundleKey,
+};
 use kftray_commons::models::config_model::Config;
+use kftray_commons::models::import_model::{
+    ImportDiff,
+    ImportOptions,
+};
 use log::{
     error,
     info,
@@ -69,3 +85,46 @@ pub async fn import_configs_cmd(json: String) -> Result<(), String> {
     }
     Ok(())
 }
+
+#[tauri::command]
+pub async fn merge_configs_cmd(json: String, options: ImportOptions) -> Result<ImportDiff, String> {
+    info!(
+        "Merging configs (dry run: {}, prune: {})",
+        options.dry_run, options.prune
+    );
+    merge_configs(json, options).await.map_err(|e| {
+        error!("Error merging configs: {}", e);
+        format!("Error merging configs: {}", e)
+    })
+}
+
+#[tauri::command]
+pub async fn export_encrypted_configs_cmd(encryption: BundleEncryption) -> Result<String, String> {
+    export_encrypted_configs(&encryption).await.map_err(|e| {
+        error!("Error exporting config bundle: {}", e);
+        e
+    })
+}
+
+/// Imports a config bundle, returning the key it was signed with.
+#[tauri::command]
+pub async fn import_encrypted_configs_cmd(
+    bundle: String, key: BundleKey, trusted_signers: Vec<String>,
+) -> Result<String, String> {
+    import_encrypted_configs(&bundle, &key, &trusted_signers)
+        .await
+        .map_err(|e| {
+            error!("Error importing config bundle: {}", e);
+            e
+        })
+}
+
+#[tauri::command]
+pub async fn get_bundle_signing_key_cmd() -> Result<String, String> {
+    signing_public_key()
+}
+
+#[tauri::command]
+pub async fn generate_bundle_identity_cmd() -> Result<BundleIdentity, String> {
+    Ok(generate_bundle_identity())
+}
@@ -0,0 +1,51 @@
+use kftray_commons::config_merge::merge_configs;
+use kftray_commons::models::import_model::{
+    ImportDiff,
+    ImportOptions,
+};
+use kftray_config_source::credentials::{
+    delete_token,
+    get_token,
+    store_token,
+};
+use kftray_config_source::{
+    ConfigSource,
+    FetchedConfig,
+};
+use log::info;
+
+/// Merges the configs read from a Git repository, HTTPS URL or local
+/// directory into the local configs.
+///
+/// Without a token, the one stored for the source's host is used.
+#[tauri::command]
+pub async fn merge_configs_from_source_cmd(
+    source: ConfigSource, token: Option<String>, options: ImportOptions,
+) -> Result<ImportDiff, String> {
+    info!("Importing configs from {}", source);
+
+    let token = token
+        .filter(|t| !t.is_empty())
+        .or_else(|| source.host().and_then(|host| get_token(&host)));
+
+    match source.fetch(token, None).await.map_err(|e| e.to_string())? {
+        FetchedConfig::Changed { content, .. } => merge_configs(content, options).await,
+        FetchedConfig::Unchanged => Ok(ImportDiff::default()),
+    }
+}
+
+/// Stores the token for the host of a source, or removes it when empty.
+#[tauri::command]
+pub async fn save_source_token_cmd(
+    source: ConfigSource, token: Option<String>,
+) -> Result<(), String> {
+    let host = source
+        .host()
+        .ok_or_else(|| format!("{} does not use a token", source))?;
+
+    match token.filter(|t| !t.is_empty()) {
+        Some(token) => store_token(&host, &token),
+        None => delete_token(&host),
+    }
+    .map_err(|e| e.to_string())
+}
@@ -1,34 +1,72 @@
-use std::path::Path;
-use std::path::PathBuf;
-
-use git2::{
-    CertificateCheckStatus,
-    Cred,
-    FetchOptions,
-    RemoteCallbacks,
-    Repository,
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{
+    SystemTime,
+    UNIX_EPOCH,
 };
+
 use keyring::{
     Entry,
     Error as KeyringError,
 };
 use kftray_commons::{
+    config::get_config,
+    config_merge::merge_configs,
+    config_state::get_configs_state,
+    git_sync::{
+        apply_git_sync,
+        delete_git_sync,
+        get_git_sync,
+        plan_git_sync,
+        record_git_sync,
+        save_git_sync,
+    },
     models::config_model::Config,
+    models::git_sync_model::{
+        GitSyncResult,
+        GitSyncSubscription,
+    },
+    models::import_model::{
+        ImportDiff,
+        ImportOptions,
+    },
     utils::config::import_configs,
     utils::github::clear_existing_configs,
     utils::migration::migrate_configs,
 };
+use kftray_config_source::git::clone_and_read_config;
+use kftray_config_source::{
+    ConfigSource,
+    FetchedConfig,
+    GitOptions,
+};
+use kftray_http_logs::HttpLogState;
 use log::{
+    debug,
     error,
     info,
     warn,
 };
-use reqwest::Url;
 use tauri::{
     Error as TauriError,
     InvokeError,
 };
-use tempfile::TempDir;
+use tokio::time::{
+    interval_at,
+    Duration,
+    Instant,
+};
+
+use crate::commands::portforward::{
+    start_config_forward,
+    stop_config_forward,
+};
+
+/// Keyring entry holding the token of the git sync subscription
+const GIT_SYNC_KEYRING_SERVICE: &str = "kftray";
+const GIT_SYNC_KEYRING_NAME: &str = "git_sync_token";
+/// How often the poller checks whether the subscription is due for a sync
+const GIT_SYNC_TICK: Duration = Duration::from_secs(60);
 
 #[derive(Debug)]
 pub enum CustomError {
@@ -86,547 +124,302 @@ pub fn delete_key(service: &str, name: &str) -> std::result::Result<(), CustomEr
     Ok(())
 }
 
-mod credentials {
-    use super::*;
-
-    pub fn try_credentials_from_file() -> Vec<(String, String)> {
-        let home_dir = std::env::var("HOME").unwrap_or_default();
-        let credentials_path = Path::new(&home_dir).join(".git-credentials");
+fn read_repository_config(
+    repo_url: &str, config_path: &str, use_system_credentials: bool, github_token: Option<String>,
+) -> Result<String, String> {
+    let options = GitOptions {
+        use_system_credentials,
+        token: github_token,
+        ..Default::default()
+    };
+    clone_and_read_config(repo_url, config_path, &options)
+        .map(|(content, _)| content)
+        .map_err(|e| e.to_string())
+}
 
-        match std::fs::read_to_string(credentials_path) {
-            Ok(content) => parse_git_credentials(&content),
-            Err(_) => Vec::new(),
-        }
-    }
+async fn process_config_content(config_content: &str, flush: bool) -> Result<(), String> {
+    let configs: Vec<Config> = serde_json::from_str(config_content)
+        .map_err(|e| format!("Failed to parse config JSON: {}", e))?;
 
-    fn parse_git_credentials(content: &str) -> Vec<(String, String)> {
-        content
-            .lines()
-            .filter_map(|line| {
-                Url::parse(line.trim()).ok().and_then(|url| {
-                    let username = url.username().to_string();
-                    let password = url.password()?.to_string();
-                    if !username.is_empty() {
-                        Some((username, password))
-                    } else {
-                        None
-                    }
-                })
-            })
-            .collect()
+    if flush {
+        info!("Clearing existing configurations");
+        clear_existing_configs().await.map_err(|e| e.to_string())?;
     }
 
-    pub fn get_git_credentials(url: &str, username: &str) -> Result<Cred, git2::Error> {
-        info!("Getting credentials for URL: {}", url);
-
-        if url.starts_with("git@") || url.starts_with("ssh://") {
-            if let Ok(cred) = try_ssh_authentication(username) {
-                return Ok(cred);
-            }
-        }
-        if let Ok(cred) = try_credential_helper(url, username) {
-            return Ok(cred);
-        }
-
-        // Fall back to stored credentials
-        try_stored_credentials()
-    }
+    import_configurations(&configs).await?;
+    migrate_configurations().await
+}
 
-    fn try_ssh_agent(username: &str) -> Result<Cred, git2::Error> {
-        match Cred::ssh_key_from_agent(username) {
-            Ok(cred) => {
-                info!("Successfully authenticated with SSH agent");
-                Ok(cred)
-            }
-            Err(e) => {
-                info!("SSH agent authentication failed: {}", e);
-                Err(e)
-            }
-        }
+async fn import_configurations(configs: &[Config]) -> Result<(), String> {
+    info!("Importing {} new configurations", configs.len());
+    for config in configs {
+        let config_json = serde_json::to_string(config)
+            .map_err(|e| format!("Failed to serialize config: {}", e))?;
+        import_configs(config_json).await?;
     }
+    Ok(())
+}
 
-    fn try_git_ssh_config(username: &str) -> Result<Cred, git2::Error> {
-        let config = git2::Config::open_default()?;
-
-        let key_path_str = config.get_string("core.sshCommand").map_err(|e| {
-            info!("No core.sshCommand in git config: {}", e);
-            e
-        })?;
-
-        let key_arg_pos = key_path_str.find(" -i ").ok_or_else(|| {
-            info!("No -i flag in core.sshCommand");
-            git2::Error::from_str("No -i flag in core.sshCommand")
-        })?;
-
-        let key_path_start = key_arg_pos + 4;
-        let key_path_end = key_path_str[key_path_start..]
-            .find(' ')
-            .map(|pos| key_path_start + pos)
-            .unwrap_or(key_path_str.len());
-
-        let key_path_str = &key_path_str[key_path_start..key_path_end];
-        let key_path = Path::new(key_path_str);
-
-        if !key_path.exists() {
-            info!(
-                "SSH key from git config doesn't exist: {}",
-                key_path.display()
-            );
-            return Err(git2::Error::from_str(
-                "SSH key from git config doesn't exist",
-            ));
+async fn migrate_configurations() -> Result<(), String> {
+    match migrate_configs().await {
+        Ok(_) => {
+            info!("Configuration import completed successfully");
+            Ok(())
         }
-
-        info!("Trying SSH key from git config: {}", key_path.display());
-        match Cred::ssh_key(username, None, key_path, None) {
-            Ok(cred) => {
-                info!("Successfully authenticated with SSH key from git config");
-                Ok(cred)
-            }
-            Err(e) => {
-                info!("Failed to use SSH key from git config: {}", e);
-                Err(e)
-            }
+        Err(e) => {
+            error!("Error migrating configs: {}. Please check if the configurations are valid and compatible with the current system/version.", e);
+            Err(format!("Config migration failed: {}", e))
         }
     }
+}
 
-    fn try_env_ssh_key(username: &str) -> Result<Cred, git2::Error> {
-        let key_path_str = std::env::var("SSH_KEY_PATH")
-            .map_err(|_| git2::Error::from_str("SSH_KEY_PATH environment variable not set"))?;
-
-        let key_path = PathBuf::from(key_path_str);
-        if !key_path.exists() {
-            info!(
-                "SSH key from environment variable doesn't exist: {}",
-                key_path.display()
-            );
-            return Err(git2::Error::from_str(
-                "SSH key from environment variable doesn't exist",
-            ));
-        }
-
-        info!("Trying SSH key from SSH_KEY_PATH: {}", key_path.display());
-        match Cred::ssh_key(username, None, &key_path, None) {
-            Ok(cred) => {
-                info!("Successfully authenticated with SSH key from environment variable");
-                Ok(cred)
-            }
-            Err(e) => {
-                info!("Failed to use SSH key from environment variable: {}", e);
-                Err(e)
-            }
-        }
-    }
+#[tauri::command]
+pub async fn import_configs_from_github(
+    repo_url: String, config_path: String, use_system_credentials: bool, flush: bool,
+    github_token: Option<String>,
+) -> Result<(), String> {
+    let config_content = read_repository_config(
+        &repo_url,
+        &config_path,
+        use_system_credentials,
+        github_token,
+    )?;
+    process_config_content(&config_content, flush).await
+}
 
-    fn get_ssh_directories() -> Vec<PathBuf> {
-        let mut ssh_dirs = Vec::new();
+/// Merges the configs of a file in a Git repository into the local configs,
+/// updating the matching ones in place instead of importing duplicates.
+#[tauri::command]
+pub async fn merge_configs_from_github(
+    repo_url: String, config_path: String, use_system_credentials: bool,
+    github_token: Option<String>, options: ImportOptions,
+) -> Result<ImportDiff, String> {
+    let config_content = read_repository_config(
+        &repo_url,
+        &config_path,
+        use_system_credentials,
+        github_token,
+    )?;
+    merge_configs(config_content, options).await
+}
 
-        if let Ok(home_dir) = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE")) {
-            ssh_dirs.push(PathBuf::from(home_dir).join(".ssh"));
-        }
+#[tauri::command]
+pub async fn get_git_sync_cmd() -> Result<Option<GitSyncSubscription>, String> {
+    get_git_sync().await
+}
 
-        if let Ok(custom_ssh_dir) = std::env::var("SSH_DIR") {
-            ssh_dirs.push(PathBuf::from(custom_ssh_dir));
+/// Saves the git sync subscription. Its token is kept in the OS keyring, and
+/// the sync state is reset when the repository, ref or path changed.
+#[tauri::command]
+pub async fn save_git_sync_cmd(
+    mut subscription: GitSyncSubscription, github_token: Option<String>,
+) -> Result<(), String> {
+    let existing = get_git_sync().await?;
+    let same_source = existing.as_ref().is_some_and(|existing| {
+        existing.repo_url == subscription.repo_url
+            && existing.git_ref == subscription.git_ref
+            && existing.config_path == subscription.config_path
+    });
+
+    match existing.filter(|_| same_source) {
+        Some(existing) => {
+            subscription.last_synced_commit = existing.last_synced_commit;
+            subscription.last_synced_at = existing.last_synced_at;
+            subscription.last_error = existing.last_error;
         }
-
-        ssh_dirs
-    }
-
-    fn try_standard_key_names(username: &str, ssh_dirs: &[PathBuf]) -> Result<Cred, git2::Error> {
-        let key_names = ["id_ed25519", "id_rsa", "id_ecdsa", "id_dsa"];
-
-        for dir in ssh_dirs {
-            if !dir.exists() || !dir.is_dir() {
-                continue;
-            }
-
-            for key_name in &key_names {
-                let key_path = dir.join(key_name);
-                if key_path.exists() {
-                    info!("Trying standard SSH key: {}", key_path.display());
-                    if let Ok(cred) = Cred::ssh_key(username, None, &key_path, None) {
-                        info!(
-                            "Successfully authenticated with standard SSH key: {}",
-                            key_name
-                        );
-                        return Ok(cred);
-                    }
-                }
-            }
+        None => {
+            subscription.last_synced_commit = None;
+            subscription.last_synced_at = None;
+            subscription.last_error = None;
         }
-
-        Err(git2::Error::from_str(
-            "No standard SSH keys found or none worked",
-        ))
     }
 
-    fn try_key_file(username: &str, path: &Path) -> Result<Cred, git2::Error> {
-        if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-            if file_name.ends_with(".pub")
-                || file_name == "known_hosts"
-                || file_name == "authorized_keys"
-                || file_name == "config"
-            {
-                return Err(git2::Error::from_str("Not a private key file"));
-            }
-        } else {
-            return Err(git2::Error::from_str("Invalid file name"));
-        }
-
-        info!("Trying potential SSH key: {}", path.display());
-        match Cred::ssh_key(username, None, path, None) {
-            Ok(cred) => {
-                info!(
-                    "Successfully authenticated with SSH key: {}",
-                    path.display()
-                );
-                Ok(cred)
-            }
-            Err(e) => Err(e),
-        }
+    match github_token.filter(|token| !token.is_empty()) {
+        Some(token) => store_key(GIT_SYNC_KEYRING_SERVICE, GIT_SYNC_KEYRING_NAME, &token)
+            .map_err(|e| format!("Failed to store git sync token: {:?}", e))?,
+        None => delete_git_sync_token(),
     }
 
-    fn scan_directory_for_keys(username: &str, dir: &Path) -> Result<Cred, git2::Error> {
-        if !dir.exists() || !dir.is_dir() {
-            return Err(git2::Error::from_str(
-                "Directory doesn't exist or is not a directory",
-            ));
-        }
-
-        info!("Scanning for SSH keys in: {}", dir.display());
-
-        let entries = match std::fs::read_dir(dir) {
-            Ok(entries) => entries,
-            Err(e) => {
-                info!("Failed to read SSH directory {}: {}", dir.display(), e);
-                return Err(git2::Error::from_str(&format!(
-                    "Failed to read directory: {}",
-                    e
-                )));
-            }
-        };
-
-        for entry in entries {
-            let entry = match entry {
-                Ok(entry) => entry,
-                Err(_) => continue,
-            };
-
-            let path = entry.path();
-
-            if !path.is_file() {
-                continue;
-            }
+    save_git_sync(&subscription).await
+}
 
-            if let Ok(cred) = try_key_file(username, &path) {
-                return Ok(cred);
-            }
-        }
+/// Removes the git sync subscription, keeping the synced configs.
+#[tauri::command]
+pub async fn delete_git_sync_cmd() -> Result<(), String> {
+    delete_git_sync().await?;
+    delete_git_sync_token();
+    Ok(())
+}
 
-        Err(git2::Error::from_str(
-            "No valid SSH keys found in directory",
-        ))
-    }
+#[tauri::command]
+pub async fn sync_git_configs_cmd(
+    force: bool, http_log_state: tauri::State<'_, HttpLogState>,
+) -> Result<GitSyncResult, String> {
+    run_git_sync(Arc::new(http_log_state.inner().clone()), force).await
+}
 
-    fn scan_subdirectories_for_keys(username: &str, dir: &Path) -> Result<Cred, git2::Error> {
-        if !dir.exists() || !dir.is_dir() {
-            return Err(git2::Error::from_str(
-                "Directory doesn't exist or is not a directory",
-            ));
+/// Syncs the configs with the subscribed repository and records the outcome
+/// on the subscription.
+///
+/// Nothing is cloned when the remote ref still points to the last synced
+/// commit, unless `force` is set. Running forwards are only stopped when
+/// their config was removed, and restarted when it changed.
+pub async fn run_git_sync(
+    http_log_state: Arc<HttpLogState>, force: bool,
+) -> Result<GitSyncResult, String> {
+    let subscription = get_git_sync()
+        .await?
+        .ok_or_else(|| "No git sync subscription configured".to_string())?;
+
+    match sync_subscription(&subscription, http_log_state, force).await {
+        Ok(result) => {
+            record_git_sync(Some(result.commit.clone()), None).await?;
+            Ok(result)
         }
-
-        let subdirs = match std::fs::read_dir(dir) {
-            Ok(entries) => entries,
-            Err(e) => {
-                info!("Failed to read directory {}: {}", dir.display(), e);
-                return Err(git2::Error::from_str(&format!(
-                    "Failed to read directory: {}",
-                    e
-                )));
-            }
-        };
-
-        for subdir_entry in subdirs {
-            let subdir_entry = match subdir_entry {
-                Ok(entry) => entry,
-                Err(_) => continue,
-            };
-
-            let subdir_path = subdir_entry.path();
-            if !subdir_path.is_dir() {
-                continue;
-            }
-
-            let subdir_entries = match std::fs::read_dir(&subdir_path) {
-                Ok(entries) => entries,
-                Err(_) => continue,
-            };
-
-            for file_entry in subdir_entries {
-                let file_entry = match file_entry {
-                    Ok(entry) => entry,
-                    Err(_) => continue,
-                };
-
-                let file_path = file_entry.path();
-                if !file_path.is_file() {
-                    continue;
-                }
-
-                if let Ok(cred) = try_key_file(username, &file_path) {
-                    return Ok(cred);
-                }
-            }
+        Err(e) => {
+            error!("Git sync of {} failed: {}", subscription.repo_url, e);
+            record_git_sync(None, Some(e.clone())).await?;
+            Err(e)
         }
-
-        Err(git2::Error::from_str(
-            "No valid SSH keys found in subdirectories",
-        ))
     }
+}
 
-    fn try_ssh_authentication(username: &str) -> Result<Cred, git2::Error> {
-        if let Ok(cred) = try_ssh_agent(username) {
-            return Ok(cred);
-        }
-
-        if let Ok(cred) = try_git_ssh_config(username) {
-            return Ok(cred);
-        }
-
-        if let Ok(cred) = try_env_ssh_key(username) {
-            return Ok(cred);
-        }
-
-        let ssh_dirs = get_ssh_directories();
-
-        if let Ok(cred) = try_standard_key_names(username, &ssh_dirs) {
-            return Ok(cred);
+async fn sync_subscription(
+    subscription: &GitSyncSubscription, http_log_state: Arc<HttpLogState>, force: bool,
+) -> Result<GitSyncResult, String> {
+    let source = ConfigSource::Git {
+        repo_url: subscription.repo_url.clone(),
+        git_ref: subscription.git_ref.clone(),
+        config_path: subscription.config_path.clone(),
+        use_system_credentials: subscription.use_system_credentials,
+    };
+    let last_commit = subscription.last_synced_commit.clone().filter(|_| !force);
+
+    let (content, commit) = match source
+        .fetch(get_git_sync_token(), last_commit.as_deref())
+        .await
+        .map_err(|e| e.to_string())?
+    {
+        FetchedConfig::Changed { content, revision } => (content, revision),
+        FetchedConfig::Unchanged => {
+            let commit = last_commit.unwrap_or_default();
+            debug!("Git sync is up to date at {}", commit);
+            return Ok(GitSyncResult {
+                commit,
+                ..Default::default()
+            });
         }
+    };
 
-        for dir in &ssh_dirs {
-            if let Ok(cred) = scan_directory_for_keys(username, dir) {
-                return Ok(cred);
-            }
+    let configs: Vec<Config> = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse config JSON: {}", e))?;
+    let plan = plan_git_sync(configs).await?;
+
+    let alias = |config: &Config| config.alias.clone().unwrap_or_default();
+    let mut result = GitSyncResult {
+        commit,
+        added: plan.added.iter().map(|(_, c)| alias(c)).collect(),
+        updated: plan.updated.iter().map(|(_, c)| alias(c)).collect(),
+        removed: plan.removed.iter().map(alias).collect(),
+        unchanged: plan.unchanged.len(),
+        restarted: Vec::new(),
+    };
+
+    if plan.is_empty() {
+        info!("Git sync at {} found no config changes", result.commit);
+        return Ok(result);
+    }
 
-            if let Ok(cred) = scan_subdirectories_for_keys(username, dir) {
-                return Ok(cred);
+    let running: HashSet<i64> = get_configs_state()
+        .await?
+        .into_iter()
+        .filter(|state| state.is_running)
+        .map(|state| state.config_id)
+        .collect();
+
+    for config in &plan.removed {
+        if config.id.is_some_and(|id| running.contains(&id)) {
+            if let Err(e) = stop_config_forward(config).await {
+                warn!("Failed to stop removed forward {}: {}", alias(config), e);
             }
         }
-
-        Err(git2::Error::from_str(
-            "SSH authentication failed: no valid SSH keys found",
-        ))
     }
 
-    fn try_credential_helper(url: &str, username: &str) -> Result<Cred, git2::Error> {
-        if let Ok(config) = git2::Config::open_default() {
-            match Cred::credential_helper(&config, url, Some(username)) {
-                Ok(cred) => {
-                    info!("Successfully retrieved credentials from OS credential store");
-                    Ok(cred)
-                }
-                Err(e) => {
-                    info!("Credential helper failed: {}", e);
-                    Err(e)
-                }
-            }
-        } else {
-            Err(git2::Error::from_str("Failed to open git config"))
+    let mut restart = Vec::new();
+    for (_, config) in &plan.updated {
+        let Some(id) = config.id.filter(|id| running.contains(id)) else {
+            continue;
+        };
+        let previous = get_config(id).await?;
+        if let Err(e) = stop_config_forward(&previous).await {
+            warn!("Failed to stop changed forward {}: {}", alias(&previous), e);
         }
+        restart.push(id);
     }
 
-    fn try_stored_credentials() -> Result<Cred, git2::Error> {
-        let credentials = try_credentials_from_file();
+    apply_git_sync(&plan).await?;
 
-        for (username, password) in credentials {
-            info!("Trying stored credentials for username: {}", username);
-            if let Ok(cred) = Cred::userpass_plaintext(&username, &password) {
-                info!("Successfully authenticated with stored credentials");
-                return Ok(cred);
-            }
+    for id in restart {
+        let config = get_config(id).await?;
+        let name = alias(&config);
+        match start_config_forward(config, http_log_state.clone()).await {
+            Ok(_) => result.restarted.push(name),
+            Err(e) => warn!("Failed to restart forward {} after git sync: {}", name, e),
         }
-
-        Err(git2::Error::from_str("No valid credentials found"))
     }
-}
 
-fn clone_and_read_config(
-    repo_url: &str, config_path: &str, use_system_credentials: bool, github_token: Option<String>,
-) -> Result<String, String> {
-    let temp_dir = TempDir::new().map_err(|e| format!("Failed to create temp dir: {}", e))?;
-
-    let callbacks = setup_git_callbacks(use_system_credentials, github_token);
-    let mut builder = setup_repo_builder(callbacks);
-
-    info!("Attempting to clone repository: {}", repo_url);
-    clone_repository(&mut builder, repo_url, temp_dir.path())?;
-
-    read_config_file(temp_dir.path(), config_path)
+    Ok(result)
 }
 
-fn setup_git_callbacks(
-    use_system_credentials: bool, github_token: Option<String>,
-) -> RemoteCallbacks<'static> {
-    let mut callbacks = RemoteCallbacks::new();
-
-    // Only set up credentials callback if authentication is needed
-    if use_system_credentials || github_token.is_some() {
-        let token = github_token.clone();
+/// Syncs the subscription in the background whenever its polling interval
+/// has passed since the last sync or the last failed attempt.
+pub async fn poll_git_sync(http_log_state: Arc<HttpLogState>) {
+    let mut ticker = interval_at(Instant::now() + GIT_SYNC_TICK, GIT_SYNC_TICK);
+    let mut last_attempt: Option<Instant> = None;
 
-        let attempts = std::sync::atomic::AtomicUsize::new(0);
+    loop {
+        ticker.tick().await;
 
-        callbacks.credentials(move |url, username_from_url, allowed_types| {
-            let current_attempt = attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-            if current_attempt >= 3 {
-                return Err(git2::Error::from_str(
-                    "Authentication failed after 3 attempts",
-                ));
-            }
-
-            info!(
-                "Auth attempt {} - URL: {}, Username: {:?}, Allowed types: {:?}",
-                current_attempt + 1,
-                url,
-                username_from_url,
-                allowed_types
-            );
-
-            let is_https_url = url.starts_with("https://");
-
-            if is_https_url
-                && token.is_some()
-                && allowed_types.contains(git2::CredentialType::USER_PASS_PLAINTEXT)
-            {
-                info!("Using token authentication for HTTPS");
-                return Cred::userpass_plaintext("git", token.as_ref().unwrap());
-            }
-
-            if use_system_credentials {
-                let username = username_from_url.unwrap_or("git");
-                credentials::get_git_credentials(url, username)
-            } else {
-                Err(git2::Error::from_str("No authentication method configured"))
+        let subscription = match get_git_sync().await {
+            Ok(Some(subscription)) if subscription.polling_interval > 0 => subscription,
+            Ok(_) => continue,
+            Err(e) => {
+                error!("Failed to read git sync subscription: {}", e);
+                continue;
             }
-        });
-    }
-
-    callbacks.certificate_check(|_cert, _hostname| Ok(CertificateCheckStatus::CertificateOk));
-
-    callbacks
-}
-
-fn setup_repo_builder(callbacks: RemoteCallbacks) -> git2::build::RepoBuilder {
-    let mut fetch_opts = FetchOptions::new();
-    fetch_opts.remote_callbacks(callbacks);
-
-    let mut builder = git2::build::RepoBuilder::new();
-    builder.fetch_options(fetch_opts);
-    builder
-}
+        };
 
-fn clone_repository(
-    builder: &mut git2::build::RepoBuilder, repo_url: &str, path: &Path,
-) -> Result<Repository, String> {
-    match builder.clone(repo_url, path) {
-        Ok(repo) => Ok(repo),
-        Err(e) => {
-            warn!(
-                "Repository clone failed: {}, trying fallback with system git command",
-                e
-            );
-
-            try_clone_with_system_git(repo_url, path).map_err(|err| format!(
-                    "Failed to clone repository. Please check your credentials and repository URL. Error: {}",
-                    err
-                ))
+        let period = Duration::from_secs(subscription.polling_interval * 60);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        let synced_recently = subscription
+            .last_synced_at
+            .is_some_and(|at| now - at < period.as_secs() as i64);
+        let attempted_recently = last_attempt.is_some_and(|at| at.elapsed() < period);
+        if synced_recently || attempted_recently {
+            continue;
         }
-    }
-}
-
-fn try_clone_with_system_git(repo_url: &str, path: &Path) -> Result<Repository, String> {
-    use std::process::Command;
-
-    let output = Command::new("git")
-        .arg("clone")
-        .arg("--depth=1")
-        .arg("--single-branch")
-        .arg("--no-tags")
-        .arg("--filter=blob:none")
-        .arg("--recurse-submodules=no")
-        .arg(repo_url)
-        .arg(path)
-        .output()
-        .map_err(|e| format!("Failed to execute git command: {}", e))?;
-
-    if output.status.success() {
-        info!("Successfully cloned repository using system git");
-        Repository::open(path).map_err(|e| format!("Failed to open repository: {}", e))
-    } else {
-        let error_msg = String::from_utf8_lossy(&output.stderr);
-        error!("System git clone failed: {}", error_msg);
-        Err(format!("System git clone failed: {}", error_msg))
-    }
-}
 
-fn read_config_file(temp_dir: &Path, config_path: &str) -> Result<String, String> {
-    let config_path = Path::new(config_path);
-    let full_path = temp_dir.join(config_path);
-
-    std::fs::read_to_string(&full_path).map_err(|e| {
-        format!(
-            "Failed to read config file at {}: {}",
-            full_path.display(),
-            e
-        )
-    })
-}
-
-async fn process_config_content(config_content: &str, flush: bool) -> Result<(), String> {
-    let configs: Vec<Config> = serde_json::from_str(config_content)
-        .map_err(|e| format!("Failed to parse config JSON: {}", e))?;
-
-    if flush {
-        info!("Clearing existing configurations");
-        clear_existing_configs().await.map_err(|e| e.to_string())?;
+        last_attempt = Some(Instant::now());
+        match run_git_sync(http_log_state.clone(), false).await {
+            Ok(result) => debug!("Git sync poll finished: {:?}", result),
+            Err(e) => warn!("Git sync poll failed: {}", e),
+        }
     }
-
-    import_configurations(&configs).await?;
-    migrate_configurations().await
 }
 
-async fn import_configurations(configs: &[Config]) -> Result<(), String> {
-    info!("Importing {} new configurations", configs.len());
-    for config in configs {
-        let config_json = serde_json::to_string(config)
-            .map_err(|e| format!("Failed to serialize config: {}", e))?;
-        import_configs(config_json).await?;
-    }
-    Ok(())
+fn get_git_sync_token() -> Option<String> {
+    get_key(GIT_SYNC_KEYRING_SERVICE, GIT_SYNC_KEYRING_NAME).ok()
 }
 
-async fn migrate_configurations() -> Result<(), String> {
-    match migrate_configs().await {
-        Ok(_) => {
-            info!("Configuration import completed successfully");
-            Ok(())
-        }
-        Err(e) => {
-            error!("Error migrating configs: {}. Please check if the configurations are valid and compatible with the current system/version.", e);
-            Err(format!("Config migration failed: {}", e))
+fn delete_git_sync_token() {
+    if let Err(CustomError::Keyring(e)) =
+        delete_key(GIT_SYNC_KEYRING_SERVICE, GIT_SYNC_KEYRING_NAME)
+    {
+        if !matches!(e, KeyringError::NoEntry) {
+            warn!("Failed to delete git sync token: {}", e);
         }
     }
 }
-
-#[tauri::command]
-pub async fn import_configs_from_github(
-    repo_url: String, config_path: String, use_system_credentials: bool, flush: bool,
-    github_token: Option<String>,
-) -> Result<(), String> {
-    let config_content = clone_and_read_config(
-        &repo_url,
-        &config_path,
-        use_system_credentials,
-        github_token,
-    )?;
-    process_config_content(&config_content, flush).await
-}
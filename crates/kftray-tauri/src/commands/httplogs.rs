@@ -5,7 +5,18 @@ use std::path::{
 };
 
 use kftray_commons::utils::config_dir::get_log_folder_path;
-use kftray_http_logs::HttpLogState;
+use kftray_http_logs::{
+    CassetteConfig,
+    HttpLogState,
+    LogConfig,
+    LogFilterConfig,
+    LogProtocol,
+    LogUsageReport,
+    MockRulesConfig,
+    PruneReport,
+    RedactionConfig,
+    RetentionManager,
+};
 use log::{
     error,
     info,
@@ -33,6 +44,147 @@ pub async fn get_http_logs_cmd(
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn set_http_log_redaction_cmd(
+    state: tauri::State<'_, HttpLogState>, config_id: i64, redaction: RedactionConfig,
+) -> Result<(), String> {
+    state
+        .set_redaction_config(config_id, redaction)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_http_log_redaction_cmd(
+    state: tauri::State<'_, HttpLogState>, config_id: i64,
+) -> Result<RedactionConfig, String> {
+    state
+        .get_redaction_config(config_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_http_log_filter_cmd(
+    state: tauri::State<'_, HttpLogState>, config_id: i64, filter: LogFilterConfig,
+) -> Result<(), String> {
+    state
+        .set_filter_config(config_id, filter)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_http_log_filter_cmd(
+    state: tauri::State<'_, HttpLogState>, config_id: i64,
+) -> Result<LogFilterConfig, String> {
+    state
+        .get_filter_config(config_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_http_log_grpc_descriptor_cmd(
+    state: tauri::State<'_, HttpLogState>, config_id: i64, descriptor_set: Option<String>,
+) -> Result<(), String> {
+    state
+        .set_grpc_descriptor_set(config_id, descriptor_set.map(PathBuf::from))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_http_log_grpc_descriptor_cmd(
+    state: tauri::State<'_, HttpLogState>, config_id: i64,
+) -> Result<Option<String>, String> {
+    state
+        .get_grpc_descriptor_set(config_id)
+        .await
+        .map(|path| path.map(|p| p.to_string_lossy().into_owned()))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_http_log_protocol_cmd(
+    state: tauri::State<'_, HttpLogState>, config_id: i64, protocol: LogProtocol,
+) -> Result<(), String> {
+    state
+        .set_log_protocol(config_id, protocol)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_http_log_protocol_cmd(
+    state: tauri::State<'_, HttpLogState>, config_id: i64,
+) -> Result<LogProtocol, String> {
+    state
+        .get_log_protocol(config_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_http_mock_rules_cmd(
+    state: tauri::State<'_, HttpLogState>, config_id: i64, rules: MockRulesConfig,
+) -> Result<(), String> {
+    state
+        .set_mock_rules(config_id, rules)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_http_mock_rules_cmd(
+    state: tauri::State<'_, HttpLogState>, config_id: i64,
+) -> Result<MockRulesConfig, String> {
+    state
+        .get_mock_rules(config_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_http_cassette_cmd(
+    state: tauri::State<'_, HttpLogState>, config_id: i64, cassette: Option<CassetteConfig>,
+) -> Result<(), String> {
+    state
+        .set_cassette(config_id, cassette)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_http_cassette_cmd(
+    state: tauri::State<'_, HttpLogState>, config_id: i64,
+) -> Result<Option<CassetteConfig>, String> {
+    state
+        .get_cassette(config_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_http_log_quota_cmd(
+    state: tauri::State<'_, HttpLogState>, config_id: i64, quota: Option<u64>,
+) -> Result<(), String> {
+    state
+        .set_disk_quota(config_id, quota)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_http_log_quota_cmd(
+    state: tauri::State<'_, HttpLogState>, config_id: i64,
+) -> Result<Option<u64>, String> {
+    state
+        .get_disk_quota(config_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
 // File System Operations
 
 #[tauri::command]
@@ -47,6 +199,42 @@ pub async fn get_http_log_size() -> Result<u64, String> {
     calculate_folder_size(&log_folder_path)
 }
 
+#[tauri::command]
+pub async fn get_http_log_usage(
+    state: tauri::State<'_, HttpLogState>,
+) -> Result<LogUsageReport, String> {
+    retention_manager(&state)
+        .await?
+        .usage_report()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn prune_http_logs(state: tauri::State<'_, HttpLogState>) -> Result<PruneReport, String> {
+    let report = retention_manager(&state)
+        .await?
+        .prune()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    info!(
+        "Pruned {} expired and {} over-quota log files ({} bytes)",
+        report.expired_files, report.quota_files, report.freed_bytes
+    );
+
+    Ok(report)
+}
+
+async fn retention_manager(state: &HttpLogState) -> Result<RetentionManager, String> {
+    let log_folder_path = get_log_folder_path()?;
+    Ok(RetentionManager::new(
+        LogConfig::builder(log_folder_path)
+            .config_disk_quotas(state.disk_quotas().await)
+            .build(),
+    ))
+}
+
 fn get_and_validate_log_folder() -> Result<PathBuf, String> {
     let log_folder_path = get_log_folder_path()?;
 
@@ -1,4 +1,6 @@
 use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
 
 use anyhow::Result;
 use k8s_openapi::api::core::v1::{
@@ -8,15 +10,31 @@ use k8s_openapi::api::core::v1::{
 };
 use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
 use kftray_commons::config_model::Config;
+use kftray_commons::discovery::get_discovered_configs;
+use kftray_commons::models::discovery_model::DiscoveredConfig;
+use kftray_http_logs::HttpLogState;
 use kftray_portforward::kube::client::create_client_with_specific_context;
 use kftray_portforward::kube::models::{
+    DiscoveryReport,
+    DiscoveryWatchOptions,
     KubeContextInfo,
     KubeNamespaceInfo,
     KubeServiceInfo,
     KubeServicePortInfo,
+    ManifestDiscoveryOptions,
+    MultiContextDiscoveryOptions,
     PodInfo,
+    ServiceDiscovery,
+};
+use kftray_portforward::kube::{
+    discover_context_services,
+    discover_manifest_configs,
+    discover_service_configs,
+    retrieve_service_configs,
+    running_discovery_watches,
+    start_discovery_watch,
+    stop_discovery_watch,
 };
-use kftray_portforward::kube::retrieve_service_configs;
 use kube::Resource;
 use kube::{
     api::{
@@ -289,3 +307,83 @@ pub async fn get_services_with_annotations(
 
     retrieve_service_configs(&context_name, kubeconfig_path).await
 }
+
+/// Discovers annotated services in a context, limited to the given
+/// namespaces when there are any, reporting the namespaces it skipped.
+#[tauri::command]
+pub async fn discover_context_services_cmd(
+    context_name: String, kubeconfig_path: Option<String>, namespaces: Option<Vec<String>>,
+) -> Result<ServiceDiscovery, String> {
+    info!(
+        "discover_context_services_cmd called with context: '{}' and namespaces: {:?}",
+        context_name, namespaces
+    );
+
+    discover_context_services(
+        &context_name,
+        kubeconfig_path,
+        &namespaces.unwrap_or_default(),
+    )
+    .await
+}
+
+/// Discovers annotated services in several contexts at once, reporting the
+/// configs that are not configured yet.
+#[tauri::command]
+pub async fn discover_services_cmd(
+    options: MultiContextDiscoveryOptions,
+) -> Result<DiscoveryReport, String> {
+    info!(
+        "discover_services_cmd called with contexts: {:?}",
+        options.contexts
+    );
+
+    discover_service_configs(&options).await
+}
+
+/// Generates configs from rendered manifests, like `helm template` output,
+/// without cluster access.
+#[tauri::command]
+pub async fn get_manifest_configs(
+    path: String, options: ManifestDiscoveryOptions,
+) -> Result<Vec<Config>, String> {
+    info!(
+        "get_manifest_configs called with path: '{}' and context: '{}'",
+        path, options.context
+    );
+
+    tokio::task::spawn_blocking(move || discover_manifest_configs(Path::new(&path), &options))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Keeps the configs of a context's annotated services in sync until the
+/// watch is stopped.
+#[tauri::command]
+pub async fn start_discovery_watch_cmd(
+    options: DiscoveryWatchOptions, http_log_state: tauri::State<'_, HttpLogState>,
+) -> Result<(), String> {
+    info!(
+        "start_discovery_watch_cmd called with context: '{}'",
+        options.context
+    );
+
+    start_discovery_watch(options, Arc::new(http_log_state.inner().clone())).await
+}
+
+#[tauri::command]
+pub async fn stop_discovery_watch_cmd(context: String) -> Result<bool, String> {
+    Ok(stop_discovery_watch(&context))
+}
+
+#[tauri::command]
+pub async fn get_discovery_watches_cmd() -> Result<Vec<String>, String> {
+    Ok(running_discovery_watches())
+}
+
+#[tauri::command]
+pub async fn get_discovered_configs_cmd(
+    context: Option<String>,
+) -> Result<Vec<DiscoveredConfig>, String> {
+    get_discovered_configs(context.as_deref()).await
+}
@@ -1,4 +1,5 @@
 pub mod config;
+pub mod config_source;
 pub mod config_state;
 pub mod github;
 pub mod httplogs;
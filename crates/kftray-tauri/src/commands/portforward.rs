@@ -5,12 +5,28 @@ use kftray_commons::models::config_model::Config;
 use kftray_commons::models::response::CustomResponse;
 use kftray_commons::utils::config_state::get_configs_state;
 use kftray_http_logs::HttpLogState;
+use kftray_portforward::kube::fault::{
+    get_fault_profile,
+    set_fault_profile,
+    FaultPreset,
+    FaultProfile,
+};
+use kftray_portforward::kube::models::{
+    OrphanedPod,
+    PreflightReport,
+};
 use kftray_portforward::kube::{
+    check_forward_permissions,
+    delete_orphaned_proxy_pods,
     deploy_and_forward_pod,
+    deploy_and_forward_shared,
+    find_orphaned_proxy_pods,
     start_port_forward,
+    start_reverse_tunnel,
     stop_all_port_forward,
     stop_port_forward,
     stop_proxy_forward,
+    stop_shared_proxy_forward,
 };
 use log::error;
 use log::info;
@@ -80,6 +96,36 @@ fn config_compare_changes<T: PartialEq>(prev: &[T], current: &[T]) -> bool {
     true
 }
 
+/// Starts the forward of a config the way its workload type requires.
+pub(crate) async fn start_config_forward(
+    config: Config, http_log_state: Arc<HttpLogState>,
+) -> Result<Vec<CustomResponse>, String> {
+    let configs = vec![config.clone()];
+    match config.workload_type.as_deref() {
+        Some("proxy") => deploy_and_forward_pod(configs, http_log_state).await,
+        Some("reverse") => start_reverse_tunnel(configs).await,
+        Some("shared-proxy") => deploy_and_forward_shared(configs).await,
+        _ => start_port_forward(configs, &config.protocol, http_log_state).await,
+    }
+}
+
+/// Stops the forward of a config the way its workload type requires.
+pub(crate) async fn stop_config_forward(config: &Config) -> Result<CustomResponse, String> {
+    let config_id = config.id.unwrap_or_default();
+    match config.workload_type.as_deref() {
+        Some("proxy") | Some("reverse") => {
+            stop_proxy_forward(
+                config_id,
+                &config.namespace,
+                config.service.clone().unwrap_or_default(),
+            )
+            .await
+        }
+        Some("shared-proxy") => stop_shared_proxy_forward(config_id).await,
+        _ => stop_port_forward(config_id.to_string()).await,
+    }
+}
+
 #[tauri::command]
 pub async fn start_port_forward_udp_cmd(
     configs: Vec<Config>, http_log_state: tauri::State<'_, HttpLogState>,
@@ -128,6 +174,13 @@ pub async fn deploy_and_forward_pod_cmd(
     deploy_and_forward_pod(configs.clone(), Arc::new(http_log_state.inner().clone())).await
 }
 
+#[tauri::command]
+pub async fn start_reverse_tunnel_cmd(
+    configs: Vec<Config>, _app_handle: tauri::AppHandle,
+) -> Result<Vec<CustomResponse>, String> {
+    start_reverse_tunnel(configs).await
+}
+
 #[tauri::command]
 pub async fn stop_proxy_forward_cmd(
     config_id: String, namespace: &str, service_name: String, _app_handle: tauri::AppHandle,
@@ -139,6 +192,66 @@ pub async fn stop_proxy_forward_cmd(
     stop_proxy_forward(config_id, namespace, service_name).await
 }
 
+#[tauri::command]
+pub async fn deploy_and_forward_shared_cmd(
+    configs: Vec<Config>, _app_handle: tauri::AppHandle,
+) -> Result<Vec<CustomResponse>, String> {
+    deploy_and_forward_shared(configs).await
+}
+
+#[tauri::command]
+pub async fn stop_shared_proxy_forward_cmd(
+    config_id: i64, _app_handle: tauri::AppHandle,
+) -> Result<CustomResponse, String> {
+    stop_shared_proxy_forward(config_id).await
+}
+
+/// Checks the RBAC permissions each config's forward needs, without
+/// starting it.
+#[tauri::command]
+pub async fn check_forward_permissions_cmd(
+    configs: Vec<Config>,
+) -> Result<Vec<PreflightReport>, String> {
+    let mut reports = Vec::new();
+    for config in &configs {
+        reports.push(check_forward_permissions(config).await?);
+    }
+    Ok(reports)
+}
+
+#[tauri::command]
+pub async fn find_orphaned_proxy_pods_cmd(
+    context_name: &str, kubeconfig: Option<String>, user: Option<String>,
+) -> Result<Vec<OrphanedPod>, String> {
+    find_orphaned_proxy_pods(kubeconfig, Some(context_name), user).await
+}
+
+#[tauri::command]
+pub async fn delete_orphaned_proxy_pods_cmd(
+    context_name: &str, kubeconfig: Option<String>, user: Option<String>,
+) -> Result<Vec<OrphanedPod>, String> {
+    delete_orphaned_proxy_pods(kubeconfig, Some(context_name), user).await
+}
+
+#[tauri::command]
+pub async fn set_fault_profile_cmd(
+    config_id: i64, profile: Option<FaultProfile>,
+) -> Result<(), String> {
+    set_fault_profile(config_id, profile).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_fault_preset_cmd(
+    config_id: i64, preset: Option<FaultPreset>,
+) -> Result<(), String> {
+    set_fault_profile(config_id, preset.map(|preset| preset.profile())).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_fault_profile_cmd(config_id: i64) -> Result<Option<FaultProfile>, String> {
+    Ok(get_fault_profile(config_id))
+}
+
 #[tauri::command]
 pub async fn handle_exit_app(app_handle: tauri::AppHandle) {
     let windows_map = app_handle.windows();
@@ -10,6 +10,8 @@ use kftray_commons::config_state_model::ConfigState;
 use kftray_commons::models::config_model::Config;
 use kftray_http_logs::HttpLogState;
 use kftray_portforward::kube::deploy_and_forward_pod;
+use kftray_portforward::kube::deploy_and_forward_shared;
+use kftray_portforward::kube::start_reverse_tunnel;
 use kftray_portforward::start_port_forward;
 use log::{
     debug,
@@ -194,6 +196,8 @@ async fn start_port_forwarding(config: Config) -> Result<(), String> {
     let forward_future = async {
         match config.workload_type.as_deref() {
             Some("proxy") => deploy_and_forward_pod(configs, http_log_state.clone()).await,
+            Some("reverse") => start_reverse_tunnel(configs).await,
+            Some("shared-proxy") => deploy_and_forward_shared(configs).await,
             _ => start_port_forward(configs, protocol, http_log_state.clone()).await,
         }
     };
@@ -108,6 +108,11 @@ fn main() {
                 check_and_emit_changes(app_handle_clone).await;
             });
 
+            let git_sync_log_state = Arc::new(app.state::<HttpLogState>().inner().clone());
+            tauri::async_runtime::spawn(async move {
+                commands::github::poll_git_sync(git_sync_log_state).await;
+            });
+
             #[cfg(target_os = "macos")]
             {
                 app.set_activation_policy(tauri::ActivationPolicy::Accessory);
@@ -148,10 +153,40 @@ fn main() {
             commands::kubecontext::list_pods,
             commands::kubecontext::list_ports,
             commands::kubecontext::get_services_with_annotations,
+            commands::kubecontext::discover_services_cmd,
+            commands::kubecontext::discover_context_services_cmd,
+            commands::kubecontext::get_manifest_configs,
+            commands::kubecontext::start_discovery_watch_cmd,
+            commands::kubecontext::stop_discovery_watch_cmd,
+            commands::kubecontext::get_discovery_watches_cmd,
+            commands::kubecontext::get_discovered_configs_cmd,
             commands::portforward::deploy_and_forward_pod_cmd,
+            commands::portforward::start_reverse_tunnel_cmd,
+            commands::portforward::deploy_and_forward_shared_cmd,
+            commands::portforward::stop_shared_proxy_forward_cmd,
             commands::portforward::stop_proxy_forward_cmd,
+            commands::portforward::check_forward_permissions_cmd,
+            commands::portforward::find_orphaned_proxy_pods_cmd,
+            commands::portforward::delete_orphaned_proxy_pods_cmd,
+            commands::portforward::set_fault_profile_cmd,
+            commands::portforward::set_fault_preset_cmd,
+            commands::portforward::get_fault_profile_cmd,
             commands::httplogs::set_http_logs_cmd,
             commands::httplogs::get_http_logs_cmd,
+            commands::httplogs::set_http_log_redaction_cmd,
+            commands::httplogs::get_http_log_redaction_cmd,
+            commands::httplogs::set_http_log_filter_cmd,
+            commands::httplogs::get_http_log_filter_cmd,
+            commands::httplogs::set_http_log_grpc_descriptor_cmd,
+            commands::httplogs::get_http_log_grpc_descriptor_cmd,
+            commands::httplogs::set_http_log_protocol_cmd,
+            commands::httplogs::get_http_log_protocol_cmd,
+            commands::httplogs::set_http_mock_rules_cmd,
+            commands::httplogs::get_http_mock_rules_cmd,
+            commands::httplogs::set_http_cassette_cmd,
+            commands::httplogs::get_http_cassette_cmd,
+            commands::httplogs::set_http_log_quota_cmd,
+            commands::httplogs::get_http_log_quota_cmd,
             commands::config::get_configs_cmd,
             commands::config::insert_config_cmd,
             commands::config::delete_config_cmd,
@@ -159,17 +194,31 @@ fn main() {
             commands::config::update_config_cmd,
             commands::config::export_configs_cmd,
             commands::config::import_configs_cmd,
+            commands::config::merge_configs_cmd,
+            commands::config::export_encrypted_configs_cmd,
+            commands::config::import_encrypted_configs_cmd,
+            commands::config::get_bundle_signing_key_cmd,
+            commands::config::generate_bundle_identity_cmd,
+            commands::config_source::merge_configs_from_source_cmd,
+            commands::config_source::save_source_token_cmd,
             commands::config::delete_configs_cmd,
             commands::config::delete_all_configs_cmd,
             commands::window_state::open_save_dialog,
             commands::window_state::close_save_dialog,
             commands::github::import_configs_from_github,
+            commands::github::merge_configs_from_github,
             commands::httplogs::open_log_file,
             commands::httplogs::clear_http_logs,
             commands::httplogs::get_http_log_size,
+            commands::httplogs::get_http_log_usage,
+            commands::httplogs::prune_http_logs,
             commands::github::store_key,
             commands::github::get_key,
             commands::github::delete_key,
+            commands::github::get_git_sync_cmd,
+            commands::github::save_git_sync_cmd,
+            commands::github::delete_git_sync_cmd,
+            commands::github::sync_git_configs_cmd,
             commands::window_state::toggle_pin_state,
             commands::config_state::get_config_states,
         ])
@@ -14,9 +14,12 @@ use kftray_http_logs::HttpLogState;
 use kftray_portforward::kube::stop_all_port_forward;
 use kftray_portforward::kube::{
     deploy_and_forward_pod,
+    deploy_and_forward_shared,
     start_port_forward,
+    start_reverse_tunnel,
     stop_port_forward,
     stop_proxy_forward,
+    stop_shared_proxy_forward,
 };
 use log::error;
 
@@ -36,6 +39,20 @@ pub async fn start_port_forwarding(app: &mut App, config: Config) {
                 app.state = AppState::ShowErrorPopup;
             }
         }
+        Some("shared-proxy") => {
+            if let Err(e) = deploy_and_forward_shared(vec![config.clone()]).await {
+                error!("Failed to start shared proxy forward: {:?}", e);
+                app.error_message = Some(format!("Failed to start shared proxy forward: {:?}", e));
+                app.state = AppState::ShowErrorPopup;
+            }
+        }
+        Some("reverse") => {
+            if let Err(e) = start_reverse_tunnel(vec![config.clone()]).await {
+                error!("Failed to start reverse tunnel: {:?}", e);
+                app.error_message = Some(format!("Failed to start reverse tunnel: {:?}", e));
+                app.state = AppState::ShowErrorPopup;
+            }
+        }
         Some("service") | Some("pod") => match config.protocol.as_str() {
             "tcp" => {
                 let log_state = Arc::new(HttpLogState::new());
@@ -64,7 +81,7 @@ pub async fn start_port_forwarding(app: &mut App, config: Config) {
 
 pub async fn stop_port_forwarding(app: &mut App, config: Config) {
     match config.workload_type.as_deref() {
-        Some("proxy") => {
+        Some("proxy") | Some("reverse") => {
             if let Err(e) = stop_proxy_forward(
                 config.id.unwrap_or_default(),
                 &config.namespace,
@@ -77,6 +94,13 @@ pub async fn stop_port_forwarding(app: &mut App, config: Config) {
                 app.state = AppState::ShowErrorPopup;
             }
         }
+        Some("shared-proxy") => {
+            if let Err(e) = stop_shared_proxy_forward(config.id.unwrap_or_default()).await {
+                error!("Failed to stop shared proxy forward: {:?}", e);
+                app.error_message = Some(format!("Failed to stop shared proxy forward: {:?}", e));
+                app.state = AppState::ShowErrorPopup;
+            }
+        }
         Some("service") | Some("pod") => {
             if let Err(e) = stop_port_forward(config.id.unwrap_or_default().to_string()).await {
                 error!("Failed to stop port forward: {:?}", e);
@@ -25,6 +25,7 @@ use tokio::time::{
 
 use crate::tui::input::{
     handle_input,
+    poll_git_import,
     App,
 };
 use crate::tui::ui::draw_ui;
@@ -67,6 +68,7 @@ async fn run_app<B: ratatui::backend::Backend>(
         let mut config_states = read_config_states().await.unwrap_or_default();
 
         app.update_configs(&configs, &config_states);
+        poll_git_import(app);
 
         terminal.draw(|f| {
             draw_ui(f, app, &config_states);
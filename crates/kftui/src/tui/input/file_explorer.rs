@@ -6,10 +6,15 @@ use crossterm::event::{
     KeyEvent,
     KeyModifiers,
 };
+use kftray_commons::models::import_model::{
+    ImportDiff,
+    ImportOptions,
+};
 use ratatui_explorer::Input;
 
 use crate::tui::input::App;
 use crate::tui::input::AppState;
+use crate::tui::input::DeleteButton;
 use crate::utils::config::{
     export_configs_to_file,
     import_configs_from_file,
@@ -40,10 +45,25 @@ fn handle_file_error(app: &mut App, error: std::io::Error) {
     app.state = AppState::ShowErrorPopup;
 }
 
+/// Shows the diff an import would apply, which is only applied once
+/// confirmed.
 async fn handle_import(app: &mut App, selected_path: &Path) -> Result<(), std::io::Error> {
     if selected_path.is_file() {
-        match import_configs_from_file(selected_path.to_str().unwrap()).await {
-            Ok(_) => show_confirmation_popup(app, "Import successful".to_string()),
+        let options = ImportOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        match import_configs_from_file(selected_path.to_str().unwrap(), options).await {
+            Ok(diff) if diff.is_empty() => show_confirmation_popup(
+                app,
+                format!("Nothing to import, {} configs unchanged", diff.unchanged),
+            ),
+            Ok(diff) => {
+                app.pending_import = Some(selected_path.to_path_buf());
+                app.selected_import_button = DeleteButton::Confirm;
+                app.import_export_message = Some(import_preview(&diff));
+                app.state = AppState::ShowImportConfirmation;
+            }
             Err(e) => show_error_popup(app, format!("Import failed: {}", e)),
         }
     } else {
@@ -69,13 +89,91 @@ async fn handle_export(app: &mut App, export_path: &Path) -> Result<(), std::io:
     Ok(())
 }
 
-fn show_confirmation_popup(app: &mut App, message: String) {
+pub async fn handle_import_confirmation_input(
+    app: &mut App, key: KeyCode,
+) -> Result<(), std::io::Error> {
+    match key {
+        KeyCode::Left | KeyCode::Right => {
+            app.selected_import_button = match app.selected_import_button {
+                DeleteButton::Confirm => DeleteButton::Close,
+                DeleteButton::Close => DeleteButton::Confirm,
+            };
+        }
+        KeyCode::Enter => match app.pending_import.take() {
+            Some(path) if app.selected_import_button == DeleteButton::Confirm => {
+                match import_configs_from_file(path.to_str().unwrap(), ImportOptions::default())
+                    .await
+                {
+                    Ok(diff) => show_confirmation_popup(app, import_summary(&diff)),
+                    Err(e) => show_error_popup(app, format!("Import failed: {}", e)),
+                }
+            }
+            _ => close_import_confirmation(app),
+        },
+        KeyCode::Esc => {
+            app.pending_import = None;
+            close_import_confirmation(app);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn close_import_confirmation(app: &mut App) {
+    app.import_export_message = None;
+    app.state = AppState::Normal;
+}
+
+/// Lists what an import would change, for confirmation before applying it.
+pub(crate) fn import_preview(diff: &ImportDiff) -> String {
+    let mut lines = Vec::new();
+    if !diff.added.is_empty() {
+        let added: Vec<&str> = diff.added.iter().map(|c| c.identity.as_str()).collect();
+        lines.push(format!("Add: {}", added.join(", ")));
+    }
+    for change in &diff.changed {
+        lines.push(format!(
+            "Change {}: {}",
+            change.identity,
+            change.fields.join(", ")
+        ));
+    }
+    if !diff.removed.is_empty() {
+        let removed: Vec<&str> = diff.removed.iter().map(|c| c.identity.as_str()).collect();
+        lines.push(format!("Remove: {}", removed.join(", ")));
+    }
+    for conflict in &diff.conflicting {
+        lines.push(format!("Skip {}: {}", conflict.identity, conflict.reason));
+    }
+    lines.push(format!("{} unchanged", diff.unchanged));
+    lines.join("\n")
+}
+
+pub(crate) fn import_summary(diff: &ImportDiff) -> String {
+    let mut message = format!(
+        "Import successful: {} added, {} changed, {} unchanged",
+        diff.added.len(),
+        diff.changed.len(),
+        diff.unchanged
+    );
+    if !diff.conflicting.is_empty() {
+        let conflicts: Vec<String> = diff
+            .conflicting
+            .iter()
+            .map(|c| format!("{}: {}", c.identity, c.reason))
+            .collect();
+        message.push_str(&format!("\nSkipped conflicting:\n{}", conflicts.join("\n")));
+    }
+    message
+}
+
+pub(crate) fn show_confirmation_popup(app: &mut App, message: String) {
     app.import_export_message = Some(message);
     app.state = AppState::ShowConfirmationPopup;
     log::debug!("State changed to ShowConfirmationPopup");
 }
 
-fn show_error_popup(app: &mut App, message: String) {
+pub(crate) fn show_error_popup(app: &mut App, message: String) {
     app.import_export_message = Some(message.clone());
     app.error_message = Some(message);
     app.state = AppState::ShowErrorPopup;
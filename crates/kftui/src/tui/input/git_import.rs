@@ -0,0 +1,233 @@
+use std::sync::Arc;
+
+use crossterm::event::KeyCode;
+use kftray_commons::models::import_model::ImportDiff;
+use kftray_config_source::{
+    ConfigSource,
+    GitProgress,
+};
+use tokio::sync::mpsc::{
+    unbounded_channel,
+    UnboundedReceiver,
+};
+
+use crate::tui::input::file_explorer::{
+    import_summary,
+    show_confirmation_popup,
+    show_error_popup,
+};
+use crate::tui::input::{
+    App,
+    AppState,
+};
+use crate::utils::config::import_configs_from_source;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum GitImportField {
+    RepoUrl,
+    GitRef,
+    ConfigPath,
+    Token,
+    SystemCredentials,
+}
+
+impl GitImportField {
+    const ALL: [GitImportField; 5] = [
+        GitImportField::RepoUrl,
+        GitImportField::GitRef,
+        GitImportField::ConfigPath,
+        GitImportField::Token,
+        GitImportField::SystemCredentials,
+    ];
+
+    fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|f| *f == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    fn previous(self) -> Self {
+        let index = Self::ALL.iter().position(|f| *f == self).unwrap_or(0);
+        Self::ALL[(index + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
+
+/// Fields of the import from Git dialog.
+pub struct GitImportForm {
+    pub repo_url: String,
+    pub git_ref: String,
+    pub config_path: String,
+    pub token: String,
+    pub use_system_credentials: bool,
+    pub selected_field: GitImportField,
+}
+
+impl Default for GitImportForm {
+    fn default() -> Self {
+        Self {
+            repo_url: String::new(),
+            git_ref: String::new(),
+            config_path: "configs.json".to_string(),
+            token: String::new(),
+            use_system_credentials: true,
+            selected_field: GitImportField::RepoUrl,
+        }
+    }
+}
+
+impl GitImportForm {
+    fn selected_text(&mut self) -> Option<&mut String> {
+        match self.selected_field {
+            GitImportField::RepoUrl => Some(&mut self.repo_url),
+            GitImportField::GitRef => Some(&mut self.git_ref),
+            GitImportField::ConfigPath => Some(&mut self.config_path),
+            GitImportField::Token => Some(&mut self.token),
+            GitImportField::SystemCredentials => None,
+        }
+    }
+
+    fn source(&self) -> ConfigSource {
+        let git_ref = self.git_ref.trim();
+        ConfigSource::Git {
+            repo_url: self.repo_url.trim().to_string(),
+            git_ref: (!git_ref.is_empty()).then(|| git_ref.to_string()),
+            config_path: self.config_path.trim().to_string(),
+            use_system_credentials: self.use_system_credentials,
+        }
+    }
+}
+
+pub enum GitImportEvent {
+    Progress(GitProgress),
+    Done(Result<ImportDiff, String>),
+}
+
+/// An import from Git running in the background.
+pub struct GitImportTask {
+    pub source: String,
+    pub status: String,
+    /// Auth methods tried so far, in order.
+    pub auth_attempts: Vec<String>,
+    events: UnboundedReceiver<GitImportEvent>,
+}
+
+pub fn open_git_import(app: &mut App) {
+    app.state = AppState::ShowGitImport;
+    app.git_import_form.selected_field = GitImportField::RepoUrl;
+}
+
+pub async fn handle_git_import_input(app: &mut App, key: KeyCode) -> std::io::Result<()> {
+    let form = &mut app.git_import_form;
+
+    match key {
+        KeyCode::Esc => app.state = AppState::Normal,
+        KeyCode::Tab | KeyCode::Down => form.selected_field = form.selected_field.next(),
+        KeyCode::BackTab | KeyCode::Up => form.selected_field = form.selected_field.previous(),
+        KeyCode::Enter => start_git_import(app),
+        KeyCode::Char(' ') if form.selected_field == GitImportField::SystemCredentials => {
+            form.use_system_credentials = !form.use_system_credentials;
+        }
+        KeyCode::Char(c) => {
+            if let Some(text) = form.selected_text() {
+                text.push(c);
+            }
+        }
+        KeyCode::Backspace => {
+            if let Some(text) = form.selected_text() {
+                text.pop();
+            }
+        }
+        _ => log::debug!("Unhandled key in git import: {:?}", key),
+    }
+    Ok(())
+}
+
+fn start_git_import(app: &mut App) {
+    let form = &app.git_import_form;
+    if form.repo_url.trim().is_empty() || form.config_path.trim().is_empty() {
+        show_error_popup(
+            app,
+            "Repository URL and config path are required".to_string(),
+        );
+        return;
+    }
+
+    let source = form.source();
+    let token = Some(form.token.clone()).filter(|t| !t.is_empty());
+    let (sender, events) = unbounded_channel();
+
+    app.git_import_task = Some(GitImportTask {
+        source: source.to_string(),
+        status: "Starting".to_string(),
+        auth_attempts: Vec::new(),
+        events,
+    });
+    app.state = AppState::GitImportInProgress;
+
+    let progress_sender = sender.clone();
+    let progress = Arc::new(move |progress: GitProgress| {
+        let _ = progress_sender.send(GitImportEvent::Progress(progress));
+    });
+
+    tokio::spawn(async move {
+        let result = import_configs_from_source(source, token, progress).await;
+        let _ = sender.send(GitImportEvent::Done(result));
+    });
+}
+
+/// Applies the events of a running import, showing its outcome once done.
+pub fn poll_git_import(app: &mut App) {
+    let Some(task) = app.git_import_task.as_mut() else {
+        return;
+    };
+
+    let mut outcome = None;
+    while let Ok(event) = task.events.try_recv() {
+        match event {
+            GitImportEvent::Progress(progress) => apply_progress(task, progress),
+            GitImportEvent::Done(result) => outcome = Some(result),
+        }
+    }
+
+    match outcome {
+        Some(Ok(diff)) => {
+            app.git_import_task = None;
+            show_confirmation_popup(app, import_summary(&diff));
+        }
+        Some(Err(e)) => {
+            app.git_import_task = None;
+            show_error_popup(app, format!("Import failed: {}", e));
+        }
+        None => {}
+    }
+}
+
+fn apply_progress(task: &mut GitImportTask, progress: GitProgress) {
+    task.status = match progress {
+        GitProgress::Connecting { url } => format!("Connecting to {}", url),
+        GitProgress::AuthAttempt { attempt, method } => {
+            let method = method.to_string();
+            if !task.auth_attempts.contains(&method) {
+                task.auth_attempts.push(method.clone());
+            }
+            format!("Authenticating (attempt {}) with {}", attempt, method)
+        }
+        GitProgress::Receiving {
+            received_objects,
+            total_objects,
+            received_bytes,
+        } => format!(
+            "Receiving objects {}/{} ({} KiB)",
+            received_objects,
+            total_objects,
+            received_bytes / 1024
+        ),
+        GitProgress::Resolving {
+            indexed_deltas,
+            total_deltas,
+        } => format!("Resolving deltas {}/{}", indexed_deltas, total_deltas),
+        GitProgress::SystemGitFallback => "Retrying with the git command".to_string(),
+        GitProgress::CheckingOut { commit } => {
+            format!("Checking out {}", &commit[..commit.len().min(12)])
+        }
+    };
+}
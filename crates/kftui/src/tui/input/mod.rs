@@ -1,4 +1,5 @@
 mod file_explorer;
+mod git_import;
 mod navigation;
 mod popup;
 
@@ -13,10 +14,16 @@ use crossterm::event::{
 };
 use crossterm::terminal::size;
 pub use file_explorer::*;
+pub use git_import::*;
 use kftray_commons::models::{
     config_model::Config,
     config_state_model::ConfigState,
 };
+use kftray_portforward::kube::fault::{
+    get_fault_profile,
+    set_fault_profile,
+    FaultPreset,
+};
 use log::LevelFilter;
 pub use popup::*;
 use ratatui::widgets::ListState;
@@ -64,7 +71,10 @@ pub enum AppState {
     ShowHelp,
     ShowAbout,
     ShowDeleteConfirmation,
+    ShowImportConfirmation,
     ShowContextSelection,
+    ShowGitImport,
+    GitImportInProgress,
 }
 
 pub struct App {
@@ -89,13 +99,20 @@ pub struct App {
     pub selected_menu_item: usize,
     pub delete_confirmation_message: Option<String>,
     pub selected_delete_button: DeleteButton,
+    /// File whose import waits for confirmation of its dry-run diff.
+    pub pending_import: Option<std::path::PathBuf>,
+    pub selected_import_button: DeleteButton,
     pub visible_rows: usize,
     pub table_state_stopped: TableState,
     pub table_state_running: TableState,
     pub contexts: Vec<String>,
+    /// Contexts marked for discovery, the highlighted one is used when empty.
+    pub selected_contexts: HashSet<String>,
     pub selected_context_index: usize,
     pub context_list_state: ListState,
     pub logger_state: TuiWidgetState,
+    pub git_import_form: GitImportForm,
+    pub git_import_task: Option<GitImportTask>,
 }
 
 impl App {
@@ -127,13 +144,18 @@ impl App {
             selected_menu_item: 0,
             delete_confirmation_message: None,
             selected_delete_button: DeleteButton::Confirm,
+            pending_import: None,
+            selected_import_button: DeleteButton::Confirm,
             visible_rows: 0,
             table_state_stopped: TableState::default(),
             table_state_running: TableState::default(),
             contexts: Vec::new(),
+            selected_contexts: HashSet::new(),
             selected_context_index: 0,
             context_list_state: ListState::default(),
             logger_state,
+            git_import_form: GitImportForm::default(),
+            git_import_task: None,
         };
 
         if let Ok((_, height)) = size() {
@@ -288,10 +310,21 @@ pub async fn handle_input(app: &mut App, _config_states: &mut [ConfigState]) ->
                     log::debug!("Handling ShowDeleteConfirmation state");
                     handle_delete_confirmation_input(app, key.code).await?;
                 }
+                AppState::ShowImportConfirmation => {
+                    log::debug!("Handling ShowImportConfirmation state");
+                    handle_import_confirmation_input(app, key.code).await?;
+                }
                 AppState::ShowContextSelection => {
                     log::debug!("Handling ShowContextSelection state");
                     handle_context_selection_input(app, key.code).await?;
                 }
+                AppState::ShowGitImport => {
+                    log::debug!("Handling ShowGitImport state");
+                    handle_git_import_input(app, key.code).await?;
+                }
+                AppState::GitImportInProgress => {
+                    log::debug!("Ignoring input while a git import is running");
+                }
                 AppState::Normal => {
                     log::debug!("Handling Normal state");
                     handle_normal_input(app, key.code).await?;
@@ -447,7 +480,7 @@ async fn handle_menu_input(app: &mut App, key: KeyCode) -> io::Result<()> {
             }
         }
         KeyCode::Right => {
-            if app.selected_menu_item < 5 {
+            if app.selected_menu_item < 6 {
                 app.selected_menu_item += 1
             }
         }
@@ -461,9 +494,10 @@ async fn handle_menu_input(app: &mut App, key: KeyCode) -> io::Result<()> {
             0 => app.state = AppState::ShowHelp,
             1 => handle_auto_add_configs(app).await,
             2 => open_import_file_explorer(app),
-            3 => open_export_file_explorer(app),
-            4 => app.state = AppState::ShowAbout,
-            5 => stop_all_port_forward_and_exit(app).await,
+            3 => open_git_import(app),
+            4 => open_export_file_explorer(app),
+            5 => app.state = AppState::ShowAbout,
+            6 => stop_all_port_forward_and_exit(app).await,
             _ => {}
         },
         _ => {}
@@ -550,6 +584,7 @@ async fn handle_running_table_input(app: &mut App, key: KeyCode) -> io::Result<(
         }
         KeyCode::Char(' ') => toggle_row_selection(app),
         KeyCode::Char('f') => handle_port_forwarding(app).await?,
+        KeyCode::Char('n') => cycle_fault_preset(app),
         KeyCode::Char('d') => show_delete_confirmation(app),
         KeyCode::Char('a') => toggle_select_all(app),
         _ => {}
@@ -615,6 +650,10 @@ async fn handle_common_hotkeys(app: &mut App, key: KeyCode) -> io::Result<bool>
             open_import_file_explorer(app);
             Ok(true)
         }
+        KeyCode::Char('g') => {
+            open_git_import(app);
+            Ok(true)
+        }
         KeyCode::Char('e') => {
             open_export_file_explorer(app);
             Ok(true)
@@ -646,6 +685,51 @@ fn toggle_row_selection(app: &mut App) {
     }
 }
 
+fn cycle_fault_preset(app: &mut App) {
+    let rows: Vec<usize> = if app.selected_rows_running.is_empty() {
+        vec![app.selected_row_running]
+    } else {
+        app.selected_rows_running.iter().copied().collect()
+    };
+
+    let configs: Vec<Config> = rows
+        .iter()
+        .filter_map(|&row| app.running_configs.get(row).cloned())
+        .collect();
+
+    for config in configs {
+        let config_id = config.id.unwrap_or_default();
+        let current = get_fault_profile(config_id).and_then(|p| FaultPreset::matching(&p));
+
+        let next = match current {
+            None => FaultPreset::ALL.first().copied(),
+            Some(preset) => FaultPreset::ALL
+                .iter()
+                .skip_while(|p| **p != preset)
+                .nth(1)
+                .copied(),
+        };
+
+        if let Err(e) = set_fault_profile(config_id, next.map(|preset| preset.profile())) {
+            app.error_message = Some(format!("Failed to set network fault: {}", e));
+            app.state = AppState::ShowErrorPopup;
+            return;
+        }
+
+        match next {
+            Some(preset) => log::info!(
+                "Network fault '{}' enabled for {}",
+                preset,
+                config.alias.clone().unwrap_or_default()
+            ),
+            None => log::info!(
+                "Network fault disabled for {}",
+                config.alias.clone().unwrap_or_default()
+            ),
+        }
+    }
+}
+
 async fn handle_port_forwarding(app: &mut App) -> io::Result<()> {
     let (selected_rows, configs, selected_row) = match app.active_table {
         ActiveTable::Stopped => (
@@ -754,8 +838,33 @@ fn open_export_file_explorer(app: &mut App) {
 
 pub async fn handle_context_selection_input(app: &mut App, key: KeyCode) -> io::Result<()> {
     if let KeyCode::Enter = key {
-        if let Some(selected_context) = app.contexts.get(app.selected_context_index).cloned() {
-            handle_context_selection(app, &selected_context).await;
+        let contexts: Vec<String> = if app.selected_contexts.is_empty() {
+            app.contexts
+                .get(app.selected_context_index)
+                .cloned()
+                .into_iter()
+                .collect()
+        } else {
+            app.contexts
+                .iter()
+                .filter(|context| app.selected_contexts.contains(*context))
+                .cloned()
+                .collect()
+        };
+        if !contexts.is_empty() {
+            handle_context_selection(app, contexts).await;
+        }
+    } else if let KeyCode::Char(' ') = key {
+        if let Some(context) = app.contexts.get(app.selected_context_index).cloned() {
+            if !app.selected_contexts.remove(&context) {
+                app.selected_contexts.insert(context);
+            }
+        }
+    } else if let KeyCode::Char('a') = key {
+        if app.selected_contexts.len() == app.contexts.len() {
+            app.selected_contexts.clear();
+        } else {
+            app.selected_contexts = app.contexts.iter().cloned().collect();
         }
     } else if let KeyCode::Up = key {
         if app.selected_context_index > 0 {
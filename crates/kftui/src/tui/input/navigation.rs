@@ -1,12 +1,20 @@
 use kftray_commons::models::config_model::Config;
 use kftray_commons::utils::config::insert_config;
 use kftray_portforward::kube::client::list_kube_contexts;
-use kftray_portforward::kube::retrieve_service_configs;
+use kftray_portforward::kube::discover_service_configs;
+use kftray_portforward::kube::models::{
+    DiscoveryReport,
+    MultiContextDiscoveryOptions,
+};
 
 use crate::core::port_forward::{
     start_port_forwarding,
     stop_port_forwarding,
 };
+use crate::tui::input::file_explorer::{
+    show_confirmation_popup,
+    show_error_popup,
+};
 use crate::tui::input::ActiveTable;
 use crate::tui::input::{
     App,
@@ -33,27 +41,70 @@ pub async fn handle_auto_add_configs(app: &mut App) {
 
     app.state = AppState::ShowContextSelection;
     app.contexts = contexts;
+    app.selected_contexts.clear();
     app.selected_context_index = 0;
     app.context_list_state.select(Some(0));
 }
 
-pub async fn handle_context_selection(app: &mut App, context: &str) {
-    let configs = match retrieve_service_configs(context, None).await {
-        Ok(configs) => configs,
+/// Discovers the services of the given contexts in parallel and adds the
+/// configs that are not configured yet.
+pub async fn handle_context_selection(app: &mut App, contexts: Vec<String>) {
+    let options = MultiContextDiscoveryOptions {
+        contexts,
+        ..Default::default()
+    };
+    let report = match discover_service_configs(&options).await {
+        Ok(report) => report,
         Err(e) => {
-            app.error_message = Some(format!("Failed to retrieve service configs: {}", e));
-            app.state = AppState::ShowErrorPopup;
+            show_error_popup(app, format!("Failed to retrieve service configs: {}", e));
             return;
         }
     };
 
-    for config in configs {
-        if let Err(e) = insert_config(config).await {
-            app.error_message = Some(format!("Failed to insert config: {}", e));
-            app.state = AppState::ShowErrorPopup;
+    for config in report.new_configs() {
+        if let Err(e) = insert_config(config.clone()).await {
+            show_error_popup(app, format!("Failed to insert config: {}", e));
             return;
         }
     }
 
-    app.state = AppState::Normal;
+    app.selected_contexts.clear();
+    show_confirmation_popup(app, discovery_summary(&report));
+}
+
+fn discovery_summary(report: &DiscoveryReport) -> String {
+    let existing: usize = report.contexts.iter().map(|c| c.existing).sum();
+    let duplicates: usize = report.contexts.iter().map(|c| c.duplicates).sum();
+
+    let mut message = format!(
+        "Discovered {} new configs in {} contexts, {} already configured",
+        report.new_configs().count(),
+        report.contexts.len(),
+        existing
+    );
+    if duplicates > 0 {
+        message.push_str(&format!(", {} duplicates skipped", duplicates));
+    }
+
+    let failed: Vec<String> = report
+        .failed()
+        .map(|c| format!("{}: {}", c.context, c.error.as_deref().unwrap_or_default()))
+        .collect();
+    if !failed.is_empty() {
+        message.push_str(&format!("\nFailed contexts:\n{}", failed.join("\n")));
+    }
+
+    let skipped: Vec<String> = report
+        .contexts
+        .iter()
+        .flat_map(|c| {
+            c.skipped_namespaces
+                .iter()
+                .map(move |s| format!("{}/{}: {}", c.context, s.namespace, s.reason))
+        })
+        .collect();
+    if !skipped.is_empty() {
+        message.push_str(&format!("\nSkipped namespaces:\n{}", skipped.join("\n")));
+    }
+    message
 }
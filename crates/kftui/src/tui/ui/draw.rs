@@ -35,6 +35,7 @@ use crate::tui::input::{
 use crate::tui::ui::render_context_selection_popup;
 use crate::tui::ui::render_delete_confirmation_popup;
 use crate::tui::ui::render_details;
+use crate::tui::ui::render_import_confirmation_popup;
 use crate::tui::ui::MAUVE;
 use crate::tui::ui::{
     centered_rect,
@@ -44,6 +45,8 @@ use crate::tui::ui::{
     render_background_overlay,
     render_confirmation_popup,
     render_error_popup,
+    render_git_import_popup,
+    render_git_import_progress_popup,
     render_help_popup,
     render_input_prompt,
     render_legend,
@@ -179,11 +182,33 @@ pub fn draw_ui(f: &mut Frame, app: &mut App, config_states: &[ConfigState]) {
                 app.selected_delete_button,
             );
         }
+        AppState::ShowImportConfirmation => {
+            let import_area = centered_rect(60, 50, size);
+            render_background_overlay(f, size);
+            render_import_confirmation_popup(
+                f,
+                &app.import_export_message,
+                import_area,
+                app.selected_import_button,
+            );
+        }
         AppState::ShowContextSelection => {
             let context_selection_area = centered_rect(50, 50, size);
             render_background_overlay(f, size);
             render_context_selection_popup(f, app, context_selection_area);
         }
+        AppState::ShowGitImport => {
+            let git_import_area = centered_rect(60, 50, size);
+            render_background_overlay(f, size);
+            render_git_import_popup(f, &app.git_import_form, git_import_area);
+        }
+        AppState::GitImportInProgress => {
+            if let Some(task) = &app.git_import_task {
+                let progress_area = centered_rect(60, 40, size);
+                render_background_overlay(f, size);
+                render_git_import_progress_popup(f, task, progress_area);
+            }
+        }
         _ => {}
     }
 }
@@ -229,7 +254,15 @@ pub fn render_logs(f: &mut Frame, app: &mut App, area: Rect, has_focus: bool) {
     f.render_widget(logs_widget, area);
 }
 pub fn draw_header(f: &mut Frame, app: &App, area: Rect) {
-    let menu_titles = ["Help", "Auto Import", "Import", "Export", "About", "Quit"];
+    let menu_titles = [
+        "Help",
+        "Auto Import",
+        "Import",
+        "Git Import",
+        "Export",
+        "About",
+        "Quit",
+    ];
     let menu: Vec<Line> = menu_titles
         .iter()
         .enumerate()
@@ -27,6 +27,11 @@ use ratatui::{
 use crate::core::built_info;
 use crate::tui::input::App;
 use crate::tui::input::DeleteButton;
+use crate::tui::input::{
+    GitImportField,
+    GitImportForm,
+    GitImportTask,
+};
 use crate::tui::ui::centered_rect;
 use crate::tui::ui::{
     resize_ascii_art,
@@ -40,6 +45,7 @@ use crate::tui::ui::{
     MAUVE,
     PINK,
     RED,
+    SUBTEXT0,
     TEAL,
     TEXT,
     YELLOW,
@@ -142,6 +148,10 @@ pub fn render_help_popup(f: &mut Frame, area: Rect) {
             "f: Start/Stop Port Forward",
             Style::default().fg(YELLOW),
         )),
+        Line::from(Span::styled(
+            "n: Cycle Network Fault (Running Table)",
+            Style::default().fg(YELLOW),
+        )),
         Line::from(Span::styled(
             "Space: Select/Deselect",
             Style::default().fg(YELLOW),
@@ -152,6 +162,10 @@ pub fn render_help_popup(f: &mut Frame, area: Rect) {
         )),
         Line::from(Span::styled("h: Show Help", Style::default().fg(YELLOW))),
         Line::from(Span::styled("i: Import", Style::default().fg(YELLOW))),
+        Line::from(Span::styled(
+            "g: Import from Git",
+            Style::default().fg(YELLOW),
+        )),
         Line::from(Span::styled("e: Export", Style::default().fg(YELLOW))),
         Line::from(Span::styled(
             "d: Delete Selected",
@@ -330,6 +344,39 @@ pub fn render_delete_confirmation_popup(
     f.render_widget(close_button, close_button_area);
 }
 
+pub fn render_import_confirmation_popup(
+    f: &mut Frame, preview: &Option<String>, area: Rect, selected_button: DeleteButton,
+) {
+    let preview_text = preview.as_deref().unwrap_or("");
+    render_popup(
+        f,
+        area,
+        "Import Preview",
+        MAUVE,
+        Text::raw(preview_text),
+        Alignment::Left,
+    );
+
+    let confirm_button = create_button("<Import>", selected_button == DeleteButton::Confirm);
+    let close_button = create_button("<Close>", selected_button == DeleteButton::Close);
+
+    let confirm_button_area = Rect::new(
+        area.x + (area.width / 2) - 15,
+        area.y + area.height - 4,
+        10,
+        3,
+    );
+    let close_button_area = Rect::new(
+        area.x + (area.width / 2) + 5,
+        area.y + area.height - 4,
+        10,
+        3,
+    );
+
+    f.render_widget(confirm_button, confirm_button_area);
+    f.render_widget(close_button, close_button_area);
+}
+
 fn create_button(label: &str, is_selected: bool) -> Paragraph<'_> {
     let style = if is_selected {
         Style::default().fg(LAVENDER).add_modifier(Modifier::BOLD)
@@ -350,7 +397,14 @@ pub fn render_context_selection_popup(f: &mut Frame, app: &mut App, area: Rect)
     let contexts: Vec<ListItem> = app
         .contexts
         .iter()
-        .map(|context| ListItem::new(context.clone()))
+        .map(|context| {
+            let marker = if app.selected_contexts.contains(context) {
+                "[x] "
+            } else {
+                "[ ] "
+            };
+            ListItem::new(format!("{}{}", marker, context))
+        })
         .collect();
 
     let context_list = List::new(contexts)
@@ -374,6 +428,14 @@ pub fn render_context_selection_popup(f: &mut Frame, app: &mut App, area: Rect)
             Span::raw("."),
         ]),
         Line::from(""),
+        Line::from(vec![
+            Span::raw("- Press "),
+            Span::styled("Space", Style::default().fg(YELLOW)),
+            Span::raw(" to mark several contexts, or "),
+            Span::styled("a", Style::default().fg(YELLOW)),
+            Span::raw(" to mark them all. Marked contexts are scanned in parallel, and configs that already exist are skipped."),
+        ]),
+        Line::from(""),
         Line::from(vec![
             Span::raw("- If a service has "),
             Span::styled(
@@ -399,3 +461,94 @@ pub fn render_context_selection_popup(f: &mut Frame, app: &mut App, area: Rect)
 
     f.render_widget(explanation_paragraph, explanation_area);
 }
+
+pub fn render_git_import_popup(f: &mut Frame, form: &GitImportForm, area: Rect) {
+    let masked_token = "*".repeat(form.token.chars().count());
+    let system_credentials = if form.use_system_credentials {
+        "[x]"
+    } else {
+        "[ ]"
+    };
+    let fields = [
+        (
+            GitImportField::RepoUrl,
+            "Repository URL",
+            form.repo_url.as_str(),
+        ),
+        (
+            GitImportField::GitRef,
+            "Branch, tag or commit",
+            form.git_ref.as_str(),
+        ),
+        (
+            GitImportField::ConfigPath,
+            "Config path",
+            form.config_path.as_str(),
+        ),
+        (GitImportField::Token, "Token", masked_token.as_str()),
+        (
+            GitImportField::SystemCredentials,
+            "Use system credentials",
+            system_credentials,
+        ),
+    ];
+
+    let mut lines = vec![Line::from("")];
+    for (field, label, value) in fields {
+        let style = if form.selected_field == field {
+            Style::default().fg(YELLOW).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(TEXT)
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!("{:<24}", label), style),
+            Span::styled(value.to_string(), Style::default().fg(LAVENDER)),
+        ]));
+        lines.push(Line::from(""));
+    }
+    lines.push(Line::from(Span::styled(
+        "tab/↑/↓: field | space: toggle | enter: import | esc: cancel",
+        Style::default().fg(SUBTEXT0),
+    )));
+
+    render_popup(
+        f,
+        area,
+        "Import from Git",
+        PINK,
+        Text::from(lines),
+        Alignment::Left,
+    );
+}
+
+pub fn render_git_import_progress_popup(f: &mut Frame, task: &GitImportTask, area: Rect) {
+    let mut lines = vec![
+        Line::from(""),
+        Line::from(Span::styled(
+            task.source.clone(),
+            Style::default().fg(LAVENDER),
+        )),
+        Line::from(""),
+        Line::from(Span::styled(
+            task.status.clone(),
+            Style::default().fg(YELLOW),
+        )),
+    ];
+
+    if !task.auth_attempts.is_empty() {
+        lines.push(Line::from(""));
+        lines.push(Line::from("Credentials tried:"));
+        for method in &task.auth_attempts {
+            lines.push(Line::from(format!("- {}", method)));
+        }
+    }
+
+    render_popup(
+        f,
+        area,
+        "Importing from Git",
+        MAUVE,
+        Text::from(lines),
+        Alignment::Left,
+    );
+}
@@ -2,6 +2,10 @@ use std::collections::HashSet;
 
 use kftray_commons::models::config_model::Config;
 use kftray_commons::models::config_state_model::ConfigState;
+use kftray_portforward::kube::fault::{
+    get_fault_profile,
+    FaultPreset,
+};
 use ratatui::prelude::Alignment;
 use ratatui::widgets::BorderType;
 use ratatui::widgets::TableState;
@@ -47,6 +51,18 @@ use crate::tui::ui::{
     YELLOW,
 };
 
+fn alias_with_fault(config: &Config) -> String {
+    let alias = config.alias.clone().unwrap_or_default();
+
+    match get_fault_profile(config.id.unwrap_or_default()) {
+        Some(profile) => match FaultPreset::matching(&profile) {
+            Some(preset) => format!("{} [{}]", alias, preset),
+            None => format!("{} [fault]", alias),
+        },
+        None => alias,
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn draw_configs_table(
     frame: &mut Frame, area: Rect, configs: &[Config], config_states: &[ConfigState],
@@ -75,7 +91,7 @@ pub fn draw_configs_table(
             };
 
             Row::new(vec![
-                Cell::from(config.alias.clone().unwrap_or_default()),
+                Cell::from(alias_with_fault(config)),
                 Cell::from(config.workload_type.clone().unwrap_or_default()),
                 Cell::from(
                     config
@@ -1,9 +1,25 @@
-use kftray_commons::config::{
-    export_configs,
-    import_configs,
+use kftray_commons::config::export_configs;
+use kftray_commons::config_merge::merge_configs;
+use kftray_commons::models::import_model::{
+    ImportDiff,
+    ImportOptions,
+};
+use kftray_config_source::credentials::{
+    get_token,
+    store_token,
+};
+use kftray_config_source::{
+    ConfigSource,
+    FetchedConfig,
+    ProgressCallback,
 };
 
-pub async fn import_configs_from_file(file_path: &str) -> Result<(), String> {
+/// Merges the configs of a file into the local ones, so importing the same
+/// file again updates the configs instead of duplicating them. A dry run only
+/// returns the diff the import would apply.
+pub async fn import_configs_from_file(
+    file_path: &str, options: ImportOptions,
+) -> Result<ImportDiff, String> {
     log::debug!("Starting import of configs from file: {}", file_path);
     let json = std::fs::read_to_string(file_path).map_err(|e| {
         let err_msg = format!("Failed to read file {}: {}", file_path, e);
@@ -12,13 +28,57 @@ pub async fn import_configs_from_file(file_path: &str) -> Result<(), String> {
     })?;
     log::debug!("File content read successfully. Size: {} bytes", json.len());
 
-    import_configs(json).await.map_err(|e| {
+    let diff = merge_configs(json, options).await.map_err(|e| {
         let err_msg = format!("Failed to import configs: {}", e);
         log::error!("{}", err_msg);
         err_msg
     })?;
     log::debug!("Successfully imported configs from file: {}", file_path);
-    Ok(())
+    Ok(diff)
+}
+
+/// Merges the configs read from a source into the local ones.
+///
+/// Without a token, the one stored for the source's host is used. A token
+/// that was given is stored for the host once the import succeeded.
+pub async fn import_configs_from_source(
+    source: ConfigSource, token: Option<String>, progress: ProgressCallback,
+) -> Result<ImportDiff, String> {
+    log::debug!("Starting import of configs from {}", source);
+    let host = source.host();
+    let given_token = token.filter(|t| !t.is_empty());
+    let token = given_token
+        .clone()
+        .or_else(|| host.as_deref().and_then(get_token));
+
+    let content = match source
+        .fetch_with_progress(token, None, Some(progress))
+        .await
+        .map_err(|e| {
+            let err_msg = format!("Failed to read configs from {}: {}", source, e);
+            log::error!("{}", err_msg);
+            err_msg
+        })? {
+        FetchedConfig::Changed { content, .. } => content,
+        FetchedConfig::Unchanged => return Ok(ImportDiff::default()),
+    };
+
+    let diff = merge_configs(content, ImportOptions::default())
+        .await
+        .map_err(|e| {
+            let err_msg = format!("Failed to import configs: {}", e);
+            log::error!("{}", err_msg);
+            err_msg
+        })?;
+
+    if let (Some(host), Some(token)) = (host, given_token) {
+        if let Err(e) = store_token(&host, &token) {
+            log::warn!("Failed to store token for {}: {}", host, e);
+        }
+    }
+
+    log::debug!("Successfully imported configs from {}", source);
+    Ok(diff)
 }
 
 pub async fn export_configs_to_file(file_path: &str) -> Result<(), String> {